@@ -0,0 +1,71 @@
+//! `--jobs` wall time: `cargo bench --bench jobs`.
+//!
+//! Generates a tree of Rust files with test modules and runs the release
+//! binary over it serially and with one thread per CPU, plain and with
+//! `--strip-test-annotations` (the slowest transform). Prints the median of
+//! a few runs for each; there is nothing to compare against on one CPU.
+
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+const FILES: usize = 2000;
+const RUNS: usize = 5;
+
+fn main() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    write_tree(tmp.path());
+    let cpus = thread::available_parallelism().map_or(1, usize::from);
+    println!("{FILES} files, {cpus} CPU(s), median of {RUNS} runs");
+    for extra in [&[][..], &["--strip-test-annotations"][..]] {
+        for jobs in ["1", "0"] {
+            let median = median_run(tmp.path(), jobs, extra);
+            let what = if extra.is_empty() { "plain" } else { extra[0] };
+            println!(
+                "{what:<26} --jobs {jobs}: {:>8.1} ms",
+                median.as_secs_f64() * 1e3
+            );
+        }
+    }
+}
+
+fn write_tree(root: &Path) {
+    for i in 0..FILES {
+        let dir = root.join(format!("m{}", i % 50));
+        fs::create_dir_all(&dir).expect("mkdir");
+        let body: String = (0..40)
+            .map(|n| format!("pub fn f{n}(x: u32) -> u32 {{\n    x.wrapping_mul({n}) + 1\n}}\n\n"))
+            .collect();
+        let tests: String = (0..20)
+            .map(|n| format!("    #[test]\n    fn t{n}() {{\n        assert_eq!(super::f{n}(1), {n} + 1);\n    }}\n\n"))
+            .collect();
+        fs::write(
+            dir.join(format!("f{i}.rs")),
+            format!("{body}#[cfg(test)]\nmod tests {{\n{tests}}}\n"),
+        )
+        .expect("write");
+    }
+}
+
+fn median_run(root: &Path, jobs: &str, extra: &[&str]) -> Duration {
+    let mut times: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let started = Instant::now();
+            let status = Command::new(env!("CARGO_BIN_EXE_fencecat"))
+                .current_dir(root)
+                .args([".", "--quiet", "--jobs", jobs])
+                .args(extra)
+                .stdout(Stdio::null())
+                .status()
+                .expect("run fencecat");
+            assert!(status.success());
+            started.elapsed()
+        })
+        .collect();
+    times.sort();
+    times[RUNS / 2]
+}
@@ -1,12 +1,129 @@
 use std::{
+    fs,
     io::{self, Write as _},
+    path::{Path, PathBuf},
     process::{Command, Stdio},
 };
 
-pub fn copy_to_clipboard_multi(text: &str) -> Result<(), String> {
+use clap::ValueEnum;
+
+/// Clipboard flavors offered by `--copy`, besides plain text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum CopyMime {
+    /// Plain text only
+    #[default]
+    Plain,
+    /// Also offer `text/markdown` (Wayland) or HTML (macOS, Windows)
+    Markdown,
+    /// Also offer `text/html` rendered from the fences, on every platform
+    Html,
+}
+
+impl CopyMime {
+    #[must_use]
+    pub const fn mime_type(self) -> &'static str {
+        match self {
+            Self::Plain => "text/plain;charset=utf-8",
+            Self::Markdown => "text/markdown",
+            Self::Html => "text/html",
+        }
+    }
+}
+
+/// Where a copy of the output goes: the system clipboard, a file
+/// (`--save-clip` without `--copy`), or both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClipboardSink {
+    Clipboard,
+    File(PathBuf),
+    Both(PathBuf),
+}
+
+impl ClipboardSink {
+    /// Sink for the `--copy` / `--save-clip` combination, if any.
+    #[must_use]
+    pub fn new(copy: bool, save: Option<PathBuf>) -> Option<Self> {
+        match (copy, save) {
+            (true, None) => Some(Self::Clipboard),
+            (false, Some(path)) => Some(Self::File(path)),
+            (true, Some(path)) => Some(Self::Both(path)),
+            (false, None) => None,
+        }
+    }
+
+    #[must_use]
+    pub const fn copies(&self) -> bool {
+        matches!(self, Self::Clipboard | Self::Both(_))
+    }
+
+    #[must_use]
+    pub fn file(&self) -> Option<&Path> {
+        match self {
+            Self::File(path) | Self::Both(path) => Some(path),
+            Self::Clipboard => None,
+        }
+    }
+}
+
+/// Write `text` to `path` via a temporary sibling file and a rename, so
+/// readers never see a partially written file.
+///
+/// # Errors
+/// Returns the I/O error of the write or rename; the temporary file is
+/// removed on failure.
+pub fn write_atomic(path: &Path, text: &str) -> io::Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(name);
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp = path.with_file_name(tmp_name);
+
+    let result = fs::File::create(&tmp)
+        .and_then(|mut f| {
+            f.write_all(text.as_bytes())?;
+            f.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Copy `text` with the first clipboard backend that works. A rich `mime`
+/// flavor is best effort: when offering it fails, plain text is copied.
+///
+/// # Errors
+/// Returns the last backend error when nothing could be copied.
+pub fn copy_to_clipboard_multi(text: &str, mime: CopyMime) -> Result<(), String> {
+    // 0) HTML with a plain-text alternative needs a library that offers both
+    // flavors at once; the CLI tools below offer one
+    let mime = if mime == CopyMime::Html {
+        match arboard_html(text) {
+            Ok(()) if html_copy_persisted() => return Ok(()),
+            Ok(()) => eprintln!(">> HTML clipboard flavor did not persist; copying plain text"),
+            Err(e) => eprintln!(">> HTML clipboard flavor failed: {e}; copying plain text"),
+        }
+        CopyMime::Plain
+    } else {
+        mime
+    };
+
     // 1) Wayland-native CLI (best on Wayland)
     if is_wayland() && cmd_exists("wl-copy") {
-        if let Err(e) = wl_copy(text) {
+        let copied = match wl_copy(text, mime) {
+            Err(e) if mime != CopyMime::Plain => {
+                eprintln!(
+                    ">> wl-copy --type {} failed: {e}; copying plain text",
+                    mime.mime_type()
+                );
+                wl_copy(text, CopyMime::Plain)
+            }
+            result => result,
+        };
+        if let Err(e) = copied {
             eprintln!(">> wl-copy failed: {e}");
         } else if verify_wl_paste_non_empty() {
             return Ok(());
@@ -32,6 +149,15 @@ pub fn copy_to_clipboard_multi(text: &str) -> Result<(), String> {
     }
 
     // 3) OS-specific fallbacks
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    {
+        if mime == CopyMime::Markdown {
+            match arboard_html(text) {
+                Ok(()) => return Ok(()),
+                Err(e) => eprintln!(">> HTML clipboard flavor failed: {e}; copying plain text"),
+            }
+        }
+    }
     #[cfg(target_os = "macos")]
     {
         if cmd_exists("pbcopy") {
@@ -88,19 +214,24 @@ fn run_with_stdin(bin: &str, args: &[&str], data: &[u8]) -> io::Result<()> {
 }
 
 // wl-clipboard
-fn wl_copy(text: &str) -> io::Result<()> {
-    // Explicit type avoids some MIME weirdness; -n avoids trailing newline issues.
-    run_with_stdin(
-        "wl-copy",
-        &["--type", "text/plain;charset=utf-8", "-n"],
-        text.as_bytes(),
-    )
+/// Explicit type avoids some MIME weirdness; -n avoids trailing newline issues.
+/// wl-copy also offers the plain-text aliases for any `text/*` type, so a
+/// single `text/markdown` offer still pastes as plain text.
+const fn wl_copy_args(mime: CopyMime) -> [&'static str; 3] {
+    ["--type", mime.mime_type(), "-n"]
+}
+fn wl_copy(text: &str, mime: CopyMime) -> io::Result<()> {
+    run_with_stdin("wl-copy", &wl_copy_args(mime), text.as_bytes())
 }
 fn verify_wl_paste_non_empty() -> bool {
     if !cmd_exists("wl-paste") {
         return true; // can't verify; assume okay
     }
-    match Command::new("wl-paste").arg("-n").output() {
+    // Only the plain flavor is checked, whatever else was offered
+    match Command::new("wl-paste")
+        .args(["-n", "--type", "text/plain"])
+        .output()
+    {
         Ok(out) => !out.stdout.is_empty(),
         Err(_) => true,
     }
@@ -156,6 +287,21 @@ fn powershell_clip(text: &str) -> io::Result<()> {
     )
 }
 
+/// On Linux the clipboard is served by its owner, so content set through
+/// arboard survives our exit only if a clipboard manager takes it over. Check
+/// with a paste tool where there is one.
+fn html_copy_persisted() -> bool {
+    if cfg!(any(target_os = "macos", target_os = "windows")) {
+        true
+    } else if is_wayland() && cmd_exists("wl-paste") {
+        verify_wl_paste_non_empty()
+    } else if is_x11() && cmd_exists("xclip") {
+        verify_xclip_non_empty()
+    } else {
+        true
+    }
+}
+
 // library fallback (works great on macOS/Windows; mixed on Linux depending on desktop)
 fn arboard_fallback(text: &str) -> Result<(), String> {
     match arboard::Clipboard::new() {
@@ -163,3 +309,46 @@ fn arboard_fallback(text: &str) -> Result<(), String> {
         Err(e) => Err(e.to_string()),
     }
 }
+
+// HTML flavor with a plain-text alternative
+fn arboard_html(text: &str) -> Result<(), String> {
+    let mut cb = arboard::Clipboard::new().map_err(|e| e.to_string())?;
+    let (html, plain) = html_flavors(text);
+    cb.set_html(html, Some(plain)).map_err(|e| e.to_string())
+}
+
+/// What `--clipboard-html` offers for `text`: the fences rendered as HTML,
+/// and the Markdown itself as the plain-text alternative.
+fn html_flavors(text: &str) -> (String, String) {
+    (crate::output::html::fences_to_html(text), text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CopyMime, html_flavors, wl_copy_args};
+
+    #[test]
+    fn wl_copy_offers_the_requested_type() {
+        assert_eq!(
+            wl_copy_args(CopyMime::Plain),
+            ["--type", "text/plain;charset=utf-8", "-n"]
+        );
+        assert_eq!(
+            wl_copy_args(CopyMime::Markdown),
+            ["--type", "text/markdown", "-n"]
+        );
+    }
+
+    #[test]
+    fn html_flavor_renders_the_fences_and_keeps_the_markdown() {
+        let dump = "```\nmain.rs\n```\n\n```rust edition=2024 main.rs\nfn main() {}\n\n```\n\n";
+        let (html, plain) = html_flavors(dump);
+        assert_eq!(
+            html,
+            "<pre><code>main.rs\n</code></pre>\n\
+             <p><code>main.rs</code></p>\n\
+             <pre><code class=\"language-rust\">fn main() {}\n</code></pre>\n"
+        );
+        assert_eq!(plain, dump);
+    }
+}
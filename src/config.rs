@@ -0,0 +1,56 @@
+use crate::tokens::Tokenizer;
+
+/// Settings bundle for a particular LLM chat interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LlmPreset {
+    pub name: &'static str,
+    /// Warn once the output is estimated to exceed this many tokens.
+    pub token_budget_warn: usize,
+    /// Tokenizer used for the estimate.
+    pub tokenizer: Tokenizer,
+    /// Flags the preset turns on, as command-line words. Flags given
+    /// explicitly override them.
+    pub default_flags: &'static [&'static str],
+}
+
+pub const LLM_PRESETS: &[LlmPreset] = &[
+    LlmPreset {
+        name: "gpt-4o",
+        token_budget_warn: 120_000,
+        tokenizer: Tokenizer::Cl100k,
+        default_flags: &["--format", "md"],
+    },
+    LlmPreset {
+        name: "claude-3.5-sonnet",
+        token_budget_warn: 180_000,
+        tokenizer: Tokenizer::Claude,
+        default_flags: &[],
+    },
+    LlmPreset {
+        name: "gemini-1.5-pro",
+        token_budget_warn: 900_000,
+        tokenizer: Tokenizer::Gemini,
+        default_flags: &[],
+    },
+];
+
+/// Look up a preset by name (case-insensitive).
+#[must_use]
+pub fn find_llm_preset(name: &str) -> Option<&'static LlmPreset> {
+    LLM_PRESETS
+        .iter()
+        .find(|p| p.name.eq_ignore_ascii_case(name.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::find_llm_preset;
+
+    #[test]
+    fn preset_lookup_ignores_case() {
+        let p = find_llm_preset("GPT-4o").expect("preset");
+        assert_eq!(p.token_budget_warn, 120_000);
+        assert_eq!(p.default_flags, ["--format", "md"]);
+        assert!(find_llm_preset("nope").is_none());
+    }
+}
@@ -0,0 +1,5 @@
+//! Input sources other than the filesystem walk.
+
+pub mod archive;
+#[cfg(feature = "testing")]
+pub mod fixture;
@@ -0,0 +1,176 @@
+//! `--from-archive`: collect the members of a ZIP or tar file without
+//! extracting them.
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use fencecat::FileInfo;
+use flate2::read::GzDecoder;
+
+use crate::{
+    Candidate, CollectConfig, Collected, Error, PatternHits, SkipReason, error_kind,
+    filter_candidate, io_context,
+};
+
+/// Archive formats `--from-archive` reads, told apart by file name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// Collect the files of the archive at `path`, filtered like a walk.
+pub fn collect_from_archive(path: &Path, cfg: &CollectConfig) -> Result<Collected, Error> {
+    match ArchiveKind::from_path(path) {
+        Some(ArchiveKind::Zip) => collect_from_zip(path, cfg),
+        Some(kind) => collect_from_tar(path, kind == ArchiveKind::TarGz, cfg),
+        None => Err(Error::Usage(format!(
+            "unsupported archive (expected .zip, .tar, .tar.gz or .tgz): {}",
+            path.display()
+        ))),
+    }
+}
+
+pub fn collect_from_zip(path: &Path, cfg: &CollectConfig) -> Result<Collected, Error> {
+    let bad = |e: &dyn std::fmt::Display| Error::Runtime(format!("{}: {e}", path.display()));
+    let file = File::open(path).map_err(|e| bad(&e))?;
+    let mut zip = zip::ZipArchive::new(file).map_err(|e| bad(&e))?;
+    let mut collected = Collected::default();
+    for i in 0..zip.len() {
+        let mut member = zip.by_index(i).map_err(|e| bad(&e))?;
+        let name = member.name().to_string();
+        if member.is_dir() {
+            continue;
+        }
+        let Some(path) = member_path(&name, cfg)? else {
+            continue;
+        };
+        if skip_oversized(&path, member.size(), cfg, &mut collected)? {
+            continue;
+        }
+        let mut data = Vec::new();
+        if let Err(e) = member.read_to_end(&mut data) {
+            let e = io_context("read error")(e);
+            collected.file_error(cfg, error_kind(&e), Some(&name), &e)?;
+            continue;
+        }
+        // ZIP timestamps carry no time zone; leave the date unknown
+        add_member(&path, data, None, cfg, &mut collected)?;
+    }
+    Ok(collected)
+}
+
+/// `gzip` selects `.tar.gz`; members other than regular files are skipped.
+pub fn collect_from_tar(path: &Path, gzip: bool, cfg: &CollectConfig) -> Result<Collected, Error> {
+    let bad = |e: &io::Error| Error::Runtime(format!("{}: {e}", path.display()));
+    let file = File::open(path).map_err(|e| bad(&e))?;
+    let reader: Box<dyn Read> = if gzip {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    let mut tar = tar::Archive::new(reader);
+    let mut collected = Collected::default();
+    for entry in tar.entries().map_err(|e| bad(&e))? {
+        let mut entry = entry.map_err(|e| bad(&e))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let Some(path) = member_path(&name, cfg)? else {
+            continue;
+        };
+        if skip_oversized(&path, entry.size(), cfg, &mut collected)? {
+            continue;
+        }
+        let mtime = entry
+            .header()
+            .mtime()
+            .ok()
+            .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+        let mut data = Vec::new();
+        if let Err(e) = entry.read_to_end(&mut data) {
+            let e = io_context("read error")(e);
+            collected.file_error(cfg, error_kind(&e), Some(&name), &e)?;
+            continue;
+        }
+        add_member(&path, data, mtime, cfg, &mut collected)?;
+    }
+    Ok(collected)
+}
+
+/// Path of a member below the archive root, or `None` when a `!` glob
+/// excludes it. Names that would land outside the root (`..`, absolute
+/// paths) end the run with an error.
+fn member_path(name: &str, cfg: &CollectConfig) -> Result<Option<PathBuf>, Error> {
+    let path = fencecat::safe_join(Path::new(""), name).ok_or_else(|| {
+        Error::Runtime(format!(
+            "archive member {name} escapes the archive root; refusing to read the archive"
+        ))
+    })?;
+    let ignored = cfg
+        .overrides
+        .as_ref()
+        .is_some_and(|ov| ov.matched(&path, false).is_ignore());
+    Ok((!ignored).then_some(path))
+}
+
+/// Leave out a member whose declared size is over `--max-file-size`, before
+/// reading it into memory. Returns whether it was left out.
+fn skip_oversized(
+    path: &Path,
+    size: u64,
+    cfg: &CollectConfig,
+    collected: &mut Collected,
+) -> Result<bool, Error> {
+    if cfg.max_file_size.is_none_or(|max| size <= max) {
+        return Ok(false);
+    }
+    let decision = Ok((Err(SkipReason::TooLarge), PatternHits::default()));
+    collected.admit(cfg, Path::new(""), path, decision)?;
+    Ok(true)
+}
+
+/// Put one member through the filters other than `--glob`, which
+/// [`member_path`] applied.
+fn add_member(
+    path: &Path,
+    data: Vec<u8>,
+    mtime: Option<SystemTime>,
+    cfg: &CollectConfig,
+    collected: &mut Collected,
+) -> Result<(), Error> {
+    let cand = Candidate::Member {
+        path,
+        data: &data,
+        mtime,
+    };
+    let decision = filter_candidate(&cand, Path::new(""), cfg).map(|(verdict, hits)| {
+        let verdict = verdict.map(|f| FileInfo {
+            data: Some(data),
+            ..f
+        });
+        (verdict, hits)
+    });
+    collected.admit(cfg, Path::new(""), path, decision)
+}
@@ -0,0 +1,111 @@
+//! `--fake-root`: materialize a JSON manifest of files in a temp directory
+//! and scan that instead of the real filesystem. Only built with the
+//! `testing` feature.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use serde::Deserialize;
+use tempfile::TempDir;
+
+use crate::Error;
+
+/// One manifest entry: a `/`-separated path relative to the fixture and the
+/// file's full text.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    path: String,
+    content: String,
+}
+
+/// A manifest written out to a temp directory, removed again on drop.
+#[derive(Debug)]
+pub struct Fixture {
+    dir: TempDir,
+}
+
+impl Fixture {
+    /// Parse `manifest` (a JSON array of `{"path", "content"}` objects) and
+    /// create its files. Absolute paths, `..` and duplicates are usage
+    /// errors, so a manifest can never write outside the temp directory.
+    pub fn create(manifest: &str) -> Result<Self, Error> {
+        let entries: Vec<Entry> = serde_json::from_str(manifest)
+            .map_err(|e| Error::Usage(format!("invalid --fake-root-manifest: {e}")))?;
+        let dir = tempfile::Builder::new()
+            .prefix("fencecat-fixture-")
+            .tempdir()
+            .map_err(|e| Error::Runtime(format!("failed to create fixture directory: {e}")))?;
+        let mut seen = HashSet::new();
+        for entry in &entries {
+            let rel = checked_path(&entry.path)?;
+            if rel.as_os_str().is_empty() {
+                return Err(Error::Usage(
+                    "--fake-root-manifest has an entry without a file name".to_string(),
+                ));
+            }
+            if !seen.insert(rel.clone()) {
+                return Err(Error::Usage(format!(
+                    "--fake-root-manifest lists {} twice",
+                    entry.path
+                )));
+            }
+            let path = dir.path().join(&rel);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|e| {
+                    Error::Runtime(format!("failed to create {}: {e}", parent.display()))
+                })?;
+            }
+            fs::write(&path, &entry.content).map_err(|e| {
+                Error::Runtime(format!("failed to write fixture file {}: {e}", entry.path))
+            })?;
+        }
+        Ok(Self { dir })
+    }
+
+    /// `root` (the `--fake-root` value) inside the fixture, which must be a
+    /// directory or file the manifest created.
+    pub fn resolve(&self, root: &Path) -> Result<PathBuf, Error> {
+        let rel = checked_path(&root.to_string_lossy())?;
+        let path = self.dir.path().join(rel);
+        if path.exists() {
+            Ok(path)
+        } else {
+            Err(Error::Usage(format!(
+                "--fake-root {} is not in the manifest",
+                root.display()
+            )))
+        }
+    }
+}
+
+/// `path` as a relative path that stays inside the fixture (`.` and empty
+/// components dropped).
+fn checked_path(path: &str) -> Result<PathBuf, Error> {
+    let mut rel = PathBuf::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => rel.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(Error::Usage(format!(
+                    "fixture path {path:?} must be relative and stay inside the fixture"
+                )));
+            }
+        }
+    }
+    Ok(rel)
+}
+
+/// The manifest given to `--fake-root-manifest`: inline when it starts with
+/// `[`, otherwise the name of a JSON file.
+pub fn read_manifest(arg: &str) -> Result<String, Error> {
+    if arg.trim_start().starts_with('[') {
+        return Ok(arg.to_string());
+    }
+    fs::read_to_string(arg)
+        .map_err(|e| Error::Usage(format!("failed to read --fake-root-manifest {arg}: {e}")))
+}
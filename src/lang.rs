@@ -0,0 +1,379 @@
+use std::{borrow::Cow, collections::HashMap, fmt};
+
+pub mod python;
+pub mod rust;
+pub mod typescript;
+
+/// Why an external tool could not transform a file (`--typescript-strip-types`,
+/// `--python-stub`).
+#[derive(Debug, Clone)]
+pub enum TransformError {
+    /// The tool could not be started.
+    Unavailable(String),
+    /// The tool ran but produced nothing usable.
+    Failed(String),
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unavailable(msg) | Self::Failed(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+/// Fence language for a (lowercased, dot-less) file extension.
+#[must_use]
+pub fn language_for_ext(ext: &str) -> Option<&'static str> {
+    let lang = match ext {
+        "rs" => "rust",
+        "py" | "pyi" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "tsx",
+        "go" => "go",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "swift" => "swift",
+        "rb" => "ruby",
+        "pl" | "pm" => "perl",
+        "php" => "php",
+        "lua" => "lua",
+        "sh" => "sh",
+        "bash" => "bash",
+        "zsh" => "zsh",
+        "fish" => "fish",
+        "ps1" => "powershell",
+        "md" | "markdown" => "markdown",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "json" => "json",
+        "xml" => "xml",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "sql" => "sql",
+        "nix" => "nix",
+        "zig" => "zig",
+        "hs" => "haskell",
+        "ex" | "exs" => "elixir",
+        "erl" => "erlang",
+        "scala" => "scala",
+        "dart" => "dart",
+        "vue" => "vue",
+        "svelte" => "svelte",
+        "proto" => "protobuf",
+        "tf" => "hcl",
+        "dockerfile" | "containerfile" => "dockerfile",
+        "mk" | "mak" => "makefile",
+        "just" => "just",
+        "cmake" => "cmake",
+        "gradle" => "groovy",
+        _ => return None,
+    };
+    Some(lang)
+}
+
+/// Human-readable name of a fence language, for headings: `typescript` →
+/// `TypeScript`. Languages without a known spelling are capitalized.
+#[must_use]
+pub fn language_display_name(lang: &str) -> Cow<'static, str> {
+    let name = match lang {
+        "javascript" => "JavaScript",
+        "jsx" => "JSX",
+        "typescript" => "TypeScript",
+        "tsx" => "TSX",
+        "cpp" => "C++",
+        "csharp" => "C#",
+        "php" => "PHP",
+        "sh" => "Shell",
+        "powershell" => "PowerShell",
+        "toml" => "TOML",
+        "yaml" => "YAML",
+        "json" => "JSON",
+        "xml" => "XML",
+        "html" => "HTML",
+        "css" => "CSS",
+        "scss" => "SCSS",
+        "sql" => "SQL",
+        "hcl" => "HCL",
+        "cmake" => "CMake",
+        "protobuf" => "Protocol Buffers",
+        _ => {
+            let mut chars = lang.chars();
+            return Cow::Owned(
+                chars
+                    .next()
+                    .map(|c| c.to_uppercase().chain(chars).collect())
+                    .unwrap_or_default(),
+            );
+        }
+    };
+    Cow::Borrowed(name)
+}
+
+/// Extensions whose files are binary in practice, skipped by name
+/// (`--ignore-binary-extensions`) instead of after reading their first bytes.
+pub const KNOWN_BINARY_EXTENSIONS: &[&str] = &[
+    // Images
+    "png", "jpg", "jpeg", "gif", "webp", "bmp", "ico", "tif", "tiff", "avif", "heic", "psd",
+    // Audio and video
+    "mp3", "wav", "ogg", "flac", "m4a", "mp4", "webm", "mov", "mkv", "avi",
+    // Executables and libraries
+    "exe", "dll", "so", "dylib", "a", "lib", "o", "obj", "rlib", "wasm", "class", "pyc", "pyo",
+    // Archives
+    "zip", "tar", "gz", "tgz", "bz2", "xz", "zst", "7z", "rar", "jar",
+    // Documents, fonts and databases
+    "pdf", "doc", "docx", "xls", "xlsx", "ppt", "pptx", "woff", "woff2", "ttf", "otf", "eot",
+    "sqlite", "db",
+];
+
+/// MIME type for common image, audio and video extensions.
+#[must_use]
+pub fn media_type_for_ext(ext: &str) -> Option<&'static str> {
+    let mime = match ext {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "ico" => "image/x-icon",
+        "tif" | "tiff" => "image/tiff",
+        "avif" => "image/avif",
+        "heic" => "image/heic",
+        "svg" => "image/svg+xml",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" => "audio/ogg",
+        "flac" => "audio/flac",
+        "m4a" => "audio/mp4",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "mov" => "video/quicktime",
+        "mkv" => "video/x-matroska",
+        "avi" => "video/x-msvideo",
+        _ => return None,
+    };
+    Some(mime)
+}
+
+/// Well-known file names that carry no (or a misleading) extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownFile {
+    /// Fence language, e.g. `makefile`.
+    pub lang: &'static str,
+    /// Token `--ext` matches on, e.g. `dockerfile` or `license`.
+    pub token: &'static str,
+}
+
+/// `(name, lang, token, matches_suffixed)`; names compare case-insensitively.
+const KNOWN_FILES: &[(&str, &str, &str, bool)] = &[
+    ("Makefile", "makefile", "makefile", true),
+    ("GNUmakefile", "makefile", "makefile", false),
+    ("Dockerfile", "dockerfile", "dockerfile", true),
+    ("Containerfile", "dockerfile", "dockerfile", true),
+    ("Justfile", "just", "justfile", false),
+    (".justfile", "just", "justfile", false),
+    ("CMakeLists.txt", "cmake", "cmake", false),
+    ("Gemfile", "ruby", "gemfile", false),
+    ("Rakefile", "ruby", "rakefile", false),
+    ("Vagrantfile", "ruby", "vagrantfile", false),
+    ("Podfile", "ruby", "podfile", false),
+    ("Jenkinsfile", "groovy", "jenkinsfile", false),
+    ("Procfile", "yaml", "procfile", false),
+    ("BUILD", "starlark", "bazel", false),
+    ("WORKSPACE", "starlark", "bazel", false),
+    ("LICENSE", "text", "license", true),
+    ("COPYING", "text", "license", false),
+    ("NOTICE", "text", "notice", false),
+];
+
+/// Look up a file name in the built-in table.
+///
+/// Names marked as suffixable also match variants such as `Dockerfile.prod`,
+/// `Dockerfile-dev` or `LICENSE-MIT`.
+#[must_use]
+pub fn known_filename(name: &str) -> Option<KnownFile> {
+    KNOWN_FILES
+        .iter()
+        .find(|(known, .., suffixed)| {
+            name.eq_ignore_ascii_case(known)
+                || (*suffixed
+                    && name.len() > known.len()
+                    && name.is_char_boundary(known.len())
+                    && name[..known.len()].eq_ignore_ascii_case(known)
+                    && matches!(name.as_bytes()[known.len()], b'.' | b'-'))
+        })
+        .map(|&(_, lang, token, _)| KnownFile { lang, token })
+}
+
+/// User overrides from `--lang-map EXT=LANG`, keyed by lowercase extension
+/// (or well-known file token such as `dockerfile`).
+#[derive(Debug, Clone, Default)]
+pub struct LangMap(HashMap<String, String>);
+
+impl LangMap {
+    #[must_use]
+    pub fn new(entries: &[(String, String)]) -> Self {
+        Self(entries.iter().cloned().collect())
+    }
+
+    /// `Some("")` means the tag is suppressed for this key.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    /// Pick the fence language for a file.
+    ///
+    /// Precedence: user map (by extension, then well-known name), well-known
+    /// name table, extension table, shebang.
+    #[must_use]
+    pub fn resolve(
+        &self,
+        ext: Option<&str>,
+        known: Option<KnownFile>,
+        shebang: Option<Shebang>,
+    ) -> Option<Cow<'static, str>> {
+        let user = ext
+            .and_then(|e| self.get(e))
+            .or_else(|| known.and_then(|k| self.get(k.token)));
+        if let Some(lang) = user {
+            return (!lang.is_empty()).then(|| Cow::Owned(lang.to_string()));
+        }
+        known
+            .map(|k| k.lang)
+            .or_else(|| ext.and_then(language_for_ext))
+            .or_else(|| shebang.map(|sb| sb.lang))
+            .map(Cow::Borrowed)
+    }
+}
+
+/// Parse one `--lang-map` entry (`EXT=LANG`, LANG may be empty).
+///
+/// # Errors
+/// Returns a message when the entry has no `=` or an empty extension.
+pub fn parse_lang_map_entry(s: &str) -> Result<(String, String), String> {
+    let Some((ext, lang)) = s.split_once('=') else {
+        return Err(format!(
+            "expected EXT=LANG (e.g. vy=python), got `{s}`; use EXT= to drop the tag"
+        ));
+    };
+    let ext = ext.trim().trim_start_matches('.').to_ascii_lowercase();
+    if ext.is_empty() {
+        return Err(format!("missing extension in `{s}`"));
+    }
+    Ok((ext, lang.trim().to_string()))
+}
+
+/// Language guessed from a `#!` interpreter line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shebang {
+    /// Fence language, e.g. `python`.
+    pub lang: &'static str,
+    /// Extension the script would normally carry, e.g. `py`; used for `--ext` matching.
+    pub ext: &'static str,
+}
+
+/// Detect the interpreter of a script from its first bytes.
+///
+/// Handles direct paths (`#!/bin/bash -eu`) and `env` indirection
+/// (`#!/usr/bin/env -S python3 -u`), ignoring version suffixes.
+#[must_use]
+pub fn detect_shebang(head: &[u8]) -> Option<Shebang> {
+    let rest = head.strip_prefix(b"#!")?;
+    let line = rest.split(|&b| b == b'\n').next()?;
+    let line = std::str::from_utf8(line).ok()?;
+    let mut words = line.split_whitespace();
+
+    let mut interp = basename(words.next()?);
+    if interp == "env" {
+        interp = words
+            .find(|w| !w.starts_with('-') && !w.contains('='))
+            .map(basename)?;
+    }
+    let name = interp.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+    let (lang, ext) = match name {
+        "sh" | "dash" | "ksh" => ("sh", "sh"),
+        "bash" => ("bash", "sh"),
+        "zsh" => ("zsh", "zsh"),
+        "python" => ("python", "py"),
+        "node" | "nodejs" | "deno" | "bun" => ("javascript", "js"),
+        "ruby" => ("ruby", "rb"),
+        "perl" => ("perl", "pl"),
+        _ => return None,
+    };
+    Some(Shebang { lang, ext })
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        LangMap, detect_shebang, known_filename, language_display_name, parse_lang_map_entry,
+    };
+
+    #[test]
+    fn display_names() {
+        assert_eq!(language_display_name("typescript"), "TypeScript");
+        assert_eq!(language_display_name("rust"), "Rust");
+        assert_eq!(language_display_name("cpp"), "C++");
+    }
+
+    #[test]
+    fn lang_map_overrides_builtin() {
+        let map = LangMap::new(&[
+            parse_lang_map_entry("md=mdx").expect("entry"),
+            parse_lang_map_entry(".toml=").expect("entry"),
+        ]);
+        assert_eq!(map.resolve(Some("md"), None, None).as_deref(), Some("mdx"));
+        assert_eq!(map.resolve(Some("toml"), None, None), None);
+        assert_eq!(map.resolve(Some("rs"), None, None).as_deref(), Some("rust"));
+        assert!(parse_lang_map_entry("vy:python").is_err());
+    }
+
+    #[test]
+    fn known_filenames_and_variants() {
+        assert_eq!(known_filename("Makefile").map(|k| k.lang), Some("makefile"));
+        assert_eq!(known_filename("makefile").map(|k| k.lang), Some("makefile"));
+        let prod = known_filename("Dockerfile.prod").expect("variant");
+        assert_eq!((prod.lang, prod.token), ("dockerfile", "dockerfile"));
+        assert!(known_filename("Dockerfile-dev").is_some());
+        assert_eq!(
+            known_filename("LICENSE-MIT").map(|k| k.token),
+            Some("license")
+        );
+        assert!(known_filename("Dockerfiles").is_none());
+        assert!(known_filename("Gemfile.lock").is_none());
+    }
+
+    #[test]
+    fn env_python3() {
+        let s = detect_shebang(b"#!/usr/bin/env python3\nprint(1)\n").expect("shebang");
+        assert_eq!((s.lang, s.ext), ("python", "py"));
+    }
+
+    #[test]
+    fn shebang_with_flags() {
+        let s = detect_shebang(b"#!/bin/bash -eu\nset -x\n").expect("shebang");
+        assert_eq!((s.lang, s.ext), ("bash", "sh"));
+        let s = detect_shebang(b"#!/usr/bin/env -S node --harmony\n").expect("shebang");
+        assert_eq!(s.lang, "javascript");
+    }
+
+    #[test]
+    fn no_shebang() {
+        assert!(detect_shebang(b"print(1)\n").is_none());
+        assert!(detect_shebang(b"#!/usr/bin/unknown-tool\n").is_none());
+    }
+}
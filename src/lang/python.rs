@@ -0,0 +1,160 @@
+//! `.pyi`-style stubs for `--python-stub`, built by Python's own `ast`
+//! module in a `python3` subprocess.
+
+use std::{
+    io::Write as _,
+    process::{Command, Stdio},
+};
+
+use super::TransformError;
+
+/// Reads a module on stdin and prints its stub: imports, module-level
+/// assignments (values replaced by `...`), classes and functions with their
+/// decorators, signatures and docstrings, bodies replaced by `...`.
+/// `argv[1] == "1"` keeps `_private` names; dunders are always kept.
+const STUB_SCRIPT: &str = r#"
+import ast, sys
+
+keep_private = sys.argv[1] == "1"
+
+def public(name):
+    return keep_private or not name.startswith("_") or (name.startswith("__") and name.endswith("__"))
+
+def docstring(node):
+    body = node.body
+    if body and isinstance(body[0], ast.Expr) and isinstance(body[0].value, ast.Constant) and isinstance(body[0].value.value, str):
+        return [body[0]]
+    return []
+
+def ellipsis():
+    return ast.Expr(ast.Constant(...))
+
+def stub_body(nodes, in_class):
+    out = []
+    for node in nodes:
+        if isinstance(node, (ast.Import, ast.ImportFrom)) and not in_class:
+            out.append(node)
+        elif isinstance(node, (ast.FunctionDef, ast.AsyncFunctionDef)):
+            if public(node.name):
+                node.body = docstring(node) + [ellipsis()]
+                out.append(node)
+        elif isinstance(node, ast.ClassDef):
+            if public(node.name):
+                node.body = docstring(node) + stub_body(node.body, True) or [ellipsis()]
+                out.append(node)
+        elif isinstance(node, ast.AnnAssign) and isinstance(node.target, ast.Name):
+            if public(node.target.id):
+                node.value = ast.Constant(...) if node.value is not None else None
+                out.append(node)
+        elif isinstance(node, ast.Assign) and all(isinstance(t, ast.Name) for t in node.targets):
+            if any(public(t.id) for t in node.targets):
+                node.value = ast.Constant(...)
+                out.append(node)
+    return out
+
+tree = ast.parse(sys.stdin.read())
+tree.body = docstring(tree) + stub_body(tree.body, False)
+print(ast.unparse(tree))
+"#;
+
+/// Reduce a Python module to a `.pyi`-style stub: signatures, classes,
+/// docstrings and imports, with `...` for bodies and values. Members named
+/// `_like_this` are left out unless `include_private`.
+///
+/// # Errors
+/// `Unavailable` when no Python interpreter can be started, `Failed` when
+/// it cannot parse the module.
+pub fn extract_python_stub(content: &str, include_private: bool) -> Result<String, TransformError> {
+    let python = which::which("python3")
+        .or_else(|_| which::which("python"))
+        .map_err(|e| TransformError::Unavailable(format!("no Python interpreter: {e}")))?;
+    let mut child = Command::new(python)
+        .args(["-c", STUB_SCRIPT, if include_private { "1" } else { "0" }])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| TransformError::Unavailable(format!("no Python interpreter: {e}")))?;
+    // A write error means Python exited early; its stderr says why
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(content.as_bytes());
+    }
+    let out = child
+        .wait_with_output()
+        .map_err(|e| TransformError::Failed(e.to_string()))?;
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        let msg = stderr.lines().last().unwrap_or("python failed").trim();
+        return Err(TransformError::Failed(msg.to_string()));
+    }
+    String::from_utf8(out.stdout).map_err(|e| TransformError::Failed(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TransformError, extract_python_stub};
+
+    #[test]
+    fn stubs_keep_interfaces_and_drop_bodies() {
+        let module = r#""""Geometry helpers."""
+import math
+from typing import Optional
+
+SCALE: float = 2.0
+_cache = {}
+
+class Vector(Base):
+    """A 2D vector."""
+    dims: int = 2
+
+    def __init__(self, x: float, y: float) -> None:
+        self.x = x
+        self.y = y
+
+    @property
+    def norm(self) -> float:
+        """Length."""
+        return math.hypot(self.x, self.y)
+
+    def _helper(self):
+        pass
+
+def _private(): pass
+
+async def fetch(url: str, timeout: Optional[int] = None) -> bytes:
+    return b""
+"#;
+        let stub = match extract_python_stub(module, false) {
+            Ok(stub) => stub,
+            // No Python on this machine
+            Err(TransformError::Unavailable(_)) => return,
+            Err(e) => panic!("python failed: {e}"),
+        };
+        assert_eq!(
+            stub,
+            r#""""Geometry helpers."""
+import math
+from typing import Optional
+SCALE: float = ...
+
+class Vector(Base):
+    """A 2D vector."""
+    dims: int = ...
+
+    def __init__(self, x: float, y: float) -> None:
+        ...
+
+    @property
+    def norm(self) -> float:
+        """Length."""
+        ...
+
+async def fetch(url: str, timeout: Optional[int]=None) -> bytes:
+    ...
+"#
+        );
+        let with_private = extract_python_stub(module, true).expect("python ran before");
+        assert!(with_private.contains("def _helper(self):"));
+        assert!(with_private.contains("_cache = ..."));
+    }
+}
@@ -0,0 +1,326 @@
+//! Rust-specific metadata for fence info strings and test stripping.
+
+use std::{fs, path::Path};
+
+use syn::{Attribute, ImplItem, Item};
+
+/// Editions `--rust-edition` accepts.
+pub const EDITIONS: [&str; 4] = ["2015", "2018", "2021", "2024"];
+
+/// Edition declared by `root/Cargo.toml`, if there is one.
+///
+/// Reads `[package] edition`, following `edition.workspace = true` to
+/// `[workspace.package]`; a virtual workspace manifest uses its
+/// `[workspace.package] edition`. A package without an `edition` key is
+/// `2015`, as Cargo treats it.
+#[must_use]
+pub fn detect_rust_edition(root: &Path) -> Option<String> {
+    let text = fs::read_to_string(root.join("Cargo.toml")).ok()?;
+    edition_from_manifest(&text)
+}
+
+fn edition_from_manifest(text: &str) -> Option<String> {
+    let manifest: toml::Table = text.parse().ok()?;
+    let workspace_edition = || {
+        manifest
+            .get("workspace")?
+            .get("package")?
+            .get("edition")?
+            .as_str()
+            .map(str::to_string)
+    };
+    let Some(package) = manifest.get("package") else {
+        return workspace_edition();
+    };
+    match package.get("edition") {
+        None => Some("2015".to_string()),
+        Some(toml::Value::String(edition)) => Some(edition.clone()),
+        Some(inherited)
+            if inherited.get("workspace").and_then(toml::Value::as_bool) == Some(true) =>
+        {
+            workspace_edition()
+        }
+        Some(_) => None,
+    }
+}
+
+/// Whether an attribute marks a test function: `#[test]` or a runner's
+/// variant such as `#[tokio::test]`.
+fn is_test_attr(attr: &Attribute) -> bool {
+    attr.path()
+        .segments
+        .last()
+        .is_some_and(|s| s.ident == "test")
+}
+
+/// Whether an attribute is exactly `#[cfg(test)]`.
+fn is_cfg_test(attr: &Attribute) -> bool {
+    attr.path().is_ident("cfg")
+        && attr
+            .parse_args::<syn::Ident>()
+            .is_ok_and(|ident| ident == "test")
+}
+
+fn item_attrs(item: &Item) -> &[Attribute] {
+    match item {
+        Item::Const(i) => &i.attrs,
+        Item::Enum(i) => &i.attrs,
+        Item::ExternCrate(i) => &i.attrs,
+        Item::Fn(i) => &i.attrs,
+        Item::ForeignMod(i) => &i.attrs,
+        Item::Impl(i) => &i.attrs,
+        Item::Macro(i) => &i.attrs,
+        Item::Mod(i) => &i.attrs,
+        Item::Static(i) => &i.attrs,
+        Item::Struct(i) => &i.attrs,
+        Item::Trait(i) => &i.attrs,
+        Item::TraitAlias(i) => &i.attrs,
+        Item::Type(i) => &i.attrs,
+        Item::Union(i) => &i.attrs,
+        Item::Use(i) => &i.attrs,
+        _ => &[],
+    }
+}
+
+/// Remove test items from `items`, recursing into inline modules and impl
+/// blocks. Returns whether anything was removed.
+fn strip_items(items: &mut Vec<Item>, keep_test_modules: bool) -> bool {
+    let before = items.len();
+    items.retain(|item| {
+        let attrs = item_attrs(item);
+        let test_fn = matches!(item, Item::Fn(_)) && attrs.iter().any(is_test_attr);
+        let test_mod = !keep_test_modules
+            && (attrs.iter().any(is_cfg_test)
+                || matches!(item, Item::Mod(m) if m.ident == "tests"));
+        !(test_fn || test_mod)
+    });
+    let mut changed = items.len() != before;
+    for item in items {
+        match item {
+            Item::Mod(m) => {
+                if let Some((_, inner)) = &mut m.content {
+                    changed |= strip_items(inner, keep_test_modules);
+                }
+            }
+            Item::Impl(imp) if !keep_test_modules => {
+                let before = imp.items.len();
+                imp.items
+                    .retain(|i| !matches!(i, ImplItem::Fn(f) if f.attrs.iter().any(is_cfg_test)));
+                changed |= imp.items.len() != before;
+            }
+            _ => {}
+        }
+    }
+    changed
+}
+
+fn strip(content: &str, keep_test_modules: bool) -> Result<String, syn::Error> {
+    let mut file = syn::parse_file(content)?;
+    if strip_items(&mut file.items, keep_test_modules) {
+        Ok(prettyplease::unparse(&file))
+    } else {
+        Ok(content.to_string())
+    }
+}
+
+/// `--strip-test-annotations`: remove `#[test]` functions, `#[cfg(test)]`
+/// items and modules named `tests`.
+///
+/// A file with something removed is pretty-printed from its syntax tree,
+/// which drops ordinary comments (doc comments survive); a file without test
+/// items is returned as is.
+///
+/// # Errors
+/// Returns the parse error when `content` is not valid Rust.
+pub fn strip_test_items(content: &str) -> Result<String, syn::Error> {
+    strip(content, false)
+}
+
+/// Like [`strip_test_items`], but only `#[test]` functions go; test modules
+/// and their helpers stay (`--keep-test-modules`).
+///
+/// # Errors
+/// Returns the parse error when `content` is not valid Rust.
+pub fn strip_test_functions(content: &str) -> Result<String, syn::Error> {
+    strip(content, true)
+}
+
+/// Rough cyclomatic complexity of Rust source for `--sort-by-complexity`:
+/// the number of `if`, `for` and `while` keywords, match arms (`=>`), `&&`
+/// and `||`, scanned token by token.
+///
+/// Comments, string and character literals are skipped; nothing is parsed,
+/// so a closure without parameters (`|| x`) and `macro_rules!` arms count
+/// too.
+#[must_use]
+pub fn estimate_complexity(content: &str) -> usize {
+    let bytes = content.as_bytes();
+    let mut count = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+        if let Some(len) = skipped_len(rest) {
+            i += len;
+            continue;
+        }
+        i += match rest {
+            [b'=', b'>', ..] | [b'&', b'&', ..] | [b'|', b'|', ..] => {
+                count += 1;
+                2
+            }
+            [c, ..] if c.is_ascii_alphabetic() || *c == b'_' => {
+                let len = rest
+                    .iter()
+                    .position(|b| !b.is_ascii_alphanumeric() && *b != b'_')
+                    .unwrap_or(rest.len());
+                if matches!(&rest[..len], b"if" | b"for" | b"while") {
+                    count += 1;
+                }
+                len
+            }
+            _ => 1,
+        };
+    }
+    count
+}
+
+/// Length of the comment or literal at the start of `s`, if there is one.
+fn skipped_len(s: &[u8]) -> Option<usize> {
+    match s {
+        [b'/', b'/', ..] => Some(s.iter().position(|&b| b == b'\n').unwrap_or(s.len())),
+        [b'/', b'*', ..] => Some(block_comment_len(s)),
+        [b'"', ..] => Some(quoted_len(s, b'"')),
+        [b'r', b'"' | b'#', ..] | [b'b', b'r', b'"' | b'#', ..] => raw_string_len(s),
+        [b'\'', b'\\', ..] => Some(quoted_len(s, b'\'')),
+        // A character literal, or nothing for a lifetime
+        [b'\'', ..] => char_literal_len(s),
+        _ => None,
+    }
+}
+
+/// Length of a (possibly nested) `/* */` comment at the start of `s`.
+fn block_comment_len(s: &[u8]) -> usize {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < s.len() {
+        match &s[i..] {
+            [b'/', b'*', ..] => {
+                depth += 1;
+                i += 2;
+            }
+            [b'*', b'/', ..] => {
+                depth -= 1;
+                i += 2;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    s.len()
+}
+
+/// Length of a literal opened by `quote` at the start of `s`, escapes included.
+fn quoted_len(s: &[u8], quote: u8) -> usize {
+    let mut i = 1;
+    while i < s.len() {
+        match s[i] {
+            b'\\' => i += 2,
+            c if c == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    s.len()
+}
+
+/// Length of `r#"…"#` (or `br…`) at the start of `s`.
+fn raw_string_len(s: &[u8]) -> Option<usize> {
+    let start = if s.starts_with(b"br") { 2 } else { 1 };
+    let hashes = s[start..].iter().take_while(|&&b| b == b'#').count();
+    if s.get(start + hashes) != Some(&b'"') {
+        return None;
+    }
+    let body = start + hashes + 1;
+    let mut close = vec![b'"'];
+    close.resize(hashes + 1, b'#');
+    let end = s[body..]
+        .windows(close.len())
+        .position(|w| w == close.as_slice())
+        .map_or(s.len(), |p| body + p + close.len());
+    Some(end)
+}
+
+/// Length of an unescaped character literal such as `'x'` or `'é'` at the
+/// start of `s`; `None` for a lifetime.
+fn char_literal_len(s: &[u8]) -> Option<usize> {
+    let width = std::str::from_utf8(&s[1..s.len().min(5)])
+        .or_else(|e| std::str::from_utf8(&s[1..1 + e.valid_up_to()]))
+        .ok()?
+        .chars()
+        .next()?
+        .len_utf8();
+    (s.get(1 + width) == Some(&b'\'')).then_some(width + 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        edition_from_manifest, estimate_complexity, strip_test_functions, strip_test_items,
+    };
+
+    const SRC: &str = "fn add(a: u8, b: u8) -> u8 {\n    a + b\n}\n\n#[test]\nfn top() {}\n\n#[cfg(test)]\nmod tests {\n    fn helper() {}\n\n    #[tokio::test]\n    async fn t() {}\n}\n";
+
+    #[test]
+    fn strips_tests_and_test_modules() {
+        assert_eq!(
+            strip_test_items(SRC).expect("parse"),
+            "fn add(a: u8, b: u8) -> u8 {\n    a + b\n}\n"
+        );
+        assert_eq!(
+            strip_test_functions(SRC).expect("parse"),
+            "fn add(a: u8, b: u8) -> u8 {\n    a + b\n}\n#[cfg(test)]\nmod tests {\n    fn helper() {}\n}\n"
+        );
+        let plain = "// keep me\nfn a() {}\n";
+        assert_eq!(strip_test_items(plain).expect("parse"), plain);
+        assert!(strip_test_items("fn (").is_err());
+    }
+
+    #[test]
+    fn reads_package_and_workspace_editions() {
+        let pkg = "[package]\nname = \"x\"\nedition = \"2021\"\n";
+        assert_eq!(edition_from_manifest(pkg).as_deref(), Some("2021"));
+        assert_eq!(
+            edition_from_manifest("[package]\nname = \"x\"\n").as_deref(),
+            Some("2015")
+        );
+        let inherited = "[workspace.package]\nedition = \"2024\"\n\n[package]\nname = \"x\"\nedition.workspace = true\n";
+        assert_eq!(edition_from_manifest(inherited).as_deref(), Some("2024"));
+        let virtual_ws = "[workspace]\nmembers = [\"a\"]\n";
+        assert_eq!(edition_from_manifest(virtual_ws), None);
+        assert_eq!(edition_from_manifest("not toml ["), None);
+    }
+
+    #[test]
+    fn counts_branches_outside_comments_and_literals() {
+        let src = r##"
+fn f<'a>(x: &'a str, n: u8) -> u8 {
+    // if this were code: if for while
+    /* nested /* if */ && */
+    let s = "if || while";
+    let r = r#"for "&&" if"#;
+    let c = '|';
+    if n > 1 && x.is_empty() || n == 0 {
+        for _ in 0..n {}
+    }
+    while false {}
+    match n {
+        0 => 1,
+        _ => 2,
+    }
+}
+"##;
+        assert_eq!(estimate_complexity(src), 7);
+        assert_eq!(estimate_complexity("fn verify() -> iff {}"), 0);
+    }
+}
@@ -0,0 +1,288 @@
+//! TypeScript type stripping for `--typescript-strip-types`.
+//!
+//! Files are compiled in-process by SWC first. The ones SWC cannot handle
+//! (syntax it rejects, an `es3` target) go to the project's own compiler,
+//! all in one `npx --no --offline tsc` run, so nothing is downloaded.
+
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use swc_core::{
+    common::{
+        FileName, GLOBALS, Globals, Mark, SourceMap, Spanned as _, comments::Comments, sync::Lrc,
+    },
+    ecma::{
+        ast::EsVersion,
+        codegen::{Emitter, Node as _, text_writer::JsWriter},
+        parser::{Parser, StringInput, Syntax, TsSyntax},
+        transforms::{
+            base::{
+                fixer::fixer,
+                helpers::{HELPERS, Helpers, inject_helpers},
+                hygiene::hygiene,
+                resolver,
+            },
+            compat::{es2015, es2016, es2017, es2018, es2019, es2020, es2021, es2022},
+            typescript::strip,
+        },
+    },
+};
+
+use super::TransformError;
+
+/// Remove type annotations from TypeScript (`tsx` keeps JSX as is), emitting
+/// JavaScript for the `target` ECMAScript version (`es2022`, `esnext`, …).
+/// This is [`strip_typescript_types_batch`] for a single file.
+///
+/// # Errors
+/// As for [`strip_typescript_types_batch`].
+pub fn strip_typescript_types(
+    content: &str,
+    tsx: bool,
+    target: &str,
+    project: &Path,
+) -> Result<String, TransformError> {
+    strip_typescript_types_batch(&[(content, tsx)], target, project)
+        .pop()
+        .expect("one result per input")
+}
+
+/// [`strip_typescript_types`] for many files, one result per input. Each
+/// file goes through SWC; the ones it cannot compile are handed to a single
+/// `tsc` run, looked up from `project` (its `node_modules/.bin`, then
+/// `PATH`).
+///
+/// Comments are dropped as well (`tsc --removeComments --stripInternal`).
+/// Type errors do not matter; only a missing output does.
+///
+/// # Errors
+/// Per file: SWC's error when `tsc` cannot be started, `Unavailable` when
+/// there is neither (an `es3` target without `tsc`), `Failed` when `tsc`
+/// produced no JavaScript.
+pub fn strip_typescript_types_batch(
+    inputs: &[(&str, bool)],
+    target: &str,
+    project: &Path,
+) -> Vec<Result<String, TransformError>> {
+    let mut results: Vec<_> = inputs
+        .iter()
+        .map(|&(content, tsx)| strip_with_swc(content, tsx, target))
+        .collect();
+    let pending: Vec<usize> = (0..inputs.len()).filter(|&i| results[i].is_err()).collect();
+    if pending.is_empty() {
+        return results;
+    }
+    let batch: Vec<_> = pending.iter().map(|&i| inputs[i]).collect();
+    match run_tsc(&batch, target, project) {
+        Ok(compiled) => {
+            for (i, js) in pending.into_iter().zip(compiled) {
+                results[i] = js;
+            }
+        }
+        // SWC's own error says more than a missing tsc, unless SWC never tried
+        Err(e) => {
+            for i in pending {
+                if matches!(results[i], Err(TransformError::Unavailable(_))) {
+                    results[i] = Err(e.clone());
+                }
+            }
+        }
+    }
+    results
+}
+
+/// The ECMAScript version `target` names, as `tsc --target` spells it.
+fn es_version(target: &str) -> Option<EsVersion> {
+    Some(match target.to_ascii_lowercase().as_str() {
+        "es3" => EsVersion::Es3,
+        "es5" => EsVersion::Es5,
+        "es6" | "es2015" => EsVersion::Es2015,
+        "es2016" => EsVersion::Es2016,
+        "es2017" => EsVersion::Es2017,
+        "es2018" => EsVersion::Es2018,
+        "es2019" => EsVersion::Es2019,
+        "es2020" => EsVersion::Es2020,
+        "es2021" => EsVersion::Es2021,
+        "es2022" => EsVersion::Es2022,
+        "es2023" => EsVersion::Es2023,
+        "es2024" => EsVersion::Es2024,
+        "esnext" => EsVersion::EsNext,
+        _ => return None,
+    })
+}
+
+fn strip_with_swc(content: &str, tsx: bool, target: &str) -> Result<String, TransformError> {
+    let version = es_version(target)
+        .filter(|&v| v >= EsVersion::Es5)
+        .ok_or_else(|| TransformError::Unavailable(format!("SWC cannot target {target}")))?;
+    let cm: Lrc<SourceMap> = Lrc::default();
+    let fm = cm.new_source_file(Lrc::new(FileName::Anon), content.to_string());
+    let syntax = Syntax::Typescript(TsSyntax {
+        tsx,
+        decorators: true,
+        ..TsSyntax::default()
+    });
+    let mut parser = Parser::new(syntax, StringInput::from(&*fm), None);
+    let parsed = parser.parse_program();
+    let error = match (parsed, parser.take_errors().into_iter().next()) {
+        (Ok(program), None) => Ok(program),
+        (Err(e), _) | (Ok(_), Some(e)) => Err(e),
+    };
+    let program = error.map_err(|e| {
+        let line = cm.lookup_char_pos(e.span().lo).line;
+        TransformError::Failed(format!("line {line}: {}", e.kind().msg()))
+    })?;
+
+    let program = GLOBALS.set(&Globals::default(), || {
+        HELPERS.set(&Helpers::new(false), || {
+            let unresolved = Mark::new();
+            let top_level = Mark::new();
+            let below = |v: EsVersion| version < v;
+            program
+                .apply(resolver(unresolved, top_level, true))
+                .apply(strip(unresolved, top_level))
+                .apply((
+                    below(EsVersion::Es2022).then(|| es2022(Default::default(), unresolved)),
+                    below(EsVersion::Es2021).then(es2021),
+                    below(EsVersion::Es2020).then(|| es2020(Default::default(), unresolved)),
+                    below(EsVersion::Es2019).then(es2019),
+                    below(EsVersion::Es2018).then(|| es2018(Default::default())),
+                    below(EsVersion::Es2017).then(|| es2017(Default::default(), unresolved)),
+                    below(EsVersion::Es2016).then(es2016),
+                    below(EsVersion::Es2015)
+                        .then(|| es2015(unresolved, None::<&dyn Comments>, Default::default())),
+                ))
+                .apply(inject_helpers(unresolved))
+                .apply(hygiene())
+                .apply(fixer(None))
+        })
+    });
+
+    let mut buf = Vec::new();
+    let mut emitter = Emitter {
+        cfg: swc_core::ecma::codegen::Config::default().with_target(version),
+        cm: cm.clone(),
+        comments: None,
+        wr: JsWriter::new(cm, "\n", &mut buf, None),
+    };
+    program
+        .emit_with(&mut emitter)
+        .map_err(|e| TransformError::Failed(e.to_string()))?;
+    String::from_utf8(buf).map_err(|e| TransformError::Failed(e.to_string()))
+}
+
+/// One `tsc` run over all of `inputs`, in a private temp directory. The
+/// outer error means `tsc` could not be run at all.
+fn run_tsc(
+    inputs: &[(&str, bool)],
+    target: &str,
+    project: &Path,
+) -> Result<Vec<Result<String, TransformError>>, TransformError> {
+    let unavailable = |e: &dyn std::fmt::Display| {
+        TransformError::Unavailable(format!("no TypeScript compiler: {e}"))
+    };
+    let npx = which::which("npx").map_err(|e| unavailable(&e))?;
+    let dir = tempfile::Builder::new()
+        .prefix("fencecat-ts-")
+        .tempdir()
+        .map_err(|e| TransformError::Failed(e.to_string()))?;
+    let names: Vec<(String, String)> = inputs
+        .iter()
+        .enumerate()
+        .map(|(i, &(_, tsx))| {
+            let ext = if tsx { ("tsx", "jsx") } else { ("ts", "js") };
+            (format!("{i}.{}", ext.0), format!("{i}.{}", ext.1))
+        })
+        .collect();
+    for ((input, _), &(content, _)) in names.iter().zip(inputs) {
+        fs::write(dir.path().join(input), content)
+            .map_err(|e| TransformError::Failed(e.to_string()))?;
+    }
+
+    let out_dir = dir.path().join("out");
+    let mut cmd = Command::new(npx);
+    cmd.args(["--no", "--offline", "--", "tsc", "--removeComments"])
+        .args(["--stripInternal", "--skipLibCheck", "--noResolve"])
+        .args(["--target", target, "--outDir"])
+        .arg(&out_dir);
+    if inputs.iter().any(|&(_, tsx)| tsx) {
+        cmd.args(["--jsx", "preserve"]);
+    }
+    let out = cmd
+        .args(names.iter().map(|(input, _)| dir.path().join(input)))
+        .current_dir(project)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| unavailable(&e))?;
+    // tsc exits non-zero on type errors but still emits; npx without tsc emits nothing
+    if !out_dir.is_dir() {
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        let msg = stderr
+            .lines()
+            .chain(stdout.lines())
+            .find(|l| !l.trim().is_empty())
+            .unwrap_or("no output")
+            .trim()
+            .to_string();
+        return Err(unavailable(&msg));
+    }
+    Ok(names
+        .iter()
+        .map(|(_, output)| {
+            fs::read_to_string(out_dir.join(output))
+                .map_err(|_| TransformError::Failed("tsc emitted no JavaScript".to_string()))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{TransformError, strip_typescript_types, strip_typescript_types_batch};
+
+    #[test]
+    fn swc_strips_types_and_lowers_to_the_target() {
+        let ts = "\
+// a comment
+interface Point { x: number }
+enum Dir { Up }
+export const norm = (p: Point): number => p.x ** 2;
+export const first = (xs?: number[]) => xs?.[0] as number;
+";
+        let js = strip_typescript_types(ts, false, "es2022", Path::new(".")).expect("swc");
+        assert!(!js.contains("interface") && !js.contains(": number") && !js.contains("comment"));
+        assert!(js.contains("var Dir"));
+        assert!(js.contains("p.x ** 2") && js.contains("xs?.[0]"));
+
+        let es5 = strip_typescript_types(ts, false, "ES5", Path::new(".")).expect("swc");
+        assert!(!es5.contains("=>") && !es5.contains("const ") && !es5.contains("?."));
+        assert!(es5.contains("Math.pow(p.x, 2)"));
+
+        let tsx = "export const A = (p: { n: number }) => <b>{p.n}</b>;\n";
+        let jsx = strip_typescript_types(tsx, true, "esnext", Path::new(".")).expect("swc");
+        assert!(jsx.contains("<b>{p.n}</b>") && !jsx.contains("number"));
+    }
+
+    #[test]
+    fn files_swc_rejects_are_reported_per_file() {
+        let empty = tempfile::tempdir().expect("tempdir");
+        let results = strip_typescript_types_batch(
+            &[("let a: number = 1;\n", false), ("let = ;\n", false)],
+            "es2020",
+            empty.path(),
+        );
+        assert_eq!(results[0].as_deref().ok(), Some("let a = 1;\n"));
+        // Without tsc (or with one that fails too) the SWC error stands
+        match &results[1] {
+            Err(TransformError::Failed(msg) | TransformError::Unavailable(msg)) => {
+                assert!(!msg.is_empty());
+            }
+            Ok(js) => panic!("compiled {js:?}"),
+        }
+    }
+}
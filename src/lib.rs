@@ -1,6 +1,585 @@
-use std::path::Path;
+use std::{
+    borrow::Cow,
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    fs::File,
+    io::{self, BufRead as _, BufReader, Write as _},
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
+use unicode_normalization::UnicodeNormalization as _;
+
+pub mod audit;
 pub mod clipboard;
+pub mod config;
+pub mod lang;
+pub mod output;
+pub mod parse;
+pub mod redact;
+pub mod script;
+pub mod summary;
+pub mod timings;
+pub mod tokens;
+pub mod transform;
+pub mod units;
+
+pub use units::{group_digits, human_size};
+
+/// A file selected for output.
+#[derive(Debug, Clone)]
+pub struct FileInfo {
+    pub path: PathBuf,
+    /// Display label, relative to the scan root with `/` separators.
+    pub rel: String,
+    pub size: u64,
+    /// Fence language, from the extension or a shebang line.
+    pub lang: Option<Cow<'static, str>>,
+    pub mtime: Option<SystemTime>,
+    /// Line count, filled in when the content is read.
+    pub lines: Option<usize>,
+    /// Hex SHA-256 of the file bytes, filled in when the content is read.
+    pub sha256: Option<String>,
+    /// MIME type when the file is emitted as a placeholder note instead of content.
+    pub media: Option<&'static str>,
+    /// Link target as stored in the link, when emitted as a `--symlinks annotate` note.
+    pub symlink: Option<String>,
+    /// Unix permission bits, recorded under `--preserve-permissions`.
+    pub mode: Option<u32>,
+    /// Any execute bit is set (always `false` on Windows).
+    pub executable: bool,
+    /// Short id of the content, set when another file has the same content
+    /// (`--content-hash-header`).
+    pub content_id: Option<String>,
+    /// Label of the earlier file with identical content; this one is emitted
+    /// as a cross-reference note (`--content-hash-header`).
+    pub duplicate_of: Option<String>,
+    /// Id of the `--fence-anchor` before the fence, unique in the output.
+    pub anchor: Option<String>,
+    /// Bytes already in memory: an archive member's, or a file's the
+    /// filters read in full (`--min-lines`, `--strict-binary`). Others are
+    /// read from `path`.
+    pub data: Option<Vec<u8>>,
+}
+
+/// Order of files within a group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// By path.
+    Name,
+    /// Largest first, ties by path.
+    Size,
+}
+
+/// The order of labels wherever files are sorted by path: code point
+/// order of their NFC forms, so a name the filesystem reports decomposed
+/// (`e` + U+0301, as macOS may) sorts like its precomposed twin (`é`) and
+/// identical trees list the same on every platform. Labels that differ
+/// only in normalization fall back to byte order. The labels themselves
+/// are never normalized.
+#[must_use]
+pub fn compare_labels(a: &str, b: &str) -> Ordering {
+    if a.is_ascii() && b.is_ascii() {
+        return a.cmp(b);
+    }
+    a.nfc().cmp(b.nfc()).then_with(|| a.cmp(b))
+}
+
+/// Sort `files` with `key` across the whole list. With `ext_order`
+/// (`--sort-extensions-first`), files are grouped by extension first: the
+/// listed ones in the given order, then the others alphabetically, then
+/// files without one; `key` orders each group.
+pub fn sort_files(files: &mut [FileInfo], key: SortKey, ext_order: Option<&[String]>) {
+    let by_key = |a: &FileInfo, b: &FileInfo| match key {
+        SortKey::Name => compare_labels(&a.rel, &b.rel),
+        SortKey::Size => Reverse(a.size)
+            .cmp(&Reverse(b.size))
+            .then_with(|| compare_labels(&a.rel, &b.rel)),
+    };
+    match ext_order {
+        Some(order) => files.sort_by(|a, b| {
+            ext_rank(a, order)
+                .cmp(&ext_rank(b, order))
+                .then_with(|| by_key(a, b))
+        }),
+        None => files.sort_by(by_key),
+    }
+}
+
+/// Position of `f`'s extension group under `order` (lowercase extensions
+/// without the dot), with the extension to order unlisted groups by. Files
+/// are grouped by their last extension; a compound one (`tar.gz`, `d.ts`)
+/// is a group of its own only when `order` lists it.
+fn ext_rank(f: &FileInfo, order: &[String]) -> (usize, String) {
+    let Some(ext) = compound_extension(Path::new(&f.rel)).map(|e| e.to_ascii_lowercase()) else {
+        return (order.len() + 1, String::new());
+    };
+    if let Some(rank) = order.iter().position(|o| *o == ext) {
+        return (rank, ext);
+    }
+    let last = ext.rsplit('.').next().unwrap_or(&ext).to_string();
+    let rank = order.iter().position(|o| *o == last).unwrap_or(order.len());
+    (rank, last)
+}
+
+/// Lowercase last extension of a file's label, `""` when it has none.
+fn ext_key(f: &FileInfo) -> String {
+    Path::new(&f.rel)
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+/// Two-level sort for `--sort-within-dir`: groups by the first path
+/// component in lexicographic order (files at the root come first), then
+/// `inner_key` (and `ext_order`, as in [`sort_files`]) within each group.
+pub fn stable_sort_within_dirs(
+    files: &mut [FileInfo],
+    inner_key: SortKey,
+    ext_order: Option<&[String]>,
+) {
+    sort_files(files, inner_key, ext_order);
+    files.sort_by(|a, b| compare_labels(top_dir(&a.rel), top_dir(&b.rel)));
+}
+
+/// First component of a relative label; files at the root give `""`.
+fn top_dir(rel: &str) -> &str {
+    rel.split_once('/').map_or("", |(dir, _)| dir)
+}
+
+/// Keep at most `n` files per extension (`--limit-ext`), the first ones in
+/// the current order. Extensions compare case-insensitively; files without
+/// one form a group of their own.
+#[must_use]
+pub fn limit_by_ext(files: Vec<FileInfo>, n: usize) -> Vec<FileInfo> {
+    limit_by(files, n, ext_key)
+}
+
+/// Keep at most `n` files per top-level directory (`--limit-dir`), the first
+/// ones in the current order. Files at the root form one group.
+#[must_use]
+pub fn limit_by_dir(files: Vec<FileInfo>, n: usize) -> Vec<FileInfo> {
+    limit_by(files, n, |f| top_dir(&f.rel).to_string())
+}
+
+fn limit_by(files: Vec<FileInfo>, n: usize, key: impl Fn(&FileInfo) -> String) -> Vec<FileInfo> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    files
+        .into_iter()
+        .filter(|f| {
+            let count = seen.entry(key(f)).or_insert(0);
+            *count += 1;
+            *count <= n
+        })
+        .collect()
+}
+
+/// Shorten labels that run through directories holding a single included
+/// file (`--collapse-single-file-dirs`): `src/utils/math/helpers/vector.rs`
+/// becomes `src/…/vector.rs` when `src/utils` holds nothing else.
+///
+/// Only labels with at least `min_depth` directories are shortened, the
+/// top-level directory is always kept, and a run of a single directory is
+/// not worth an ellipsis. Labels that would clash stay as they are. Only
+/// `rel` changes; files are still read from `path`.
+pub fn collapse_paths(files: &mut [FileInfo], min_depth: usize) {
+    let mut per_dir: HashMap<&str, usize> = HashMap::new();
+    for f in files.iter() {
+        for (i, _) in f.rel.match_indices('/') {
+            *per_dir.entry(&f.rel[..i]).or_insert(0) += 1;
+        }
+    }
+    let collapsed: Vec<Option<String>> = files
+        .iter()
+        .map(|f| {
+            let slashes: Vec<usize> = f.rel.match_indices('/').map(|(i, _)| i).collect();
+            if slashes.len() < min_depth.max(1) {
+                return None;
+            }
+            // First directory below the top level that holds only this file
+            let from =
+                (1..slashes.len()).find(|&d| per_dir.get(&f.rel[..slashes[d]]) == Some(&1))?;
+            (slashes.len() - from >= 2).then(|| {
+                format!(
+                    "{}/…{}",
+                    &f.rel[..slashes[from - 1]],
+                    &f.rel[slashes[slashes.len() - 1]..]
+                )
+            })
+        })
+        .collect();
+
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    for (f, c) in files.iter().zip(&collapsed) {
+        *labels
+            .entry(c.clone().unwrap_or_else(|| f.rel.clone()))
+            .or_insert(0) += 1;
+    }
+    for (f, c) in files.iter_mut().zip(collapsed) {
+        if let Some(label) = c
+            && labels.get(&label) == Some(&1)
+        {
+            f.rel = label;
+        }
+    }
+}
+
+/// Per-file stats shown by `--fence-metadata-header`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMetadataHeader<'a> {
+    pub path: &'a str,
+    pub size: u64,
+    pub lines: Option<usize>,
+    pub mtime: Option<String>,
+    pub sha256: Option<&'a str>,
+    pub mode: Option<u32>,
+    pub content_id: Option<&'a str>,
+}
+
+impl<'a> From<&'a FileInfo> for FileMetadataHeader<'a> {
+    fn from(info: &'a FileInfo) -> Self {
+        Self {
+            path: &info.rel,
+            size: info.size,
+            lines: info.lines,
+            mtime: info.mtime.map(format_utc_timestamp),
+            sha256: info.sha256.as_deref(),
+            mode: info.mode,
+            content_id: info.content_id.as_deref(),
+        }
+    }
+}
+
+impl fmt::Display for FileMetadataHeader<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<!-- fencecat: path={} size={}", self.path, self.size)?;
+        if let Some(lines) = self.lines {
+            write!(f, " lines={lines}")?;
+        }
+        if let Some(mtime) = &self.mtime {
+            write!(f, " mtime={mtime}")?;
+        }
+        if let Some(sha) = self.sha256 {
+            write!(f, " sha256={sha}")?;
+        }
+        if let Some(mode) = self.mode {
+            write!(f, " mode={mode:04o}")?;
+        }
+        if let Some(id) = self.content_id {
+            write!(f, " content={id}")?;
+        }
+        f.write_str(" -->")
+    }
+}
+
+/// One-line HTML comment with a file's stats, placed above its fence.
+#[must_use]
+pub fn render_metadata_comment(info: &FileInfo) -> String {
+    FileMetadataHeader::from(info).to_string()
+}
+
+/// Parse a byte size such as `4096`, `300k`, `1.5MB` or `2MiB`.
+///
+/// Units are binary and case-insensitive: `k`/`kb`/`kib` = 1024, `m…` = 1024²,
+/// `g…` = 1024³.
+///
+/// # Errors
+/// Returns a message for empty input, unknown units, or overflow.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let t = s.trim();
+    let split = t
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(t.len());
+    let (num, unit) = t.split_at(split);
+    let mult: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+        other => return Err(format!("unknown size unit `{other}` in `{s}`")),
+    };
+    let (whole, frac) = num.split_once('.').unwrap_or((num, ""));
+    if whole.is_empty() && frac.is_empty() {
+        return Err(format!("missing number in `{s}`"));
+    }
+    let whole: u64 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().map_err(|_| format!("invalid size `{s}`"))?
+    };
+    // Fractional part, scaled exactly in integers (at most 3 digits)
+    let frac_digits = &frac[..frac.len().min(3)];
+    let frac_val: u64 = if frac_digits.is_empty() {
+        0
+    } else {
+        frac_digits
+            .parse()
+            .map_err(|_| format!("invalid size `{s}`"))?
+    };
+    let frac_scale = 10u64.pow(u32::try_from(frac_digits.len()).unwrap_or(0));
+    whole
+        .checked_mul(mult)
+        .and_then(|w| w.checked_add(frac_val * mult / frac_scale))
+        .ok_or_else(|| format!("size `{s}` is too large"))
+}
+
+/// Print `msg` to stderr and read the answer from the terminal itself (not
+/// stdin, which may be a pipe). Only an answer starting with `y`/`Y`
+/// confirms; without a terminal the answer is no.
+#[must_use]
+pub fn prompt_confirm(msg: &str) -> bool {
+    let tty = if cfg!(windows) { "CONIN$" } else { "/dev/tty" };
+    let Ok(tty) = File::open(tty) else {
+        return false;
+    };
+    eprint!("{msg} ");
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    if BufReader::new(tty).read_line(&mut answer).is_err() {
+        return false;
+    }
+    answer.trim_start().starts_with(['y', 'Y'])
+}
+
+/// Format a timestamp as ISO 8601 in UTC, e.g. `2024-06-01T10:00:00Z`.
+#[must_use]
+pub fn format_utc_timestamp(t: SystemTime) -> String {
+    let secs = t
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs().cast_signed());
+    let days = secs.div_euclid(86_400);
+    let rem = secs.rem_euclid(86_400);
+
+    // Civil-from-days (Howard Hinnant)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// A Markdown code fence: `len` repetitions of `ch` (`` ` `` or `~`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fence {
+    pub ch: char,
+    pub len: usize,
+}
+
+impl fmt::Display for Fence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for _ in 0..self.len {
+            fmt::Write::write_char(f, self.ch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Length of the longest run of `ch` in `content`.
+fn longest_run(content: &str, ch: char) -> usize {
+    let mut best = 0;
+    let mut cur = 0;
+    for c in content.chars() {
+        if c == ch {
+            cur += 1;
+            best = best.max(cur);
+        } else {
+            cur = 0;
+        }
+    }
+    best
+}
+
+/// Pick a fence that cannot be closed by anything inside `content`.
+///
+/// With `prefer == '~'` (used for Markdown), content that contains backtick
+/// fences but no tilde fences gets a tilde fence instead of an escalated
+/// backtick one. Otherwise backticks are used, one longer than the longest
+/// backtick run (at least three).
+#[must_use]
+pub fn choose_fence(content: &str, prefer: char) -> Fence {
+    let backticks = longest_run(content, '`');
+    if prefer == '~' && backticks >= 3 {
+        let tildes = longest_run(content, '~');
+        if tildes < 3 {
+            return Fence {
+                ch: '~',
+                len: (tildes + 1).max(3),
+            };
+        }
+    }
+    Fence {
+        ch: '`',
+        len: (backticks + 1).max(3),
+    }
+}
+
+/// Quote a path for pasting into a shell. Strings made only of characters
+/// that are never special (`A-Za-z0-9` and `_-./:@%+,=`) are returned as is.
+///
+/// On Unix this is POSIX quoting: the string is wrapped in single quotes and
+/// embedded single quotes become `'\''`. On Windows it is CMD style: double
+/// quotes, with embedded double quotes doubled.
+#[must_use]
+pub fn shell_quote(s: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_-./:@%+,=".contains(c);
+    if !s.is_empty() && s.chars().all(safe) {
+        return s.to_string();
+    }
+    if cfg!(windows) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+/// Substitute `{name}` placeholders in `template` with `vars`. `{{` and `}}`
+/// stand for literal braces.
+///
+/// # Errors
+/// Returns a message for a placeholder missing from `vars` or an unmatched
+/// brace.
+pub fn render_filename_template(
+    template: &str,
+    vars: &HashMap<&str, &str>,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        out.push_str(&rest[..i]);
+        let tail = &rest[i..];
+        if let Some(after) = tail.strip_prefix("{{") {
+            out.push('{');
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix("}}") {
+            out.push('}');
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix('{')
+            && let Some((name, after)) = after.split_once('}')
+        {
+            let value = vars.get(name).ok_or_else(|| {
+                let mut known: Vec<&str> = vars.keys().copied().collect();
+                known.sort_unstable();
+                format!(
+                    "unknown placeholder `{{{name}}}` in `{template}` (known: {})",
+                    known.join(", ")
+                )
+            })?;
+            out.push_str(value);
+            rest = after;
+        } else {
+            return Err(format!("unmatched `{}` in `{template}`", &tail[..1]));
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// HTML anchor id for a label: ASCII alphanumerics lowercased, everything
+/// else replaced with `-` (`src/main.rs` → `src-main-rs`).
+#[must_use]
+pub fn path_to_anchor_id(rel: &str) -> String {
+    rel.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// `--fence-anchor` ids for labels in output order: [`path_to_anchor_id`],
+/// with `-1`, `-2`, … appended to repeats the way GitHub numbers duplicate
+/// heading ids (`a-b.rs`, `a.b.rs` → `a-b-rs`, `a-b-rs-1`).
+#[must_use]
+pub fn anchor_ids<'a>(rels: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut taken = HashSet::new();
+    rels.into_iter()
+        .map(|rel| {
+            let base = path_to_anchor_id(rel);
+            let mut id = base.clone();
+            let mut n = 0;
+            while !taken.insert(id.clone()) {
+                n += 1;
+                id = format!("{base}-{n}");
+            }
+            id
+        })
+        .collect()
+}
+
+/// Backslash-escape the characters that would end or restyle Markdown link
+/// text (`src/[id]/page.tsx` → `src/\[id\]/page.tsx`).
+#[must_use]
+pub fn escape_link_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '[' | ']' | '(' | ')' | '*' | '_' | '`' | '<' | '>'
+        ) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Markdown table of contents (`--section-toc`), followed by a blank line:
+/// one `(shown, anchor)` entry per file, shown as `shown` and linked to the
+/// `--fence-anchor` id `anchor`.
+#[must_use]
+pub fn emit_toc<'a, D: fmt::Display>(entries: impl IntoIterator<Item = (D, &'a str)>) -> String {
+    let mut out: String = entries
+        .into_iter()
+        .map(|(shown, anchor)| format!("- [{}](#{anchor})\n", escape_link_text(&shown.to_string())))
+        .collect();
+    out.push('\n');
+    out
+}
+
+/// File contents by path from one or more fencecat outputs (the parts of a
+/// `--chunk-by-tokens` run, in any order), with the pieces of files split
+/// by `--fence-continuation` joined back together.
+#[must_use]
+pub fn reassemble_fences(chunks: &[&str]) -> HashMap<String, String> {
+    let sections = chunks
+        .iter()
+        .flat_map(|text| parse::parse_dump(text))
+        .collect();
+    parse::merge_continuations(sections)
+        .into_iter()
+        .map(|s| (s.path, s.content))
+        .collect()
+}
+
+/// The last two extensions of a file name (`backup.tar.gz` → `tar.gz`), or
+/// just the one there is (`main.rs` → `rs`). The leading dot of a dotfile
+/// does not start an extension.
+#[must_use]
+pub fn compound_extension(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    let name = name.strip_prefix('.').unwrap_or(name);
+    let mut dots = name.rmatch_indices('.').map(|(i, _)| i);
+    let last = dots.next()?;
+    let start = dots.next().filter(|&i| i > 0).unwrap_or(last);
+    let ext = &name[start + 1..];
+    (!ext.is_empty() && !ext.starts_with('.') && !ext.ends_with('.')).then(|| ext.to_string())
+}
 
 #[must_use]
 pub fn rel_string(root: &Path, path: &Path) -> String {
@@ -17,11 +596,698 @@ pub fn rel_string(root: &Path, path: &Path) -> String {
     }
 }
 
+/// The deepest directory holding all of `files`, compared by their path
+/// components as given; `.` when they share none.
+#[must_use]
+pub fn common_parent(files: &[&Path]) -> PathBuf {
+    let mut parents = files
+        .iter()
+        .map(|f| f.parent().unwrap_or_else(|| Path::new("")));
+    let Some(first) = parents.next() else {
+        return PathBuf::from(".");
+    };
+    let mut common: Vec<_> = first.components().collect();
+    for parent in parents {
+        let shared = common
+            .iter()
+            .zip(parent.components())
+            .take_while(|(a, b)| **a == *b)
+            .count();
+        common.truncate(shared);
+    }
+    if common.is_empty() {
+        PathBuf::from(".")
+    } else {
+        common.iter().collect()
+    }
+}
+
+/// Draw `/`-separated labels as a `tree`-style outline under `.`, entries
+/// sorted by name and directories marked with a trailing `/`.
+#[must_use]
+pub fn render_tree<'a>(labels: impl IntoIterator<Item = &'a str>) -> String {
+    #[derive(Default)]
+    struct Node<'a>(BTreeMap<Name<'a>, Node<'a>>);
+
+    /// A path component, ordered by [`compare_labels`].
+    #[derive(PartialEq, Eq)]
+    struct Name<'a>(&'a str);
+
+    impl Ord for Name<'_> {
+        fn cmp(&self, other: &Self) -> Ordering {
+            compare_labels(self.0, other.0)
+        }
+    }
+
+    impl PartialOrd for Name<'_> {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    fn draw(node: &Node<'_>, prefix: &str, out: &mut String) {
+        let count = node.0.len();
+        for (i, (name, child)) in node.0.iter().enumerate() {
+            let last = i + 1 == count;
+            let slash = if child.0.is_empty() { "" } else { "/" };
+            let branch = if last { "└── " } else { "├── " };
+            out.push_str(&format!("{prefix}{branch}{}{slash}\n", name.0));
+            let indent = if last { "    " } else { "│   " };
+            draw(child, &format!("{prefix}{indent}"), out);
+        }
+    }
+
+    let mut root = Node::default();
+    for label in labels {
+        let mut node = &mut root;
+        for part in label.split('/').filter(|p| !p.is_empty()) {
+            node = node.0.entry(Name(part)).or_default();
+        }
+    }
+    let mut out = String::from(".\n");
+    draw(&root, "", &mut out);
+    out
+}
+
+/// Replace a leading `home` directory in a `/`-separated path with `~`
+/// (`/home/me/src/a.rs` → `~/src/a.rs`). Only whole components match.
+#[must_use]
+pub fn collapse_home<'a>(path: &'a str, home: &str) -> Cow<'a, str> {
+    let home = home.trim_end_matches('/');
+    match path.strip_prefix(home) {
+        Some(rest) if !home.is_empty() && (rest.is_empty() || rest.starts_with('/')) => {
+            Cow::Owned(format!("~{rest}"))
+        }
+        _ => Cow::Borrowed(path),
+    }
+}
+
+/// Expand a leading `~` in `path` to `home`, as a shell would; `None` when
+/// there is none (`~user` forms are not expanded).
+#[must_use]
+pub fn expand_home(path: &Path, home: &Path) -> Option<PathBuf> {
+    let s = path.to_str()?;
+    let rest = s.strip_prefix('~')?;
+    if rest.is_empty() {
+        return Some(home.to_path_buf());
+    }
+    let rest = rest.strip_prefix(['/', '\\'])?;
+    Some(home.join(rest))
+}
+
+/// Whether a PATH argument is meant as a glob: it has `*`, `?`, `[` or `{`.
+#[must_use]
+pub fn is_glob_pattern(path: &str) -> bool {
+    path.contains(['*', '?', '[', '{'])
+}
+
+/// Expand a glob the shell left alone (Windows shells never expand them).
+/// `/` and (on Windows) `\` separate components, `*` stays within one and
+/// `**` spans several. Only the directory named by the literal components
+/// in front is walked, with the walker `walker` builds for it (ignore
+/// rules and all), no deeper than the pattern reaches. Hidden entries are
+/// also visited when the pattern spells a leading `.` itself (`src/.*.rs`).
+/// Returns that directory and the matching files, sorted.
+///
+/// # Errors
+/// Returns the parse error of an invalid pattern.
+pub fn expand_glob(
+    pattern: &str,
+    walker: impl FnOnce(&Path) -> ignore::WalkBuilder,
+) -> Result<(PathBuf, Vec<PathBuf>), globset::Error> {
+    let pattern = glob_separators(pattern);
+    let matcher = globset::GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .build()?
+        .compile_matcher();
+    let (base, literal) = split_glob(&pattern);
+    let rest: Vec<&str> = pattern.split('/').skip(literal).collect();
+    let depth = (!rest.contains(&"**")).then_some(rest.len());
+
+    let mut wb = walker(&base);
+    wb.max_depth(depth);
+    if rest.iter().any(|c| c.starts_with('.')) {
+        wb.hidden(false);
+    }
+    let mut matches: Vec<PathBuf> = wb
+        .build()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_some_and(|ft| ft.is_file()))
+        .map(ignore::DirEntry::into_path)
+        .filter(|path| {
+            let candidate = path.to_string_lossy().replace('\\', "/");
+            let candidate = if literal == 0 {
+                candidate.strip_prefix("./").unwrap_or(&candidate)
+            } else {
+                &candidate
+            };
+            matcher.is_match(candidate)
+        })
+        .collect();
+    matches.sort();
+    Ok((base, matches))
+}
+
+/// The directory named by the literal components in front of a glob, which
+/// [`expand_glob`] walks and labels are relative to (`.` for none).
+#[must_use]
+pub fn glob_base(pattern: &str) -> PathBuf {
+    split_glob(&glob_separators(pattern)).0
+}
+
+/// `pattern` with `/` separators only.
+fn glob_separators(pattern: &str) -> String {
+    if cfg!(windows) {
+        pattern.replace('\\', "/")
+    } else {
+        pattern.to_string()
+    }
+}
+
+/// The literal directory in front of a `/`-separated glob and how many
+/// components it takes up. The last component never counts as literal.
+fn split_glob(pattern: &str) -> (PathBuf, usize) {
+    let components: Vec<&str> = pattern.split('/').collect();
+    let literal = components
+        .iter()
+        .take_while(|c| !is_glob_pattern(c))
+        .count()
+        .min(components.len() - 1);
+    let base = match components[..literal].join("/") {
+        _ if literal == 0 => PathBuf::from("."),
+        // `/*.rs`: the only literal component is the empty one before `/`
+        base if base.is_empty() => PathBuf::from("/"),
+        base => PathBuf::from(base),
+    };
+    (base, literal)
+}
+
+/// Join a dump label onto `root`, refusing anything that would land outside
+/// it: absolute paths, drive prefixes and `..` components. Labels use `/`
+/// separators; `\` is treated as one too.
+#[must_use]
+pub fn safe_join(root: &Path, label: &str) -> Option<PathBuf> {
+    let mut out = root.to_path_buf();
+    let mut depth = 0;
+    for part in label.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => return None,
+            p if p.contains(':') => return None,
+            p => {
+                out.push(p);
+                depth += 1;
+            }
+        }
+    }
+    (depth > 0 && !label.starts_with(['/', '\\'])).then_some(out)
+}
+
+/// Naming conventions for test files and directories, matched against a
+/// `/`-separated relative path by [`is_test_file`] (`--exclude-tests`,
+/// `--only-tests`).
+pub const TEST_FILE_GLOBS: &[&str] = &[
+    "**/tests/**",
+    "**/test/**",
+    "**/__tests__/**",
+    "**/spec/**",
+    "**/*_test.rs",
+    "**/*_tests.rs",
+    "**/*.{test,spec}.{js,jsx,mjs,cjs,ts,tsx,mts,cts}",
+    "**/test_*.py",
+    "**/*_test.py",
+    "**/conftest.py",
+    "**/*_test.go",
+    "**/*_spec.rb",
+    "**/*{Test,Tests}.{java,kt,cs}",
+];
+
+static TEST_FILES: LazyLock<globset::GlobSet> = LazyLock::new(|| {
+    let mut set = globset::GlobSetBuilder::new();
+    for pattern in TEST_FILE_GLOBS {
+        let glob = globset::GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .expect("valid test file glob");
+        set.add(glob);
+    }
+    set.build().expect("valid test file globs")
+});
+
+/// Whether `rel` looks like a test by [`TEST_FILE_GLOBS`]. A leading `./`
+/// or `../` does not count as a directory name.
+#[must_use]
+pub fn is_test_file(rel: &str) -> bool {
+    let mut rel = rel;
+    while let Some(rest) = rel.strip_prefix("./").or_else(|| rel.strip_prefix("../")) {
+        rel = rest;
+    }
+    TEST_FILES.is_match(rel)
+}
+
+/// What the first bytes of a file tell us. The default is what an unread
+/// file is assumed to be: text, without a shebang.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sniff {
+    /// Heuristic: the head contains a NUL byte.
+    pub binary: bool,
+    pub shebang: Option<lang::Shebang>,
+    /// The head looks like a previous fencecat dump.
+    pub fencecat_output: bool,
+}
+
+/// Classify a file from its first few KB.
+#[must_use]
+pub fn sniff(head: &[u8]) -> Sniff {
+    Sniff {
+        binary: head.contains(&0),
+        shebang: lang::detect_shebang(head),
+        fencecat_output: looks_like_fencecat_output(&String::from_utf8_lossy(head)),
+    }
+}
+
+/// Whether `text` is fencecat output. Either a line opens with one of
+/// fencecat's own markers (a `--fence-metadata-header` comment or a
+/// `--chunk-by-tokens` part header, both `<!-- fencecat: … -->`), or the text
+/// has fencecat's default shape: after any preamble (dir list, tree), at
+/// least two closed fences labeled with a path, each body ending in the blank
+/// line fencecat appends, and nothing but blank lines and HTML tags between
+/// them. Docs label fences with paths too, but rarely pad every one or leave
+/// no prose between them.
+#[must_use]
+pub fn looks_like_fencecat_output(text: &str) -> bool {
+    let mut labeled = 0;
+    // Closing fence and whether the last body line so far was blank
+    let mut open: Option<(&str, bool)> = None;
+    let mut seen_fence = false;
+    for line in text.lines() {
+        if line.starts_with("<!-- fencecat:") {
+            return true;
+        }
+        if let Some((fence, blank)) = &mut open {
+            if line == *fence {
+                if !*blank {
+                    return false;
+                }
+                labeled += 1;
+                open = None;
+            } else {
+                *blank = line.is_empty();
+            }
+            continue;
+        }
+        let ticks = line.len() - line.trim_start_matches('`').len();
+        let tildes = line.len() - line.trim_start_matches('~').len();
+        let width = ticks.max(tildes);
+        if width >= 3 {
+            let label = line[width..].split_whitespace().last();
+            if !label.is_some_and(|l| l.contains('/') || l.contains('.')) {
+                return false;
+            }
+            open = Some((&line[..width], false));
+            seen_fence = true;
+        } else if seen_fence && !line.is_empty() && !line.starts_with('<') {
+            return false;
+        }
+    }
+    labeled >= 2
+}
+
 #[cfg(test)]
 mod tests {
-    use std::path::{Path, PathBuf};
+    use std::{
+        cmp::Ordering,
+        collections::HashMap,
+        path::{Path, PathBuf},
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use super::{
+        Fence, FileInfo, SortKey, anchor_ids, choose_fence, collapse_home, collapse_paths,
+        common_parent, compare_labels, compound_extension, emit_toc, expand_glob, expand_home,
+        format_utc_timestamp, is_glob_pattern, is_test_file, limit_by_dir, limit_by_ext,
+        looks_like_fencecat_output, parse_size, path_to_anchor_id, rel_string,
+        render_filename_template, render_metadata_comment, render_tree, safe_join, shell_quote,
+        sniff, sort_files, stable_sort_within_dirs,
+    };
 
-    use super::rel_string;
+    fn file(rel: &str, size: u64) -> FileInfo {
+        FileInfo {
+            path: PathBuf::from(rel),
+            rel: rel.into(),
+            size,
+            lang: None,
+            mtime: None,
+            lines: None,
+            sha256: None,
+            media: None,
+            symlink: None,
+            mode: None,
+            executable: false,
+            content_id: None,
+            duplicate_of: None,
+            anchor: None,
+            data: None,
+        }
+    }
+
+    #[test]
+    fn filename_template_substitutes_known_placeholders() {
+        let vars = HashMap::from([("date", "2024-06-01"), ("dir", "src"), ("count", "2")]);
+        assert_eq!(
+            render_filename_template("{date}_{dir}_context.md", &vars).as_deref(),
+            Ok("2024-06-01_src_context.md")
+        );
+        assert_eq!(
+            render_filename_template("{{{count}}}.md", &vars).as_deref(),
+            Ok("{2}.md")
+        );
+        assert_eq!(
+            render_filename_template("{name}.md", &vars),
+            Err("unknown placeholder `{name}` in `{name}.md` (known: count, date, dir)".into())
+        );
+        assert!(render_filename_template("{dir.md", &vars).is_err());
+        assert!(render_filename_template("dir}.md", &vars).is_err());
+    }
+
+    #[test]
+    fn tree_nests_directories_in_name_order() {
+        let tree = render_tree([
+            "tests/cli.rs",
+            "src/main.rs",
+            "Cargo.toml",
+            "src/lang/rust.rs",
+        ]);
+        let expected = [
+            ".",
+            "├── Cargo.toml",
+            "├── src/",
+            "│   ├── lang/",
+            "│   │   └── rust.rs",
+            "│   └── main.rs",
+            "└── tests/",
+            "    └── cli.rs",
+        ];
+        assert_eq!(tree, expected.join("\n") + "\n");
+    }
+
+    #[test]
+    fn home_is_collapsed_and_expanded_on_component_boundaries() {
+        assert_eq!(collapse_home("/home/me/src/a.rs", "/home/me"), "~/src/a.rs");
+        assert_eq!(collapse_home("/home/me", "/home/me/"), "~");
+        assert_eq!(
+            collapse_home("/home/meg/a.rs", "/home/me"),
+            "/home/meg/a.rs"
+        );
+        assert_eq!(collapse_home("src/a.rs", "/home/me"), "src/a.rs");
+
+        let home = Path::new("/home/me");
+        assert_eq!(
+            expand_home(Path::new("~/src"), home),
+            Some(PathBuf::from("/home/me/src"))
+        );
+        assert_eq!(expand_home(Path::new("~"), home), Some(home.to_path_buf()));
+        assert_eq!(expand_home(Path::new("~bob/src"), home), None);
+        assert_eq!(expand_home(Path::new("src/~"), home), None);
+    }
+
+    #[test]
+    fn single_file_dirs_collapse_below_the_top_level() {
+        let mut files = vec![
+            file("src/main.rs", 1),
+            file("src/utils/math/helpers/vector.rs", 1),
+            file("src/net/a/b/x.rs", 1),
+            file("src/net/y.rs", 1),
+            file("src/io/z.rs", 1),
+            file("docs/guide/deep/nested/page.md", 1),
+        ];
+        collapse_paths(&mut files, 3);
+        let rels: Vec<_> = files.iter().map(|f| f.rel.as_str()).collect();
+        assert_eq!(
+            rels,
+            [
+                "src/main.rs",
+                "src/…/vector.rs",
+                "src/net/…/x.rs",
+                "src/net/y.rs",
+                "src/io/z.rs",
+                "docs/…/page.md",
+            ]
+        );
+        assert_eq!(
+            files[1].path,
+            PathBuf::from("src/utils/math/helpers/vector.rs")
+        );
+
+        // Shallow paths stay, and so do labels that would clash
+        let mut files = vec![
+            file("a/b/c.rs", 1),
+            file("x/one/two/m.rs", 1),
+            file("x/uno/dos/m.rs", 1),
+        ];
+        collapse_paths(&mut files, 3);
+        let rels: Vec<_> = files.iter().map(|f| f.rel.as_str()).collect();
+        assert_eq!(rels, ["a/b/c.rs", "x/one/two/m.rs", "x/uno/dos/m.rs"]);
+    }
+
+    #[test]
+    fn toc_links_files_to_their_anchors() {
+        let files = [
+            ("src/main.rs", "src-main-rs"),
+            ("app/README.md", "readme-md"),
+            ("app/[id]/page_1.tsx", "app--id--page-1-tsx"),
+        ];
+        assert_eq!(
+            emit_toc(files),
+            "- [src/main.rs](#src-main-rs)\n- [app/README.md](#readme-md)\n\
+             - [app/\\[id\\]/page\\_1.tsx](#app--id--page-1-tsx)\n\n"
+        );
+    }
+
+    #[test]
+    fn compound_extension_takes_up_to_two_components() {
+        let ext = |p: &str| compound_extension(Path::new(p));
+        assert_eq!(ext("backups/site.tar.gz").as_deref(), Some("tar.gz"));
+        assert_eq!(ext("types/index.d.ts").as_deref(), Some("d.ts"));
+        assert_eq!(ext("src/main.rs").as_deref(), Some("rs"));
+        assert_eq!(ext(".env.local").as_deref(), Some("local"));
+        assert_eq!(ext("a.b.c.d").as_deref(), Some("c.d"));
+        assert_eq!(ext(".bashrc"), None);
+        assert_eq!(ext("Makefile"), None);
+        assert_eq!(ext("trailing."), None);
+    }
+
+    #[test]
+    fn limits_keep_the_first_files_of_each_group() {
+        let files = vec![
+            file("src/a.rs", 1),
+            file("tests/x.RS", 2),
+            file("src/b.toml", 3),
+            file("Makefile", 4),
+            file("src/c.rs", 5),
+            file("README", 6),
+        ];
+        let kept = limit_by_ext(files.clone(), 1);
+        let order: Vec<&str> = kept.iter().map(|f| f.rel.as_str()).collect();
+        assert_eq!(order, ["src/a.rs", "src/b.toml", "Makefile"]);
+
+        let kept = limit_by_dir(files, 2);
+        let order: Vec<&str> = kept.iter().map(|f| f.rel.as_str()).collect();
+        assert_eq!(
+            order,
+            ["src/a.rs", "tests/x.RS", "src/b.toml", "Makefile", "README"]
+        );
+    }
+
+    #[test]
+    fn labels_sort_by_their_normalized_form() {
+        // Precomposed é (U+00E9) and e + combining acute (U+0301)
+        let nfc = "caf\u{e9}-1.txt";
+        let nfd = "cafe\u{301}-2.txt";
+        // Byte order would put the decomposed name first
+        assert!(nfd < nfc);
+        assert_eq!(compare_labels(nfc, nfd), Ordering::Less);
+        assert_eq!(compare_labels("cafez.txt", nfc), Ordering::Less);
+        assert_eq!(
+            compare_labels("caf\u{e9}", "cafe\u{301}"),
+            Ordering::Greater
+        );
+
+        for key in [SortKey::Name, SortKey::Size] {
+            let mut files = vec![file(nfc, 1), file("cafez.txt", 1), file(nfd, 1)];
+            sort_files(&mut files, key, None);
+            let order: Vec<&str> = files.iter().map(|f| f.rel.as_str()).collect();
+            assert_eq!(order, ["cafez.txt", nfc, nfd]);
+        }
+        assert_eq!(
+            render_tree([nfd, nfc, "cafez.txt"]),
+            format!(".\n├── cafez.txt\n├── {nfc}\n└── {nfd}\n")
+        );
+    }
+
+    #[test]
+    fn extension_groups_come_before_the_sort_key() {
+        let labels =
+            |files: &[FileInfo]| -> Vec<String> { files.iter().map(|f| f.rel.clone()).collect() };
+        let mut files = vec![
+            file("README.md", 40),
+            file("Cargo.toml", 10),
+            file("Makefile", 5),
+            file("src/lib.rs", 20),
+            file("build.rs", 30),
+            file("docs/guide.MD", 50),
+            file("dist/site.tar.gz", 60),
+            file("notes.gz", 1),
+            file("a.ts", 3),
+            file("b.test.ts", 2),
+            file("vite.config.ts", 4),
+        ];
+        sort_files(&mut files, SortKey::Name, Some(&[]));
+        assert_eq!(
+            labels(&files),
+            [
+                "dist/site.tar.gz",
+                "notes.gz",
+                "README.md",
+                "docs/guide.MD",
+                "build.rs",
+                "src/lib.rs",
+                "Cargo.toml",
+                "a.ts",
+                "b.test.ts",
+                "vite.config.ts",
+                "Makefile"
+            ]
+        );
+        let order = ["rs".to_string(), "tar.gz".to_string(), "toml".to_string()];
+        sort_files(&mut files, SortKey::Size, Some(&order));
+        assert_eq!(
+            labels(&files),
+            [
+                "build.rs",
+                "src/lib.rs",
+                "dist/site.tar.gz",
+                "Cargo.toml",
+                "notes.gz",
+                "docs/guide.MD",
+                "README.md",
+                "vite.config.ts",
+                "a.ts",
+                "b.test.ts",
+                "Makefile"
+            ]
+        );
+    }
+
+    #[test]
+    fn common_parent_of_named_files() {
+        let parent = |files: &[&str]| {
+            let paths: Vec<&Path> = files.iter().map(Path::new).collect();
+            common_parent(&paths)
+        };
+        assert_eq!(parent(&["src/lib.rs", "src/main.rs"]), Path::new("src"));
+        assert_eq!(parent(&["README.md", "src/lib.rs"]), Path::new("."));
+        assert_eq!(
+            parent(&["a/b/c.rs", "a/b/d/e.rs", "a/f.rs"]),
+            Path::new("a")
+        );
+        assert_eq!(parent(&["/x/y.rs", "/x/z.rs"]), Path::new("/x"));
+        assert_eq!(parent(&["src/lib.rs", "/src/lib.rs"]), Path::new("."));
+    }
+
+    #[test]
+    fn recognizes_test_files_by_convention() {
+        for rel in [
+            "tests/cli.rs",
+            "crates/core/tests/fixtures/a.json",
+            "src/__tests__/App.js",
+            "spec/models/user_spec.rb",
+            "src/parser_test.rs",
+            "web/src/api.test.ts",
+            "web/src/Button.spec.tsx",
+            "pkg/test_utils.py",
+            "conftest.py",
+            "server/handler_test.go",
+            "src/main/java/FooTest.java",
+            "./tests/a.rs",
+        ] {
+            assert!(is_test_file(rel), "{rel}");
+        }
+        for rel in [
+            "src/lib.rs",
+            "src/testing.rs",
+            "src/contest.py",
+            "docs/spec.md",
+            "src/latest/main.rs",
+            "web/src/api.ts",
+            "../src/lib.rs",
+        ] {
+            assert!(!is_test_file(rel), "{rel}");
+        }
+    }
+
+    #[test]
+    fn sort_within_dirs_keeps_directory_order() {
+        let mut files = vec![
+            file("tests/cli.rs", 900),
+            file("src/b.rs", 10),
+            file("src/a/deep.rs", 50),
+            file("Cargo.toml", 5),
+            file("src/c.rs", 300),
+            file("tests/x.rs", 1),
+        ];
+        stable_sort_within_dirs(&mut files, SortKey::Size, None);
+        let order: Vec<&str> = files.iter().map(|f| f.rel.as_str()).collect();
+        assert_eq!(
+            order,
+            [
+                "Cargo.toml",
+                "src/c.rs",
+                "src/a/deep.rs",
+                "src/b.rs",
+                "tests/cli.rs",
+                "tests/x.rs"
+            ]
+        );
+
+        stable_sort_within_dirs(&mut files, SortKey::Name, None);
+        let order: Vec<&str> = files.iter().map(|f| f.rel.as_str()).collect();
+        assert_eq!(
+            order,
+            [
+                "Cargo.toml",
+                "src/a/deep.rs",
+                "src/b.rs",
+                "src/c.rs",
+                "tests/cli.rs",
+                "tests/x.rs"
+            ]
+        );
+    }
+
+    #[test]
+    fn safe_join_stays_inside_root() {
+        let root = Path::new("out");
+        assert_eq!(
+            safe_join(root, "src/./main.rs"),
+            Some(PathBuf::from("out/src/main.rs"))
+        );
+        assert_eq!(safe_join(root, "../etc/passwd"), None);
+        assert_eq!(safe_join(root, "src/../../x"), None);
+        assert_eq!(safe_join(root, "/etc/passwd"), None);
+        assert_eq!(safe_join(root, "C:\\Windows\\x"), None);
+        assert_eq!(safe_join(root, "."), None);
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn shell_quote_posix() {
+        assert_eq!(shell_quote("src/main.rs"), "src/main.rs");
+        assert_eq!(shell_quote("src/my file.rs"), "'src/my file.rs'");
+        assert_eq!(shell_quote("a$HOME`id`.sh"), "'a$HOME`id`.sh'");
+        assert_eq!(shell_quote("it's.txt"), "'it'\\''s.txt'");
+        assert_eq!(shell_quote(""), "''");
+    }
 
     #[test]
     fn rel_from_dot_strips_prefix_and_normalizes() {
@@ -37,4 +1303,178 @@ mod tests {
         path.push("mod.rs");
         assert_eq!(rel_string(&root, &path), "src/mod.rs");
     }
+
+    #[test]
+    fn shebang_file_with_nul_is_still_binary() {
+        let s = sniff(b"#!/bin/sh\n\0\x01\x02");
+        assert!(s.binary);
+        assert!(s.shebang.is_some());
+    }
+
+    #[test]
+    fn utc_timestamp_formats_iso8601() {
+        let t = UNIX_EPOCH + Duration::from_secs(1_717_236_000);
+        assert_eq!(format_utc_timestamp(t), "2024-06-01T10:00:00Z");
+        assert_eq!(format_utc_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn metadata_comment_lists_known_stats() {
+        let info = FileInfo {
+            path: PathBuf::from("src/main.rs"),
+            rel: "src/main.rs".into(),
+            size: 4321,
+            lang: Some("rust".into()),
+            mtime: Some(UNIX_EPOCH + Duration::from_secs(1_717_236_000)),
+            lines: Some(150),
+            sha256: Some("abcd1234".into()),
+            media: None,
+            symlink: None,
+            mode: Some(0o755),
+            executable: true,
+            content_id: None,
+            duplicate_of: None,
+            anchor: None,
+            data: None,
+        };
+        assert_eq!(
+            render_metadata_comment(&info),
+            "<!-- fencecat: path=src/main.rs size=4321 lines=150 mtime=2024-06-01T10:00:00Z sha256=abcd1234 mode=0755 -->"
+        );
+    }
+
+    const README_BACKTICKS: &str = "# Demo\n\n```rust\nfn main() {}\n```\n";
+    const README_BOTH: &str = "# Demo\n\n```sh\nls\n```\n\n~~~\nraw\n~~~\n";
+
+    #[test]
+    fn markdown_prefers_tildes_over_escalated_backticks() {
+        assert_eq!(
+            choose_fence(README_BACKTICKS, '~'),
+            Fence { ch: '~', len: 3 }
+        );
+        assert_eq!(choose_fence(README_BACKTICKS, '~').to_string(), "~~~");
+        assert_eq!(
+            choose_fence(README_BACKTICKS, '`'),
+            Fence { ch: '`', len: 4 }
+        );
+    }
+
+    #[test]
+    fn markdown_with_both_fence_kinds_falls_back_to_backticks() {
+        assert_eq!(choose_fence(README_BOTH, '~'), Fence { ch: '`', len: 4 });
+        assert_eq!(choose_fence("plain\n", '~'), Fence { ch: '`', len: 3 });
+    }
+
+    #[test]
+    fn anchor_ids_are_lowercase_and_dashed() {
+        assert_eq!(path_to_anchor_id("src/main.rs"), "src-main-rs");
+        assert_eq!(path_to_anchor_id("Docs/My File.MD"), "docs-my-file-md");
+    }
+
+    #[test]
+    fn repeated_anchor_ids_are_numbered() {
+        let ids = anchor_ids(["a-b.rs", "a.b.rs", "a-b-rs-1", "a_b.rs", "c.rs"]);
+        assert_eq!(
+            ids,
+            ["a-b-rs", "a-b-rs-1", "a-b-rs-1-1", "a-b-rs-2", "c-rs"]
+        );
+    }
+
+    #[test]
+    fn detects_fencecat_dumps_not_plain_docs() {
+        let part = "<!-- fencecat: part 2/3 -->\n\n```rust src/a.rs\nfn a() {}\n```\n";
+        assert!(looks_like_fencecat_output(part));
+        let meta = "<!-- fencecat: path=b.toml size=6 -->\n```b.toml\nx = 1\n```\n";
+        assert!(looks_like_fencecat_output(meta));
+        let doc = "# Guide\n\n```rust src/a.rs\nfn a() {}\n```\n\n```sh scripts/run.sh\nls\n```\n\n\
+                   ```toml Cargo.toml\nx = 1\n```\n\nParts open with `<!-- fencecat: part 1/2 -->`.\n";
+        assert!(!looks_like_fencecat_output(doc));
+
+        let dump = "- src/a.rs\n- b.toml\n\n<a id=\"src-a-rs\"></a>\n```rust src/a.rs\nfn a() {}\n\n```\n\n\
+                    ````toml b.toml\nx = 1\n```\n\n````\n\n```markdown README.md\n# hi";
+        assert!(looks_like_fencecat_output(dump));
+        assert!(looks_like_fencecat_output(&dump.replace('\n', "\r\n")));
+        let prose = dump.replace("\n\n```markdown", "\nSee also:\n```markdown");
+        assert!(!looks_like_fencecat_output(&prose));
+        let unpadded = "```rust src/a.rs\nfn a() {}\n```\n\n```toml b.toml\nx = 1\n\n```\n";
+        assert!(!looks_like_fencecat_output(unpadded));
+    }
+
+    #[test]
+    fn expands_globs_below_their_literal_prefix() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        for path in [
+            "src/a.rs",
+            "src/b.py",
+            "src/net/c.rs",
+            "d.rs",
+            ".git/e.rs",
+            "src/.f.rs",
+        ] {
+            let path = tmp.path().join(path);
+            std::fs::create_dir_all(path.parent().expect("parent")).expect("mkdir");
+            std::fs::write(path, "").expect("write");
+        }
+        let root = tmp.path().to_str().expect("utf-8");
+        let walk = |hidden: bool| {
+            move |base: &Path| {
+                let mut wb = ignore::WalkBuilder::new(base);
+                wb.standard_filters(false).hidden(!hidden);
+                wb
+            }
+        };
+        let rel = |(base, matches): (PathBuf, Vec<PathBuf>)| {
+            let names: Vec<String> = matches
+                .iter()
+                .map(|p| {
+                    p.strip_prefix(tmp.path())
+                        .expect("inside")
+                        .to_string_lossy()
+                        .replace('\\', "/")
+                })
+                .collect();
+            (
+                base.strip_prefix(tmp.path()).map(Path::to_path_buf).ok(),
+                names,
+            )
+        };
+        assert_eq!(
+            rel(expand_glob(&format!("{root}/src/*.rs"), walk(false)).expect("glob")),
+            (Some(PathBuf::from("src")), vec!["src/a.rs".to_string()])
+        );
+        assert_eq!(
+            rel(expand_glob(&format!("{root}/**/*.rs"), walk(false)).expect("glob")).1,
+            ["d.rs", "src/a.rs", "src/net/c.rs"]
+        );
+        assert_eq!(
+            rel(expand_glob(&format!("{root}/**/*.rs"), walk(true)).expect("glob")).1,
+            [".git/e.rs", "d.rs", "src/.f.rs", "src/a.rs", "src/net/c.rs"]
+        );
+        assert_eq!(
+            rel(expand_glob(&format!("{root}/src/.*.rs"), walk(false)).expect("glob")).1,
+            ["src/.f.rs"]
+        );
+        assert_eq!(
+            rel(expand_glob(&format!("{root}/src/*.{{rs,py}}"), walk(false)).expect("glob")).1,
+            ["src/a.rs", "src/b.py"]
+        );
+        assert!(
+            expand_glob(&format!("{root}/*.txt"), walk(false))
+                .expect("glob")
+                .1
+                .is_empty()
+        );
+        assert!(expand_glob("src/[a.rs", walk(false)).is_err());
+        assert!(is_glob_pattern("src\\*.rs") && !is_glob_pattern("src/main.rs"));
+    }
+
+    #[test]
+    fn parse_size_units() {
+        assert_eq!(parse_size("4096"), Ok(4096));
+        assert_eq!(parse_size("100k"), Ok(102_400));
+        assert_eq!(parse_size("1.5MB"), Ok(1_572_864));
+        assert_eq!(parse_size("2 MiB"), Ok(2_097_152));
+        assert!(parse_size("10x").is_err());
+        assert!(parse_size("").is_err());
+    }
 }
@@ -1,20 +1,76 @@
 use std::{
+    borrow::Cow,
     cmp::Reverse,
-    collections::HashSet,
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::OsString,
     fs::{self, File},
-    io::{self, Read as _},
-    path::{Path, PathBuf},
+    io::{self, IsTerminal as _, Read as _},
+    path::{Component, Path, PathBuf},
+    process::{Command, ExitCode, Stdio},
+    time::{Duration, SystemTime},
 };
 
-use clap::{ArgAction, Parser};
-use ignore::WalkBuilder;
-use regex::RegexSet;
+use clap::{
+    ArgAction, CommandFactory as _, FromArgMatches as _, Parser, ValueEnum, parser::ValueSource,
+};
+use dialoguer::{Confirm, MultiSelect};
+use fencecat::{
+    FileInfo, Sniff, SortKey, anchor_ids,
+    audit::{self, FileAudit, Finding, Severity, find_secrets, render_audit},
+    choose_fence,
+    clipboard::{ClipboardSink, CopyMime, write_atomic},
+    collapse_home, collapse_paths, compare_labels, compound_extension,
+    config::{LLM_PRESETS, LlmPreset},
+    emit_toc, escape_link_text, expand_home,
+    lang::{
+        KNOWN_BINARY_EXTENSIONS, LangMap, TransformError, known_filename, language_display_name,
+        media_type_for_ext, parse_lang_map_entry,
+        python::extract_python_stub,
+        rust::{
+            EDITIONS, detect_rust_edition, estimate_complexity, strip_test_functions,
+            strip_test_items,
+        },
+        typescript::{strip_typescript_types, strip_typescript_types_batch},
+    },
+    limit_by_dir, limit_by_ext,
+    output::{
+        diff::{colorize_diff_fences, unified_diff},
+        json::{self, JsonFile},
+    },
+    parse::{EXEC_MARKER, merge_continuations, parse_dump},
+    parse_size, prompt_confirm,
+    redact::{MIN_SECRET_LEN, compile_redactions, load_env_redaction_patterns, redact},
+    render_filename_template, render_metadata_comment, render_tree, safe_join,
+    script::TransformScript,
+    shell_quote, sort_files, stable_sort_within_dirs,
+    summary::generate_repo_summary,
+    timings::{self, Phase, Timings, span},
+    tokens::{BudgetPolicy, Tokenizer, estimate_tokens, plan_budget, split_by_tokens},
+    transform::{
+        EofNewline, OutputNewline, RepetitionDetector, expand_tabs, fence_body, soft_wrap,
+        strip_ansi, strip_leading_imports, substitute_env_vars,
+    },
+    units::{fmt_count, fmt_size},
+};
+use ignore::{
+    WalkBuilder,
+    overrides::{Override, OverrideBuilder},
+};
+use rayon::iter::{
+    IndexedParallelIterator as _, IntoParallelRefMutIterator as _, ParallelIterator as _,
+};
+use regex::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+use serde::Serialize;
+use sha2::{Digest as _, Sha256};
+
+mod input;
 
 #[allow(clippy::struct_excessive_bools)]
 #[derive(Parser, Debug)]
 #[command(
     name = "fencecat",
     version,
+    args_override_self = true,
     about = "Recursively emit Markdown code fences labeled with relative file paths.
 Useful for sharing source trees in LLM chats or other issue trackers."
 )]
@@ -23,16 +79,158 @@ struct Cli {
     #[arg(value_name = "PATH", default_value = ".")]
     dir: PathBuf,
 
+    /// More files or directories to emit after PATH, in the order given
+    #[arg(value_name = "PATH", conflicts_with = "from_archive")]
+    more_files: Vec<PathBuf>,
+
+    /// Read the files from a .zip, .tar, .tar.gz or .tgz archive instead of
+    /// PATH, without extracting it. Filters apply to the member paths
+    #[arg(long = "from-archive", value_name = "FILE")]
+    from_archive: Option<PathBuf>,
+
+    /// Scan PATH inside a temp directory built from --fake-root-manifest
+    /// instead of the real filesystem (`.` for the fixture's top)
+    #[cfg(feature = "testing")]
+    #[arg(
+        long = "fake-root",
+        value_name = "PATH",
+        requires = "fake_root_manifest",
+        conflicts_with = "from_archive"
+    )]
+    fake_root: Option<PathBuf>,
+
+    /// The files for --fake-root: a JSON array of {"path", "content"}
+    /// objects, inline when it starts with `[`, otherwise a file to read
+    #[cfg(feature = "testing")]
+    #[arg(
+        long = "fake-root-manifest",
+        value_name = "JSON",
+        requires = "fake_root"
+    )]
+    fake_root_manifest: Option<String>,
+
     /// Copy the full output to the clipboard
     #[arg(short = 'c', long = "copy", action = ArgAction::SetTrue)]
     copy: bool,
 
+    /// Refuse --copy when the output exceeds SIZE. Many clipboard managers cap
+    /// entries at 1-4 MB and silently truncate or drop larger ones
+    #[arg(
+        long = "clipboard-size-limit",
+        value_name = "SIZE",
+        value_parser = parse_size,
+        default_value = "4MB"
+    )]
+    clipboard_size_limit: u64,
+
+    /// Ask on the terminal before a --copy larger than
+    /// --clipboard-confirm-threshold replaces the clipboard
+    #[arg(long = "clipboard-confirm", action = ArgAction::SetTrue)]
+    clipboard_confirm: bool,
+
+    /// Output size above which --clipboard-confirm asks
+    #[arg(
+        long = "clipboard-confirm-threshold",
+        value_name = "SIZE",
+        value_parser = parse_size,
+        default_value = "10KB"
+    )]
+    clipboard_confirm_threshold: u64,
+
+    /// Ask the --clipboard-confirm question even when stdin is not a terminal
+    /// (the answer is read from the terminal)
+    #[arg(long = "force-interactive", action = ArgAction::SetTrue)]
+    force_interactive: bool,
+
+    /// Also save what --copy sends to the clipboard to FILE (written
+    /// atomically). Without --copy only the file is written
+    #[arg(long = "save-clip", value_name = "FILE")]
+    save_clip: Option<PathBuf>,
+
+    /// Copy even when the output exceeds --clipboard-size-limit
+    #[arg(long = "force-clipboard", action = ArgAction::SetTrue)]
+    force_clipboard: bool,
+
     /// Order files by size (largest first)
     #[arg(short = 'B', long = "biggest-first", action = ArgAction::SetTrue)]
     biggest_first: bool,
 
+    /// Keep top-level directories in alphabetical order and sort only within
+    /// each one (by size with -B)
+    #[arg(long = "sort-within-dir", action = ArgAction::SetTrue)]
+    sort_within_dir: bool,
+
+    /// Group files by language under `## Rust`-style headings (unknown
+    /// languages last, under `## Other`)
+    #[arg(long = "group-by-lang", action = ArgAction::SetTrue, conflicts_with = "sort_within_dir")]
+    group_by_lang: bool,
+
+    /// Group files by extension (alphabetically, files without one last)
+    /// before the main sort, which orders each group
+    #[arg(long = "sort-extensions-first", action = ArgAction::SetTrue)]
+    sort_extensions_first: bool,
+
+    /// Extension groups to put first, in this order (implies
+    /// --sort-extensions-first); the others follow alphabetically
+    #[arg(
+        long = "extension-order",
+        value_name = "EXT[,EXT...]",
+        value_delimiter = ','
+    )]
+    extension_order: Option<Vec<String>>,
+
+    /// Order Rust files by estimated cyclomatic complexity (most complex
+    /// first), then the other files by path
+    #[arg(
+        long = "sort-by-complexity",
+        action = ArgAction::SetTrue,
+        conflicts_with_all = [
+            "biggest_first",
+            "sort_within_dir",
+            "group_by_lang",
+            "sort_extensions_first",
+            "extension_order",
+        ]
+    )]
+    sort_by_complexity: bool,
+
+    /// With --sort-by-complexity, leave out Rust files with fewer than N
+    /// branches (`if`, `for`, `while`, match arms, `&&`, `||`)
+    #[arg(
+        long = "complexity-threshold",
+        value_name = "N",
+        requires = "sort_by_complexity"
+    )]
+    complexity_threshold: Option<usize>,
+
+    /// Keep at most N files per extension, the first ones in output order
+    /// (the largest with -B)
+    #[arg(long = "limit-ext", value_name = "N")]
+    limit_ext: Option<usize>,
+
+    /// Keep at most N files per top-level directory, the first ones in output
+    /// order. Stacks with --limit-ext
+    #[arg(long = "limit-dir", value_name = "N")]
+    limit_dir: Option<usize>,
+
+    /// Shorten labels through directories that hold a single file:
+    /// src/utils/math/helpers/vector.rs becomes src/…/vector.rs
+    #[arg(long = "collapse-single-file-dirs", action = ArgAction::SetTrue)]
+    collapse_single_file_dirs: bool,
+
+    /// Only shorten labels with at least N directories (the top-level one is
+    /// always kept)
+    #[arg(
+        long = "collapse-min-depth",
+        value_name = "N",
+        default_value_t = 3,
+        requires = "collapse_single_file_dirs"
+    )]
+    collapse_min_depth: usize,
+
     /// Only include files whose extension matches any of the given ones (comma-separated).
     /// Examples: --ext rs,ts,py   or   --ext .md,.toml
+    /// Entries prefixed with `!` exclude instead, like --not-ext: --ext 'rs,toml,!lock'
     #[arg(
         short,
         long = "ext",
@@ -46,6 +244,10 @@ struct Cli {
     #[arg(long = "not-ext", value_name = "EXT[,EXT...]", value_delimiter = ',')]
     not_ext: Option<Vec<String>>,
 
+    /// Match --ext/--not-ext case-sensitively (`Script.PY` no longer matches `py`)
+    #[arg(long = "ext-case-sensitive", action = ArgAction::SetTrue)]
+    ext_case_sensitive: bool,
+
     /// Only include paths matching these regexes (relative to CWD).
     /// Can be specified multiple times.
     #[arg(short, long = "regex", action = ArgAction::Append)]
@@ -56,296 +258,5314 @@ struct Cli {
     #[arg(long = "not-regex", action = ArgAction::Append)]
     not_regex: Option<Vec<String>>,
 
+    /// Drop test files by common conventions: tests/, test/, __tests__/,
+    /// spec/, *_test.rs, *.test.ts, test_*.py, *_test.go, ...
+    #[arg(
+        long = "exclude-tests",
+        visible_alias = "exclude-test-files",
+        action = ArgAction::SetTrue,
+        conflicts_with = "only_tests"
+    )]
+    exclude_tests: bool,
+
+    /// Keep only the files --exclude-tests would drop
+    #[arg(long = "only-tests", action = ArgAction::SetTrue)]
+    only_tests: bool,
+
+    /// Never descend into directories whose path (relative to CWD, with a
+    /// trailing /) matches these regexes, e.g. `(^|/)target/$`. Can be
+    /// specified multiple times.
+    #[arg(long = "prune-regex", value_name = "PATTERN", action = ArgAction::Append)]
+    prune_regex: Option<Vec<String>>,
+
+    /// Match --regex/--not-regex/--prune-regex case-insensitively (same as
+    /// prefixing each with (?i))
+    #[arg(long = "ignore-case-regex", action = ArgAction::SetTrue)]
+    ignore_case_regex: bool,
+
+    /// Only include paths matching these globs (relative to the scan root);
+    /// prefix with ! to exclude instead. Can be specified multiple times.
+    #[arg(short = 'g', long = "glob", value_name = "GLOB", action = ArgAction::Append)]
+    glob: Vec<String>,
+
+    /// Read --regex patterns from FILE, one per line (# comments and blank
+    /// lines ignored; - reads stdin). Can be specified multiple times.
+    #[arg(long = "regex-file", value_name = "FILE", action = ArgAction::Append)]
+    regex_file: Vec<PathBuf>,
+
+    /// Read --not-regex patterns from FILE (same format as --regex-file)
+    #[arg(long = "not-regex-file", value_name = "FILE", action = ArgAction::Append)]
+    not_regex_file: Vec<PathBuf>,
+
+    /// Read --ext extensions from FILE (same format as --regex-file)
+    #[arg(long = "ext-file", value_name = "FILE", action = ArgAction::Append)]
+    ext_file: Vec<PathBuf>,
+
+    /// Read --glob patterns from FILE (same format as --regex-file)
+    #[arg(long = "glob-file", value_name = "FILE", action = ArgAction::Append)]
+    glob_file: Vec<PathBuf>,
+
     /// Include hidden and gitignored files (disable ignore rules)
     #[arg(short = 'H', long = "no-ignore")]
     no_ignore: bool,
 
+    /// Apply the ignore rules to a file named as PATH too, skipping it when
+    /// it is ignored (by default naming a file includes it)
+    #[arg(
+        long = "honor-ignore-for-explicit",
+        action = ArgAction::SetTrue,
+        conflicts_with = "no_ignore"
+    )]
+    honor_ignore_for_explicit: bool,
+
+    /// Skip files with well-known binary extensions (png, exe, zip, ...)
+    /// without opening them. On by default unless -H is given
+    #[arg(
+        long = "ignore-binary-extensions",
+        action = ArgAction::SetTrue,
+        overrides_with = "no_ignore_binary_extensions"
+    )]
+    ignore_binary_extensions: bool,
+
+    /// Open files with binary extensions to check their content like any other
+    #[arg(
+        long = "no-ignore-binary-extensions",
+        action = ArgAction::SetTrue,
+        overrides_with = "ignore_binary_extensions"
+    )]
+    no_ignore_binary_extensions: bool,
+
+    /// Include files with these extensions even when they look binary (NUL
+    /// bytes, a binary extension): --passthrough-binary svg,ipynb
+    #[arg(
+        long = "passthrough-binary",
+        value_name = "EXT[,EXT...]",
+        value_delimiter = ','
+    )]
+    passthrough_binary: Vec<String>,
+
     /// Prepend a plain file listing (like `dir`) before the fences (no timestamps/metadata)
     #[arg(short = 'D', long = "dir-list", action = ArgAction::SetTrue)]
     dir_list: bool,
-}
 
-impl Cli {
-    pub fn build_walkdir(&self) -> WalkBuilder {
-        let mut wb = WalkBuilder::new(&self.dir);
-        if self.no_ignore {
-            wb.hidden(false)
-                .ignore(false)
-                .git_ignore(false)
-                .git_global(false)
-                .git_exclude(false)
-                .parents(false);
-        }
-        wb
-    }
-}
+    /// Prepend a `tree`-style outline of the emitted files, then the fences
+    #[arg(long = "with-tree", visible_alias = "prepend-tree", action = ArgAction::SetTrue)]
+    with_tree: bool,
 
-/// Heuristic: consider a file "binary" if the first few KB contain a NUL byte.
-fn is_binary(path: &Path) -> io::Result<bool> {
-    let mut f = File::open(path)?;
-    let mut buf = [0u8; 8192];
-    let n = f.read(&mut buf)?;
-    Ok(buf[..n].contains(&0))
-}
+    /// Also list the visited files the filters left out, tagged with the
+    /// reason. Gitignored, hidden and `!`-globbed paths are never visited and
+    /// do not appear
+    #[arg(long = "dir-list-excluded", action = ArgAction::SetTrue)]
+    dir_list_excluded: bool,
 
-fn choose_fence(content: &str) -> String {
-    for n in 3..=10 {
-        let fence = "`".repeat(n);
-        if !content.contains(&fence) {
-            return fence;
-        }
-    }
-    "````````````".to_string()
-}
+    /// Show at most N entries in the --dir-list-excluded listing
+    #[arg(
+        long = "dir-list-excluded-limit",
+        value_name = "N",
+        default_value_t = 100
+    )]
+    dir_list_excluded_limit: usize,
 
-#[derive(Debug)]
-struct FileInfo {
-    path: PathBuf,
-    rel: String,
-    size: u64,
-}
+    /// In the dir list, also show directories whose files were all filtered out
+    #[arg(long = "emit-empty-dir-markers", action = ArgAction::SetTrue)]
+    emit_empty_dir_markers: bool,
 
-/// Helper to normalize extension lists (strip dots, lowercase)
-fn normalize_ext_list(list: &[String]) -> HashSet<String> {
-    list.iter()
-        .map(|s| s.trim().trim_start_matches('.').to_ascii_lowercase())
-        .filter(|s| !s.is_empty())
-        .collect()
-}
+    /// Let --ext/--not-ext match extensionless scripts by their shebang (e.g. --ext sh)
+    #[arg(long = "ext-by-shebang", action = ArgAction::SetTrue)]
+    ext_by_shebang: bool,
 
-fn build_ext_filters(cli: &Cli) -> (Option<HashSet<String>>, Option<HashSet<String>>) {
-    let allow = cli.ext.as_ref().map(|v| normalize_ext_list(v));
-    let deny = cli.not_ext.as_ref().map(|v| normalize_ext_list(v));
-    (allow, deny)
-}
+    /// Replace the import block at the top of each file with a short marker
+    /// (rust, python, js/ts, go, c/cpp)
+    #[arg(long = "strip-leading-imports", action = ArgAction::SetTrue)]
+    strip_leading_imports: bool,
 
-fn compile_regex_sets(cli: &Cli) -> (Option<RegexSet>, Option<RegexSet>) {
-    let allow = cli
-        .regex
-        .as_ref()
-        .map(|v| RegexSet::new(v).expect("Invalid regex in --regex"));
-    let deny = cli
-        .not_regex
-        .as_ref()
-        .map(|v| RegexSet::new(v).expect("Invalid regex in --not-regex"));
-    (allow, deny)
-}
+    /// Remove ANSI escape sequences (colors, cursor movement) from emitted
+    /// content, e.g. captured terminal logs
+    #[arg(long = "strip-ansi", action = ArgAction::SetTrue)]
+    strip_ansi: bool,
 
-fn make_fileinfo_if_included(
-    path: &Path,
-    root_for_rel: &Path,
-    ext_allow: Option<&HashSet<String>>,
-    ext_deny: Option<&HashSet<String>>,
-    re_allow: Option<&RegexSet>,
-    re_deny: Option<&RegexSet>,
-) -> Option<FileInfo> {
-    // 1. Check Extensions
-    if ext_allow.is_some() || ext_deny.is_some() {
-        let ext = path
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(str::to_ascii_lowercase);
+    /// Remove `#[test]` functions, `#[cfg(test)]` items and `mod tests` from
+    /// Rust files (reformats files that change; unparsable files are kept)
+    #[arg(long = "strip-test-annotations", action = ArgAction::SetTrue)]
+    strip_test_annotations: bool,
 
-        // If whitelist exists, file must match it
-        if let Some(allow) = ext_allow
-            && !ext.as_ref().is_some_and(|e| allow.contains(e))
-        {
-            return None;
-        }
-        // If blacklist exists, file must NOT match it
-        if let Some(deny) = ext_deny
-            && ext.as_ref().is_some_and(|e| deny.contains(e))
-        {
-            return None;
-        }
-    }
+    /// With --strip-test-annotations, remove only `#[test]` functions and
+    /// keep test modules
+    #[arg(
+        long = "keep-test-modules",
+        action = ArgAction::SetTrue,
+        requires = "strip_test_annotations"
+    )]
+    keep_test_modules: bool,
 
-    // 2. Check Regex (against path relative to CWD)
-    if re_allow.is_some() || re_deny.is_some() {
-        // Calculate path relative to CWD (".").
-        // If we are in `src/`, and path is `src/main.rs`, this returns `main.rs`.
-        // If we are in `.`, and path is `src/main.rs`, this returns `src/main.rs`.
-        let cwd_rel = fencecat::rel_string(Path::new("."), path);
-
-        if let Some(allow) = re_allow
-            && !allow.is_match(&cwd_rel)
-        {
-            return None;
-        }
-        if let Some(deny) = re_deny
-            && deny.is_match(&cwd_rel)
-        {
-            return None;
-        }
-    }
+    /// Emit .ts/.tsx files as JavaScript with the types removed, compiled by
+    /// SWC or else the project's `npx --no tsc`; failures are kept verbatim
+    #[arg(long = "typescript-strip-types", action = ArgAction::SetTrue)]
+    typescript_strip_types: bool,
 
-    let md = match path.metadata() {
-        Ok(m) => m,
-        Err(e) => {
-            eprintln!("skip {}: metadata error: {e}", path.display());
-            return None;
-        }
-    };
-    if md.len() == 0 {
-        return None;
-    }
+    /// Emit Python files as `.pyi`-style stubs (signatures, classes,
+    /// docstrings) built by `python3`'s ast module; kept verbatim without Python
+    #[arg(long = "python-stub", action = ArgAction::SetTrue)]
+    python_stub: bool,
 
-    match is_binary(path) {
-        Ok(true) => return None,
-        Ok(false) => {}
-        Err(e) => {
-            eprintln!("skip {}: read error: {e}", path.display());
-            return None;
-        }
-    }
+    /// With --python-stub, keep `_private` members too
+    #[arg(
+        long = "python-stub-include-private",
+        action = ArgAction::SetTrue,
+        requires = "python_stub"
+    )]
+    python_stub_include_private: bool,
 
-    let rel = fencecat::rel_string(root_for_rel, path);
+    /// ECMAScript version --typescript-strip-types compiles down to
+    #[arg(
+        long = "ts-target",
+        value_name = "ES_VERSION",
+        default_value = "es2022",
+        requires = "typescript_strip_types"
+    )]
+    ts_target: String,
 
-    Some(FileInfo {
-        path: path.to_path_buf(),
-        rel,
-        size: md.len(),
-    })
-}
+    /// Emit an HTML comment with path, size, lines, mtime and sha256 above each fence
+    #[arg(long = "fence-metadata-header", action = ArgAction::SetTrue)]
+    fence_metadata_header: bool,
 
-fn collect_from_dir(
-    cli: &Cli,
-    ext_allow: Option<&HashSet<String>>,
-    ext_deny: Option<&HashSet<String>>,
-    re_allow: Option<&RegexSet>,
-    re_deny: Option<&RegexSet>,
-) -> Vec<FileInfo> {
-    let walker = cli.build_walkdir().build();
-    let mut files: Vec<FileInfo> = Vec::new();
-
-    for dent in walker {
-        let entry = match dent {
-            Ok(e) => e,
-            Err(err) => {
-                eprintln!("walk error: {err}");
-                continue;
-            }
-        };
-        if entry.file_type().is_some_and(|ft| ft.is_file()) {
-            let path = entry.path();
-            if let Some(info) =
-                make_fileinfo_if_included(path, &cli.dir, ext_allow, ext_deny, re_allow, re_deny)
-            {
-                files.push(info);
-            }
-        }
-    }
+    /// Emit files whose content repeats an earlier file's as a note naming
+    /// that file; the metadata comments of all copies share a content id
+    #[arg(long = "content-hash-header", action = ArgAction::SetTrue)]
+    content_hash_header: bool,
 
-    if cli.biggest_first {
-        files.sort_by(|a, b| {
-            Reverse(a.size)
-                .cmp(&Reverse(b.size))
-                .then_with(|| a.rel.cmp(&b.rel))
-        });
-    } else {
-        files.sort_by(|a, b| a.rel.cmp(&b.rel));
-    }
-    files
-}
+    /// Emit a placeholder note (path, size, type) for image/audio/video files
+    /// instead of dropping them
+    #[arg(long = "note-images", action = ArgAction::SetTrue)]
+    note_images: bool,
 
-fn collect_from_single(
-    cli: &Cli,
-    ext_allow: Option<&HashSet<String>>,
-    ext_deny: Option<&HashSet<String>>,
-    re_allow: Option<&RegexSet>,
-    re_deny: Option<&RegexSet>,
-) -> Vec<FileInfo> {
-    let path = &cli.dir;
-    let parent = path.parent().unwrap_or_else(|| Path::new("."));
-    make_fileinfo_if_included(path, parent, ext_allow, ext_deny, re_allow, re_deny)
-        .into_iter()
-        .collect()
-}
+    /// Override the fence language for an extension, e.g. --lang-map vy=python.
+    /// An empty LANG drops the tag. Can be specified multiple times.
+    #[arg(long = "lang-map", value_name = "EXT=LANG", value_parser = parse_lang_map_entry, action = ArgAction::Append)]
+    lang_map: Vec<(String, String)>,
 
-fn collect_any(cli: &Cli) -> Vec<FileInfo> {
-    let (ext_allow, ext_deny) = build_ext_filters(cli);
-    let (re_allow, re_deny) = compile_regex_sets(cli);
+    /// Replace file headers (first --repeat-header-lines lines) shared by more
+    /// than --repeat-threshold files with a pointer to the first occurrence
+    #[arg(long = "compress-repetitive", action = ArgAction::SetTrue)]
+    compress_repetitive: bool,
 
-    if !cli.dir.exists() {
-        eprintln!("No such file or directory: {}", cli.dir.display());
-        std::process::exit(1);
-    }
+    /// Header length compared by --compress-repetitive
+    #[arg(
+        long = "repeat-header-lines",
+        value_name = "N",
+        default_value_t = 5,
+        requires = "compress_repetitive"
+    )]
+    repeat_header_lines: usize,
 
-    if cli.dir.is_file() {
-        collect_from_single(
-            cli,
-            ext_allow.as_ref(),
-            ext_deny.as_ref(),
-            re_allow.as_ref(),
-            re_deny.as_ref(),
-        )
-    } else if cli.dir.is_dir() {
-        collect_from_dir(
-            cli,
-            ext_allow.as_ref(),
-            ext_deny.as_ref(),
-            re_allow.as_ref(),
-            re_deny.as_ref(),
-        )
-    } else {
-        eprintln!("Not a regular file or directory: {}", cli.dir.display());
-        std::process::exit(1);
-    }
-}
+    /// Collapse a header once more than K files share it
+    #[arg(
+        long = "repeat-threshold",
+        value_name = "K",
+        default_value_t = 3,
+        requires = "compress_repetitive"
+    )]
+    repeat_threshold: usize,
 
-fn emit_dir_listing(files: &[FileInfo]) -> String {
-    let mut s = String::new();
-    s.push_str("```\n");
-    for f in files {
-        s.push_str(&f.rel);
-        s.push('\n');
-    }
-    s.push_str("```\n\n");
-    s
-}
+    /// Expand leading (indentation) tabs to N-column tab stops in emitted content
+    #[arg(long = "tab-width", value_name = "N")]
+    tab_width: Option<usize>,
 
-fn main() {
-    let cli = Cli::parse();
+    /// With --tab-width, expand every tab, not just indentation
+    #[arg(long = "expand-all-tabs", action = ArgAction::SetTrue, requires = "tab_width")]
+    expand_all_tabs: bool,
 
-    let files = collect_any(&cli);
+    /// Soft-wrap emitted lines longer than N characters at whitespace, marking
+    /// each continuation with ↪ (changes content; for chats that don't scroll)
+    #[arg(long = "wrap", visible_alias = "output-wrap-columns", value_name = "N")]
+    wrap: Option<usize>,
 
-    let mut out = String::new();
+    /// Always use backtick fences for Markdown files (by default, Markdown that
+    /// contains backtick fences is wrapped in ~~~ instead)
+    #[arg(long = "no-smart-md-fence", action = ArgAction::SetTrue)]
+    no_smart_md_fence: bool,
 
-    if cli.dir_list {
-        out.push_str(&emit_dir_listing(&files));
-    }
+    /// Prefix each fence label and --dir-list entry with its position in the
+    /// output and the total, e.g. [14/58]
+    #[arg(long = "number-files", action = ArgAction::SetTrue)]
+    number_files: bool,
 
-    for f in &files {
-        let bytes = match fs::read(&f.path) {
-            Ok(b) => b,
-            Err(e) => {
-                eprintln!("skip {}: read error: {e}", f.path.display());
-                continue;
-            }
-        };
-        let content = String::from_utf8_lossy(&bytes);
-        let fence = choose_fence(&content);
+    /// Shell-quote paths in fence labels and --dir-list (POSIX single quotes;
+    /// CMD-style double quotes on Windows)
+    #[arg(long = "shell-quote", action = ArgAction::SetTrue)]
+    shell_quote: bool,
 
-        out.push_str(&fence);
-        out.push_str(&f.rel);
-        out.push('\n');
+    /// Label files with their absolute path instead of the path under PATH
+    #[arg(long = "absolute-paths", action = ArgAction::SetTrue)]
+    absolute_paths: bool,
 
-        out.push_str(&content);
-        if !content.ends_with('\n') {
-            out.push('\n');
-        }
+    /// Label files relative to the enclosing git repository's root instead
+    /// of PATH (`fencecat src` gives src/main.rs); only PATH is walked
+    #[arg(
+        long = "label-from-git-root",
+        action = ArgAction::SetTrue,
+        conflicts_with_all = ["absolute_paths", "path_style_relative_to_home", "from_archive"]
+    )]
+    label_from_git_root: bool,
 
-        out.push('\n');
-        out.push_str(&fence);
-        out.push_str("\n\n");
-    }
+    /// Start every label with the name of the scanned directory
+    /// (`fencecat backend` gives backend/src/api.rs; `.` gives its real name).
+    /// For a single file, the name of its directory
+    #[arg(
+        long = "include-root-name",
+        action = ArgAction::SetTrue,
+        conflicts_with_all = [
+            "absolute_paths",
+            "path_style_relative_to_home",
+            "label_from_git_root",
+            "from_archive",
+        ]
+    )]
+    include_root_name: bool,
 
-    print!("{out}");
+    /// Label files with their absolute path, writing the home directory as ~
+    /// (shorter, and keeps the user name out of shared prompts)
+    #[arg(long = "path-style-relative-to-home", action = ArgAction::SetTrue)]
+    path_style_relative_to_home: bool,
 
-    if cli.copy {
-        match fencecat::clipboard::copy_to_clipboard_multi(&out) {
-            Ok(()) => eprintln!(">> copied to clipboard"),
-            Err(e) => eprintln!(">> failed to copy to clipboard: {e}"),
+    /// Mark executable files with `(exec)` in the dir listing and fence
+    /// headers; --extract sets the execute bit again. No-op on Windows
+    #[arg(long = "show-exec", action = ArgAction::SetTrue)]
+    show_exec: bool,
+
+    /// Prepended to labels by --absolute-paths/--path-style-relative-to-home.
+    #[arg(skip)]
+    label_prefix: Option<String>,
+
+    /// Emit an HTML anchor (e.g. <a id="src-main-rs"></a>) before each fence;
+    /// the --dir-list becomes a linked table of contents
+    #[arg(long = "fence-anchor", action = ArgAction::SetTrue)]
+    fence_anchor: bool,
+
+    /// Start the output with a linked table of contents of the files
+    /// (implies --fence-anchor; the --dir-list stays a plain fence)
+    #[arg(long = "section-toc", action = ArgAction::SetTrue)]
+    section_toc: bool,
+
+    /// Start the output with a block quote describing the project: name,
+    /// version and description from Cargo.toml or package.json, language,
+    /// frameworks, file count and total size (Markdown only)
+    #[arg(
+        long = "repo-summary",
+        action = ArgAction::SetTrue,
+        overrides_with = "no_repo_summary"
+    )]
+    repo_summary: bool,
+
+    /// Leave out the summary, e.g. when FENCECAT_OPTS turns it on
+    #[arg(long = "no-repo-summary", action = ArgAction::SetTrue, overrides_with = "repo_summary")]
+    no_repo_summary: bool,
+
+    /// Interactively pick which of the matched files to emit (needs a terminal;
+    /// otherwise everything is emitted)
+    #[arg(long = "select", action = ArgAction::SetTrue)]
+    select: bool,
+
+    /// Skip files larger than SIZE (e.g. 300k, 2MB)
+    #[arg(long = "max-file-size", value_name = "SIZE", value_parser = parse_size)]
+    max_file_size: Option<u64>,
+
+    /// Skip files with fewer than N lines (counted before any transform)
+    #[arg(long = "min-lines", value_name = "N")]
+    min_lines: Option<usize>,
+
+    /// Skip files with more than N lines (counted before any transform)
+    #[arg(long = "max-lines", value_name = "N")]
+    max_lines: Option<usize>,
+
+    /// Warn on stderr about included files larger than SIZE (default 100k)
+    #[arg(
+        long = "warn-large-file",
+        value_name = "SIZE",
+        value_parser = parse_size,
+        num_args = 0..=1,
+        default_missing_value = "100k"
+    )]
+    warn_large_file: Option<u64>,
+
+    /// Transform each file's content with a Rhai script that sees `path` and
+    /// `content` and returns the new content (sandboxed; see README)
+    #[arg(long = "transform-script", value_name = "FILE")]
+    transform_script: Option<PathBuf>,
+
+    /// Replace `${VAR}` references in file content with environment variable
+    /// values, after all other transforms; unset variables are left as is
+    #[arg(long = "stdin-env-vars", action = ArgAction::SetTrue)]
+    stdin_env_vars: bool,
+
+    /// With --stdin-env-vars, warn about each unset variable
+    #[arg(long = "warn-missing-vars", action = ArgAction::SetTrue, requires = "stdin_env_vars")]
+    warn_missing_vars: bool,
+
+    /// Replace the values defined in a .env file (8 characters or longer)
+    /// with [REDACTED] wherever they appear; without PATH, the .env in the
+    /// scanned directory
+    #[arg(long = "env-file", value_name = "PATH", num_args = 0..=1)]
+    env_file: Option<Option<PathBuf>>,
+
+    /// What to do with a binary file given as PATH
+    #[arg(long = "binary", value_enum, default_value_t = BinaryPolicy::Error)]
+    binary: BinaryPolicy,
+
+    /// Skip a file given as PATH when the filters (--ext, --regex, ...) would
+    /// skip it in a walk, instead of warning and emitting it anyway
+    #[arg(long = "apply-filters-to-args", action = ArgAction::SetTrue)]
+    apply_filters_to_args: bool,
+
+    /// What to do with symlinks found while walking
+    #[arg(long = "symlinks", value_enum, default_value_t = SymlinkPolicy::Skip)]
+    symlinks: SymlinkPolicy,
+
+    /// Stop the walk after visiting more than N entries (files and
+    /// directories) and ask before going on; without a terminal, abort.
+    /// 0 disables the limit
+    #[arg(long = "scan-limit", value_name = "N", default_value_t = 200_000)]
+    scan_limit: usize,
+
+    /// Scan the filesystem root or the home directory, and walk past
+    /// --scan-limit, without asking
+    #[arg(long = "force", action = ArgAction::SetTrue)]
+    force: bool,
+
+    /// Skip directories with more than MAX_FILES immediate entries (e.g. node_modules)
+    #[arg(long = "exclude-large-dirs", value_name = "MAX_FILES")]
+    exclude_large_dirs: Option<usize>,
+
+    /// Output format: Markdown fences or a JSON array of file records
+    #[arg(long = "format", value_enum, default_value_t = OutputFormat::Md)]
+    format: OutputFormat,
+
+    /// Print the JSON Schema for --format json output and exit
+    #[arg(long = "output-json-schema", action = ArgAction::SetTrue)]
+    output_json_schema: bool,
+
+    /// Print a JSON array with every visited file, whether it is included and
+    /// why not, then exit without emitting anything
+    #[arg(long = "explain-json", action = ArgAction::SetTrue)]
+    explain_json: bool,
+
+    /// With --explain-json, decide from names, sizes and ignore rules only,
+    /// without opening any file: no binary, shebang, UTF-8 or line checks
+    /// (approximate, but fast on slow filesystems)
+    #[arg(long = "no-content-checks", action = ArgAction::SetTrue, requires = "explain_json")]
+    no_content_checks: bool,
+
+    /// Write the output to FILE instead of stdout (excluded from the scan)
+    #[arg(short = 'o', long = "output", value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Start the --output file with a UTF-8 byte order mark, for Windows
+    /// tools that need one (stdout and the clipboard never get it)
+    #[arg(long = "output-bom", action = ArgAction::SetTrue, requires = "output")]
+    output_bom: bool,
+
+    /// End every line of the --output file with CRLF, file content included
+    /// (for Notepad and other CRLF-only readers)
+    #[arg(long = "output-crlf", action = ArgAction::SetTrue, requires = "output")]
+    output_crlf: bool,
+
+    /// Also POST the output to URL (Content-Type text/markdown, or
+    /// application/json with --format json); a non-2xx answer exits 1
+    #[arg(long = "http-post", value_name = "URL", value_parser = parse_http_url)]
+    http_post: Option<String>,
+
+    /// Add a header to the --http-post request, such as an Authorization
+    /// token (repeatable)
+    #[arg(
+        long = "http-header",
+        value_name = "KEY: VALUE",
+        value_parser = parse_http_header,
+        requires = "http_post"
+    )]
+    http_header: Vec<(String, String)>,
+
+    /// Save the body of the --http-post response to PATH
+    #[arg(
+        long = "http-post-response-file",
+        value_name = "PATH",
+        requires = "http_post"
+    )]
+    http_post_response_file: Option<PathBuf>,
+
+    /// With --http-post, do not print the output to stdout
+    #[arg(long = "no-print", action = ArgAction::SetTrue, requires = "http_post")]
+    no_print: bool,
+
+    /// Include files that look like previous fencecat output (skipped by default)
+    #[arg(long = "include-fencecat-output", action = ArgAction::SetTrue)]
+    include_fencecat_output: bool,
+
+    /// Always include PATH (relative to the scan root), bypassing .gitignore,
+    /// --ext/--regex/--glob filters and size limits. Can be repeated
+    #[arg(long = "force-include", value_name = "PATH")]
+    force_include: Vec<PathBuf>,
+
+    /// Shorthand for --force-include Cargo.lock (skipped quietly if absent)
+    #[arg(long = "include-cargo-lock", action = ArgAction::SetTrue)]
+    include_cargo_lock: bool,
+
+    /// Shorthand for --force-include package-lock.json (skipped quietly if absent)
+    #[arg(long = "include-package-lock", action = ArgAction::SetTrue)]
+    include_package_lock: bool,
+
+    /// Record Unix file modes in the metadata comment above each fence (and in
+    /// JSON); with --extract, restore them (at most 0755). No-op on Windows
+    #[arg(long = "preserve-permissions", action = ArgAction::SetTrue)]
+    preserve_permissions: bool,
+
+    /// Read a fencecat dump on stdin and write each file back under DIR
+    /// (paths leaving DIR or passing through a symlink are refused), instead
+    /// of scanning PATH
+    #[arg(
+        long = "extract",
+        value_name = "DIR",
+        conflicts_with_all = ["dir", "more_files"]
+    )]
+    extract: Option<PathBuf>,
+
+    /// Read a previous fencecat dump on stdin and emit only files that are new
+    /// or changed since then (removed files are listed as notes)
+    #[arg(long = "diff-against-stdin", action = ArgAction::SetTrue)]
+    diff_against_stdin: bool,
+
+    /// How --diff-against-stdin shows changed files
+    #[arg(long = "diff-style", value_enum, default_value_t = DiffStyle::Unified)]
+    diff_style: DiffStyle,
+
+    /// Keep the output within N estimated tokens by dropping files, in output
+    /// order (see --budget-policy). Counted with --count-tokens' tokenizer
+    #[arg(long = "max-tokens", value_name = "N")]
+    max_tokens: Option<usize>,
+
+    /// Which files --max-tokens drops once the budget is exhausted
+    #[arg(long = "budget-policy", value_enum, default_value_t = BudgetPolicy::Stop)]
+    budget_policy: BudgetPolicy,
+
+    /// Split the Markdown output into parts of at most N estimated tokens,
+    /// never splitting a file (see --fence-continuation). With -o, parts go
+    /// to FILE.part1.md, ...
+    #[arg(long = "chunk-by-tokens", value_name = "N")]
+    chunk_by_tokens: Option<usize>,
+
+    /// With --chunk-by-tokens, split a file too large for one part across
+    /// several, marked `chunk=K/N` so --extract joins the pieces again
+    #[arg(
+        long = "fence-continuation",
+        action = ArgAction::SetTrue,
+        requires = "chunk_by_tokens"
+    )]
+    fence_continuation: bool,
+
+    /// Name the -o part files after TEMPLATE (placed next to FILE) instead
+    /// of FILE.partK.md. Placeholders: {dir} (scanned directory name),
+    /// {date} (YYYY-MM-DD, UTC), {count} (part number), {ext} (FILE's
+    /// extension)
+    #[arg(
+        long = "output-filename-template",
+        value_name = "TEMPLATE",
+        requires = "output"
+    )]
+    output_filename_template: Option<String>,
+
+    /// Print the packing plan for a TOKENS budget (per-file and cumulative
+    /// counts, the cut line, dropped files per policy) instead of the content
+    #[arg(long = "budget-report", value_name = "TOKENS")]
+    budget_report: Option<usize>,
+
+    /// Print a red/yellow/green verdict per file (likely secrets, files only
+    /// -H let in, the --max-tokens/--token-budget-warn budget) instead of the
+    /// content; the exit code is the worst verdict (0, 4 or 5)
+    #[arg(
+        long = "audit",
+        action = ArgAction::SetTrue,
+        conflicts_with_all = ["budget_report", "diff_against_stdin", "chunk_by_tokens"]
+    )]
+    audit: bool,
+
+    /// Color added/removed lines of --diff-style unified output on stdout
+    /// (never in -o files or the clipboard)
+    #[arg(long = "color-diff", action = ArgAction::SetTrue)]
+    color_diff: bool,
+
+    /// When to use colors: auto (only if stdout is a terminal), always, never
+    #[arg(long = "color", value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Trailing newlines in fences: preserve the content as is, ensure exactly
+    /// one, or strip them all
+    #[arg(long = "eof-newline", value_enum, default_value_t = EofNewline::Preserve)]
+    eof_newline: EofNewline,
+
+    /// Line endings of the lines fencecat writes itself (fences, blank
+    /// separators, listings, notes); file content keeps its own. native is
+    /// crlf on Windows
+    #[arg(long = "output-newline", value_enum, default_value_t = OutputNewline::Lf)]
+    output_newline: OutputNewline,
+
+    /// Annotate Rust fences with `edition=YEAR`; `auto` reads it from the
+    /// scan root's Cargo.toml (no annotation if there is none)
+    #[arg(
+        long = "rust-edition",
+        value_name = "YEAR|auto",
+        value_parser = clap::builder::PossibleValuesParser::new(
+            std::iter::once("auto").chain(EDITIONS)
+        )
+    )]
+    rust_edition: Option<String>,
+
+    /// Extra clipboard flavor for --copy: markdown offers text/markdown
+    /// (Wayland) or HTML (macOS, Windows) next to plain text
+    #[arg(long = "copy-mime", value_enum, default_value_t = CopyMime::Plain)]
+    copy_mime: CopyMime,
+
+    /// Also put an HTML rendering of the output on the clipboard, so rich
+    /// editors paste formatted code blocks (same as --copy-mime html)
+    #[arg(long = "clipboard-html", action = ArgAction::SetTrue, conflicts_with = "copy_mime")]
+    clipboard_html: bool,
+
+    /// Read and transform files on N threads (0: one per CPU). The walk and
+    /// the output order are unaffected; warnings may come out of order
+    #[arg(short = 'j', long = "jobs", value_name = "N", default_value_t = 1)]
+    jobs: usize,
+
+    /// Print how long each stage took (walk, filter, read, decode, format,
+    /// tokenize, write, clipboard) to stderr, even with --quiet
+    #[arg(long = "timings", visible_alias = "profile", action = ArgAction::SetTrue)]
+    timings: bool,
+
+    /// Ignore default options from the FENCECAT_OPTS environment variable
+    #[arg(long = "no-env", action = ArgAction::SetTrue)]
+    no_env: bool,
+
+    /// Print diagnostics to stderr, such as the options taken from FENCECAT_OPTS
+    #[arg(short = 'v', long = "verbose", action = ArgAction::SetTrue)]
+    verbose: bool,
+
+    /// Show at most N per-file walk and read errors, then only a count per
+    /// kind at the end (0 = all; --verbose shows all too)
+    #[arg(long = "warn-limit", value_name = "N", default_value_t = 20)]
+    warn_limit: usize,
+
+    /// Write sizes as exact byte counts and counts without digit grouping in
+    /// messages and notes (for scripts), instead of 12.0 KiB and 3,412
+    #[arg(long = "bytes", action = ArgAction::SetTrue)]
+    bytes: bool,
+
+    /// Drop informational `>>` lines such as "copied to clipboard" (warnings
+    /// and errors still print)
+    #[arg(short = 'q', long = "quiet", action = ArgAction::SetTrue, overrides_with = "no_quiet")]
+    quiet: bool,
+
+    /// Keep the informational lines even when --stdout-format-override-when-piped
+    /// would drop them
+    #[arg(long = "no-quiet", action = ArgAction::SetTrue, overrides_with = "quiet")]
+    no_quiet: bool,
+
+    /// Behave like a pipeline tool when stdout is not a terminal: imply
+    /// --quiet unless --no-quiet is given (colors are already off)
+    #[arg(long = "stdout-format-override-when-piped", action = ArgAction::SetTrue)]
+    stdout_format_override_when_piped: bool,
+
+    /// Words injected from FENCECAT_OPTS (for --verbose).
+    #[arg(skip)]
+    env_opts: Vec<String>,
+
+    /// Print how many files each --regex, --not-regex and --glob pattern
+    /// matched to stderr (also shown with --verbose)
+    #[arg(long = "filter-stats", action = ArgAction::SetTrue)]
+    filter_stats: bool,
+
+    /// Treat every file that is not valid UTF-8 as binary and skip it, instead
+    /// of decoding it with replacement characters
+    #[arg(long = "strict-binary", action = ArgAction::SetTrue)]
+    strict_binary: bool,
+
+    /// Emit a fence for empty files too; by default they only appear in the
+    /// --dir-list, marked `(empty)`
+    #[arg(long = "include-empty", action = ArgAction::SetTrue)]
+    include_empty: bool,
+
+    /// Exit with code 0 instead of 3 when no files are left to emit
+    #[arg(long = "allow-empty", action = ArgAction::SetTrue)]
+    allow_empty: bool,
+
+    /// Exit with code 1 when any file could not be walked or read
+    #[arg(long = "strict", action = ArgAction::SetTrue)]
+    strict: bool,
+
+    /// What a walk, metadata or read error does: `skip` the path silently,
+    /// `warn` and go on, or `fail` at the first one
+    #[arg(long = "on-error", value_name = "POLICY", value_enum, default_value_t = OnError::Warn)]
+    on_error: OnError,
+
+    /// Apply token settings and default flags for a known LLM (explicit
+    /// flags still win). See --list-llm-presets
+    #[arg(long = "preset-llm", value_name = "MODEL", value_parser = parse_llm_preset)]
+    preset_llm: Option<&'static LlmPreset>,
+
+    /// Print the available --preset-llm models and exit
+    #[arg(long = "list-llm-presets", action = ArgAction::SetTrue)]
+    list_llm_presets: bool,
+
+    /// Print an estimated token count of the output to stderr
+    #[arg(long = "count-tokens", value_name = "TOKENIZER")]
+    count_tokens: Option<Tokenizer>,
+
+    /// Warn on stderr when the estimated token count exceeds this budget
+    #[arg(long = "token-budget-warn", value_name = "TOKENS")]
+    token_budget_warn: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Markdown code fences
+    Md,
+    /// JSON array of file records
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SymlinkPolicy {
+    /// Leave symlinks out (links given as PATH are still read)
+    Skip,
+    /// Emit the content of the link target; descend into linked directories
+    Follow,
+    /// Emit a one-line note with the link target instead of its content
+    Annotate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum BinaryPolicy {
+    /// Fail with an error
+    Error,
+    /// Emit a one-line note with the size instead of the content
+    Placeholder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+enum OnError {
+    /// Leave the path out without a word (still counted for --strict)
+    Skip,
+    /// Print the error, leave the path out and go on
+    #[default]
+    Warn,
+    /// Stop at the first error, without output
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    const fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            Self::Auto => is_terminal,
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum DiffStyle {
+    /// A `diff` fence with a unified diff against the previous content
+    Unified,
+    /// The complete new content
+    Full,
+}
+
+/// An absolute `http://` or `https://` URL with a host.
+fn parse_http_url(s: &str) -> Result<String, String> {
+    let uri: ureq::http::Uri = s.parse().map_err(|e| format!("{e}"))?;
+    match uri.scheme_str() {
+        Some("http" | "https") if uri.host().is_some() => Ok(s.to_string()),
+        _ => Err("expected an http:// or https:// URL".to_string()),
+    }
+}
+
+/// `KEY: VALUE`, with a valid header name.
+fn parse_http_header(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once(':')
+        .ok_or_else(|| "expected KEY: VALUE".to_string())?;
+    let key = key.trim();
+    ureq::http::HeaderName::from_bytes(key.as_bytes())
+        .map_err(|_| format!("invalid header name {key:?}"))?;
+    Ok((key.to_string(), value.trim().to_string()))
+}
+
+fn parse_llm_preset(s: &str) -> Result<&'static LlmPreset, String> {
+    fencecat::config::find_llm_preset(s).ok_or_else(|| {
+        let names: Vec<&str> = LLM_PRESETS.iter().map(|p| p.name).collect();
+        format!("unknown preset (available: {})", names.join(", "))
+    })
+}
+
+impl Cli {
+    /// A size for messages and notes, exact under `--bytes`.
+    fn size(&self, bytes: u64) -> String {
+        fmt_size(bytes, self.bytes)
+    }
+
+    /// A count for messages, without `,` grouping under `--bytes`.
+    fn count(&self, n: usize) -> String {
+        fmt_count(n, self.bytes)
+    }
+
+    /// `--ignore-binary-extensions`: the last of the two flags wins, and
+    /// without either it follows ignore rules (off under `-H`).
+    const fn skips_binary_extensions(&self) -> bool {
+        if self.ignore_binary_extensions {
+            true
+        } else if self.no_ignore_binary_extensions {
+            false
+        } else {
+            !self.no_ignore
+        }
+    }
+
+    /// The walker for `root`, PATH or one of several named directories.
+    /// Directories matching `prune` (`--prune-regex`) are not entered.
+    pub fn build_walkdir(&self, root: &Path, prune: Option<&RegexSet>) -> WalkBuilder {
+        let mut wb = WalkBuilder::new(root);
+        if self.no_ignore {
+            wb.hidden(false)
+                .ignore(false)
+                .git_ignore(false)
+                .git_global(false)
+                .git_exclude(false)
+                .parents(false);
+        } else {
+            apply_git_config(&mut wb, root);
+        }
+        wb.follow_links(self.symlinks == SymlinkPolicy::Follow);
+        let max = self.exclude_large_dirs;
+        let prune = prune.cloned();
+        if max.is_some() || prune.is_some() {
+            wb.filter_entry(move |entry| {
+                if entry.depth() == 0 || !entry.file_type().is_some_and(|ft| ft.is_dir()) {
+                    return true;
+                }
+                if let Some(prune) = &prune {
+                    let cwd_rel = fencecat::rel_string(Path::new("."), entry.path());
+                    if prune.is_match(&format!("{cwd_rel}/")) {
+                        return false;
+                    }
+                }
+                let Some(max) = max else {
+                    return true;
+                };
+                match dir_child_count(entry.path()) {
+                    Ok(n) if n > max => {
+                        eprintln!(
+                            "skip {}: {n} entries exceeds --exclude-large-dirs {max}",
+                            entry.path().display()
+                        );
+                        false
+                    }
+                    _ => true,
+                }
+            });
+        }
+        wb
+    }
+
+    /// Merge `--*-file` pattern lists into the inline filter flags, validating
+    /// each entry so errors point at `file:line`.
+    fn load_filter_files(&mut self) -> Result<(), Error> {
+        let mut stdin_used = false;
+        for path in &self.regex_file {
+            for (line, pat) in read_pattern_file(path, &mut stdin_used)? {
+                validate_regex(&pat, self.ignore_case_regex, path, line)?;
+                self.regex.get_or_insert_with(Vec::new).push(pat);
+            }
+        }
+        for path in &self.not_regex_file {
+            for (line, pat) in read_pattern_file(path, &mut stdin_used)? {
+                validate_regex(&pat, self.ignore_case_regex, path, line)?;
+                self.not_regex.get_or_insert_with(Vec::new).push(pat);
+            }
+        }
+        for path in &self.ext_file {
+            for (_, entry) in read_pattern_file(path, &mut stdin_used)? {
+                let exts = self.ext.get_or_insert_with(Vec::new);
+                exts.extend(entry.split(',').map(str::to_string));
+            }
+        }
+        for path in &self.glob_file {
+            for (line, pat) in read_pattern_file(path, &mut stdin_used)? {
+                OverrideBuilder::new(".").add(&pat).map_err(|e| {
+                    Error::Usage(format!("{}:{line}: invalid glob: {e}", path.display()))
+                })?;
+                self.glob.push(pat);
+            }
+        }
+        Ok(())
+    }
+
+    /// `--force-include` paths plus the lockfile shorthands; the flag is
+    /// `true` for explicit paths, whose absence is an error. A path that
+    /// would leave the scan root (absolute, or with `..`) is refused, as it
+    /// could not be labeled relative to it.
+    fn forced_paths(&self) -> Result<Vec<(PathBuf, bool)>, Error> {
+        let base = self.label_base();
+        let mut explicit = Vec::new();
+        for p in &self.force_include {
+            let inside = p
+                .components()
+                .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+                && p.components().any(|c| matches!(c, Component::Normal(_)));
+            if !inside {
+                return Err(Error::Usage(format!(
+                    "--force-include {}: not a path under the scan root",
+                    p.display()
+                )));
+            }
+            explicit.push((base.join(p), true));
+        }
+        let lockfiles = [
+            (self.include_cargo_lock, "Cargo.lock"),
+            (self.include_package_lock, "package-lock.json"),
+        ]
+        .into_iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, name)| (base.join(name), false));
+        Ok(explicit.into_iter().chain(lockfiles).collect())
+    }
+
+    /// Expand a leading `~` in PATH and --from-archive, unless such a file
+    /// really exists.
+    fn expand_home_in_paths(&mut self) {
+        let Some(home) = std::env::home_dir() else {
+            return;
+        };
+        for path in std::iter::once(&mut self.dir)
+            .chain(&mut self.more_files)
+            .chain(&mut self.from_archive)
+        {
+            if !path.exists()
+                && let Some(expanded) = expand_home(path, &home)
+            {
+                *path = expanded;
+            }
+        }
+    }
+
+    /// `--fake-root`: write the manifest's files to a temp directory and
+    /// point PATH at the fake root inside it. The directory lives as long as
+    /// the returned fixture.
+    #[cfg(feature = "testing")]
+    fn materialize_fake_root(&mut self) -> Result<Option<input::fixture::Fixture>, Error> {
+        let (Some(root), Some(manifest)) = (&self.fake_root, &self.fake_root_manifest) else {
+            return Ok(None);
+        };
+        let fixture = input::fixture::Fixture::create(&input::fixture::read_manifest(manifest)?)?;
+        self.dir = fixture.resolve(root)?;
+        Ok(Some(fixture))
+    }
+
+    /// PATH when it does not exist but looks like a glob (see `collect_from_glob`).
+    fn glob_pattern(&self) -> Option<&str> {
+        as_glob(&self.dir)
+    }
+
+    /// The `--extension-order` list (lowercase, without dots), empty for a
+    /// plain `--sort-extensions-first`; `None` when grouping is off.
+    fn ext_order(&self) -> Option<Vec<String>> {
+        if let Some(order) = &self.extension_order {
+            return Some(
+                order
+                    .iter()
+                    .map(|e| e.trim().trim_start_matches('.').to_ascii_lowercase())
+                    .filter(|e| !e.is_empty())
+                    .collect(),
+            );
+        }
+        self.sort_extensions_first.then(Vec::new)
+    }
+
+    /// PATH and the paths after it, when more than one was given.
+    fn named_files(&self) -> Option<Vec<&Path>> {
+        (!self.more_files.is_empty()).then(|| {
+            std::iter::once(self.dir.as_path())
+                .chain(self.more_files.iter().map(PathBuf::as_path))
+                .collect()
+        })
+    }
+
+    /// Whether an ordering flag was given, which overrides the command-line
+    /// order of several named files.
+    fn sorts_explicitly(&self) -> bool {
+        self.biggest_first
+            || self.sort_within_dir
+            || self.sort_extensions_first
+            || self.extension_order.is_some()
+            || self.group_by_lang
+    }
+
+    /// The directory labels are relative to: PATH, the directory of a file
+    /// given as PATH (or the closest one holding all named paths), or the
+    /// literal directory in front of a glob.
+    fn label_base(&self) -> PathBuf {
+        if let Some(files) = self.named_files() {
+            // A glob stands for files in its literal directory
+            let files: Vec<PathBuf> = files
+                .iter()
+                .map(|p| match as_glob(p) {
+                    Some(pattern) => fencecat::glob_base(pattern).join("*"),
+                    None => p.to_path_buf(),
+                })
+                .collect();
+            let files: Vec<&Path> = files.iter().map(PathBuf::as_path).collect();
+            return fencecat::common_parent(&files);
+        }
+        let base = if self.dir.is_file() {
+            self.dir.parent().unwrap_or_else(|| Path::new("."))
+        } else if let Some(pattern) = self.glob_pattern() {
+            return fencecat::glob_base(pattern);
+        } else {
+            &self.dir
+        };
+        if base.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            base.to_path_buf()
+        }
+    }
+
+    /// `--label-from-git-root`: PATH's directory relative to the repository
+    /// root, with a trailing `/` (empty at the root). `None` outside a repo.
+    fn git_root_prefix(&self) -> Option<String> {
+        let base = self.label_base();
+        let base = base.as_path();
+        let top = git_output(base, &["rev-parse", "--show-toplevel"])?;
+        let top = fs::canonicalize(top).ok()?;
+        let base = fs::canonicalize(base).ok()?;
+        let rel = fencecat::rel_string(&top, &base);
+        Some(if rel.is_empty() {
+            rel
+        } else {
+            format!("{rel}/")
+        })
+    }
+
+    /// `--include-root-name`: the final component of the canonicalized scan
+    /// root (a file's directory), with a trailing `/`. `None` for `/`.
+    fn root_name_prefix(&self) -> Option<String> {
+        let base = self.label_base();
+        let base = base.as_path();
+        let base = fs::canonicalize(base).ok()?;
+        Some(format!("{}/", base.file_name()?.to_string_lossy()))
+    }
+
+    /// The absolute directory labels are relative to, for
+    /// --absolute-paths/--path-style-relative-to-home.
+    fn resolve_label_prefix(&mut self) {
+        if !self.absolute_paths && !self.path_style_relative_to_home || self.from_archive.is_some()
+        {
+            return;
+        }
+        let Ok(base) = fs::canonicalize(self.label_base()) else {
+            return;
+        };
+        let mut prefix = base.to_string_lossy().replace('\\', "/");
+        if self.path_style_relative_to_home
+            && let Some(home) = std::env::home_dir().and_then(|h| fs::canonicalize(h).ok())
+        {
+            prefix =
+                collapse_home(&prefix, &home.to_string_lossy().replace('\\', "/")).into_owned();
+        }
+        if !prefix.ends_with('/') {
+            prefix.push('/');
+        }
+        self.label_prefix = Some(prefix);
+    }
+
+    /// `--stdout-format-override-when-piped`: quiet by default when piped.
+    fn resolve_quiet(&mut self) {
+        if self.stdout_format_override_when_piped && !self.no_quiet && !io::stdout().is_terminal() {
+            self.quiet = true;
+        }
+    }
+
+    /// `--rust-edition auto`: the edition of the scan root's `Cargo.toml`.
+    fn resolve_rust_edition(&mut self) {
+        if self.rust_edition.as_deref() == Some("auto") {
+            self.rust_edition = self
+                .dir
+                .is_dir()
+                .then(|| detect_rust_edition(&self.dir))
+                .flatten();
+        }
+    }
+
+    /// Fill settings the user did not give explicitly from `--preset-llm`.
+    fn apply_llm_preset(&mut self) {
+        let Some(preset) = self.preset_llm else {
+            return;
+        };
+        self.token_budget_warn
+            .get_or_insert(preset.token_budget_warn);
+        self.count_tokens.get_or_insert(preset.tokenizer);
+    }
+
+    /// Refuse `--format json` with the flags that only render Markdown. An
+    /// explicit `--format md`, from FENCECAT_OPTS or a preset too, is fine.
+    fn check_markdown_only(&self) -> Result<(), Error> {
+        let markdown_only = [
+            (self.repo_summary, "--repo-summary"),
+            (self.diff_against_stdin, "--diff-against-stdin"),
+            (self.chunk_by_tokens.is_some(), "--chunk-by-tokens <N>"),
+        ];
+        match markdown_only.iter().find(|(given, _)| *given) {
+            Some((_, flag)) if self.format == OutputFormat::Json => Err(Error::Usage(format!(
+                "the argument '--format json' cannot be used with '{flag}'"
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether `path` is one of the (canonical) `exclude` paths. Only canonicalizes
+/// when the file name already matches, so the common case stays cheap.
+fn is_excluded(path: &Path, exclude: &[PathBuf]) -> bool {
+    exclude.iter().any(|ex| {
+        ex.file_name() == path.file_name() && fs::canonicalize(path).is_ok_and(|canon| &canon == ex)
+    })
+}
+
+/// Where the `-o` parts of `--chunk-by-tokens` go: the canonical directory
+/// and a pattern matching the part file names of any run (`context.part2.md`,
+/// or the `--output-filename-template` name with any `{count}` and `{date}`).
+#[derive(Debug)]
+struct OutputParts {
+    dir: PathBuf,
+    names: Regex,
+}
+
+impl OutputParts {
+    fn from_cli(cli: &Cli) -> Option<Self> {
+        let path = cli.output.as_deref()?;
+        let dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        let names = if let Some(template) = &cli.output_filename_template {
+            // Stand-ins that regex::escape leaves alone, swapped for patterns after
+            const COUNT: &str = "\u{e000}";
+            const DATE: &str = "\u{e001}";
+            let name = render_part_name(cli, template, path, DATE, COUNT).ok()?;
+            regex::escape(&name)
+                .replace(COUNT, "[0-9]+")
+                .replace(DATE, "[0-9]{4}-[0-9]{2}-[0-9]{2}")
+        } else {
+            let stem = path.file_stem()?.to_string_lossy();
+            let ext = path
+                .extension()
+                .map(|e| format!(r"\.{}", regex::escape(&e.to_string_lossy())))
+                .unwrap_or_default();
+            format!(r"{}\.part[0-9]+{ext}", regex::escape(&stem))
+        };
+        Some(Self {
+            dir: fs::canonicalize(dir).ok()?,
+            names: Regex::new(&format!("^{names}$")).ok()?,
+        })
+    }
+
+    /// Whether `path` is one of the parts, checking the name first so the
+    /// common case stays cheap.
+    fn contains(&self, path: &Path) -> bool {
+        path.file_name()
+            .is_some_and(|n| self.names.is_match(&n.to_string_lossy()))
+            && path
+                .parent()
+                .and_then(|p| fs::canonicalize(p).ok())
+                .is_some_and(|p| p == self.dir)
+    }
+}
+
+/// Read a pattern list: one entry per line, with blank lines and `#` comments
+/// skipped. Returns `(line_number, entry)` pairs. `-` reads stdin, at most
+/// once per run.
+fn read_pattern_file(path: &Path, stdin_used: &mut bool) -> Result<Vec<(usize, String)>, Error> {
+    let text = if path == Path::new("-") {
+        if std::mem::replace(stdin_used, true) {
+            return Err(Error::Usage(
+                "stdin (-) can only be used by one pattern file".to_string(),
+            ));
+        }
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(path)
+    }
+    .map_err(|e| Error::Usage(format!("cannot read {}: {e}", path.display())))?;
+
+    Ok(text
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l.trim()))
+        .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'))
+        .map(|(i, l)| (i, l.to_string()))
+        .collect())
+}
+
+fn validate_regex(pat: &str, ignore_case: bool, path: &Path, line: usize) -> Result<(), Error> {
+    RegexBuilder::new(pat)
+        .case_insensitive(ignore_case)
+        .build()
+        .map(drop)
+        .map_err(|e| Error::Usage(format!("{}:{line}: invalid regex: {e}", path.display())))
+}
+
+/// Count the immediate entries of a directory (not recursive).
+fn dir_child_count(path: &Path) -> io::Result<usize> {
+    Ok(fs::read_dir(path)?.count())
+}
+
+/// Read the first few KB of a file for binary and shebang detection.
+fn sniff_file(path: &Path) -> io::Result<Sniff> {
+    let mut f = File::open(path)?;
+    let mut buf = [0u8; 8192];
+    let n = f.read(&mut buf)?;
+    Ok(fencecat::sniff(&buf[..n]))
+}
+
+/// Everything gathered from the input: included files plus the directories
+/// the walk visited (relative labels, root excluded).
+#[derive(Debug, Default)]
+struct Collected {
+    files: Vec<FileInfo>,
+    dirs: Vec<String>,
+    /// Walk, metadata and read errors seen so far (files were skipped).
+    errors: usize,
+    /// `--timings` spans, `None` when the flag is off.
+    timings: Option<Timings>,
+    filter_stats: FilterStats,
+    /// Collected files that could not be read when emitting (label, error),
+    /// typically deleted since the walk.
+    vanished: Vec<(String, String)>,
+    /// Files the filters turned down, kept only for `--dir-list-excluded` and
+    /// `--explain-json`.
+    skipped: Vec<Skipped>,
+    /// Files `--strict-binary` turned down for not being UTF-8.
+    not_utf8: usize,
+    /// Per-file errors held back by `--warn-limit`.
+    warnings: Warnings,
+    /// Files named on the command line or matched by a glob there, which
+    /// keep their fence when empty.
+    named: HashSet<PathBuf>,
+}
+
+/// Per-file error lines printed so far, and the ones `--warn-limit` held
+/// back, counted by kind.
+#[derive(Debug, Default)]
+struct Warnings {
+    shown: usize,
+    suppressed: BTreeMap<&'static str, usize>,
+}
+
+impl Warnings {
+    /// Print `msg` unless `limit` lines were printed already; count it
+    /// under `kind` either way.
+    fn warn(&mut self, limit: Option<usize>, kind: &'static str, msg: impl std::fmt::Display) {
+        if limit.is_some_and(|limit| self.shown >= limit) {
+            *self.suppressed.entry(kind).or_default() += 1;
+        } else {
+            self.shown += 1;
+            eprintln!("{msg}");
+        }
+    }
+
+    /// One line per kind of error that was held back.
+    fn report(&self, cli: &Cli) {
+        for (kind, n) in &self.suppressed {
+            eprintln!(
+                ">> … and {} more {kind} error{} (--warn-limit)",
+                cli.count(*n),
+                if *n == 1 { "" } else { "s" }
+            );
+        }
+    }
+}
+
+/// Kind of a per-file error, for the `--warn-limit` summary.
+fn error_kind(e: &io::Error) -> &'static str {
+    match e.kind() {
+        io::ErrorKind::PermissionDenied => "permission-denied",
+        io::ErrorKind::NotFound => "not-found",
+        _ => "I/O",
+    }
+}
+
+/// Prefix an I/O error with what was being done (`read error: …`), keeping
+/// its kind for [`error_kind`].
+fn io_context(what: &'static str) -> impl Fn(io::Error) -> io::Error {
+    move |e| io::Error::new(e.kind(), format!("{what}: {e}"))
+}
+
+impl Collected {
+    /// Put `prefix` in front of every label (`--label-from-git-root`,
+    /// `--include-root-name`).
+    fn prefix_labels(&mut self, prefix: &str) {
+        if prefix.is_empty() {
+            return;
+        }
+        let labels = self
+            .files
+            .iter_mut()
+            .map(|f| &mut f.rel)
+            .chain(&mut self.dirs)
+            .chain(self.skipped.iter_mut().map(|s| &mut s.rel));
+        for label in labels {
+            label.insert_str(0, prefix);
+        }
+    }
+
+    /// Count a walk, metadata or read error and deal with it as `--on-error`
+    /// says: `skip X: …` under `warn` (or nothing under `skip`), or the error
+    /// that ends the run under `fail`. `path` is `None` for walk errors, whose
+    /// message names the path itself.
+    fn file_error(
+        &mut self,
+        cfg: &CollectConfig,
+        kind: &'static str,
+        path: Option<&dyn std::fmt::Display>,
+        e: &dyn std::fmt::Display,
+    ) -> Result<(), Error> {
+        self.errors += 1;
+        match (cfg.on_error, path) {
+            (OnError::Skip, _) => Ok(()),
+            (OnError::Warn, Some(path)) => {
+                self.warnings
+                    .warn(cfg.warn_limit, kind, format_args!("skip {path}: {e}"));
+                Ok(())
+            }
+            (OnError::Warn, None) => {
+                self.warnings
+                    .warn(cfg.warn_limit, kind, format_args!("walk error: {e}"));
+                Ok(())
+            }
+            (OnError::Fail, Some(path)) => {
+                Err(Error::Runtime(format!("{path}: {e} (--on-error fail)")))
+            }
+            (OnError::Fail, None) => {
+                Err(Error::Runtime(format!("walk error: {e} (--on-error fail)")))
+            }
+        }
+    }
+
+    /// Take in one filter decision: keep the file, note why it was left out,
+    /// or report the error that stopped the filters.
+    fn admit(
+        &mut self,
+        cfg: &CollectConfig,
+        root_for_rel: &Path,
+        path: &Path,
+        decision: io::Result<(Verdict, PatternHits)>,
+    ) -> Result<(), Error> {
+        match decision {
+            Ok((verdict, hits)) => {
+                self.filter_stats.record(&hits);
+                match verdict.as_ref().err() {
+                    Some(SkipReason::NotUtf8) => self.not_utf8 += 1,
+                    Some(SkipReason::FencecatOutput)
+                        if !cfg.quiet && cfg.on_error != OnError::Skip =>
+                    {
+                        self.warnings.warn(
+                            cfg.warn_limit,
+                            "fencecat-output",
+                            format_args!(
+                                ">> skip {}: looks like fencecat output \
+                                 (use --include-fencecat-output to keep it)",
+                                fencecat::rel_string(root_for_rel, path)
+                            ),
+                        );
+                    }
+                    _ => {}
+                }
+                match verdict {
+                    Ok(info) => self.files.push(info),
+                    Err(reason) if cfg.record_skips => self.skipped.push(Skipped {
+                        rel: fencecat::rel_string(root_for_rel, path),
+                        reason,
+                    }),
+                    Err(_) => {}
+                }
+                Ok(())
+            }
+            Err(e) => self.file_error(cfg, error_kind(&e), Some(&path.display()), &e),
+        }
+    }
+}
+
+/// The filters' decision on one file.
+type Verdict = Result<FileInfo, SkipReason>;
+
+/// Why the filters left a visited file out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+enum SkipReason {
+    /// Our own `--output`/`--save-clip` file.
+    OwnOutput,
+    ExtNotAllowed,
+    ExtDenied,
+    RegexNotMatched,
+    RegexDenied,
+    TooLarge,
+    Binary,
+    /// Not valid UTF-8 (`--strict-binary`).
+    NotUtf8,
+    /// Below `--min-lines`.
+    TooFewLines,
+    /// Above `--max-lines`.
+    TooManyLines,
+    /// Rust below `--complexity-threshold`.
+    TooSimple,
+    /// A previous fencecat dump (see `--include-fencecat-output`).
+    FencecatOutput,
+    /// A test file under `--exclude-tests`.
+    TestFile,
+    /// Not a test file, under `--only-tests`.
+    NotTestFile,
+}
+
+impl SkipReason {
+    /// Short tag for the `--dir-list-excluded` listing.
+    const fn tag(self) -> &'static str {
+        match self {
+            Self::OwnOutput => "output",
+            Self::ExtNotAllowed | Self::ExtDenied => "ext",
+            Self::RegexNotMatched => "regex",
+            Self::RegexDenied => "not-regex",
+            Self::TooLarge => "size",
+            Self::Binary => "binary",
+            Self::NotUtf8 => "utf8",
+            Self::TooFewLines | Self::TooManyLines => "lines",
+            Self::TooSimple => "complexity",
+            Self::FencecatOutput => "dump",
+            Self::TestFile => "test",
+            Self::NotTestFile => "not-test",
+        }
+    }
+}
+
+/// A visited file that was not collected.
+#[derive(Debug)]
+struct Skipped {
+    rel: String,
+    reason: SkipReason,
+}
+
+/// Whether `--no-content-checks` let `f` in as text without reading it
+/// (media and symlink notes are decided without content anyway).
+fn is_assumed_text(cli: &Cli, f: &FileInfo) -> bool {
+    cli.no_content_checks && f.media.is_none() && f.symlink.is_none()
+}
+
+/// One element of the `--explain-json` array.
+#[derive(Debug, Serialize)]
+struct Explained<'a> {
+    path: &'a str,
+    included: bool,
+    /// `null` for included files.
+    reason: Option<SkipReason>,
+    /// Included without opening it (`--no-content-checks`).
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    assumed_text: bool,
+}
+
+/// `--explain-json`: every visited file with the filters' decision, sorted by
+/// path (pretty-printed, with trailing newline).
+fn explain_json(cli: &Cli, collected: &Collected) -> String {
+    let mut entries: Vec<Explained<'_>> = collected
+        .files
+        .iter()
+        .map(|f| Explained {
+            path: &f.rel,
+            included: true,
+            reason: None,
+            assumed_text: is_assumed_text(cli, f),
+        })
+        .chain(collected.skipped.iter().map(|s| Explained {
+            path: &s.rel,
+            included: false,
+            reason: Some(s.reason),
+            assumed_text: false,
+        }))
+        .collect();
+    entries.sort_by(|a, b| compare_labels(a.path, b.path));
+    let mut s = serde_json::to_string_pretty(&entries).unwrap_or_else(|_| "[]".to_string());
+    s.push('\n');
+    s
+}
+
+/// The `--regex`, `--not-regex` and include `--glob` patterns that fired for
+/// one file, by index into their flag's list.
+#[derive(Debug, Default)]
+struct PatternHits {
+    allow: Vec<usize>,
+    /// Non-empty means `--not-regex` rejected the file.
+    deny: Vec<usize>,
+    globs: Vec<usize>,
+    /// The file passed the other name filters and reached `--not-regex`.
+    deny_checked: bool,
+}
+
+/// Per-pattern hit counts over a run, for `--filter-stats` and the
+/// everything-excluded warning.
+#[derive(Debug, Default)]
+struct FilterStats {
+    /// Files that reached the `--not-regex` check.
+    candidates: usize,
+    /// Of those, files `--not-regex` rejected.
+    rejected: usize,
+    allow: Vec<usize>,
+    deny: Vec<usize>,
+    globs: Vec<usize>,
+}
+
+impl FilterStats {
+    fn record(&mut self, hits: &PatternHits) {
+        let count = |totals: &mut Vec<usize>, indices: &[usize]| {
+            for &i in indices {
+                if totals.len() <= i {
+                    totals.resize(i + 1, 0);
+                }
+                totals[i] += 1;
+            }
+        };
+        count(&mut self.allow, &hits.allow);
+        count(&mut self.deny, &hits.deny);
+        count(&mut self.globs, &hits.globs);
+        self.candidates += usize::from(hits.deny_checked);
+        self.rejected += usize::from(!hits.deny.is_empty());
+    }
+
+    /// Whether `--not-regex` alone emptied a non-empty selection.
+    const fn rejected_everything(&self) -> bool {
+        self.candidates > 0 && self.rejected == self.candidates
+    }
+}
+
+/// Hit count of pattern `i`, zero when it never fired.
+fn hits_of(totals: &[usize], i: usize) -> usize {
+    totals.get(i).copied().unwrap_or(0)
+}
+
+/// Filters applied to every candidate file.
+#[derive(Debug, Default)]
+struct CollectConfig {
+    ext_allow: Option<HashSet<String>>,
+    ext_deny: Option<HashSet<String>>,
+    re_allow: Option<RegexSet>,
+    re_deny: Option<RegexSet>,
+    /// `--prune-regex`, applied by the walker to directories.
+    prune: Option<RegexSet>,
+    /// Let extensionless scripts match `--ext` by their shebang.
+    ext_by_shebang: bool,
+    /// Compare extensions exactly instead of lowercasing them.
+    ext_case_sensitive: bool,
+    /// Keep image/audio/video files as placeholder notes.
+    note_images: bool,
+    lang_map: LangMap,
+    /// `--glob` patterns, applied by the walker.
+    overrides: Option<Override>,
+    /// One matcher per include `--glob` (with its index), built only when
+    /// `--filter-stats` needs per-pattern counts.
+    glob_matchers: Vec<(usize, Override)>,
+    /// Canonical paths never collected (our own output file).
+    exclude: Vec<PathBuf>,
+    /// Our own `-o` part files, never collected either.
+    output_parts: Option<OutputParts>,
+    include_fencecat_output: bool,
+    max_file_size: Option<u64>,
+    min_lines: Option<usize>,
+    max_lines: Option<usize>,
+    warn_large_file: Option<u64>,
+    /// Keep a `Skipped` record per rejected file.
+    record_skips: bool,
+    /// Reject `KNOWN_BINARY_EXTENSIONS` without sniffing.
+    skip_binary_exts: bool,
+    /// `--passthrough-binary` extensions, lowercase without the dot.
+    passthrough_binary: HashSet<String>,
+    /// Reject files that are not valid UTF-8 throughout.
+    strict_binary: bool,
+    /// Keep only test files (`--only-tests`, `Some(true)`) or only the
+    /// others (`--exclude-tests`, `Some(false)`).
+    tests: Option<bool>,
+    /// `--no-content-checks`: never open a file while filtering.
+    no_content_checks: bool,
+    /// Per-file errors shown before the rest are only counted; `None` shows
+    /// them all.
+    warn_limit: Option<usize>,
+    on_error: OnError,
+    /// `--bytes`.
+    exact_units: bool,
+    quiet: bool,
+}
+
+impl CollectConfig {
+    fn from_cli(cli: &Cli) -> Result<Self, Error> {
+        let (ext_allow, ext_deny) = build_ext_filters(cli);
+        let (re_allow, re_deny) = compile_regex_sets(cli)?;
+        Ok(Self {
+            ext_allow,
+            ext_deny,
+            re_allow,
+            re_deny,
+            prune: compile_regex_set(cli, &cli.prune_regex, "prune-regex")?,
+            ext_by_shebang: cli.ext_by_shebang,
+            ext_case_sensitive: cli.ext_case_sensitive,
+            note_images: cli.note_images,
+            lang_map: LangMap::new(&cli.lang_map),
+            overrides: build_overrides(cli, &cli.dir)?,
+            glob_matchers: if cli.filter_stats || cli.verbose {
+                build_glob_matchers(cli)?
+            } else {
+                Vec::new()
+            },
+            exclude: cli
+                .output
+                .iter()
+                .chain(&cli.save_clip)
+                .filter_map(|p| fs::canonicalize(p).ok())
+                .collect(),
+            output_parts: OutputParts::from_cli(cli),
+            include_fencecat_output: cli.include_fencecat_output,
+            max_file_size: cli.max_file_size,
+            min_lines: cli.min_lines,
+            max_lines: cli.max_lines,
+            warn_large_file: cli.warn_large_file,
+            record_skips: cli.dir_list_excluded || cli.explain_json,
+            skip_binary_exts: cli.skips_binary_extensions(),
+            passthrough_binary: cli
+                .passthrough_binary
+                .iter()
+                .map(|e| e.trim().trim_start_matches('.').to_ascii_lowercase())
+                .filter(|e| !e.is_empty())
+                .collect(),
+            strict_binary: cli.strict_binary,
+            tests: if cli.exclude_tests {
+                Some(false)
+            } else {
+                cli.only_tests.then_some(true)
+            },
+            no_content_checks: cli.no_content_checks,
+            warn_limit: (cli.warn_limit > 0 && !cli.verbose).then_some(cli.warn_limit),
+            on_error: cli.on_error,
+            exact_units: cli.bytes,
+            quiet: cli.quiet,
+        })
+    }
+}
+
+/// Helper to normalize extension lists (strip dots, lowercase unless
+/// `case_sensitive`). Entries prefixed with `!` are returned in the second
+/// set, as denials.
+fn normalize_ext_list(list: &[String], case_sensitive: bool) -> (HashSet<String>, HashSet<String>) {
+    let mut allow = HashSet::new();
+    let mut deny = HashSet::new();
+    for s in list {
+        let s = s.trim();
+        let (set, s) = match s.strip_prefix('!') {
+            Some(negated) => (&mut deny, negated),
+            None => (&mut allow, s),
+        };
+        let s = s.trim_start_matches('.');
+        if s.is_empty() {
+            continue;
+        }
+        set.insert(if case_sensitive {
+            s.to_string()
+        } else {
+            s.to_ascii_lowercase()
+        });
+    }
+    (allow, deny)
+}
+
+/// Allow and deny extension sets. `!` entries in `--ext` join `--not-ext`'s
+/// deny set; an `--ext` made only of negations is no allow list at all.
+fn build_ext_filters(cli: &Cli) -> (Option<HashSet<String>>, Option<HashSet<String>>) {
+    let cs = cli.ext_case_sensitive;
+    let (allow, negated) = match &cli.ext {
+        Some(v) => {
+            let (allow, negated) = normalize_ext_list(v, cs);
+            ((!allow.is_empty()).then_some(allow), negated)
+        }
+        None => (None, HashSet::new()),
+    };
+    let deny = match &cli.not_ext {
+        Some(v) => {
+            let (mut deny, also) = normalize_ext_list(v, cs);
+            deny.extend(also);
+            deny.extend(negated);
+            Some(deny)
+        }
+        None => (!negated.is_empty()).then_some(negated),
+    };
+    (allow, deny)
+}
+
+/// The `--glob`s for a walk of `root`.
+fn build_overrides(cli: &Cli, root: &Path) -> Result<Option<Override>, Error> {
+    if cli.glob.is_empty() {
+        return Ok(None);
+    }
+    let mut ob = OverrideBuilder::new(root);
+    for g in &cli.glob {
+        ob.add(g)
+            .map_err(|e| Error::Usage(format!("invalid glob in --glob: {e}")))?;
+    }
+    ob.build()
+        .map(Some)
+        .map_err(|e| Error::Usage(format!("invalid glob in --glob: {e}")))
+}
+
+/// Single-pattern matchers for the include `--glob`s; `!` globs exclude
+/// during the walk and cannot be counted.
+fn build_glob_matchers(cli: &Cli) -> Result<Vec<(usize, Override)>, Error> {
+    cli.glob
+        .iter()
+        .enumerate()
+        .filter(|(_, g)| !g.starts_with('!'))
+        .map(|(i, g)| {
+            let mut ob = OverrideBuilder::new(&cli.dir);
+            ob.add(g)
+                .and_then(|ob| ob.build())
+                .map(|ov| (i, ov))
+                .map_err(|e| Error::Usage(format!("invalid glob in --glob: {e}")))
+        })
+        .collect()
+}
+
+type RegexSets = (Option<RegexSet>, Option<RegexSet>);
+
+fn compile_regex_sets(cli: &Cli) -> Result<RegexSets, Error> {
+    Ok((
+        compile_regex_set(cli, &cli.regex, "regex")?,
+        compile_regex_set(cli, &cli.not_regex, "not-regex")?,
+    ))
+}
+
+/// One pattern flag as a set, honoring `--ignore-case-regex`.
+fn compile_regex_set(
+    cli: &Cli,
+    patterns: &Option<Vec<String>>,
+    flag: &str,
+) -> Result<Option<RegexSet>, Error> {
+    patterns
+        .as_ref()
+        .map(|v| {
+            RegexSetBuilder::new(v)
+                .case_insensitive(cli.ignore_case_regex)
+                .build()
+        })
+        .transpose()
+        .map_err(|e| Error::Usage(format!("invalid regex in --{flag}: {e}")))
+}
+
+/// A file put to the filters: on disk, or an archive member already in
+/// memory.
+enum Candidate<'a> {
+    Disk(&'a Path),
+    Member {
+        path: &'a Path,
+        data: &'a [u8],
+        mtime: Option<SystemTime>,
+    },
+}
+
+impl Candidate<'_> {
+    const fn path(&self) -> &Path {
+        match self {
+            Self::Disk(path) | Self::Member { path, .. } => path,
+        }
+    }
+
+    fn sniff(&self) -> io::Result<Sniff> {
+        match self {
+            Self::Disk(path) => sniff_file(path).map_err(io_context("read error")),
+            Self::Member { data, .. } => Ok(fencecat::sniff(&data[..data.len().min(8192)])),
+        }
+    }
+
+    /// The whole content, for the checks that need all of it. A file read
+    /// from disk keeps the bytes, so loading it does not read it again.
+    fn read(&self) -> io::Result<Cow<'_, [u8]>> {
+        match self {
+            Self::Disk(path) => fs::read(path)
+                .map(Cow::Owned)
+                .map_err(io_context("read error")),
+            Self::Member { data, .. } => Ok(Cow::Borrowed(data)),
+        }
+    }
+
+    /// Size, modification time and whether it is executable.
+    fn stat(&self) -> io::Result<(u64, Option<SystemTime>, bool)> {
+        match self {
+            Self::Disk(path) => {
+                let md = path.metadata().map_err(io_context("metadata error"))?;
+                Ok((md.len(), md.modified().ok(), is_executable(&md)))
+            }
+            Self::Member { data, mtime, .. } => Ok((data.len() as u64, *mtime, false)),
+        }
+    }
+}
+
+/// Apply the filters to `path`. Besides the decision, reports which
+/// patterns fired so callers can keep `--filter-stats`.
+fn make_fileinfo_if_included(
+    path: &Path,
+    root_for_rel: &Path,
+    cfg: &CollectConfig,
+) -> io::Result<(Verdict, PatternHits)> {
+    filter_candidate(&Candidate::Disk(path), root_for_rel, cfg)
+}
+
+fn filter_candidate(
+    cand: &Candidate<'_>,
+    root_for_rel: &Path,
+    cfg: &CollectConfig,
+) -> io::Result<(Verdict, PatternHits)> {
+    let mut hits = PatternHits::default();
+    for (i, glob) in &cfg.glob_matchers {
+        if glob.matched(cand.path(), false).is_whitelist() {
+            hits.globs.push(*i);
+        }
+    }
+    let verdict = filter_file(cand, root_for_rel, cfg, &mut hits)?;
+    Ok((verdict, hits))
+}
+
+fn filter_file(
+    cand: &Candidate<'_>,
+    root_for_rel: &Path,
+    cfg: &CollectConfig,
+    hits: &mut PatternHits,
+) -> io::Result<Verdict> {
+    let path = cand.path();
+    // Sniffed early only when a shebang decides the extension filter
+    let mut sniffed: Option<Sniff> = None;
+
+    if matches!(cand, Candidate::Disk(_)) && is_excluded(path, &cfg.exclude) {
+        if !cfg.quiet {
+            eprintln!(">> excluding output file {} from the scan", path.display());
+        }
+        return Ok(Err(SkipReason::OwnOutput));
+    }
+    // Parts of earlier runs, excluded without a line each
+    if matches!(cand, Candidate::Disk(_))
+        && cfg
+            .output_parts
+            .as_ref()
+            .is_some_and(|parts| parts.contains(path))
+    {
+        return Ok(Err(SkipReason::OwnOutput));
+    }
+
+    let raw_ext = path.extension().and_then(|e| e.to_str());
+    let ext = raw_ext.map(str::to_ascii_lowercase);
+    // `tar.gz`, `d.ts`: matched by --ext alongside the plain extension
+    let raw_compound = compound_extension(path).filter(|c| raw_ext != Some(c.as_str()));
+    let compound = raw_compound.as_deref().map(str::to_ascii_lowercase);
+    let known = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(known_filename);
+
+    // 1. Check Extensions (plus well-known file names and shebangs)
+    if cfg.ext_allow.is_some() || cfg.ext_deny.is_some() {
+        let mut tokens: Vec<&str> = if cfg.ext_case_sensitive {
+            raw_ext.into_iter().chain(raw_compound.as_deref()).collect()
+        } else {
+            ext.iter().chain(&compound).map(String::as_str).collect()
+        };
+        if let Some(k) = known {
+            tokens.push(k.token);
+        }
+
+        if ext.is_none() && known.is_none() && cfg.ext_by_shebang && !cfg.no_content_checks {
+            let sn = cand.sniff()?;
+            tokens.extend(sn.shebang.map(|sb| sb.ext));
+            sniffed = Some(sn);
+        }
+
+        // If whitelist exists, file must match it
+        if let Some(allow) = &cfg.ext_allow
+            && !tokens.iter().any(|t| allow.contains(*t))
+        {
+            return Ok(Err(SkipReason::ExtNotAllowed));
+        }
+        // If blacklist exists, file must NOT match it
+        if let Some(deny) = &cfg.ext_deny
+            && tokens.iter().any(|t| deny.contains(*t))
+        {
+            return Ok(Err(SkipReason::ExtDenied));
+        }
+    }
+
+    // Calculate path relative to CWD (".").
+    // If we are in `src/`, and path is `src/main.rs`, this returns `main.rs`.
+    // If we are in `.`, and path is `src/main.rs`, this returns `src/main.rs`.
+    let cwd_rel = || fencecat::rel_string(Path::new("."), path);
+
+    // 2. Check Regex (against path relative to CWD)
+    if cfg.re_allow.is_some() || cfg.re_deny.is_some() {
+        let cwd_rel = cwd_rel();
+
+        if let Some(allow) = &cfg.re_allow {
+            hits.allow.extend(allow.matches(&cwd_rel).iter());
+            if hits.allow.is_empty() {
+                return Ok(Err(SkipReason::RegexNotMatched));
+            }
+        }
+        if let Some(deny) = &cfg.re_deny {
+            hits.deny_checked = true;
+            hits.deny.extend(deny.matches(&cwd_rel).iter());
+            if !hits.deny.is_empty() {
+                return Ok(Err(SkipReason::RegexDenied));
+            }
+        }
+    }
+
+    // 3. Test file conventions, against the label: the directories above the
+    // scan root say nothing about what the file is
+    if let Some(want) = cfg.tests
+        && fencecat::is_test_file(&fencecat::rel_string(root_for_rel, path)) != want
+    {
+        return Ok(Err(if want {
+            SkipReason::NotTestFile
+        } else {
+            SkipReason::TestFile
+        }));
+    }
+
+    let (size, mtime, executable) = cand.stat()?;
+    if cfg.max_file_size.is_some_and(|max| size > max) {
+        return Ok(Err(SkipReason::TooLarge));
+    }
+
+    if cfg.note_images
+        && let Some(mime) = ext.as_deref().and_then(media_type_for_ext)
+    {
+        return Ok(Ok(FileInfo {
+            path: path.to_path_buf(),
+            rel: fencecat::rel_string(root_for_rel, path),
+            size,
+            lang: None,
+            mtime,
+            lines: None,
+            sha256: None,
+            media: Some(mime),
+            symlink: None,
+            mode: None,
+            executable,
+            content_id: None,
+            duplicate_of: None,
+            anchor: None,
+            data: None,
+        }));
+    }
+
+    let passthrough = should_bypass_binary_check(path, &cfg.passthrough_binary);
+    if cfg.skip_binary_exts
+        && !passthrough
+        && ext
+            .as_deref()
+            .is_some_and(|e| KNOWN_BINARY_EXTENSIONS.contains(&e))
+    {
+        return Ok(Err(SkipReason::Binary));
+    }
+    let sniffed = if cfg.no_content_checks {
+        Sniff::default()
+    } else {
+        sniffed.map_or_else(|| cand.sniff(), Ok)?
+    };
+    if sniffed.binary && !passthrough {
+        return Ok(Err(SkipReason::Binary));
+    }
+    let mut lines = None;
+    let mut data = None;
+    if !cfg.no_content_checks
+        && (cfg.strict_binary || cfg.min_lines.is_some() || cfg.max_lines.is_some())
+    {
+        let bytes = cand.read()?;
+        if cfg.strict_binary && std::str::from_utf8(&bytes).is_err() {
+            return Ok(Err(SkipReason::NotUtf8));
+        }
+        let count = bytes.split_inclusive(|&b| b == b'\n').count();
+        if cfg.min_lines.is_some_and(|min| count < min) {
+            return Ok(Err(SkipReason::TooFewLines));
+        }
+        if cfg.max_lines.is_some_and(|max| count > max) {
+            return Ok(Err(SkipReason::TooManyLines));
+        }
+        lines = Some(count);
+        // Members carry their bytes already
+        if let Cow::Owned(bytes) = bytes {
+            data = Some(bytes);
+        }
+    }
+    if sniffed.fencecat_output && !cfg.include_fencecat_output {
+        return Ok(Err(SkipReason::FencecatOutput));
+    }
+    if let Some(limit) = cfg.warn_large_file
+        && size > limit
+    {
+        eprintln!(
+            "warning: {} is {}; consider --max-file-size to exclude it",
+            path.display(),
+            fmt_size(size, cfg.exact_units)
+        );
+    }
+
+    let rel = fencecat::rel_string(root_for_rel, path);
+    let lang = cfg.lang_map.resolve(
+        lang_ext(cfg, compound, ext).as_deref(),
+        known,
+        sniffed.shebang,
+    );
+
+    Ok(Ok(FileInfo {
+        path: path.to_path_buf(),
+        rel,
+        size,
+        lang,
+        mtime,
+        lines,
+        sha256: None,
+        media: None,
+        symlink: None,
+        mode: None,
+        executable,
+        content_id: None,
+        duplicate_of: None,
+        anchor: None,
+        data,
+    }))
+}
+
+/// Whether `--passthrough-binary` names the extension of `path` (the last
+/// one or the compound one, case-insensitively), so binary detection does
+/// not apply to it.
+fn should_bypass_binary_check(path: &Path, bypasses: &HashSet<String>) -> bool {
+    if bypasses.is_empty() {
+        return false;
+    }
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let compound = compound_extension(path).map(|c| c.to_ascii_lowercase());
+    ext.iter().chain(&compound).any(|e| bypasses.contains(e))
+}
+
+/// The extension the language is looked up by: the compound one when
+/// `--lang-map` names it (`--lang-map d.ts=typescript`), else the last one.
+fn lang_ext(cfg: &CollectConfig, compound: Option<String>, ext: Option<String>) -> Option<String> {
+    compound.filter(|c| cfg.lang_map.get(c).is_some()).or(ext)
+}
+
+/// Build a `FileInfo` for a `--force-include` path without consulting the
+/// walker or the filters. Binary files are still refused.
+fn make_forced_fileinfo(
+    path: &Path,
+    root_for_rel: &Path,
+    cfg: &CollectConfig,
+) -> io::Result<FileInfo> {
+    let md = path.metadata().map_err(io_context("metadata error"))?;
+    if !md.is_file() {
+        return Err(io::Error::other("not a regular file"));
+    }
+    let sniffed = if cfg.no_content_checks {
+        Sniff::default()
+    } else {
+        sniff_file(path).map_err(io_context("read error"))?
+    };
+    if sniffed.binary {
+        return Err(io::Error::other("binary file"));
+    }
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let compound = compound_extension(path).map(|c| c.to_ascii_lowercase());
+    let known = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(known_filename);
+    Ok(FileInfo {
+        path: path.to_path_buf(),
+        rel: fencecat::rel_string(root_for_rel, path),
+        size: md.len(),
+        lang: cfg.lang_map.resolve(
+            lang_ext(cfg, compound, ext).as_deref(),
+            known,
+            sniffed.shebang,
+        ),
+        mtime: md.modified().ok(),
+        lines: None,
+        sha256: None,
+        media: None,
+        symlink: None,
+        mode: None,
+        executable: is_executable(&md),
+        content_id: None,
+        duplicate_of: None,
+        anchor: None,
+        data: None,
+    })
+}
+
+/// `--symlinks annotate`: the filters judge the link by its target, but the
+/// file is emitted as a note naming the target as stored in the link.
+fn make_symlink_note(
+    path: &Path,
+    root_for_rel: &Path,
+    cfg: &CollectConfig,
+) -> io::Result<(Verdict, PatternHits)> {
+    let target = fs::read_link(path).map_err(io_context("read link error"))?;
+    let (verdict, hits) = make_fileinfo_if_included(path, root_for_rel, cfg)?;
+    let verdict = verdict.map(|info| FileInfo {
+        lang: None,
+        media: None,
+        symlink: Some(target.to_string_lossy().replace('\\', "/")),
+        ..info
+    });
+    Ok((verdict, hits))
+}
+
+/// Add `--force-include` files the walk did not already pick up.
+fn add_forced_files(
+    cli: &Cli,
+    cfg: &CollectConfig,
+    collected: &mut Collected,
+) -> Result<(), Error> {
+    for (path, explicit) in cli.forced_paths()? {
+        if !explicit && !path.exists() {
+            continue;
+        }
+        match make_forced_fileinfo(&path, &cli.label_base(), cfg) {
+            Ok(info) => {
+                collected.skipped.retain(|s| s.rel != info.rel);
+                if !collected.files.iter().any(|f| f.rel == info.rel) {
+                    collected.files.push(info);
+                }
+            }
+            Err(e) => {
+                collected.file_error(cfg, error_kind(&e), Some(&path.display()), &e)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Ask before scanning on when `reason` suggests a mis-targeted run. Without
+/// a terminal to ask on, abort with `reason` and `hint`; `--force` skips the
+/// question.
+fn confirm_scan(cli: &Cli, reason: &str, hint: &str) -> Result<(), Error> {
+    if cli.force {
+        return Ok(());
+    }
+    let abort = || Error::Usage(format!("{reason}; {hint}"));
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        return Err(abort());
+    }
+    match Confirm::new()
+        .with_prompt(format!("{reason}. Continue?"))
+        .default(false)
+        .interact()
+    {
+        Ok(true) => Ok(()),
+        _ => Err(abort()),
+    }
+}
+
+/// Refuse (or ask about) the filesystem root and the home directory, which
+/// are almost always a slip of the shell history.
+fn check_scan_root(cli: &Cli, dir: &Path) -> Result<(), Error> {
+    let Ok(root) = fs::canonicalize(dir) else {
+        return Ok(());
+    };
+    let what = if root.parent().is_none() {
+        "the filesystem root"
+    } else if std::env::home_dir().and_then(|h| fs::canonicalize(h).ok()) == Some(root.clone()) {
+        "your home directory"
+    } else {
+        return Ok(());
+    };
+    confirm_scan(
+        cli,
+        &format!("{} is {what}", root.display()),
+        "pass --force to scan it anyway",
+    )
+}
+
+/// Run `git -C dir ARGS` and return its trimmed output, or `None` when git
+/// is missing or fails.
+fn git_output(dir: &Path, args: &[&str]) -> Option<String> {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let text = String::from_utf8(out.stdout).ok()?;
+    let text = text.trim();
+    (out.status.success() && !text.is_empty()).then(|| text.to_string())
+}
+
+/// Ask git, not the walker's own guesses, about the repository around `dir`.
+///
+/// The walker only looks for a `.git` directory and reads `core.excludesFile`
+/// from `~/.gitconfig`. git also honors `$GIT_DIR`/`$GIT_WORK_TREE`,
+/// `$GIT_CONFIG_GLOBAL`, includes and the repository's own config, so when
+/// git says `dir` is in a work tree, the `.gitignore` files apply even
+/// without a `.git` directory, and the excludes file git uses is added.
+fn apply_git_config(wb: &mut WalkBuilder, dir: &Path) {
+    apply_git_ignores(wb, git_ignores(dir).as_ref());
+}
+
+/// What [`apply_git_config`] learns from git, asked once and applied to
+/// as many walkers as needed.
+struct GitIgnores {
+    /// `core.excludesFile`, when it names a file.
+    excludes_file: Option<PathBuf>,
+}
+
+/// [`GitIgnores`] for `dir` (or a file's directory); `None` outside a work
+/// tree or without git.
+fn git_ignores(dir: &Path) -> Option<GitIgnores> {
+    let dir = if dir.is_file() {
+        dir.parent().unwrap_or_else(|| Path::new("."))
+    } else {
+        dir
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    if git_output(dir, &["rev-parse", "--is-inside-work-tree"]).as_deref() != Some("true") {
+        return None;
+    }
+    let excludes_file = git_output(dir, &["config", "--path", "--get", "core.excludesFile"])
+        .map(|path| dir.join(path))
+        .filter(|path| path.is_file());
+    Some(GitIgnores { excludes_file })
+}
+
+fn apply_git_ignores(wb: &mut WalkBuilder, git: Option<&GitIgnores>) {
+    let Some(git) = git else {
+        return;
+    };
+    wb.require_git(false);
+    if let Some(path) = &git.excludes_file
+        && let Some(e) = wb.add_ignore(path)
+    {
+        eprintln!(">> warning: {}: {e}", path.display());
+    }
+}
+
+fn collect_from_dir(cli: &Cli, cfg: &CollectConfig) -> Result<Collected, Error> {
+    let mut collected = Collected {
+        timings: cli.timings.then(Timings::default),
+        ..Collected::default()
+    };
+    walk_dir(
+        cli,
+        cfg,
+        &cli.dir,
+        &cli.dir,
+        cfg.overrides.as_ref(),
+        &mut collected,
+    )?;
+    add_forced_files(cli, cfg, &mut collected)?;
+    sort_collected(cli, &mut collected);
+    Ok(collected)
+}
+
+/// Walk `root`, adding what it holds to `collected` with labels relative to
+/// `root_for_rel`. `overrides` are the `--glob`s, rooted at `root`.
+fn walk_dir(
+    cli: &Cli,
+    cfg: &CollectConfig,
+    root: &Path,
+    root_for_rel: &Path,
+    overrides: Option<&Override>,
+    collected: &mut Collected,
+) -> Result<(), Error> {
+    let mut wb = cli.build_walkdir(root, cfg.prune.as_ref());
+    if let Some(ov) = overrides {
+        wb.overrides(ov.clone());
+    }
+    let mut walker = wb.build();
+    let mut visited = 0;
+
+    // Items are counted per entry; the final `None` costs time but is no entry
+    while let Some(dent) = span(&mut collected.timings, Phase::Walk, 0, || walker.next()) {
+        if let Some(t) = &mut collected.timings {
+            t.record(Phase::Walk, Duration::ZERO, 1);
+        }
+        visited += 1;
+        // Asked once, on the first entry past the limit
+        if cli.scan_limit > 0 && visited - 1 == cli.scan_limit {
+            confirm_scan(
+                cli,
+                &format!(
+                    "the walk visited {visited} entries under {} (--scan-limit {})",
+                    root.display(),
+                    cli.scan_limit
+                ),
+                "narrow the path or add filters such as --glob, raise --scan-limit \
+                 (0 disables it) or pass --force",
+            )?;
+        }
+        let entry = match dent {
+            Ok(e) => e,
+            Err(err) => {
+                let kind = err.io_error().map_or("walk", error_kind);
+                collected.file_error(cfg, kind, None, &err)?;
+                continue;
+            }
+        };
+        if entry.depth() > 0 && entry.file_type().is_some_and(|ft| ft.is_dir()) {
+            collected
+                .dirs
+                .push(fencecat::rel_string(root_for_rel, entry.path()));
+        } else if entry.file_type().is_some_and(|ft| ft.is_file()) {
+            let path = entry.path();
+            let decision = span(&mut collected.timings, Phase::Filter, 1, || {
+                make_fileinfo_if_included(path, root_for_rel, cfg)
+            });
+            collected.admit(cfg, root_for_rel, path, decision)?;
+        } else if cli.symlinks == SymlinkPolicy::Annotate
+            && entry.path_is_symlink()
+            && entry.path().is_file()
+        {
+            let path = entry.path();
+            let decision = make_symlink_note(path, root_for_rel, cfg);
+            collected.admit(cfg, root_for_rel, path, decision)?;
+        }
+    }
+    Ok(())
+}
+
+/// Order files for output (`--biggest-first`, `--sort-within-dir`,
+/// `--sort-extensions-first`, `--group-by-lang`) and the visited directories by name.
+fn sort_collected(cli: &Cli, collected: &mut Collected) {
+    sort_file_list(cli, &mut collected.files);
+    collected.dirs.sort_by(|a, b| compare_labels(a, b));
+}
+
+/// The file half of [`sort_collected`].
+fn sort_file_list(cli: &Cli, files: &mut [FileInfo]) {
+    let key = if cli.biggest_first {
+        SortKey::Size
+    } else {
+        SortKey::Name
+    };
+    let ext_order = cli.ext_order();
+    if cli.sort_within_dir {
+        stable_sort_within_dirs(files, key, ext_order.as_deref());
+    } else {
+        sort_files(files, key, ext_order.as_deref());
+    }
+    if cli.group_by_lang {
+        files.sort_by_cached_key(|f| {
+            let group = lang_group(f);
+            (group.is_none(), group)
+        });
+    }
+}
+
+/// `--sort-by-complexity`: Rust files by descending [`estimate_complexity`]
+/// (ties by path), then everything else by path. Rust files below
+/// `--complexity-threshold` move to the skipped list.
+fn sort_by_complexity(cli: &Cli, collected: &mut Collected) {
+    let mut scored: Vec<(Option<usize>, FileInfo)> = std::mem::take(&mut collected.files)
+        .into_iter()
+        .map(|f| {
+            let score = (f.lang.as_deref() == Some("rust"))
+                .then(|| match &f.data {
+                    Some(data) => Some(estimate_complexity(&String::from_utf8_lossy(data))),
+                    None => fs::read_to_string(&f.path)
+                        .ok()
+                        .map(|c| estimate_complexity(&c)),
+                })
+                .flatten();
+            (score, f)
+        })
+        .collect();
+    if let Some(min) = cli.complexity_threshold {
+        let (kept, simple): (Vec<_>, Vec<_>) = scored
+            .into_iter()
+            .partition(|(score, _)| score.is_none_or(|s| s >= min));
+        collected
+            .skipped
+            .extend(simple.into_iter().map(|(_, f)| Skipped {
+                rel: f.rel,
+                reason: SkipReason::TooSimple,
+            }));
+        scored = kept;
+    }
+    scored.sort_by(|(a, fa), (b, fb)| {
+        (a.is_none(), Reverse(a))
+            .cmp(&(b.is_none(), Reverse(b)))
+            .then_with(|| compare_labels(&fa.rel, &fb.rel))
+    });
+    collected.files = scored.into_iter().map(|(_, f)| f).collect();
+}
+
+/// `--group-by-lang` group of a file; `None` is the `Other` group.
+fn lang_group(f: &FileInfo) -> Option<Cow<'static, str>> {
+    f.lang.as_deref().map(language_display_name)
+}
+
+/// `--group-by-lang` heading to put before file `i` when it opens a group
+/// (or, with `force`, a new part of the output).
+fn group_heading(cli: &Cli, files: &[FileInfo], i: usize, force: bool) -> Option<String> {
+    if !cli.group_by_lang {
+        return None;
+    }
+    let group = lang_group(&files[i]);
+    (force || i == 0 || lang_group(&files[i - 1]) != group)
+        .then(|| format!("## {}\n\n", group.as_deref().unwrap_or("Other")))
+}
+
+/// A file named as PATH. Naming it is taken as intent: the filters only warn
+/// (unless `--apply-filters-to-args`), empty files are kept and binary files
+/// follow `--binary`.
+fn collect_from_single(cli: &Cli, cfg: &CollectConfig) -> Result<Collected, Error> {
+    let path = &cli.dir;
+    let parent = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut collected = Collected {
+        timings: cli.timings.then(Timings::default),
+        ..Collected::default()
+    };
+    collect_named(cli, cfg, path, parent, false, &mut collected)?;
+    Ok(collected)
+}
+
+/// A PATH that does not exist but looks like a glob (`src\*.rs` from a
+/// Windows shell): the pattern's literal directory is walked under the
+/// ignore rules and every matching file goes through the single-file
+/// pipeline, labeled relative to that directory. Binary matches are skipped
+/// as in a walk.
+fn collect_from_glob(cli: &Cli, cfg: &CollectConfig, pattern: &str) -> Result<Collected, Error> {
+    let mut collected = Collected {
+        timings: cli.timings.then(Timings::default),
+        ..Collected::default()
+    };
+    let started = timings::start(collected.timings.as_ref());
+    let (base, matches) = fencecat::expand_glob(pattern, |base| cli.build_walkdir(base, None))
+        .map_err(|e| Error::Usage(format!("invalid glob in PATH: {e}")))?;
+    timings::stop(&mut collected.timings, Phase::Walk, started, matches.len());
+    // No match is no file, as for a walk (exit 3 unless --allow-empty)
+    for path in &matches {
+        collect_named(cli, cfg, path, &base, true, &mut collected)?;
+    }
+    sort_collected(cli, &mut collected);
+    Ok(collected)
+}
+
+/// Several paths named on the command line (`fencecat README.md src`):
+/// files through the single-file pipeline, directories walked as PATH
+/// would be and globs expanded, with their contents sorted. They keep the
+/// order they were given in, the narrative the user chose, unless an
+/// ordering flag asks otherwise. A file named twice, or also found in a
+/// named directory or glob, is emitted once, where it first appears.
+fn collect_from_files(cli: &Cli, cfg: &CollectConfig, paths: &[&Path]) -> Result<Collected, Error> {
+    if let Some(bad) = paths
+        .iter()
+        .find(|p| !p.is_file() && !p.is_dir() && as_glob(p).is_none())
+    {
+        return Err(Error::Usage(format!(
+            "no such file or directory: {}",
+            bad.display()
+        )));
+    }
+    let base = cli.label_base();
+    let mut collected = Collected {
+        timings: cli.timings.then(Timings::default),
+        ..Collected::default()
+    };
+    let mut seen = HashSet::new();
+    for path in paths {
+        if path.is_file() {
+            if seen.insert(named_path_key(path)) {
+                collect_named(cli, cfg, path, &base, false, &mut collected)?;
+            }
+            continue;
+        }
+        if let Some(pattern) = as_glob(path) {
+            let started = timings::start(collected.timings.as_ref());
+            let (_, matches) = fencecat::expand_glob(pattern, |base| cli.build_walkdir(base, None))
+                .map_err(|e| Error::Usage(format!("invalid glob in PATH: {e}")))?;
+            timings::stop(&mut collected.timings, Phase::Walk, started, matches.len());
+            let start = collected.files.len();
+            for m in matches.iter().filter(|m| seen.insert(named_path_key(m))) {
+                collect_named(cli, cfg, m, &base, true, &mut collected)?;
+            }
+            sort_file_list(cli, &mut collected.files[start..]);
+            continue;
+        }
+        check_scan_root(cli, path)?;
+        let rel = fencecat::rel_string(&base, path);
+        if !matches!(rel.as_str(), "" | ".") {
+            collected.dirs.push(rel);
+        }
+        let start = collected.files.len();
+        let overrides = build_overrides(cli, path)?;
+        walk_dir(cli, cfg, path, &base, overrides.as_ref(), &mut collected)?;
+        let mut walked = collected.files.split_off(start);
+        walked.retain(|f| seen.insert(named_path_key(&f.path)));
+        sort_file_list(cli, &mut walked);
+        collected.files.append(&mut walked);
+    }
+    add_forced_files(cli, cfg, &mut collected)?;
+    collected.dirs.sort_by(|a, b| compare_labels(a, b));
+    collected.dirs.dedup();
+    if cli.sorts_explicitly() {
+        sort_collected(cli, &mut collected);
+    }
+    Ok(collected)
+}
+
+/// A path that does not exist but looks like a glob (`src/*.rs`), for
+/// fencecat to expand when the shell did not.
+fn as_glob(path: &Path) -> Option<&str> {
+    if path.exists() {
+        return None;
+    }
+    path.to_str().filter(|p| fencecat::is_glob_pattern(p))
+}
+
+/// The same key for every spelling of a path (`./a.rs`, `a.rs`, `src/../a.rs`):
+/// its directory canonicalized, its own name kept, since a symlink is a file
+/// of its own.
+fn named_path_key(path: &Path) -> PathBuf {
+    let parent = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    match (fs::canonicalize(parent), path.file_name()) {
+        (Ok(dir), Some(name)) => dir.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Add one explicitly named file, labeled relative to `root_for_rel`.
+/// Naming it is taken as intent: see [`collect_from_single`]. A binary file
+/// a glob matched (`globbed`) was not named itself and is skipped.
+fn collect_named(
+    cli: &Cli,
+    cfg: &CollectConfig,
+    path: &Path,
+    root_for_rel: &Path,
+    globbed: bool,
+    collected: &mut Collected,
+) -> Result<(), Error> {
+    if cli.honor_ignore_for_explicit && is_ignored(path) {
+        if !cli.quiet {
+            eprintln!(
+                ">> skip {}: ignored by the repository's ignore rules \
+                 (--honor-ignore-for-explicit)",
+                path.display()
+            );
+        }
+        return Ok(());
+    }
+    let decision = span(&mut collected.timings, Phase::Filter, 1, || {
+        make_fileinfo_if_included(path, root_for_rel, cfg)
+    });
+    // Naming a file does not override --strict-binary, which asked for clean UTF-8
+    let overrides = |reason: SkipReason| match reason {
+        SkipReason::OwnOutput | SkipReason::NotUtf8 => false,
+        SkipReason::Binary => !globbed,
+        _ => true,
+    };
+    match decision {
+        Ok((Err(reason), hits)) if !cli.apply_filters_to_args && overrides(reason) => {
+            collected.filter_stats.record(&hits);
+            collected
+                .files
+                .push(make_named_fileinfo(cli, cfg, path, root_for_rel, reason)?);
+        }
+        decision => collected.admit(cfg, root_for_rel, path, decision)?,
+    }
+    collected.named.insert(path.to_path_buf());
+    Ok(())
+}
+
+/// Whether a walk would leave `path` out under the ignore rules (hidden
+/// files aside): the file itself, or any directory between it and the
+/// repository root. Outside a repository only the file's own directory is
+/// checked.
+fn is_ignored(path: &Path) -> bool {
+    let (Some(name), Some(parent)) = (path.file_name(), path.parent()) else {
+        return false;
+    };
+    let parent = if parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent
+    };
+    // Canonical directories, but the file's own name (it may be a symlink)
+    let Ok(parent) = fs::canonicalize(parent) else {
+        return false;
+    };
+    let path = parent.join(name);
+    let top = git_output(&parent, &["rev-parse", "--show-toplevel"])
+        .and_then(|top| fs::canonicalize(top).ok());
+    // One repository above every directory checked, so git is asked once
+    let git = git_ignores(&parent);
+    let mut child = path.as_path();
+    while let Some(dir) = child.parent() {
+        let mut wb = WalkBuilder::new(dir);
+        apply_git_ignores(&mut wb, git.as_ref());
+        wb.hidden(false).max_depth(Some(1));
+        let listed = wb
+            .build()
+            .filter_map(Result::ok)
+            .any(|e| e.depth() == 1 && e.path() == child);
+        if !listed {
+            return true;
+        }
+        if top
+            .as_deref()
+            .is_none_or(|top| dir == top || !dir.starts_with(top))
+        {
+            return false;
+        }
+        child = dir;
+    }
+    false
+}
+
+/// Build the `FileInfo` for a PATH the filters turned down for `reason`.
+fn make_named_fileinfo(
+    cli: &Cli,
+    cfg: &CollectConfig,
+    path: &Path,
+    parent: &Path,
+    reason: SkipReason,
+) -> Result<FileInfo, Error> {
+    let failed = |msg: String| Error::Runtime(format!("{}: {msg}", path.display()));
+    let sniffed = if cfg.no_content_checks {
+        Sniff::default()
+    } else {
+        sniff_file(path).map_err(|e| failed(format!("read error: {e}")))?
+    };
+    if sniffed.binary {
+        if cli.binary == BinaryPolicy::Error {
+            return Err(Error::Runtime(format!(
+                "{} is a binary file (use --binary placeholder to note it instead)",
+                path.display()
+            )));
+        }
+        let md = path
+            .metadata()
+            .map_err(|e| failed(format!("metadata error: {e}")))?;
+        return Ok(FileInfo {
+            path: path.to_path_buf(),
+            rel: fencecat::rel_string(parent, path),
+            size: md.len(),
+            lang: None,
+            mtime: md.modified().ok(),
+            lines: None,
+            sha256: None,
+            media: Some("application/octet-stream"),
+            symlink: None,
+            mode: None,
+            executable: is_executable(&md),
+            content_id: None,
+            duplicate_of: None,
+            anchor: None,
+            data: None,
+        });
+    }
+    eprintln!(
+        ">> warning: the filters exclude {} ({}); emitting it because it was named \
+         (--apply-filters-to-args skips it)",
+        path.display(),
+        reason.tag()
+    );
+    make_forced_fileinfo(path, parent, cfg).map_err(|e| failed(e.to_string()))
+}
+
+fn collect_any(cli: &Cli) -> Result<Collected, Error> {
+    let cfg = CollectConfig::from_cli(cli)?;
+
+    if let Some(archive) = &cli.from_archive {
+        // Listing, reading and filtering members is one pass, timed as the walk
+        let mut timings = cli.timings.then(Timings::default);
+        let started = timings::start(timings.as_ref());
+        let mut collected = input::archive::collect_from_archive(archive, &cfg)?;
+        timings::stop(&mut timings, Phase::Walk, started, collected.files.len());
+        collected.timings = timings;
+        sort_collected(cli, &mut collected);
+        return Ok(collected);
+    }
+
+    if let Some(files) = cli.named_files() {
+        return collect_from_files(cli, &cfg, &files);
+    }
+    if let Some(pattern) = cli.glob_pattern() {
+        return collect_from_glob(cli, &cfg, pattern);
+    }
+    if !cli.dir.exists() {
+        return Err(Error::Usage(format!(
+            "no such file or directory: {}",
+            cli.dir.display()
+        )));
+    }
+
+    if cli.dir.is_file() {
+        collect_from_single(cli, &cfg)
+    } else if cli.dir.is_dir() {
+        check_scan_root(cli, &cli.dir)?;
+        collect_from_dir(cli, &cfg)
+    } else {
+        Err(Error::Usage(format!(
+            "not a regular file or directory: {}",
+            cli.dir.display()
+        )))
+    }
+}
+
+/// Directories that ended up with no included file anywhere beneath them.
+fn empty_dirs<'a>(files: &[FileInfo], dirs: &'a [String]) -> Vec<&'a str> {
+    dirs.iter()
+        .map(String::as_str)
+        .filter(|d| {
+            !files.iter().any(|f| {
+                f.rel
+                    .strip_prefix(d)
+                    .is_some_and(|rest| rest.starts_with('/'))
+            })
+        })
+        .collect()
+}
+
+/// A path as shown in fence labels and the dir listing (`--absolute-paths`,
+/// `--shell-quote`).
+fn display_path<'a>(cli: &Cli, rel: &'a str) -> Cow<'a, str> {
+    let shown = match &cli.label_prefix {
+        Some(prefix) if !Path::new(rel).is_absolute() => Cow::Owned(format!("{prefix}{rel}")),
+        _ => Cow::Borrowed(rel),
+    };
+    if cli.shell_quote {
+        Cow::Owned(shell_quote(&shown))
+    } else {
+        shown
+    }
+}
+
+/// Where the marker for the empty directory `dir` goes in `lines` (keyed by
+/// label), so the files keep their order: after the last entry of the same
+/// directory that sorts before it by name, else before the first one. A
+/// directory with no other entries is placed by its parent the same way.
+fn dir_marker_position(lines: &[(&str, String)], dir: &str) -> usize {
+    let mut scope = dir;
+    loop {
+        let (parent, name) = scope.rsplit_once('/').unwrap_or(("", scope));
+        let (mut first, mut after) = (None, None);
+        for (i, (label, _)) in lines.iter().enumerate() {
+            let inside = if parent.is_empty() {
+                Some(*label)
+            } else {
+                label
+                    .strip_prefix(parent)
+                    .and_then(|rest| rest.strip_prefix('/'))
+            };
+            let Some(inside) = inside else { continue };
+            let child = inside.split('/').next().unwrap_or(inside);
+            first.get_or_insert(i);
+            // Equal only on the levels above: the directory's own entry
+            if compare_labels(child, name).is_le() {
+                after = Some(i + 1);
+            }
+        }
+        if let Some(i) = after.or(first) {
+            return i;
+        }
+        if parent.is_empty() {
+            return lines.len();
+        }
+        scope = parent;
+    }
+}
+
+fn emit_dir_listing(
+    cli: &Cli,
+    ctx: &RenderCtx,
+    collected: &Collected,
+    contents: &[Option<String>],
+) -> String {
+    // With anchors the listing becomes a clickable Markdown list instead of a
+    // fence, unless --section-toc already printed that list
+    let links = cli.fence_anchor && !cli.section_toc;
+    let mut lines: Vec<(&str, String)> = collected
+        .files
+        .iter()
+        .zip(contents)
+        .zip(numberings(cli, &collected.files, contents))
+        .map(|((f, content), number)| {
+            let shown = file_label(cli, &f.rel, number);
+            // Only fences carry an anchor to link to; the rest stay plain items
+            let mut line = match &f.anchor {
+                Some(anchor) if links && has_fence(cli, ctx, f, content.as_deref()) => {
+                    format!("- [{}](#{anchor})", escape_link_text(&shown))
+                }
+                _ if links => format!("- {}", escape_link_text(&shown)),
+                _ => shown,
+            };
+            line.push_str(exec_marker(cli, f));
+            if f.size == 0 {
+                line.push_str(" (empty)");
+            }
+            (f.rel.as_str(), line)
+        })
+        .collect();
+    if cli.emit_empty_dir_markers {
+        let mut empty = empty_dirs(&collected.files, &collected.dirs);
+        empty.sort_by(|a, b| compare_labels(a, b));
+        for d in empty {
+            let bullet = if links { "- " } else { "" };
+            let shown = display_path(cli, d);
+            let at = dir_marker_position(&lines, d);
+            lines.insert(at, (d, format!("{bullet}{shown}/ (empty/filtered)")));
+        }
+    }
+
+    let mut s = String::new();
+    if !links {
+        s.push_str("```\n");
+    }
+    for (_, line) in lines {
+        s.push_str(&line);
+        s.push('\n');
+    }
+    if links {
+        s.push('\n');
+    } else {
+        s.push_str("```\n\n");
+    }
+    s
+}
+
+/// `--with-tree`: the collected files as an outline, in a fence of its own.
+fn emit_tree(collected: &Collected) -> String {
+    let tree = render_tree(collected.files.iter().map(|f| f.rel.as_str()));
+    format!("```\n{tree}```\n\n")
+}
+
+/// `--dir-list-excluded`: the files the filters left out, grouped by reason
+/// and capped at `--dir-list-excluded-limit` entries.
+fn emit_excluded_listing(cli: &Cli, collected: &Collected) -> String {
+    let mut skipped: Vec<&Skipped> = collected.skipped.iter().collect();
+    skipped.sort_by(|a, b| {
+        a.reason
+            .tag()
+            .cmp(b.reason.tag())
+            .then_with(|| compare_labels(&a.rel, &b.rel))
+    });
+    let width = skipped
+        .iter()
+        .map(|s| s.reason.tag().len())
+        .max()
+        .unwrap_or(0)
+        + 2;
+
+    let mut s = String::from("```excluded\n");
+    if skipped.is_empty() {
+        s.push_str("(none)\n");
+    }
+    for skip in skipped.iter().take(cli.dir_list_excluded_limit) {
+        let tag = format!("[{}]", skip.reason.tag());
+        s.push_str(&format!("{tag:<width$} {}\n", display_path(cli, &skip.rel)));
+    }
+    if let Some(more) = skipped.len().checked_sub(cli.dir_list_excluded_limit)
+        && more > 0
+    {
+        s.push_str(&format!("… and {} more\n", cli.count(more)));
+    }
+    s.push_str("```\n\n");
+    s
+}
+
+/// Let the user pick files in a terminal multi-select. Without a terminal
+/// (stdin or stderr, where the prompt is drawn) every file is kept.
+fn select_files(cli: &Cli, files: Vec<FileInfo>) -> Vec<FileInfo> {
+    if files.is_empty() {
+        return files;
+    }
+    if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+        eprintln!(">> warning: --select needs a terminal; emitting all files");
+        return files;
+    }
+    let labels: Vec<&str> = files.iter().map(|f| f.rel.as_str()).collect();
+    let picked = MultiSelect::new()
+        .with_prompt("Select files (space to toggle, enter to confirm, esc to cancel)")
+        .items(&labels)
+        .max_length(20)
+        .interact_opt();
+    match picked {
+        Ok(Some(indices)) => {
+            let keep: HashSet<usize> = indices.into_iter().collect();
+            files
+                .into_iter()
+                .enumerate()
+                .filter_map(|(i, f)| keep.contains(&i).then_some(f))
+                .collect()
+        }
+        Ok(None) => {
+            if !cli.quiet {
+                eprintln!(">> selection cancelled");
+            }
+            Vec::new()
+        }
+        Err(e) => {
+            eprintln!(">> selection failed ({e}); emitting all files");
+            files
+        }
+    }
+}
+
+fn print_llm_presets() {
+    for p in LLM_PRESETS {
+        let line = format!(
+            "{:<20} budget={:<8} tokenizer={:<8} {}",
+            p.name,
+            p.token_budget_warn,
+            p.tokenizer.name(),
+            shell_words::join(p.default_flags)
+        );
+        println!("{}", line.trim_end());
+    }
+}
+
+fn report_tokens(cli: &Cli, out: &str, timings: &mut Option<Timings>) {
+    if cli.count_tokens.is_none() && cli.token_budget_warn.is_none() {
+        return;
+    }
+    let tokenizer = cli.count_tokens.unwrap_or(Tokenizer::Cl100k);
+    let tokens = span(timings, Phase::Tokenize, 1, || {
+        estimate_tokens(out, tokenizer)
+    });
+    if cli.count_tokens.is_some() {
+        eprintln!(
+            ">> ~{} tokens ({} estimate)",
+            cli.count(tokens),
+            tokenizer.name()
+        );
+    }
+    if let Some(budget) = cli.token_budget_warn
+        && tokens > budget
+    {
+        eprintln!(
+            ">> warning: ~{} tokens exceeds budget of {}",
+            cli.count(tokens),
+            cli.count(budget)
+        );
+    }
+}
+
+/// `--typescript-strip-types` up front: each file's text as it reaches the
+/// transform, compiled, for `load_content` to pick up.
+type Precompiled = (String, Result<String, TransformError>);
+
+/// Whether `f` is TypeScript to strip: `Some(true)` for TSX.
+fn typescript_kind(f: &FileInfo) -> Option<bool> {
+    match f.lang.as_deref() {
+        Some("typescript") => Some(false),
+        Some("tsx") => Some(true),
+        _ => None,
+    }
+}
+
+/// Compile every TypeScript file among `files` before loading, so the ones
+/// SWC cannot handle share a single `tsc` run instead of one each. The
+/// result is parallel to `files`. This reads those files an extra time,
+/// which is nothing next to starting a compiler.
+fn precompile_typescript(cli: &Cli, files: &[FileInfo]) -> Vec<Option<Precompiled>> {
+    let sources: Vec<Option<(String, bool)>> = files
+        .iter()
+        .map(|f| {
+            let tsx = typescript_kind(f).filter(|_| f.media.is_none() && f.symlink.is_none())?;
+            let bytes = match &f.data {
+                Some(data) => Cow::Borrowed(data),
+                None => Cow::Owned(fs::read(&f.path).ok()?),
+            };
+            let content = String::from_utf8_lossy(&bytes);
+            let content = if cli.strip_ansi {
+                strip_ansi(&content).into_owned()
+            } else {
+                content.into_owned()
+            };
+            Some((content, tsx))
+        })
+        .collect();
+    let inputs: Vec<(&str, bool)> = sources
+        .iter()
+        .flatten()
+        .map(|(content, tsx)| (content.as_str(), *tsx))
+        .collect();
+    if inputs.is_empty() {
+        return vec![None; files.len()];
+    }
+    let paths: Vec<&Path> = files
+        .iter()
+        .zip(&sources)
+        .filter(|(_, source)| source.is_some())
+        .map(|(f, _)| f.path.as_path())
+        .collect();
+    let project = fencecat::common_parent(&paths);
+    let mut stripped = strip_typescript_types_batch(&inputs, &cli.ts_target, &project).into_iter();
+    sources
+        .into_iter()
+        .map(|source| {
+            let (content, _) = source?;
+            Some((content, stripped.next()?))
+        })
+        .collect()
+}
+
+/// Read a file and apply the content transforms (built-ins, then the user
+/// script). Fills in the stats that need the bytes (`lines`, `sha256`, and
+/// `size`, which may have changed since the walk). Read errors are returned;
+/// a failing script is reported and counted in `errors` and keeps the
+/// untransformed content.
+fn load_content(
+    cli: &Cli,
+    ctx: &RenderCtx,
+    f: &mut FileInfo,
+    typescript: Option<&Precompiled>,
+    errors: &mut usize,
+    timings: &mut Option<Timings>,
+) -> io::Result<String> {
+    if let Some(target) = &f.symlink {
+        f.lines = Some(1);
+        return Ok(format!(
+            "→ {target} (symlink){}",
+            cli.output_newline.as_str()
+        ));
+    }
+    // Bytes the filters already read are taken as they are
+    let bytes = span(timings, Phase::Read, 1, || match f.data.take() {
+        Some(data) => Ok(data),
+        None => fs::read(&f.path),
+    })?;
+    f.size = bytes.len() as u64;
+    let started = timings::start(timings.as_ref());
+    let mut content = String::from_utf8_lossy(&bytes);
+    if f.lines.is_none() {
+        f.lines = Some(content.lines().count());
+    }
+    if cli.fence_metadata_header {
+        f.sha256 = Some(format!("{:x}", Sha256::digest(&bytes)));
+    }
+    if cli.preserve_permissions && cli.from_archive.is_none() {
+        f.mode = fs::metadata(&f.path).ok().and_then(|md| unix_mode(&md));
+    }
+    if cli.strip_ansi
+        && let Cow::Owned(stripped) = strip_ansi(&content)
+    {
+        content = Cow::Owned(stripped);
+    }
+    if cli.strip_test_annotations && f.lang.as_deref() == Some("rust") {
+        let stripped = if cli.keep_test_modules {
+            strip_test_functions(&content)
+        } else {
+            strip_test_items(&content)
+        };
+        // Unparsable files are emitted verbatim
+        if let Ok(stripped) = stripped {
+            content = Cow::Owned(stripped);
+        }
+    }
+    if cli.typescript_strip_types
+        && let Some(tsx) = typescript_kind(f)
+    {
+        let stripped = match typescript {
+            Some((input, stripped)) if *input == *content => stripped.clone(),
+            _ => {
+                let project = f.path.parent().unwrap_or_else(|| Path::new("."));
+                strip_typescript_types(&content, tsx, &cli.ts_target, project)
+            }
+        };
+        match stripped {
+            Ok(js) => {
+                content = Cow::Owned(js);
+                f.lang = Some(Cow::Borrowed(if tsx { "jsx" } else { "javascript" }));
+            }
+            Err(e) => {
+                eprintln!(">> warning: {}: {e}; keeping the TypeScript", f.rel);
+                content = Cow::Owned(format!("// fencecat: types not stripped ({e})\n{content}"));
+                *errors += 1;
+            }
+        }
+    }
+    if cli.python_stub
+        && f.lang.as_deref() == Some("python")
+        && f.path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("py"))
+    {
+        match extract_python_stub(&content, cli.python_stub_include_private) {
+            Ok(stub) => content = Cow::Owned(stub),
+            Err(e) => {
+                eprintln!(">> warning: {}: {e}; keeping the full source", f.rel);
+                *errors += 1;
+            }
+        }
+    }
+    if cli.strip_leading_imports
+        && let Some(lang) = f.lang.as_deref()
+        && let Cow::Owned(stripped) = strip_leading_imports(&content, lang)
+    {
+        content = Cow::Owned(stripped);
+    }
+    if let Some(width) = cli.tab_width
+        && let Cow::Owned(expanded) = expand_tabs(&content, width, cli.expand_all_tabs)
+    {
+        content = Cow::Owned(expanded);
+    }
+    if let Some(script) = &ctx.script {
+        match script.apply(&f.rel, &content) {
+            Ok(transformed) => content = Cow::Owned(transformed),
+            Err(e) => {
+                eprintln!(
+                    ">> warning: transform-script failed on {}: {e}; keeping original",
+                    f.rel
+                );
+                *errors += 1;
+            }
+        }
+    }
+    let mut content = if cli.stdin_env_vars {
+        substitute_env_vars(&content, cli.warn_missing_vars)
+    } else {
+        content.into_owned()
+    };
+    // Last but for wrapping, so no transform reintroduces a value and no
+    // wrap splits one
+    if let Some(re) = &ctx.redactions
+        && let Cow::Owned(redacted) = redact(&content, re)
+    {
+        content = redacted;
+    }
+    if let Some(width) = cli.wrap
+        && let Cow::Owned(wrapped) = soft_wrap(&content, width)
+    {
+        content = wrapped;
+    }
+    timings::stop(timings, Phase::Decode, started, 1);
+    Ok(content)
+}
+
+fn emit_media_note(cli: &Cli, out: &mut String, f: &FileInfo, label: &str, mime: &str) {
+    // A binary's MIME type says nothing; its extension (`tar.gz`) says more
+    let kind = match compound_extension(Path::new(&f.rel)) {
+        Some(ext) if mime == "application/octet-stream" => format!("{ext}, {mime}"),
+        _ => mime.to_string(),
+    };
+    out.push_str(&cli.output_newline.apply(&format!(
+        "[media: {label} ({}, {kind}) — content omitted]\n\n",
+        cli.size(f.size)
+    )));
+}
+
+/// `--content-hash-header` stand-in for a file whose content was emitted
+/// under another label.
+fn emit_duplicate_note(cli: &Cli, out: &mut String, f: &FileInfo, label: &str) {
+    let Some(original) = &f.duplicate_of else {
+        return;
+    };
+    out.push_str(&cli.output_newline.apply(&format!(
+        "{}\n[duplicate: {label} — identical to [{}] ({}) — content omitted]\n\n",
+        render_metadata_comment(f),
+        display_path(cli, original),
+        cli.size(f.size)
+    )));
+}
+
+/// Position of a file in the output for `--number-files`, shown as `[ 3/58]`
+/// (padded so labels align).
+#[derive(Debug, Clone, Copy)]
+struct Numbering {
+    index: usize,
+    total: usize,
+}
+
+impl std::fmt::Display for Numbering {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self.total.to_string().len();
+        write!(f, "[{:>width$}/{}]", self.index, self.total)
+    }
+}
+
+/// `--number-files` numbering of each file: consecutive over the files that
+/// get a section (a fence or a note), `None` for the rest or when off.
+fn numberings(
+    cli: &Cli,
+    files: &[FileInfo],
+    contents: &[Option<String>],
+) -> Vec<Option<Numbering>> {
+    let has_section = |(f, content): (&FileInfo, &Option<String>)| {
+        f.media.is_some() || f.duplicate_of.is_some() || content.is_some()
+    };
+    let total = files
+        .iter()
+        .zip(contents)
+        .filter(|&p| has_section(p))
+        .count();
+    let mut index = 0;
+    files
+        .iter()
+        .zip(contents)
+        .map(|p| {
+            (cli.number_files && has_section(p)).then(|| {
+                index += 1;
+                Numbering { index, total }
+            })
+        })
+        .collect()
+}
+
+/// Whether `f` is an empty file listed without a fence: one found by
+/// walking a directory, unless `--include-empty`. Files named on the command
+/// line, or matched by a glob there, keep theirs, whatever PATH they are.
+fn skips_empty(cli: &Cli, named: &HashSet<PathBuf>, f: &FileInfo) -> bool {
+    f.size == 0 && !cli.include_empty && !named.contains(&f.path)
+}
+
+/// `--show-exec` suffix for executable files, understood by `parse_dump`.
+fn exec_marker(cli: &Cli, f: &FileInfo) -> &'static str {
+    if cli.show_exec && f.executable {
+        EXEC_MARKER
+    } else {
+        ""
+    }
+}
+
+/// The label of a file in fences, notes and the dir listing.
+fn file_label(cli: &Cli, rel: &str, number: Option<Numbering>) -> String {
+    let path = display_path(cli, rel);
+    match number {
+        Some(n) => format!("{n} {path}"),
+        None => path.into_owned(),
+    }
+}
+
+fn emit_fence(cli: &Cli, out: &mut String, f: &FileInfo, label: &str, content: &str) {
+    let prefer = if !cli.no_smart_md_fence && f.lang.as_deref() == Some("markdown") {
+        '~'
+    } else {
+        '`'
+    };
+    let fence = choose_fence(content, prefer).to_string();
+    let nl = cli.output_newline;
+
+    let mut head = String::new();
+    if let Some(anchor) = &f.anchor {
+        head.push_str(&format!("<a id=\"{anchor}\"></a>\n"));
+    }
+    if cli.fence_metadata_header || cli.preserve_permissions || f.content_id.is_some() {
+        head.push_str(&render_metadata_comment(f));
+        head.push('\n');
+    }
+    head.push_str(&fence);
+    if let Some(lang) = &f.lang {
+        head.push_str(lang);
+        head.push(' ');
+        if lang == "rust"
+            && let Some(edition) = &cli.rust_edition
+        {
+            head.push_str(&format!("edition={edition} "));
+        }
+    }
+    head.push_str(label);
+    head.push('\n');
+    out.push_str(&nl.apply(&head));
+
+    out.push_str(&fence_body(content, cli.eof_newline, nl.as_str()));
+    out.push_str(&nl.apply(&format!("{fence}\n\n")));
+}
+
+/// State shared by the renderers, loaded once before any file is emitted.
+#[derive(Default)]
+struct RenderCtx {
+    script: Option<TransformScript>,
+    /// Previous content by label, from `--diff-against-stdin`.
+    baseline: Option<HashMap<String, String>>,
+    /// Values from `--env-file`, replaced by `[REDACTED]` in every file.
+    redactions: Option<Regex>,
+}
+
+impl RenderCtx {
+    fn from_cli(cli: &Cli) -> Result<Self, Error> {
+        let script = cli
+            .transform_script
+            .as_deref()
+            .map(TransformScript::load)
+            .transpose()
+            .map_err(Error::Usage)?;
+        let baseline = if cli.diff_against_stdin {
+            let mut text = String::new();
+            io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| Error::Runtime(format!("cannot read previous dump on stdin: {e}")))?;
+            let sections = merge_continuations(parse_dump(&text));
+            if sections.is_empty() {
+                eprintln!(">> warning: no file sections found in the dump on stdin");
+            }
+            Some(sections.into_iter().map(|s| (s.path, s.content)).collect())
+        } else {
+            None
+        };
+        let redactions = match &cli.env_file {
+            Some(given) => load_redactions(cli, given.as_deref())?,
+            None => None,
+        };
+        Ok(Self {
+            script,
+            baseline,
+            redactions,
+        })
+    }
+}
+
+/// `--env-file`: one regex for the values in the named `.env` file, or in
+/// the scanned directory's when none is named (a missing one only warns).
+fn load_redactions(cli: &Cli, given: Option<&Path>) -> Result<Option<Regex>, Error> {
+    let path = given.map_or_else(|| cli.label_base().join(".env"), Path::to_path_buf);
+    if given.is_none() && !path.is_file() {
+        eprintln!(
+            ">> warning: --env-file: no .env file in {}",
+            cli.label_base().display()
+        );
+        return Ok(None);
+    }
+    let patterns = load_env_redaction_patterns(&path, MIN_SECRET_LEN)
+        .map_err(|e| Error::Usage(format!("failed to read --env-file {}: {e}", path.display())))?;
+    if cli.verbose {
+        eprintln!(
+            ">> redacting {} value(s) from {}",
+            cli.count(patterns.len()),
+            path.display()
+        );
+    }
+    compile_redactions(&patterns).map_err(|e| Error::Usage(format!("--env-file: {e}")))
+}
+
+/// Emit one file under `--diff-against-stdin`; unchanged files emit nothing.
+fn emit_changed(
+    cli: &Cli,
+    out: &mut String,
+    f: &FileInfo,
+    label: &str,
+    old: Option<&str>,
+    content: &str,
+) {
+    // The emitter adds a final newline, so a dump cannot tell whether one was there
+    match old {
+        Some(old) if old.trim_end_matches('\n') == content.trim_end_matches('\n') => {}
+        Some(old) if cli.diff_style == DiffStyle::Unified => {
+            // Headers name the file as its fences do, without numbering
+            let diff = unified_diff(&display_path(cli, &f.rel), old, content);
+            let fence = choose_fence(&diff, '`');
+            let nl = cli.output_newline;
+            out.push_str(&nl.apply(&format!("{fence}diff {label}\n")));
+            out.push_str(&diff);
+            out.push_str(&nl.apply(&format!("\n{fence}\n\n")));
+        }
+        _ => emit_fence(cli, out, f, label, content),
+    }
+}
+
+/// Give each file its `--fence-anchor` id, unique across the output.
+fn assign_anchors(cli: &Cli, files: &mut [FileInfo]) {
+    if !cli.fence_anchor {
+        return;
+    }
+    let ids = anchor_ids(files.iter().map(|f| f.rel.as_str()));
+    for (f, id) in files.iter_mut().zip(ids) {
+        f.anchor = Some(id);
+    }
+}
+
+/// Load every collected file's content, dropping the unreadable ones from
+/// `collected` (or failing on the first under `--on-error fail`). The result
+/// is parallel to `collected.files`; `None` marks a media placeholder.
+fn load_all(
+    cli: &Cli,
+    ctx: &RenderCtx,
+    collected: &mut Collected,
+) -> Result<Vec<Option<String>>, Error> {
+    let Collected {
+        files,
+        errors,
+        timings,
+        vanished,
+        named,
+        ..
+    } = collected;
+    let timed = timings.is_some();
+    let typescript = if cli.typescript_strip_types {
+        span(timings, Phase::Decode, 0, || {
+            precompile_typescript(cli, files)
+        })
+    } else {
+        Vec::new()
+    };
+    // Each file counts its own errors and time, merged below in file order
+    let load = |(f, typescript): (&mut FileInfo, Option<&Precompiled>)| {
+        // Empty files are listed but get no fence
+        if f.media.is_some() || skips_empty(cli, named, f) {
+            return None;
+        }
+        let mut file_errors = 0;
+        let mut file_timings = timed.then(Timings::default);
+        let content = load_content(cli, ctx, f, typescript, &mut file_errors, &mut file_timings);
+        Some((content, file_errors, file_timings))
+    };
+    let precompiled = |i: usize| typescript.get(i).and_then(Option::as_ref);
+    let loaded: Vec<_> = if cli.jobs == 1 {
+        // Read nothing past the first failure under --on-error fail
+        let mut failed = false;
+        files
+            .iter_mut()
+            .enumerate()
+            .map(|(i, f)| {
+                if failed {
+                    return None;
+                }
+                let loaded = load((f, precompiled(i)));
+                failed = cli.on_error == OnError::Fail && matches!(loaded, Some((Err(_), ..)));
+                loaded
+            })
+            .collect()
+    } else {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(cli.jobs)
+            .build()
+            .map_err(|e| Error::Runtime(format!("failed to start --jobs threads: {e}")))?;
+        let started = timings::start(timings.as_ref());
+        let loaded: Vec<_> = pool.install(|| {
+            files
+                .par_iter_mut()
+                .enumerate()
+                .map(|(i, f)| load((f, precompiled(i))))
+                .collect()
+        });
+        // The per-file spans add up the threads; note the wall time beside them
+        if let (Some(t), Some(started)) = (timings.as_mut(), started) {
+            let summed = loaded
+                .iter()
+                .flatten()
+                .filter_map(|(_, _, file_timings)| file_timings.as_ref())
+                .map(Timings::total)
+                .sum();
+            t.ran_in_parallel(pool.current_num_threads(), started.elapsed(), summed);
+        }
+        loaded
+    };
+
+    let mut contents = Vec::with_capacity(files.len());
+    let mut failed = None;
+    let mut loaded = loaded.into_iter();
+    files.retain_mut(|f| {
+        let Some((content, file_errors, file_timings)) = loaded.next().flatten() else {
+            contents.push(None);
+            return true;
+        };
+        *errors += file_errors;
+        if let (Some(t), Some(file_timings)) = (timings.as_mut(), &file_timings) {
+            t.merge(file_timings);
+        }
+        if failed.is_some() {
+            contents.push(None);
+            return true;
+        }
+        match content {
+            Ok(content) => {
+                contents.push(Some(content));
+                true
+            }
+            // Deleted or locked since the walk: drop it from the listing too
+            Err(e) => {
+                if cli.on_error == OnError::Fail && failed.is_none() {
+                    failed = Some(Error::Runtime(format!("{}: {e} (--on-error fail)", f.rel)));
+                }
+                vanished.push((f.rel.clone(), e.to_string()));
+                *errors += 1;
+                false
+            }
+        }
+    });
+    failed.map_or(Ok(contents), Err)
+}
+
+/// `--content-hash-header`: give files with identical content a shared
+/// content id, and drop the content of all but the first.
+fn mark_duplicates(collected: &mut Collected, contents: &mut [Option<String>]) {
+    let mut first: HashMap<String, usize> = HashMap::new();
+    let mut ids = Vec::with_capacity(contents.len());
+    for (i, content) in contents.iter().enumerate() {
+        let id = content
+            .as_deref()
+            .filter(|c| !c.is_empty())
+            .map(|c| format!("{:x}", Sha256::digest(c.as_bytes()))[..12].to_string());
+        if let Some(id) = &id {
+            first.entry(id.clone()).or_insert(i);
+        }
+        ids.push(id);
+    }
+    let mut shared: HashSet<&str> = HashSet::new();
+    for (i, id) in ids.iter().enumerate() {
+        if let Some(id) = id
+            && first[id] != i
+        {
+            shared.insert(id);
+        }
+    }
+    for (i, id) in ids.iter().enumerate() {
+        let Some(id) = id.as_deref().filter(|id| shared.contains(id)) else {
+            continue;
+        };
+        let original = first[id];
+        if original != i {
+            collected.files[i].duplicate_of = Some(collected.files[original].rel.clone());
+            contents[i] = None;
+        }
+        collected.files[i].content_id = Some(id.to_string());
+    }
+}
+
+/// `--compress-repetitive`: collapse headers that many files share.
+fn compress_repetitive(cli: &Cli, collected: &Collected, contents: &mut [Option<String>]) {
+    let mut detector = RepetitionDetector::new(cli.repeat_header_lines, cli.repeat_threshold);
+    for (f, content) in collected.files.iter().zip(contents.iter()) {
+        if let Some(content) = content {
+            detector.observe(&f.rel, content);
+        }
+    }
+    for (f, content) in collected.files.iter().zip(contents.iter_mut()) {
+        if let Some(c) = content
+            && let Cow::Owned(compressed) = detector.compress(&f.rel, c, f.lang.as_deref())
+        {
+            *c = compressed;
+        }
+    }
+}
+
+/// The Markdown emitted for one file: a media note, its fence or, under
+/// `--diff-against-stdin`, its change (empty when unchanged).
+fn file_section(
+    cli: &Cli,
+    ctx: &RenderCtx,
+    f: &FileInfo,
+    number: Option<Numbering>,
+    content: Option<&str>,
+) -> String {
+    let label = file_label(cli, &f.rel, number) + exec_marker(cli, f);
+    let mut out = String::new();
+    match (f.media, content) {
+        (Some(mime), _) => emit_media_note(cli, &mut out, f, &label, mime),
+        (None, None) if f.duplicate_of.is_some() => emit_duplicate_note(cli, &mut out, f, &label),
+        (None, Some(content)) => match &ctx.baseline {
+            Some(baseline) => {
+                let old = baseline.get(&f.rel).map(String::as_str);
+                emit_changed(cli, &mut out, f, &label, old, content);
+            }
+            None => emit_fence(cli, &mut out, f, &label, content),
+        },
+        (None, None) => {}
+    }
+    out
+}
+
+/// Whether [`file_section`] emits a fence for `f`, and with it the
+/// `--fence-anchor` that links point to: not for media, duplicate or
+/// unloaded (empty) files, nor for unchanged files and unified diffs under
+/// `--diff-against-stdin`.
+fn has_fence(cli: &Cli, ctx: &RenderCtx, f: &FileInfo, content: Option<&str>) -> bool {
+    let (None, Some(content)) = (f.media, content) else {
+        return false;
+    };
+    match &ctx.baseline {
+        None => true,
+        Some(baseline) => match baseline.get(&f.rel) {
+            Some(old) => {
+                cli.diff_style != DiffStyle::Unified
+                    && old.trim_end_matches('\n') != content.trim_end_matches('\n')
+            }
+            None => true,
+        },
+    }
+}
+
+/// Estimated token cost of each file's Markdown section. `--max-tokens` and
+/// `--budget-report` both budget with these numbers.
+fn section_costs(
+    cli: &Cli,
+    ctx: &RenderCtx,
+    collected: &mut Collected,
+    contents: &[Option<String>],
+) -> Vec<usize> {
+    let Collected { files, timings, .. } = collected;
+    span(timings, Phase::Tokenize, files.len(), || {
+        file_costs(cli, ctx, files, contents)
+    })
+}
+
+fn file_costs(
+    cli: &Cli,
+    ctx: &RenderCtx,
+    files: &[FileInfo],
+    contents: &[Option<String>],
+) -> Vec<usize> {
+    let tokenizer = cli.count_tokens.unwrap_or(Tokenizer::Cl100k);
+    // Numbered against the pre-budget total, which is never narrower
+    files
+        .iter()
+        .zip(contents)
+        .zip(numberings(cli, files, contents))
+        .map(|((f, c), number)| {
+            let section = file_section(cli, ctx, f, number, c.as_deref());
+            estimate_tokens(&section, tokenizer)
+        })
+        .collect()
+}
+
+/// [`plan_budget`] with each `--content-hash-header` original and its
+/// duplicate notes budgeted as one unit at the original's position, so a
+/// note is never kept for content that was dropped.
+fn plan_files(
+    files: &[FileInfo],
+    costs: &[usize],
+    budget: usize,
+    policy: BudgetPolicy,
+) -> Vec<bool> {
+    let index: HashMap<&str, usize> = files
+        .iter()
+        .enumerate()
+        .map(|(i, f)| (f.rel.as_str(), i))
+        .collect();
+    let original = |f: &FileInfo| {
+        f.duplicate_of
+            .as_deref()
+            .and_then(|rel| index.get(rel).copied())
+    };
+    let mut unit_costs = costs.to_vec();
+    for (i, f) in files.iter().enumerate() {
+        if let Some(o) = original(f) {
+            unit_costs[o] += unit_costs[i];
+            unit_costs[i] = 0;
+        }
+    }
+    let mut keep = plan_budget(&unit_costs, budget, policy);
+    for (i, f) in files.iter().enumerate() {
+        if let Some(o) = original(f) {
+            keep[i] = keep[o];
+        }
+    }
+    keep
+}
+
+/// Drop the files `--max-tokens` has no room for.
+fn apply_max_tokens(
+    cli: &Cli,
+    ctx: &RenderCtx,
+    collected: &mut Collected,
+    contents: &mut Vec<Option<String>>,
+) {
+    let Some(budget) = cli.max_tokens else {
+        return;
+    };
+    let costs = section_costs(cli, ctx, collected, contents);
+    let keep = plan_files(&collected.files, &costs, budget, cli.budget_policy);
+    let dropped = keep.iter().filter(|k| !**k).count();
+    if dropped > 0 && !cli.quiet {
+        eprintln!(
+            ">> --max-tokens {}: dropped {} file(s) ({} policy)",
+            cli.count(budget),
+            cli.count(dropped),
+            cli.budget_policy.name()
+        );
+    }
+    (collected.files, *contents) = std::mem::take(&mut collected.files)
+        .into_iter()
+        .zip(std::mem::take(contents))
+        .zip(keep)
+        .filter_map(|(pair, keep)| keep.then_some(pair))
+        .unzip();
+}
+
+/// `--budget-report`: the packing plan for `budget` tokens instead of content.
+fn render_budget_report(
+    cli: &Cli,
+    collected: &Collected,
+    costs: &[usize],
+    budget: usize,
+) -> String {
+    let files = &collected.files;
+    let policies = [BudgetPolicy::Stop, BudgetPolicy::Skip];
+    let plans = policies.map(|p| plan_files(files, costs, budget, p));
+    let dropped = |plan: &[bool]| -> Vec<&str> {
+        files
+            .iter()
+            .zip(plan)
+            .filter(|(_, keep)| !**keep)
+            .map(|(f, _)| f.rel.as_str())
+            .collect()
+    };
+    let tokenizer = cli.count_tokens.unwrap_or(Tokenizer::Cl100k);
+
+    if cli.format == OutputFormat::Json {
+        let mut cumulative = 0;
+        let rows: Vec<_> = files
+            .iter()
+            .zip(costs)
+            .zip(&plans[0])
+            .map(|((f, &tokens), &fits)| {
+                cumulative += tokens;
+                serde_json::json!({
+                    "path": f.rel,
+                    "tokens": tokens,
+                    "cumulative": cumulative,
+                    "fits": fits,
+                })
+            })
+            .collect();
+        let mut by_policy = serde_json::Map::new();
+        for (policy, plan) in policies.iter().zip(&plans) {
+            by_policy.insert(policy.name().to_string(), dropped(plan).into());
+        }
+        let report = serde_json::json!({
+            "budget": budget,
+            "tokenizer": tokenizer.name(),
+            "files": rows,
+            "dropped": by_policy,
+        });
+        return serde_json::to_string_pretty(&report).unwrap_or_default() + "\n";
+    }
+
+    let mut out = format!(
+        "budget: {} tokens ({} estimate)\n\n",
+        cli.count(budget),
+        tokenizer.name()
+    );
+    out.push_str(&format!("{:>10} {:>12}  file\n", "tokens", "cumulative"));
+    // The cut follows the stop plan, which counts duplicate notes with their
+    // originals, rather than the running sum
+    let mut cumulative = 0;
+    let mut cut = false;
+    for ((f, &tokens), &fits) in files.iter().zip(costs).zip(&plans[0]) {
+        cumulative += tokens;
+        if !cut && !fits {
+            out.push_str(&format!("{:-^44}\n", " budget exhausted "));
+            cut = true;
+        }
+        out.push_str(&format!(
+            "{:>10} {:>12}  {}\n",
+            cli.count(tokens),
+            cli.count(cumulative),
+            f.rel
+        ));
+    }
+    for (policy, plan) in policies.iter().zip(&plans) {
+        let dropped = dropped(plan);
+        out.push_str(&format!(
+            "\n--budget-policy {}: {} file(s) dropped\n",
+            policy.name(),
+            cli.count(dropped.len())
+        ));
+        for rel in dropped {
+            out.push_str(&format!("  {rel}\n"));
+        }
+    }
+    out
+}
+
+/// The summary and listings requested ahead of the fences (`--repo-summary`,
+/// `--section-toc`, `--dir-list`, `--with-tree`, `--dir-list-excluded`), with
+/// `--output-newline` applied.
+fn render_header(
+    cli: &Cli,
+    ctx: &RenderCtx,
+    collected: &Collected,
+    contents: &[Option<String>],
+) -> String {
+    let mut header = String::new();
+    if cli.repo_summary {
+        // Only what is emitted with its content: no notes, nor unfenced empty files
+        let emitted: Vec<&FileInfo> = collected
+            .files
+            .iter()
+            .zip(contents)
+            .filter(|(f, content)| f.media.is_none() && content.is_some())
+            .map(|(f, _)| f)
+            .collect();
+        header.push_str(&generate_repo_summary(
+            &cli.label_base(),
+            &emitted,
+            cli.bytes,
+        ));
+    }
+    if cli.section_toc {
+        // Only fences carry an anchor to link to
+        header.push_str(&emit_toc(
+            collected
+                .files
+                .iter()
+                .zip(contents)
+                .filter(|(f, content)| has_fence(cli, ctx, f, content.as_deref()))
+                .filter_map(|(f, _)| Some((display_path(cli, &f.rel), f.anchor.as_deref()?))),
+        ));
+    }
+    if cli.dir_list {
+        header.push_str(&emit_dir_listing(cli, ctx, collected, contents));
+    }
+    if cli.with_tree {
+        header.push_str(&emit_tree(collected));
+    }
+    if cli.dir_list_excluded {
+        header.push_str(&emit_excluded_listing(cli, collected));
+    }
+    cli.output_newline.apply(&header).into_owned()
+}
+
+fn render_markdown(
+    cli: &Cli,
+    ctx: &RenderCtx,
+    collected: &Collected,
+    contents: &[Option<String>],
+) -> String {
+    let nl = cli.output_newline;
+    let mut out = render_header(cli, ctx, collected, contents);
+
+    let numbers = numberings(cli, &collected.files, contents);
+    for (i, (f, content)) in collected.files.iter().zip(contents).enumerate() {
+        if let Some(heading) = group_heading(cli, &collected.files, i, false) {
+            out.push_str(&nl.apply(&heading));
+        }
+        let number = numbers[i];
+        out.push_str(&file_section(cli, ctx, f, number, content.as_deref()));
+    }
+
+    if let Some(baseline) = &ctx.baseline {
+        let mut removed: Vec<&str> = baseline
+            .keys()
+            .filter(|path| !collected.files.iter().any(|f| &f.rel == *path))
+            .map(String::as_str)
+            .collect();
+        removed.sort_unstable();
+        for path in removed {
+            out.push_str(&nl.apply(&format!("[removed: {path}]\n\n")));
+        }
+        if out.is_empty() && !cli.quiet {
+            eprintln!(">> no changes since the previous dump");
+        }
+    }
+    out
+}
+
+/// `--chunk-by-tokens`: group whole file sections into parts of at most
+/// `limit` estimated tokens, each opened by a `<!-- fencecat: part K/N -->`
+/// marker. A file larger than `limit` gets a part of its own.
+fn render_chunks(
+    cli: &Cli,
+    ctx: &RenderCtx,
+    collected: &Collected,
+    contents: &[Option<String>],
+    costs: &[usize],
+    limit: usize,
+) -> Vec<String> {
+    let tokenizer = cli.count_tokens.unwrap_or(Tokenizer::Cl100k);
+    let files = &collected.files;
+    let nl = cli.output_newline;
+    let header = render_header(cli, ctx, collected, contents);
+    let reserve = estimate_tokens("<!-- fencecat: part 999/999 -->\n\n", tokenizer);
+
+    let numbers = numberings(cli, files, contents);
+    // Sections in output order: whole files and --fence-continuation pieces
+    let mut units: Vec<(usize, Option<Piece<'_>>, usize)> = Vec::new();
+    for (i, &cost) in costs.iter().enumerate() {
+        let number = numbers[i];
+        let pieces = match &contents[i] {
+            Some(content)
+                if cli.fence_continuation
+                    && reserve + cost > limit
+                    && files[i].media.is_none()
+                    && ctx.baseline.is_none() =>
+            {
+                let overhead = Piece {
+                    index: 999,
+                    count: 999,
+                    content: "",
+                };
+                let overhead = estimate_tokens(
+                    &continuation_section(cli, &files[i], number, &overhead),
+                    tokenizer,
+                );
+                split_by_tokens(content, limit.saturating_sub(reserve + overhead), tokenizer)
+            }
+            _ => Vec::new(),
+        };
+        if pieces.len() < 2 {
+            units.push((i, None, cost));
+            continue;
+        }
+        let count = pieces.len();
+        for (k, content) in pieces.into_iter().enumerate() {
+            let piece = Piece {
+                index: k + 1,
+                count,
+                content,
+            };
+            let cost = estimate_tokens(
+                &continuation_section(cli, &files[i], number, &piece),
+                tokenizer,
+            );
+            units.push((i, Some(piece), cost));
+        }
+    }
+
+    // Indices into `units` of the sections in each part
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut used = estimate_tokens(&header, tokenizer) + reserve;
+    let mut current = Vec::new();
+    for (u, &(i, _, cost)) in units.iter().enumerate() {
+        if !current.is_empty() && used + cost > limit {
+            groups.push(std::mem::take(&mut current));
+            used = reserve;
+        }
+        if reserve + cost > limit {
+            eprintln!(
+                ">> warning: {} needs a part of ~{} tokens, over --chunk-by-tokens {}",
+                files[i].rel,
+                cli.count(reserve + cost),
+                cli.count(limit)
+            );
+        }
+        used += cost;
+        current.push(u);
+    }
+    if !current.is_empty() || groups.is_empty() {
+        groups.push(current);
+    }
+
+    let n = groups.len();
+    groups
+        .iter()
+        .enumerate()
+        .map(|(k, group)| {
+            let mut part = nl
+                .apply(&format!("<!-- fencecat: part {}/{n} -->\n\n", k + 1))
+                .into_owned();
+            if k == 0 {
+                part.push_str(&header);
+            }
+            for (j, &u) in group.iter().enumerate() {
+                let (i, piece, _) = &units[u];
+                let i = *i;
+                let first_piece = piece.as_ref().is_none_or(|p| p.index == 1);
+                if (first_piece || j == 0)
+                    && let Some(heading) = group_heading(cli, files, i, j == 0)
+                {
+                    part.push_str(&nl.apply(&heading));
+                }
+                let number = numbers[i];
+                part.push_str(&match piece {
+                    Some(piece) => continuation_section(cli, &files[i], number, piece),
+                    None => file_section(cli, ctx, &files[i], number, contents[i].as_deref()),
+                });
+            }
+            part
+        })
+        .collect()
+}
+
+/// Piece `index` of `count` of a file split by `--fence-continuation`.
+struct Piece<'a> {
+    index: usize,
+    count: usize,
+    content: &'a str,
+}
+
+/// A `--fence-continuation` piece: a `<!-- continuation: LABEL K/N -->`
+/// comment, then the piece fenced like the whole file with ` chunk=K/N` at
+/// the end of the info string.
+fn continuation_section(
+    cli: &Cli,
+    f: &FileInfo,
+    number: Option<Numbering>,
+    piece: &Piece<'_>,
+) -> String {
+    let position = format!("{}/{}", piece.index, piece.count);
+    let label = file_label(cli, &f.rel, number) + exec_marker(cli, f);
+    let mut out = cli
+        .output_newline
+        .apply(&format!(
+            "<!-- continuation: {} {position} -->\n",
+            display_path(cli, &f.rel)
+        ))
+        .into_owned();
+    emit_fence(
+        cli,
+        &mut out,
+        f,
+        &format!("{label} chunk={position}"),
+        piece.content,
+    );
+    out
+}
+
+fn render_json(collected: &Collected, contents: &[Option<String>]) -> String {
+    let records: Vec<JsonFile<'_>> = collected
+        .files
+        .iter()
+        .zip(contents)
+        .map(|(f, c)| JsonFile::new(f, c.as_deref()))
+        .collect();
+    json::render(&records)
+}
+
+/// `--audit`: check the collected files as they would be emitted and print
+/// a verdict per file instead of the content.
+fn run_audit(cli: &Cli, collected: &mut Collected) -> Result<ExitCode, Error> {
+    let ctx = RenderCtx::from_cli(cli)?;
+    let contents = load_all(cli, &ctx, collected)?;
+    assign_anchors(cli, &mut collected.files);
+    let costs = section_costs(cli, &ctx, collected, &contents);
+    // Only -H can let in what the ignore rules keep out
+    let respected = (cli.no_ignore && cli.from_archive.is_none() && cli.dir.is_dir())
+        .then(|| ignore_rule_paths(cli));
+    let budget = cli.max_tokens.or(cli.token_budget_warn);
+    let finding = |severity, message| Finding { severity, message };
+
+    let files: Vec<FileAudit> = collected
+        .files
+        .iter()
+        .zip(&contents)
+        .zip(&costs)
+        .map(|((f, content), &tokens)| {
+            let mut findings: Vec<Finding> = content
+                .as_deref()
+                .map(find_secrets)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(line, kind)| finding(Severity::Red, format!("line {line}: possible {kind}")))
+                .collect();
+            if respected
+                .as_ref()
+                .is_some_and(|paths| !paths.contains(&f.path))
+            {
+                findings.push(finding(
+                    Severity::Yellow,
+                    "hidden or gitignored; only -H/--no-ignore includes it".to_string(),
+                ));
+            }
+            if let Some(limit) = cli.warn_large_file
+                && f.size > limit
+            {
+                findings.push(finding(
+                    Severity::Yellow,
+                    format!(
+                        "{} is over --warn-large-file {}",
+                        cli.size(f.size),
+                        cli.size(limit)
+                    ),
+                ));
+            }
+            if let Some(budget) = budget
+                && tokens > budget
+            {
+                findings.push(finding(
+                    Severity::Yellow,
+                    format!(
+                        "~{} tokens on its own, over the budget of {}",
+                        cli.count(tokens),
+                        cli.count(budget)
+                    ),
+                ));
+            }
+            FileAudit {
+                label: display_path(cli, &f.rel).into_owned(),
+                findings,
+            }
+        })
+        .collect();
+    let total: usize = costs.iter().sum();
+    let overall: Vec<Finding> = budget
+        .filter(|&budget| total > budget)
+        .map(|budget| {
+            finding(
+                Severity::Yellow,
+                format!(
+                    "~{} tokens in all, over the budget of {}",
+                    cli.count(total),
+                    cli.count(budget)
+                ),
+            )
+        })
+        .into_iter()
+        .collect();
+
+    let color = cli.color.enabled(io::stdout().is_terminal());
+    print!("{}", render_audit(&files, &overall, color));
+    Ok(match audit::worst(&files, &overall) {
+        Severity::Green => ExitCode::SUCCESS,
+        Severity::Yellow => ExitCode::from(EXIT_AUDIT_YELLOW),
+        Severity::Red => ExitCode::from(EXIT_AUDIT_RED),
+    })
+}
+
+/// Files a walk of PATH with the ignore rules on would visit (`--audit`
+/// under `-H`).
+fn ignore_rule_paths(cli: &Cli) -> HashSet<PathBuf> {
+    let mut wb = WalkBuilder::new(&cli.dir);
+    apply_git_config(&mut wb, &cli.dir);
+    wb.follow_links(cli.symlinks == SymlinkPolicy::Follow);
+    wb.build()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_some_and(|ft| ft.is_file()))
+        .map(ignore::DirEntry::into_path)
+        .collect()
+}
+
+/// Render the output: a single part, or one per chunk under `--chunk-by-tokens`.
+fn render(cli: &Cli, collected: &mut Collected) -> Result<Vec<String>, Error> {
+    let ctx = RenderCtx::from_cli(cli)?;
+    let mut contents = load_all(cli, &ctx, collected)?;
+    assign_anchors(cli, &mut collected.files);
+    if cli.content_hash_header {
+        mark_duplicates(collected, &mut contents);
+    }
+    if cli.compress_repetitive {
+        compress_repetitive(cli, collected, &mut contents);
+    }
+    // Costed after the content changes above, as --max-tokens sees them
+    if let Some(budget) = cli.budget_report {
+        let costs = section_costs(cli, &ctx, collected, &contents);
+        let started = timings::start(collected.timings.as_ref());
+        let report = cli
+            .output_newline
+            .apply(&render_budget_report(cli, collected, &costs, budget))
+            .into_owned();
+        timings::stop(&mut collected.timings, Phase::Format, started, 1);
+        return Ok(vec![report]);
+    }
+    apply_max_tokens(cli, &ctx, collected, &mut contents);
+    let costs = match cli.chunk_by_tokens {
+        Some(_) => section_costs(cli, &ctx, collected, &contents),
+        None => Vec::new(),
+    };
+    let started = timings::start(collected.timings.as_ref());
+    let parts = match (cli.chunk_by_tokens, cli.format) {
+        (Some(limit), _) => render_chunks(cli, &ctx, collected, &contents, &costs, limit),
+        (None, OutputFormat::Md) => vec![render_markdown(cli, &ctx, collected, &contents)],
+        // File content is escaped inside JSON strings, so every break is ours
+        (None, OutputFormat::Json) => vec![
+            cli.output_newline
+                .apply(&render_json(collected, &contents))
+                .into_owned(),
+        ],
+    };
+    timings::stop(
+        &mut collected.timings,
+        Phase::Format,
+        started,
+        collected.files.len(),
+    );
+    Ok(parts)
+}
+
+/// `-o` path of part `k` under `--chunk-by-tokens`: `context.md` →
+/// `context.part2.md`, or the `--output-filename-template` name next to it.
+/// A template name that would leave the directory of `path` is an error.
+fn part_path(cli: &Cli, path: &Path, k: usize) -> Result<PathBuf, String> {
+    let Some(template) = &cli.output_filename_template else {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match path.extension() {
+            Some(ext) => format!("{stem}.part{k}.{}", ext.to_string_lossy()),
+            None => format!("{stem}.part{k}"),
+        };
+        return Ok(path.with_file_name(name));
+    };
+    let date = fencecat::format_utc_timestamp(SystemTime::now());
+    let name = render_part_name(cli, template, path, &date[..10], &k.to_string())?;
+    if name.chars().any(std::path::is_separator) || matches!(name.as_str(), "" | "." | "..") {
+        return Err(format!(
+            "--output-filename-template `{template}` gives `{name}`, which is not a file name"
+        ));
+    }
+    Ok(path.with_file_name(name))
+}
+
+/// `--output-filename-template` rendered for the `-o` file `path`, with the
+/// given `{date}` and `{count}`.
+fn render_part_name(
+    cli: &Cli,
+    template: &str,
+    path: &Path,
+    date: &str,
+    count: &str,
+) -> Result<String, String> {
+    let dir = fs::canonicalize(&cli.dir)
+        .ok()
+        .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "root".to_string());
+    let ext = path.extension().map(|e| e.to_string_lossy());
+    let vars = HashMap::from([
+        ("dir", dir.as_str()),
+        ("date", date),
+        ("count", count),
+        ("ext", ext.as_deref().unwrap_or("")),
+    ]);
+    render_filename_template(template, &vars)
+}
+
+/// `--http-post`: send the output to the endpoint, saving the response body
+/// for `--http-post-response-file`. Transport failures and non-2xx statuses
+/// are runtime errors.
+fn post_output(cli: &Cli, url: &str, out: &str) -> Result<(), Error> {
+    let failed = |e: &dyn std::fmt::Display| Error::Runtime(format!("--http-post {url}: {e}"));
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .timeout_global(Some(Duration::from_secs(HTTP_POST_TIMEOUT_SECS)))
+        .build()
+        .into();
+    let content_type = match cli.format {
+        OutputFormat::Md => "text/markdown; charset=utf-8",
+        OutputFormat::Json => "application/json",
+    };
+    let mut request = agent.post(url).header("Content-Type", content_type);
+    for (key, value) in &cli.http_header {
+        request = request.header(key, value);
+    }
+    let mut response = request.send(out).map_err(|e| failed(&e))?;
+    let status = response.status();
+    let body = response
+        .body_mut()
+        .with_config()
+        .limit(HTTP_RESPONSE_LIMIT)
+        .read_to_vec()
+        .map_err(|e| failed(&e))?;
+    if let Some(path) = &cli.http_post_response_file {
+        fs::write(path, &body)
+            .map_err(|e| Error::Runtime(format!("failed to write {}: {e}", path.display())))?;
+    }
+    if !status.is_success() {
+        let body = String::from_utf8_lossy(&body);
+        let mut msg = format!("server answered {status}");
+        if let Some(first) = body.lines().find(|l| !l.trim().is_empty()) {
+            msg.push_str(": ");
+            msg.push_str(first.trim());
+        }
+        return Err(failed(&msg));
+    }
+    if !cli.quiet {
+        eprintln!(
+            ">> posted {} to {url} ({status})",
+            cli.size(out.len() as u64)
+        );
+    }
+    Ok(())
+}
+
+/// The bytes of an `--output` file: `text` with every line ending turned
+/// into CRLF under `--output-crlf`, after a BOM under `--output-bom`.
+fn encode_output_file<'a>(cli: &Cli, text: &'a str) -> Cow<'a, str> {
+    let mut text = Cow::Borrowed(text);
+    if cli.output_crlf {
+        text = Cow::Owned(text.replace("\r\n", "\n").replace('\n', "\r\n"));
+    }
+    if cli.output_bom {
+        text = Cow::Owned(format!("\u{feff}{text}"));
+    }
+    text
+}
+
+/// Send the rendered output to its sinks: `-o` file (or stdout) and, with
+/// `--copy`, the clipboard. Both receive the very same bytes; nothing is
+/// re-rendered per sink. With several parts (`--chunk-by-tokens`), `-o`
+/// writes one numbered file per part. `confirm` asks the
+/// `--clipboard-confirm` question and returns the answer.
+fn deliver(
+    cli: &Cli,
+    parts: &[String],
+    copy: impl FnOnce(&str) -> Result<(), String>,
+    confirm: impl FnOnce(&str) -> bool,
+    timings: &mut Option<Timings>,
+) -> io::Result<()> {
+    let out = &parts.concat();
+    let write = |path: &Path, text: &str| {
+        fs::write(path, encode_output_file(cli, text).as_ref())
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))
+    };
+    let started = timings::start(timings.as_ref());
+    match &cli.output {
+        Some(path) if parts.len() > 1 => {
+            let paths = (1..=parts.len())
+                .map(|k| part_path(cli, path, k))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(io::Error::other)?;
+            if paths.iter().collect::<HashSet<_>>().len() < paths.len() {
+                return Err(io::Error::other(
+                    "--output-filename-template gives several parts the same name; add {count}",
+                ));
+            }
+            for (part_path, part) in paths.iter().zip(parts) {
+                write(part_path, part)?;
+            }
+            if !cli.quiet {
+                eprintln!(">> wrote {} parts next to {}", parts.len(), path.display());
+            }
+        }
+        Some(path) => write(path, out)?,
+        None if cli.no_print => {}
+        None if cli.color_diff && cli.color.enabled(io::stdout().is_terminal()) => {
+            print!("{}", colorize_diff_fences(out));
+        }
+        None => print!("{out}"),
+    }
+    timings::stop(timings, Phase::Write, started, parts.len());
+
+    let Some(sink) = ClipboardSink::new(cli.copy, cli.save_clip.clone()) else {
+        return Ok(());
+    };
+    let started = timings::start(timings.as_ref());
+    if let Some(path) = sink.file() {
+        write_atomic(path, out)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+        if !cli.quiet {
+            eprintln!(">> saved clipboard copy to {}", path.display());
+        }
+    }
+    if !sink.copies() {
+        timings::stop(timings, Phase::Clipboard, started, 1);
+        return Ok(());
+    }
+    // A skipped copy is no failure: the output itself was delivered above
+    if !cli.force_clipboard && out.len() as u64 > cli.clipboard_size_limit {
+        eprintln!(
+            ">> clipboard write refused: output is {}, limit is {} (use --clipboard-size-limit to adjust)",
+            cli.size(out.len() as u64),
+            cli.size(cli.clipboard_size_limit)
+        );
+    } else if cli.clipboard_confirm
+        && out.len() as u64 > cli.clipboard_confirm_threshold
+        && (cli.force_interactive || io::stdin().is_terminal())
+        && !confirm(&format!(
+            "About to copy {} to clipboard. Continue? [y/N]",
+            cli.size(out.len() as u64)
+        ))
+    {
+        if !cli.quiet {
+            eprintln!(">> clipboard left unchanged");
+        }
+    } else {
+        match copy(out) {
+            Ok(()) if cli.quiet => {}
+            Ok(()) => eprintln!(">> copied to clipboard"),
+            Err(e) => eprintln!(">> failed to copy to clipboard: {e}"),
+        }
+    }
+    timings::stop(timings, Phase::Clipboard, started, 1);
+    Ok(())
+}
+
+/// Failures that end the run, each with a documented exit code.
+#[derive(Debug)]
+enum Error {
+    /// Bad arguments, paths or patterns (exit 2).
+    Usage(String),
+    /// I/O and other runtime failures (exit 1).
+    Runtime(String),
+}
+
+impl Error {
+    const fn exit_code(&self) -> u8 {
+        match self {
+            Self::Usage(_) => 2,
+            Self::Runtime(_) => 1,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Usage(msg) | Self::Runtime(msg) => f.write_str(msg),
+        }
+    }
+}
+
+/// `--http-post` gives up on a server that takes longer than this.
+const HTTP_POST_TIMEOUT_SECS: u64 = 60;
+
+/// Largest `--http-post` response body read (and saved).
+const HTTP_RESPONSE_LIMIT: u64 = 64 * 1024 * 1024;
+
+/// Exit code when no file matched the filters.
+const EXIT_NOTHING_MATCHED: u8 = 3;
+
+/// `--audit` exit code for the worst verdict `yellow`.
+const EXIT_AUDIT_YELLOW: u8 = 4;
+
+/// `--audit` exit code for the worst verdict `red`.
+const EXIT_AUDIT_RED: u8 = 5;
+
+/// Fail under `--strict` once any file could not be walked or read.
+fn check_strict(cli: &Cli, collected: &Collected) -> Result<(), Error> {
+    if cli.strict && collected.errors > 0 {
+        let mut msg = format!(
+            "{} file(s) could not be read (--strict)",
+            cli.count(collected.errors)
+        );
+        for (rel, e) in &collected.vanished {
+            msg.push_str(&format!("\n  {rel}: {e}"));
+        }
+        return Err(Error::Runtime(msg));
+    }
+    Ok(())
+}
+
+/// List the files that disappeared between the walk and reading them, once
+/// the output is out (not under `--on-error skip`).
+fn report_vanished(cli: &Cli, collected: &Collected) {
+    if collected.vanished.is_empty() || cli.on_error == OnError::Skip {
+        return;
+    }
+    eprintln!(
+        ">> {} file(s) disappeared or became unreadable during the run:",
+        cli.count(collected.vanished.len())
+    );
+    for (rel, e) in &collected.vanished {
+        eprintln!(">>   {rel}: {e}");
+    }
+}
+
+/// Permission bits of a file; `None` where there are none (Windows).
+fn unix_mode(md: &fs::Metadata) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        Some(md.permissions().mode() & 0o7777)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = md;
+        None
+    }
+}
+
+/// Any execute bit is set; always `false` on Windows.
+fn is_executable(md: &fs::Metadata) -> bool {
+    unix_mode(md).is_some_and(|mode| mode & 0o111 != 0)
+}
+
+/// Permission bits `--extract --preserve-permissions` will restore from a
+/// dump: no setuid/setgid/sticky bits and no group/other write.
+const EXTRACT_MODE_MASK: u32 = 0o755;
+
+/// Apply recorded permission bits; a no-op on Windows.
+fn set_unix_mode(path: &Path, mode: u32) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (path, mode);
+        Ok(())
+    }
+}
+
+/// Add execute bits wherever the file is readable; a no-op on Windows.
+fn set_executable(path: &Path) -> io::Result<()> {
+    match fs::metadata(path).map(|md| unix_mode(&md))? {
+        Some(mode) => set_unix_mode(path, mode | ((mode & 0o444) >> 2)),
+        None => Ok(()),
+    }
+}
+
+/// First existing symlink between `dir` and `path` (inclusive of `path`),
+/// which `--extract` must not write through.
+fn symlink_under(dir: &Path, path: &Path) -> Option<PathBuf> {
+    let rel = path.strip_prefix(dir).ok()?;
+    let mut at = dir.to_path_buf();
+    for part in rel.components() {
+        at.push(part);
+        match fs::symlink_metadata(&at) {
+            Ok(md) if md.file_type().is_symlink() => return Some(at),
+            Ok(_) => {}
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+/// `--extract`: recreate the files of a dump read from stdin under `dir`.
+fn run_extract(cli: &Cli, dir: &Path) -> Result<ExitCode, Error> {
+    let mut text = String::new();
+    io::stdin()
+        .read_to_string(&mut text)
+        .map_err(|e| Error::Runtime(format!("cannot read dump on stdin: {e}")))?;
+    let sections = merge_continuations(parse_dump(&text));
+    if sections.is_empty() {
+        if !cli.quiet {
+            eprintln!(">> no file sections found on stdin");
+        }
+        return Ok(ExitCode::from(EXIT_NOTHING_MATCHED));
+    }
+
+    let mut written = 0;
+    let mut errors = 0;
+    for section in &sections {
+        let Some(path) = safe_join(dir, &section.path) else {
+            eprintln!("skip {}: path leaves {}", section.path, dir.display());
+            errors += 1;
+            continue;
+        };
+        if let Some(link) = symlink_under(dir, &path) {
+            eprintln!("skip {}: {} is a symlink", section.path, link.display());
+            errors += 1;
+            continue;
+        }
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&path, &section.content))
+            .and_then(|()| match section.mode {
+                Some(mode) if cli.preserve_permissions => {
+                    set_unix_mode(&path, mode & EXTRACT_MODE_MASK)
+                }
+                _ if section.executable => set_executable(&path),
+                _ => Ok(()),
+            });
+        match result {
+            Ok(()) => written += 1,
+            Err(e) => {
+                eprintln!("skip {}: write error: {e}", path.display());
+                errors += 1;
+            }
+        }
+    }
+    if !cli.quiet {
+        eprintln!(
+            ">> extracted {} file(s) into {}",
+            cli.count(written),
+            dir.display()
+        );
+    }
+    if cli.strict && errors > 0 {
+        return Err(Error::Runtime(format!(
+            "{errors} file(s) could not be extracted (--strict)"
+        )));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Warn when `--not-regex` rejected every file the other filters let
+/// through, naming the patterns that did it.
+fn warn_if_denied_everything(cli: &Cli, stats: &FilterStats) {
+    if !stats.rejected_everything() {
+        return;
+    }
+    eprintln!(
+        ">> warning: --not-regex excluded all {} file(s) that passed the other filters",
+        cli.count(stats.candidates)
+    );
+    let patterns = cli.not_regex.as_deref().unwrap_or_default();
+    let mut ranked: Vec<(&str, usize)> = patterns
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (p.as_str(), hits_of(&stats.deny, i)))
+        .filter(|(_, n)| *n > 0)
+        .collect();
+    ranked.sort_by_key(|(_, n)| Reverse(*n));
+    for (pattern, n) in ranked {
+        eprintln!(">>   `{pattern}` matched {}", cli.count(n));
+    }
+}
+
+/// `--filter-stats` table: how many files each `--regex`/`--glob` matched and
+/// each `--not-regex` rejected, flagging patterns that never fired.
+fn render_filter_stats(cli: &Cli, stats: &FilterStats) -> String {
+    let mut rows: Vec<(&str, String, Option<usize>)> = Vec::new();
+    for (i, p) in cli.regex.iter().flatten().enumerate() {
+        rows.push(("--regex", format!("`{p}`"), Some(hits_of(&stats.allow, i))));
+    }
+    for (i, p) in cli.not_regex.iter().flatten().enumerate() {
+        rows.push((
+            "--not-regex",
+            format!("`{p}`"),
+            Some(hits_of(&stats.deny, i)),
+        ));
+    }
+    for (i, p) in cli.glob.iter().enumerate() {
+        let hits = (!p.starts_with('!')).then(|| hits_of(&stats.globs, i));
+        rows.push(("--glob", format!("`{p}`"), hits));
+    }
+    let width = rows
+        .iter()
+        .map(|(_, p, _)| p.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut out = String::from(">> filter stats\n");
+    for (flag, pattern, hits) in rows {
+        let pad = width - pattern.chars().count();
+        let (count, note) = match hits {
+            Some(0) => ("0".to_string(), "  possibly obsolete"),
+            Some(n) => (cli.count(n), ""),
+            None => ("-".to_string(), "  applied during the walk, not counted"),
+        };
+        out.push_str(&format!(
+            "{flag:<11} {pattern}{:pad$} {count:>5}{note}\n",
+            ""
+        ));
+    }
+    out
+}
+
+fn run(mut cli: Cli) -> Result<ExitCode, Error> {
+    cli.resolve_quiet();
+    if cli.verbose && !cli.env_opts.is_empty() {
+        eprintln!(
+            ">> options from {OPTS_ENV}: {}",
+            shell_words::join(&cli.env_opts)
+        );
+    }
+    if cli.list_llm_presets {
+        print_llm_presets();
+        return Ok(ExitCode::SUCCESS);
+    }
+    if cli.output_json_schema {
+        print!("{}", json::SCHEMA);
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(dir) = &cli.extract {
+        return run_extract(&cli, dir);
+    }
+    cli.check_markdown_only()?;
+    cli.apply_llm_preset();
+    cli.expand_home_in_paths();
+    #[cfg(feature = "testing")]
+    let _fixture = cli.materialize_fake_root()?;
+    cli.resolve_label_prefix();
+    cli.fence_anchor |= cli.section_toc;
+    cli.resolve_rust_edition();
+    cli.load_filter_files()?;
+    if let Some(path) = &cli.output {
+        part_path(&cli, path, 1).map_err(Error::Usage)?;
+    }
+
+    let mut collected = collect_any(&cli)?;
+    if collected.errors > 0 && cli.on_error == OnError::Warn && !cli.quiet {
+        eprintln!(
+            ">> skipped {} unreadable path(s) (--on-error)",
+            cli.count(collected.errors)
+        );
+    }
+    collected.warnings.report(&cli);
+    if collected.not_utf8 > 0 && !cli.quiet {
+        eprintln!(
+            ">> skipped {} file(s) that are not valid UTF-8 (--strict-binary)",
+            cli.count(collected.not_utf8)
+        );
+    }
+    if cli.label_from_git_root {
+        match cli.git_root_prefix() {
+            Some(prefix) => collected.prefix_labels(&prefix),
+            None => eprintln!(
+                ">> warning: {} is not in a git repository; labels stay relative to it",
+                cli.dir.display()
+            ),
+        }
+    }
+    if cli.include_root_name
+        && let Some(prefix) = cli.root_name_prefix()
+    {
+        collected.prefix_labels(&prefix);
+    }
+    if cli.sort_by_complexity {
+        sort_by_complexity(&cli, &mut collected);
+    }
+    if cli.explain_json {
+        print!("{}", explain_json(&cli, &collected));
+        if cli.no_content_checks && !cli.quiet {
+            let assumed = collected
+                .files
+                .iter()
+                .filter(|f| is_assumed_text(&cli, f))
+                .count();
+            eprintln!(
+                ">> approximate: {} file(s) included as text without opening them, 0 checked \
+                 (--no-content-checks)",
+                cli.count(assumed)
+            );
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    check_strict(&cli, &collected)?;
+    if let Some(n) = cli.limit_ext {
+        collected.files = limit_by_ext(std::mem::take(&mut collected.files), n);
+    }
+    if let Some(n) = cli.limit_dir {
+        collected.files = limit_by_dir(std::mem::take(&mut collected.files), n);
+    }
+    if cli.select {
+        collected.files = select_files(&cli, std::mem::take(&mut collected.files));
+    }
+    if cli.collapse_single_file_dirs {
+        collapse_paths(&mut collected.files, cli.collapse_min_depth);
+    }
+    warn_if_denied_everything(&cli, &collected.filter_stats);
+    if (cli.filter_stats || cli.verbose)
+        && (cli.regex.is_some() || cli.not_regex.is_some() || !cli.glob.is_empty())
+    {
+        eprint!("{}", render_filter_stats(&cli, &collected.filter_stats));
+    }
+    // Empty files alone produce no section
+    if collected
+        .files
+        .iter()
+        .all(|f| f.media.is_none() && skips_empty(&cli, &collected.named, f))
+    {
+        if !cli.quiet {
+            eprintln!(">> no files matched");
+        }
+        if cli.allow_empty {
+            return Ok(ExitCode::SUCCESS);
+        }
+        return Ok(ExitCode::from(EXIT_NOTHING_MATCHED));
+    }
+
+    if cli.audit {
+        let code = run_audit(&cli, &mut collected)?;
+        check_strict(&cli, &collected)?;
+        return Ok(code);
+    }
+    let parts = render(&cli, &mut collected)?;
+    check_strict(&cli, &collected)?;
+
+    if cli.budget_report.is_none() {
+        report_tokens(&cli, &parts.concat(), &mut collected.timings);
+    }
+
+    deliver(
+        &cli,
+        &parts,
+        |s| {
+            let mime = if cli.clipboard_html {
+                CopyMime::Html
+            } else {
+                cli.copy_mime
+            };
+            fencecat::clipboard::copy_to_clipboard_multi(s, mime)
+        },
+        prompt_confirm,
+        &mut collected.timings,
+    )
+    .map_err(|e| Error::Runtime(format!("failed to write output: {e}")))?;
+    if let Some(url) = &cli.http_post {
+        post_output(&cli, url, &parts.concat())?;
+    }
+    report_vanished(&cli, &collected);
+    if let Some(t) = &collected.timings {
+        eprint!(">> timings\n{}", t.report(cli.bytes));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Parse the command line over `FENCECAT_OPTS` and the `--preset-llm`
+/// default flags, in that order, so later layers override earlier ones.
+fn parse_cli(args: Vec<OsString>, opts: Option<&str>) -> Result<Cli, Error> {
+    let at = args.len().min(1);
+    let given = args.clone();
+    let (mut args, words) = args_with_env_opts(args, opts)?;
+    let mut cli = Cli::parse_from(&args);
+    if let Some(preset) = cli.preset_llm
+        && !preset.default_flags.is_empty()
+    {
+        let at = at + words.len();
+        args.splice(at..at, preset.default_flags.iter().map(OsString::from));
+        cli = Cli::parse_from(&args);
+    }
+    if args.len() > given.len() {
+        replace_given_lists(&mut cli, &given);
+    }
+    cli.env_opts = words;
+    Ok(cli)
+}
+
+/// Declares the flags that collect several values, and `replace_given_lists`
+/// over them.
+macro_rules! list_args {
+    ($($field:ident),* $(,)?) => {
+        /// Ids of the arguments that collect several values.
+        #[cfg(test)]
+        const LIST_ARGS: &[&str] = &[$(stringify!($field)),*];
+
+        /// Make the list flags given on the real command line replace the
+        /// values FENCECAT_OPTS or the preset gave them, as single-value flags
+        /// do, instead of adding to them.
+        fn replace_given_lists(cli: &mut Cli, given: &[OsString]) {
+            let Ok(matches) = Cli::command().ignore_errors(true).try_get_matches_from(given) else {
+                return;
+            };
+            let Ok(mut explicit) = Cli::from_arg_matches(&matches) else {
+                return;
+            };
+            $(
+                if matches.value_source(stringify!($field)) == Some(ValueSource::CommandLine) {
+                    cli.$field = std::mem::take(&mut explicit.$field);
+                }
+            )*
+        }
+    };
+}
+
+list_args!(
+    more_files,
+    extension_order,
+    ext,
+    not_ext,
+    regex,
+    not_regex,
+    prune_regex,
+    glob,
+    regex_file,
+    not_regex_file,
+    ext_file,
+    glob_file,
+    passthrough_binary,
+    lang_map,
+    http_header,
+    force_include,
+);
+
+/// Environment variable holding default options, split like a shell would.
+const OPTS_ENV: &str = "FENCECAT_OPTS";
+
+/// Insert the words of `opts` right after the program name, so the explicit
+/// arguments that follow override them. `--no-env` on the real command line
+/// disables the injection. Returns the new argv and the injected words.
+fn args_with_env_opts(
+    mut args: Vec<OsString>,
+    opts: Option<&str>,
+) -> Result<(Vec<OsString>, Vec<String>), Error> {
+    let no_env = args
+        .iter()
+        .skip(1)
+        .take_while(|a| *a != "--")
+        .any(|a| a == "--no-env");
+    let Some(opts) = opts.filter(|_| !no_env) else {
+        return Ok((args, Vec::new()));
+    };
+    let words =
+        shell_words::split(opts).map_err(|e| Error::Usage(format!("invalid {OPTS_ENV}: {e}")))?;
+    let at = args.len().min(1);
+    args.splice(at..at, words.iter().map(OsString::from));
+    Ok((args, words))
+}
+
+fn main() -> ExitCode {
+    let env_opts = std::env::var(OPTS_ENV).ok();
+    let result = parse_cli(std::env::args_os().collect(), env_opts.as_deref()).and_then(run);
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::HashSet, fs, path::Path};
+
+    use clap::{ArgAction, CommandFactory as _, Parser as _};
+
+    use super::{
+        Cli, LIST_ARGS, OutputFormat, args_with_env_opts, build_ext_filters, check_strict,
+        collect_any, compile_regex_sets, deliver, parse_cli, parse_dump, render,
+        should_bypass_binary_check,
+    };
+
+    #[test]
+    fn ext_negations_join_the_deny_set() {
+        let cli = Cli::parse_from(["fencecat", "--ext", "rs,.TOML,!lock", "--not-ext", "md"]);
+        let (allow, deny) = build_ext_filters(&cli);
+        let mut allow: Vec<_> = allow.expect("allow").into_iter().collect();
+        let mut deny: Vec<_> = deny.expect("deny").into_iter().collect();
+        allow.sort();
+        deny.sort();
+        assert_eq!(allow, ["rs", "toml"]);
+        assert_eq!(deny, ["lock", "md"]);
+
+        let cli = Cli::parse_from(["fencecat", "--ext", "!lock"]);
+        let (allow, deny) = build_ext_filters(&cli);
+        assert!(allow.is_none());
+        assert_eq!(
+            deny.expect("deny").into_iter().collect::<Vec<_>>(),
+            ["lock"]
+        );
+    }
+
+    #[test]
+    fn binary_bypass_matches_plain_and_compound_extensions() {
+        let bypasses = ["svg".to_string(), "pb.txt".into()].into_iter().collect();
+        assert!(should_bypass_binary_check(Path::new("logo.SVG"), &bypasses));
+        assert!(should_bypass_binary_check(
+            Path::new("dir/model.pb.txt"),
+            &bypasses
+        ));
+        assert!(!should_bypass_binary_check(
+            Path::new("notes.txt"),
+            &bypasses
+        ));
+        assert!(!should_bypass_binary_check(Path::new("svg"), &bypasses));
+        assert!(!should_bypass_binary_check(
+            Path::new("a.svg"),
+            &HashSet::new()
+        ));
+    }
+
+    #[test]
+    fn env_opts_come_before_explicit_args() {
+        let argv = ["fencecat", "--max-file-size", "1k", "src"].map(Into::into);
+        let (args, words) = args_with_env_opts(
+            argv.to_vec(),
+            Some("--max-file-size 300k --not-regex 'my dir/'"),
+        )
+        .expect("opts");
+        assert_eq!(words, ["--max-file-size", "300k", "--not-regex", "my dir/"]);
+        let cli = Cli::parse_from(args);
+        assert_eq!(cli.max_file_size, Some(1024));
+        assert_eq!(cli.not_regex.as_deref(), Some(&["my dir/".to_string()][..]));
+
+        let argv = ["fencecat", "--no-env"].map(Into::into);
+        let (args, words) = args_with_env_opts(argv.to_vec(), Some("--strict")).expect("opts");
+        assert!(words.is_empty());
+        assert_eq!(args.len(), 2);
+        assert!(args_with_env_opts(Vec::new(), Some("'unterminated")).is_err());
+    }
+
+    #[test]
+    fn preset_flags_override_env_opts_but_not_explicit_flags() {
+        let parse = |argv: &[&str], opts| {
+            let argv = argv.iter().map(Into::into).collect();
+            parse_cli(argv, opts).expect("parse")
+        };
+        let cli = parse(
+            &["fencecat", "--preset-llm", "gpt-4o"],
+            Some("--format json"),
+        );
+        assert_eq!(cli.format, OutputFormat::Md);
+        assert_eq!(cli.env_opts, ["--format", "json"]);
+        let cli = parse(
+            &["fencecat", "--preset-llm", "gpt-4o", "--format", "json"],
+            None,
+        );
+        assert_eq!(cli.format, OutputFormat::Json);
+        let cli = parse(
+            &["fencecat", "--preset-llm", "claude-3.5-sonnet"],
+            Some("--format json"),
+        );
+        assert_eq!(cli.format, OutputFormat::Json);
+    }
+
+    #[test]
+    fn list_flags_on_the_command_line_replace_env_opts() {
+        let parse = |argv: &[&str], opts| {
+            let argv = argv.iter().map(Into::into).collect();
+            parse_cli(argv, opts).expect("parse")
+        };
+        let opts = Some("--regex a --ext rs,md --glob *.rs");
+        let cli = parse(&["fencecat", "--regex", "b", "--ext", "py"], opts);
+        assert_eq!(cli.regex.as_deref(), Some(&["b".to_owned()][..]));
+        assert_eq!(cli.ext.as_deref(), Some(&["py".to_owned()][..]));
+        assert_eq!(cli.glob, ["*.rs"]);
+        let cli = parse(&["fencecat"], opts);
+        assert_eq!(cli.regex.as_deref(), Some(&["a".to_owned()][..]));
+
+        let appended: Vec<_> = Cli::command()
+            .get_arguments()
+            .filter(|a| matches!(a.get_action(), ArgAction::Append))
+            .map(|a| a.get_id().to_string())
+            .collect();
+        assert_eq!(appended, LIST_ARGS);
+    }
+
+    #[test]
+    fn pattern_files_merge_with_inline_flags() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let list = tmp.path().join("deny.txt");
+        fs::write(&list, "# generated code\n\n_gen\\.rs$\n  target/  \n").expect("write");
+        let exts = tmp.path().join("ext.txt");
+        fs::write(&exts, "rs\ntoml,md\n").expect("write");
+
+        let mut cli = Cli::parse_from([
+            "fencecat",
+            "--not-regex",
+            "vendor/",
+            "--not-regex-file",
+            list.to_str().expect("utf-8"),
+            "--ext",
+            "py",
+            "--ext-file",
+            exts.to_str().expect("utf-8"),
+        ]);
+        cli.load_filter_files().expect("load");
+        assert_eq!(
+            cli.not_regex.as_deref(),
+            Some(&["vendor/".to_string(), "_gen\\.rs$".into(), "target/".into()][..])
+        );
+        assert_eq!(
+            cli.ext.as_deref(),
+            Some(&["py".to_string(), "rs".into(), "toml".into(), "md".into()][..])
+        );
+    }
+
+    #[test]
+    fn pattern_file_errors_name_file_and_line() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let list = tmp.path().join("re.txt");
+        fs::write(&list, "ok\n# comment\nbad(\n").expect("write");
+        let mut cli = Cli::parse_from(["fencecat", "--regex-file", list.to_str().expect("utf-8")]);
+        let err = cli
+            .load_filter_files()
+            .expect_err("invalid regex")
+            .to_string();
+        assert!(
+            err.starts_with(&format!("{}:3: invalid regex:", list.display())),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn ignore_case_regex_matches_mixed_case_paths() {
+        let cli = Cli::parse_from([
+            "fencecat",
+            "--regex",
+            "src/main\\.rs",
+            "--ignore-case-regex",
+        ]);
+        let (allow, _) = compile_regex_sets(&cli).expect("compile");
+        assert!(allow.expect("allow set").is_match("SRC/Main.RS"));
+
+        let cli = Cli::parse_from(["fencecat", "--regex", "src/main\\.rs"]);
+        let (allow, _) = compile_regex_sets(&cli).expect("compile");
+        assert!(!allow.expect("allow set").is_match("SRC/Main.RS"));
+    }
+
+    #[test]
+    fn file_and_clipboard_receive_identical_bytes() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let path = tmp.path().join("out.md");
+        let cli = Cli::parse_from(["fencecat", "-c", "-o", path.to_str().expect("utf-8")]);
+
+        let clipboard = RefCell::new(Vec::new());
+        let out = "```rust a.rs\nfn a() {}\n\n```\n\n";
+        deliver(
+            &cli,
+            &[out.to_string()],
+            |s| {
+                clipboard.borrow_mut().extend_from_slice(s.as_bytes());
+                Ok(())
+            },
+            |_| unreachable!("no prompt"),
+            &mut None,
+        )
+        .expect("deliver");
+
+        assert_eq!(fs::read(&path).expect("read"), clipboard.into_inner());
+    }
+
+    #[test]
+    fn bom_and_crlf_only_reach_the_output_file() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let path = tmp.path().join("out.md");
+        let cli = Cli::parse_from([
+            "fencecat",
+            "-c",
+            "-o",
+            path.to_str().expect("utf-8"),
+            "--output-bom",
+            "--output-crlf",
+        ]);
+
+        let clipboard = RefCell::new(String::new());
+        let out = "```rust a.rs\nfn a() {}\r\n\n```\n\n";
+        deliver(
+            &cli,
+            &[out.to_string()],
+            |s| {
+                clipboard.borrow_mut().push_str(s);
+                Ok(())
+            },
+            |_| unreachable!("no prompt"),
+            &mut None,
+        )
+        .expect("deliver");
+
+        let written = fs::read_to_string(&path).expect("read");
+        assert_eq!(
+            written,
+            "\u{feff}```rust a.rs\r\nfn a() {}\r\n\r\n```\r\n\r\n"
+        );
+        assert_eq!(clipboard.into_inner(), out);
+        assert_eq!(parse_dump(&written)[0].path, "a.rs");
+    }
+
+    #[test]
+    fn clipboard_gets_uncolored_diff() {
+        let cli = Cli::parse_from(["fencecat", "-c", "--color-diff", "--color", "always"]);
+        let clipboard = RefCell::new(String::new());
+        let out = "```diff a.rs\n-a\n+b\n\n```\n\n";
+        deliver(
+            &cli,
+            &[out.to_string()],
+            |s| {
+                clipboard.borrow_mut().push_str(s);
+                Ok(())
+            },
+            |_| unreachable!("no prompt"),
+            &mut None,
+        )
+        .expect("deliver");
+        assert_eq!(clipboard.into_inner(), out);
+    }
+
+    #[test]
+    fn clipboard_size_limit_refuses_unless_forced() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let path = tmp.path().join("out.md");
+        let out = "```rust a.rs\nfn a() {}\n\n```\n\n";
+        let args = ["fencecat", "-c", "-o", path.to_str().expect("utf-8")];
+        let limit = ["--clipboard-size-limit", "10"];
+
+        let cli = Cli::parse_from(args.iter().chain(&limit));
+        deliver(
+            &cli,
+            &[out.to_string()],
+            |_| panic!("over the limit"),
+            |_| unreachable!("no prompt"),
+            &mut None,
+        )
+        .expect("refusal is no error");
+        assert_eq!(fs::read_to_string(&path).expect("read"), out);
+
+        let cli = Cli::parse_from(args.iter().chain(&limit).chain(&["--force-clipboard"]));
+        let copied = RefCell::new(String::new());
+        deliver(
+            &cli,
+            &[out.to_string()],
+            |s| {
+                copied.borrow_mut().push_str(s);
+                Ok(())
+            },
+            |_| unreachable!("no prompt"),
+            &mut None,
+        )
+        .expect("deliver");
+        assert_eq!(copied.into_inner(), out);
+    }
+
+    #[test]
+    fn clipboard_confirm_asks_before_large_copies() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let path = tmp.path().join("out.md");
+        let out = "```rust a.rs\nfn a() {}\n\n```\n\n";
+        let cli = Cli::parse_from([
+            "fencecat",
+            "-c",
+            "-o",
+            path.to_str().expect("utf-8"),
+            "--clipboard-confirm",
+            "--clipboard-confirm-threshold",
+            "10",
+            "--force-interactive",
+        ]);
+
+        let asked = RefCell::new(String::new());
+        deliver(
+            &cli,
+            &[out.to_string()],
+            |_| panic!("declined"),
+            |q| {
+                asked.borrow_mut().push_str(q);
+                false
+            },
+            &mut None,
+        )
+        .expect("a declined copy is no error");
+        assert!(asked.into_inner().starts_with("About to copy"));
+        assert_eq!(fs::read_to_string(&path).expect("read"), out);
+
+        let copied = RefCell::new(String::new());
+        deliver(
+            &cli,
+            &[out.to_string()],
+            |s| {
+                copied.borrow_mut().push_str(s);
+                Ok(())
+            },
+            |_| true,
+            &mut None,
+        )
+        .expect("deliver");
+        assert_eq!(copied.into_inner(), out);
+
+        let cli = Cli::parse_from([
+            "fencecat",
+            "-c",
+            "-o",
+            path.to_str().expect("utf-8"),
+            "--clipboard-confirm",
+            "--force-interactive",
+        ]);
+        let copied = RefCell::new(String::new());
+        deliver(
+            &cli,
+            &[out.to_string()],
+            |s| {
+                copied.borrow_mut().push_str(s);
+                Ok(())
+            },
+            |_| panic!("under the threshold"),
+            &mut None,
+        )
+        .expect("deliver");
+        assert_eq!(copied.into_inner(), out);
+    }
+
+    #[test]
+    fn save_clip_without_copy_only_writes_the_file() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let path = tmp.path().join("clip.md");
+        fs::write(&path, "stale").expect("write");
+        let cli = Cli::parse_from(["fencecat", "--save-clip", path.to_str().expect("utf-8")]);
+
+        let out = "```rust a.rs\nfn a() {}\n\n```\n\n";
+        deliver(
+            &cli,
+            &[out.to_string()],
+            |_| panic!("clipboard must not be used"),
+            |_| unreachable!("no prompt"),
+            &mut None,
+        )
+        .expect("deliver");
+
+        assert_eq!(fs::read_to_string(&path).expect("read"), out);
+        assert_eq!(
+            fs::read_dir(tmp.path()).expect("ls").count(),
+            1,
+            "no temp file left"
+        );
+    }
+
+    #[test]
+    fn files_deleted_after_the_walk_are_dropped_and_reported() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+        fs::write(tmp.path().join("b.rs"), "fn b() {}\n").expect("write");
+        let dir = tmp.path().to_str().expect("utf-8");
+
+        for strict in [false, true] {
+            let args = ["fencecat", dir, "--dir-list"];
+            let cli = Cli::parse_from(if strict {
+                [&args[..], &["--strict"]].concat()
+            } else {
+                args.to_vec()
+            });
+            let mut collected = collect_any(&cli).expect("collect");
+            assert_eq!(collected.files.len(), 2);
+            fs::remove_file(tmp.path().join("b.rs")).expect("remove");
+
+            let parts = render(&cli, &mut collected).expect("render");
+            assert_eq!(
+                parts.concat(),
+                "```\na.rs\n```\n\n```rust a.rs\nfn a() {}\n\n```\n\n"
+            );
+            assert_eq!(collected.vanished.len(), 1);
+            assert_eq!(collected.vanished[0].0, "b.rs");
+            assert_eq!(check_strict(&cli, &collected).is_err(), strict);
+            fs::write(tmp.path().join("b.rs"), "fn b() {}\n").expect("write");
+        }
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn fake_root_feeds_collect_and_render() {
+        let manifest = r##"[
+            {"path": "src/a.rs", "content": "fn a() {}\n"},
+            {"path": "src/b.py", "content": "b = 1\n"},
+            {"path": "README.md", "content": "# top\n"}
+        ]"##;
+        let mut cli = Cli::parse_from([
+            "fencecat",
+            "--fake-root",
+            "src",
+            "--fake-root-manifest",
+            manifest,
+            "--ext",
+            "rs",
+        ]);
+        let fixture = cli.materialize_fake_root().expect("fixture");
+        assert!(fixture.is_some());
+        let mut collected = collect_any(&cli).expect("collect");
+        let labels: Vec<_> = collected.files.iter().map(|f| f.rel.as_str()).collect();
+        assert_eq!(labels, ["a.rs"]);
+        let parts = render(&cli, &mut collected).expect("render");
+        assert_eq!(parts.concat(), "```rust a.rs\nfn a() {}\n\n```\n\n");
+
+        let dir = cli.dir.clone();
+        drop(fixture);
+        assert!(!dir.exists(), "fixture removed on drop");
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn fake_root_manifest_stays_inside_the_fixture() {
+        for (root, manifest) in [
+            (".", r#"[{"path": "../escape.rs", "content": ""}]"#),
+            (".", r#"[{"path": "/etc/escape.rs", "content": ""}]"#),
+            (
+                ".",
+                r#"[{"path": "a.rs", "content": ""}, {"path": "./a.rs", "content": ""}]"#,
+            ),
+            ("missing", r#"[{"path": "a.rs", "content": ""}]"#),
+            (".", r#"[{"path": "a.rs"}]"#),
+        ] {
+            let mut cli = Cli::parse_from([
+                "fencecat",
+                "--fake-root",
+                root,
+                "--fake-root-manifest",
+                manifest,
+            ]);
+            let err = cli.materialize_fake_root().expect_err(manifest);
+            assert!(matches!(err, super::Error::Usage(_)), "{manifest}: {err}");
         }
     }
 }
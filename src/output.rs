@@ -0,0 +1,3 @@
+pub mod diff;
+pub mod html;
+pub mod json;
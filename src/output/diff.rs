@@ -0,0 +1,178 @@
+use std::borrow::Cow;
+
+use similar::TextDiff;
+
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// Unified diff of one file between a previous dump and now, with
+/// `a/PATH` / `b/PATH` headers and three lines of context.
+#[must_use]
+pub fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{path}"), &format!("b/{path}"))
+        .to_string()
+}
+
+/// Colors unified-diff lines in ANSI: file headers bold, hunk headers cyan,
+/// additions green, removals red. It follows the hunk line counts, so a
+/// removed `-- x` or added `++ y` inside a hunk is not taken for a header.
+#[derive(Debug, Default)]
+pub struct DiffColorizer {
+    /// Old and new lines still to come in the current hunk.
+    old_left: usize,
+    new_left: usize,
+}
+
+impl DiffColorizer {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Color one line (without its newline).
+    pub fn line<'a>(&mut self, line: &'a str) -> Cow<'a, str> {
+        let color = if self.old_left > 0 || self.new_left > 0 {
+            match line.as_bytes().first() {
+                Some(b'+') => {
+                    self.new_left = self.new_left.saturating_sub(1);
+                    GREEN
+                }
+                Some(b'-') => {
+                    self.old_left = self.old_left.saturating_sub(1);
+                    RED
+                }
+                // `\ No newline at end of file`
+                Some(b'\\') => return Cow::Borrowed(line),
+                _ => {
+                    self.old_left = self.old_left.saturating_sub(1);
+                    self.new_left = self.new_left.saturating_sub(1);
+                    return Cow::Borrowed(line);
+                }
+            }
+        } else if line.starts_with("+++") || line.starts_with("---") {
+            BOLD
+        } else if let Some((old, new)) = hunk_lengths(line) {
+            (self.old_left, self.new_left) = (old, new);
+            CYAN
+        } else {
+            return Cow::Borrowed(line);
+        };
+        Cow::Owned(format!("{color}{line}{RESET}"))
+    }
+}
+
+/// Old and new line counts of a `@@ -a,b +c,d @@` hunk header (a missing
+/// count is 1).
+fn hunk_lengths(line: &str) -> Option<(usize, usize)> {
+    let mut ranges = line.strip_prefix("@@ -")?.split(' ');
+    let len = |range: &str| match range.split_once(',') {
+        Some((_, n)) => n.parse().ok(),
+        None => range.parse::<usize>().ok().map(|_| 1),
+    };
+    let old = len(ranges.next()?)?;
+    let new = len(ranges.next()?.strip_prefix('+')?)?;
+    Some((old, new))
+}
+
+/// Colorize the lines inside every ```` ```diff ```` fence of a rendered dump,
+/// leaving everything else untouched. Lines may end in `\n` or `\r\n`.
+#[must_use]
+pub fn colorize_diff_fences(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    // Closing fence of the diff block we are in, and its colorizer
+    let mut closing: Option<(&str, DiffColorizer)> = None;
+    for line in text.split_inclusive('\n') {
+        let body = line.strip_suffix('\n').unwrap_or(line);
+        let body = body.strip_suffix('\r').unwrap_or(body);
+        match &mut closing {
+            Some((fence, _)) if body == *fence => {
+                closing = None;
+                out.push_str(line);
+            }
+            Some((_, colorizer)) => {
+                out.push_str(&colorizer.line(body));
+                out.push_str(&line[body.len()..]);
+            }
+            None => {
+                let ticks = body.len() - body.trim_start_matches('`').len();
+                if ticks >= 3 && body[ticks..].starts_with("diff ") {
+                    closing = Some((&body[..ticks], DiffColorizer::new()));
+                }
+                out.push_str(line);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DiffColorizer, colorize_diff_fences, unified_diff};
+
+    #[test]
+    fn colors_by_line_kind() {
+        let mut c = DiffColorizer::new();
+        assert_eq!(c.line("--- a/a.rs"), "\x1b[1m--- a/a.rs\x1b[0m");
+        assert_eq!(c.line("+++ b/a.rs"), "\x1b[1m+++ b/a.rs\x1b[0m");
+        assert_eq!(c.line("@@ -1,3 +1,3 @@"), "\x1b[36m@@ -1,3 +1,3 @@\x1b[0m");
+        assert_eq!(c.line(" same"), " same");
+        assert_eq!(c.line("-old"), "\x1b[31m-old\x1b[0m");
+        assert_eq!(c.line("+new"), "\x1b[32m+new\x1b[0m");
+        assert_eq!(c.line(" same"), " same");
+        // Past the hunk, headers again
+        assert_eq!(c.line("--- a/b.rs"), "\x1b[1m--- a/b.rs\x1b[0m");
+    }
+
+    #[test]
+    fn hunk_lines_that_look_like_headers_keep_their_color() {
+        let mut c = DiffColorizer::new();
+        let diff = unified_diff("a.sql", "-- old comment\nx\n", "++ new\nx\n");
+        let colored: Vec<_> = diff.lines().map(|l| c.line(l).into_owned()).collect();
+        assert_eq!(
+            colored,
+            [
+                "\x1b[1m--- a/a.sql\x1b[0m",
+                "\x1b[1m+++ b/a.sql\x1b[0m",
+                "\x1b[36m@@ -1,2 +1,2 @@\x1b[0m",
+                "\x1b[31m--- old comment\x1b[0m",
+                "\x1b[32m+++ new\x1b[0m",
+                " x",
+            ]
+        );
+    }
+
+    #[test]
+    fn only_diff_fences_are_colored() {
+        let text = "```rust a.rs\n-1\n```\n\n```diff b.rs\n@@ -1 +1 @@\n-x\n+y\n\n```\n\n```rust c.rs\n-1\n```\n";
+        assert_eq!(
+            colorize_diff_fences(text),
+            "```rust a.rs\n-1\n```\n\n```diff b.rs\n\x1b[36m@@ -1 +1 @@\x1b[0m\n\
+             \x1b[31m-x\x1b[0m\n\x1b[32m+y\x1b[0m\n\n```\n\n```rust c.rs\n-1\n```\n"
+        );
+    }
+
+    #[test]
+    fn crlf_diff_fences_close() {
+        let text = "```diff b.rs\r\n@@ -1 +1 @@\r\n-x\r\n+y\r\n\r\n```\r\n\r\n```rust c.rs\r\n-1\r\n```\r\n";
+        assert_eq!(
+            colorize_diff_fences(text),
+            "```diff b.rs\r\n\x1b[36m@@ -1 +1 @@\x1b[0m\r\n\x1b[31m-x\x1b[0m\r\n\
+             \x1b[32m+y\x1b[0m\r\n\r\n```\r\n\r\n```rust c.rs\r\n-1\r\n```\r\n"
+        );
+    }
+
+    #[test]
+    fn diff_has_headers_and_hunks() {
+        let diff = unified_diff("src/a.rs", "a\nb\nc\n", "a\nB\nc\n");
+        assert_eq!(
+            diff,
+            "--- a/src/a.rs\n+++ b/src/a.rs\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use crate::parse::fence_prefix;
+
+/// Escape text for HTML element content and attribute values.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Minimal HTML rendering of fenced Markdown, for the rich clipboard flavor
+/// of `--copy-mime markdown`.
+///
+/// Each fence becomes `<pre><code>` (with a `language-…` class when the info
+/// string has one) preceded by its label in `<p><code>`; other non-blank
+/// lines become paragraphs. Nothing else of Markdown is interpreted.
+#[must_use]
+pub fn fences_to_html(markdown: &str) -> String {
+    let mut out = String::new();
+    let mut lines = markdown.lines();
+    while let Some(line) = lines.next() {
+        let Some((ch, len)) = fence_prefix(line) else {
+            if !line.trim().is_empty() {
+                out.push_str(&format!("<p>{}</p>\n", escape(line)));
+            }
+            continue;
+        };
+        let mut words = line[len..].split_whitespace();
+        let (lang, label) = match (words.next(), words.next_back()) {
+            (Some(lang), Some(label)) => (Some(lang), Some(label)),
+            (Some(label), None) if label.contains(['/', '.']) => (None, Some(label)),
+            (lang, _) => (lang, None),
+        };
+        if let Some(label) = label {
+            out.push_str(&format!("<p><code>{}</code></p>\n", escape(label)));
+        }
+        match lang {
+            Some(lang) => out.push_str(&format!("<pre><code class=\"language-{}\">", escape(lang))),
+            None => out.push_str("<pre><code>"),
+        }
+        let mut body = String::new();
+        for inner in lines.by_ref() {
+            if fence_prefix(inner.trim_end())
+                .is_some_and(|(c, n)| c == ch && n >= len && n == inner.trim_end().len())
+            {
+                break;
+            }
+            body.push_str(inner);
+            body.push('\n');
+        }
+        // The emitter's blank separator line is not content
+        if body.ends_with("\n\n") {
+            body.pop();
+        }
+        out.push_str(&escape(&body));
+        out.push_str("</code></pre>\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fences_to_html;
+
+    #[test]
+    fn fences_become_pre_code_blocks() {
+        let md = "src/\n\n```rust src/a.rs\nif a < b && c {}\n\n```\n\n~~~ notes.txt\nhi\n~~~\n";
+        assert_eq!(
+            fences_to_html(md),
+            "<p>src/</p>\n\
+             <p><code>src/a.rs</code></p>\n\
+             <pre><code class=\"language-rust\">if a &lt; b &amp;&amp; c {}\n</code></pre>\n\
+             <p><code>notes.txt</code></p>\n\
+             <pre><code>hi\n</code></pre>\n"
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use serde::Serialize;
+
+use crate::FileInfo;
+
+/// One element of the `--format json` array.
+///
+/// Keep in sync with [`SCHEMA`].
+#[derive(Debug, Serialize)]
+pub struct JsonFile<'a> {
+    pub path: &'a str,
+    pub language: Option<&'a str>,
+    pub size: u64,
+    pub lines: Option<usize>,
+    /// MIME type for media placeholders (`--note-images`), whose content is `null`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media: Option<&'a str>,
+    /// Link target for `--symlinks annotate` notes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symlink: Option<&'a str>,
+    /// Unix permission bits (`--preserve-permissions`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    pub executable: bool,
+    /// Id shared by files with identical content (`--content-hash-header`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_id: Option<&'a str>,
+    /// Earlier file with the same content; `content` is then `null`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicate_of: Option<&'a str>,
+    pub content: Option<&'a str>,
+}
+
+impl<'a> JsonFile<'a> {
+    #[must_use]
+    pub fn new(info: &'a FileInfo, content: Option<&'a str>) -> Self {
+        Self {
+            path: &info.rel,
+            language: info.lang.as_deref(),
+            size: info.size,
+            lines: info.lines,
+            media: info.media,
+            symlink: info.symlink.as_deref(),
+            mode: info.mode,
+            executable: info.executable,
+            content_id: info.content_id.as_deref(),
+            duplicate_of: info.duplicate_of.as_deref(),
+            content,
+        }
+    }
+}
+
+/// Render the records as a pretty-printed JSON array (with trailing newline).
+#[must_use]
+pub fn render(files: &[JsonFile<'_>]) -> String {
+    let mut s = serde_json::to_string_pretty(files).unwrap_or_else(|_| "[]".to_string());
+    s.push('\n');
+    s
+}
+
+/// JSON Schema (draft 2020-12) for the `--format json` output.
+pub const SCHEMA: &str = r#"{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "$id": "https://github.com/xangelix/fencecat/schema/files.json",
+  "title": "fencecat --format json output",
+  "type": "array",
+  "items": {
+    "type": "object",
+    "required": ["path", "language", "size", "lines", "executable", "content"],
+    "additionalProperties": false,
+    "properties": {
+      "path": {
+        "description": "Label relative to the scan root, with / separators",
+        "type": "string"
+      },
+      "language": {
+        "description": "Fence language, or null when unknown",
+        "type": ["string", "null"]
+      },
+      "size": {
+        "description": "File size in bytes",
+        "type": "integer",
+        "minimum": 0
+      },
+      "lines": {
+        "description": "Line count of the original file, or null when not read",
+        "type": ["integer", "null"],
+        "minimum": 0
+      },
+      "media": {
+        "description": "MIME type of a media placeholder (--note-images)",
+        "type": "string"
+      },
+      "symlink": {
+        "description": "Link target as stored in the link (--symlinks annotate); content is a one-line note",
+        "type": "string"
+      },
+      "mode": {
+        "description": "Unix permission bits as a number, e.g. 493 for 0755 (--preserve-permissions)",
+        "type": "integer",
+        "minimum": 0
+      },
+      "executable": {
+        "description": "Whether any execute bit is set (always false on Windows)",
+        "type": "boolean"
+      },
+      "content_id": {
+        "description": "Id shared by files with identical content (--content-hash-header)",
+        "type": "string"
+      },
+      "duplicate_of": {
+        "description": "Path of the earlier file with identical content (--content-hash-header); content is null",
+        "type": "string"
+      },
+      "content": {
+        "description": "File content after transforms, or null for placeholders",
+        "type": ["string", "null"]
+      }
+    }
+  }
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::{JsonFile, SCHEMA, render};
+
+    #[test]
+    fn schema_covers_serialized_fields() {
+        let schema: serde_json::Value = serde_json::from_str(SCHEMA).expect("valid schema");
+        let props = &schema["items"]["properties"];
+        let sample = JsonFile {
+            path: "a.png",
+            language: None,
+            size: 1,
+            lines: None,
+            media: Some("image/png"),
+            symlink: Some("b.png"),
+            mode: Some(0o644),
+            executable: false,
+            content_id: Some("0123456789ab"),
+            duplicate_of: Some("b.png"),
+            content: None,
+        };
+        let value: serde_json::Value =
+            serde_json::from_str(&render(&[sample])).expect("valid output");
+        for key in value[0].as_object().expect("object").keys() {
+            assert!(props.get(key).is_some(), "schema lacks `{key}`");
+        }
+    }
+}
@@ -0,0 +1,281 @@
+//! Reading fencecat's own Markdown output back into file sections.
+//!
+//! The parser mirrors `emit_fence`: an opening fence of three or more
+//! backticks or tildes whose info string is `LANG PATH` or just `PATH`, the
+//! content, a blank line, and a matching closing fence. Unlabeled fences (the
+//! `--dir-list` block), anchors and media notes are skipped; a metadata
+//! comment right above a fence contributes the file mode, and an `(exec)`
+//! marker after the path (`--show-exec`) the execute bit. A trailing
+//! `chunk=K/N` (`--fence-continuation`) marks one piece of a split file;
+//! [`merge_continuations`] puts the pieces back together.
+
+use std::collections::HashMap;
+
+/// One file recovered from a dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub path: String,
+    pub lang: Option<String>,
+    pub content: String,
+    /// Unix permission bits from a preceding `<!-- fencecat: … mode=0755 -->`.
+    pub mode: Option<u32>,
+    /// The fence carried the `--show-exec` marker.
+    pub executable: bool,
+    /// `(K, N)` for piece K of a file split into N (`--fence-continuation`).
+    pub chunk: Option<(usize, usize)>,
+}
+
+/// Appended to the labels of executable files under `--show-exec`.
+pub const EXEC_MARKER: &str = " (exec)";
+
+/// Split a trailing ` chunk=K/N` off an info string.
+fn split_chunk(info: &str) -> (&str, Option<(usize, usize)>) {
+    let parsed = info.rsplit_once(" chunk=").and_then(|(rest, chunk)| {
+        let (k, n) = chunk.split_once('/')?;
+        let (k, n) = (k.parse().ok()?, n.parse().ok()?);
+        (!rest.is_empty() && (1..=n).contains(&k)).then_some((rest, (k, n)))
+    });
+    match parsed {
+        Some((rest, chunk)) => (rest, Some(chunk)),
+        None => (info, None),
+    }
+}
+
+/// Fence character and length of an opening or closing fence line.
+#[must_use]
+pub fn fence_prefix(line: &str) -> Option<(char, usize)> {
+    let ch = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = line.len() - line.trim_start_matches(ch).len();
+    (len >= 3).then_some((ch, len))
+}
+
+/// Split an info string into language and path.
+///
+/// The first word is a language only when more words follow and it looks
+/// like one (letters, digits, `+-#_`), so unlabeled paths with spaces
+/// survive. `key=value` attributes after the language (`edition=2021`) and
+/// `--number-files` prefixes are dropped, and shell-quoted paths
+/// (`--shell-quote`) are unquoted.
+fn split_info(info: &str) -> (Option<&str>, String) {
+    let is_lang = |w: &str| {
+        !w.is_empty()
+            && w.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-#_".contains(c))
+    };
+    let (lang, path) = match info.split_once(' ') {
+        Some((lang, path)) if is_lang(lang) && !path.trim().is_empty() => (Some(lang), path.trim()),
+        _ => (None, info),
+    };
+    let is_attr = |w: &str| {
+        w.split_once('=').is_some_and(|(k, v)| {
+            !k.is_empty() && k.chars().all(|c| c.is_ascii_lowercase()) && !v.contains('/')
+        })
+    };
+    let mut path = path;
+    while lang.is_some()
+        && let Some((word, rest)) = path.split_once(' ')
+        && is_attr(word)
+        && !rest.trim().is_empty()
+    {
+        path = rest.trim_start();
+    }
+    // `--number-files` prefix, e.g. `[ 3/58] `
+    let path = path
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once("] "))
+        .filter(|(n, _)| {
+            n.chars()
+                .all(|c| c.is_ascii_digit() || c == '/' || c == ' ')
+        })
+        .map_or(path, |(_, rest)| rest);
+    let unquoted = if path.starts_with(['\'', '"']) {
+        shell_words::split(path)
+            .ok()
+            .and_then(|words| <[String; 1]>::try_from(words).ok())
+            .map(|[word]| word)
+    } else {
+        None
+    };
+    (lang, unquoted.unwrap_or_else(|| path.to_string()))
+}
+
+/// Parse every path-labeled fence in `text`.
+///
+/// Tolerates a leading byte order mark, CRLF line endings (content comes
+/// back with LF), trailing spaces after fences and a missing
+/// blank line before the closing fence. An unclosed final fence runs to the
+/// end of the input.
+#[must_use]
+pub fn parse_dump(text: &str) -> Vec<Section> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut sections = Vec::new();
+    let mut mode = None;
+    let mut lines = text.split_inclusive('\n');
+    while let Some(line) = lines.next() {
+        let line = line.trim_end();
+        if let Some(meta) = line.strip_prefix("<!-- fencecat:") {
+            mode = meta
+                .split_whitespace()
+                .find_map(|kv| kv.strip_prefix("mode="))
+                .and_then(|m| u32::from_str_radix(m, 8).ok());
+            continue;
+        }
+        let Some((ch, len)) = fence_prefix(line) else {
+            // A comment above a note (`--content-hash-header`) is not the next fence's
+            mode = None;
+            continue;
+        };
+        let mode = mode.take();
+        let info = line[len..].trim();
+
+        let mut body = String::new();
+        for inner in lines.by_ref() {
+            let t = inner.trim_end();
+            if fence_prefix(t).is_some_and(|(c, n)| c == ch && n >= len && n == t.len()) {
+                break;
+            }
+            let inner = inner.strip_suffix('\n').unwrap_or(inner);
+            body.push_str(inner.strip_suffix('\r').unwrap_or(inner));
+            body.push('\n');
+        }
+        if info.is_empty() {
+            continue;
+        }
+        // The emitter always separates content and closing fence by a blank line
+        if body.ends_with("\n\n") {
+            body.pop();
+        }
+        let (info, chunk) = split_chunk(info);
+        let (info, executable) = match info.strip_suffix(EXEC_MARKER) {
+            Some(rest) if !rest.is_empty() => (rest, true),
+            _ => (info, false),
+        };
+        let (lang, path) = split_info(info);
+        sections.push(Section {
+            path,
+            lang: lang.map(str::to_string),
+            content: body,
+            mode,
+            executable,
+            chunk,
+        });
+    }
+    sections
+}
+
+/// Join the pieces of each split file into one section, at the position of
+/// its first piece, ordered by piece number. Other sections pass through.
+#[must_use]
+pub fn merge_continuations(sections: Vec<Section>) -> Vec<Section> {
+    let mut merged: Vec<Section> = Vec::new();
+    // Index in `merged` and the pieces seen so far, by path
+    let mut split: HashMap<String, (usize, Vec<(usize, String)>)> = HashMap::new();
+    for section in sections {
+        let Some((k, _)) = section.chunk else {
+            merged.push(section);
+            continue;
+        };
+        let (_, pieces) = split
+            .entry(section.path.clone())
+            .or_insert_with(|| (merged.len(), Vec::new()));
+        if pieces.is_empty() {
+            merged.push(Section {
+                content: String::new(),
+                chunk: None,
+                ..section.clone()
+            });
+        }
+        pieces.push((k, section.content));
+    }
+    for (index, mut pieces) in split.into_values() {
+        pieces.sort_by_key(|&(k, _)| k);
+        merged[index].content = pieces.into_iter().map(|(_, content)| content).collect();
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{merge_continuations, parse_dump};
+
+    #[test]
+    fn parses_emitter_output() {
+        let dump = "```\na.rs\nnotes/my file.txt\n```\n\n<!-- fencecat: path=a.rs -->\n```rust a.rs\nfn a() {}\n\n```\n\n````markdown README.md\n```sh\nls\n```\n\n````\n\n```notes/my file.txt\nhi\n\n```\n\n";
+        let sections = parse_dump(dump);
+        let got: Vec<_> = sections
+            .iter()
+            .map(|s| (s.path.as_str(), s.lang.as_deref(), s.content.as_str()))
+            .collect();
+        assert_eq!(
+            got,
+            [
+                ("a.rs", Some("rust"), "fn a() {}\n"),
+                ("README.md", Some("markdown"), "```sh\nls\n```\n"),
+                ("notes/my file.txt", None, "hi\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unquotes_and_unnumbers_labels() {
+        let dump = "```rust 'src/my file.rs'\nfn a() {}\n\n```\n\n```'it'\\''s.txt'\nx\n\n```\n";
+        let paths: Vec<_> = parse_dump(dump).into_iter().map(|s| s.path).collect();
+        assert_eq!(paths, ["src/my file.rs", "it's.txt"]);
+
+        let numbered =
+            "```rust [ 3/12] src/a.rs\nfn a() {}\n\n```\n\n```[12/12] 'b c.txt'\nx\n\n```\n";
+        let paths: Vec<_> = parse_dump(numbered).into_iter().map(|s| s.path).collect();
+        assert_eq!(paths, ["src/a.rs", "b c.txt"]);
+
+        let attributed = "```rust edition=2021 [1/1] src/a.rs\nfn a() {}\n\n```\n";
+        assert_eq!(parse_dump(attributed)[0].path, "src/a.rs");
+    }
+
+    #[test]
+    fn tolerates_crlf_and_tilde_fences() {
+        let dump =
+            "<!-- fencecat: path=doc.md mode=0640 -->\r\n~~~markdown doc.md\r\n# Title\r\n~~~\r\n";
+        let sections = parse_dump(dump);
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections[0].content, "# Title\n");
+        assert_eq!(sections[0].mode, Some(0o640));
+    }
+
+    #[test]
+    fn exec_marker_is_not_part_of_the_path() {
+        let dump = "```bash bin/deploy.sh (exec)\nexit 0\n\n```\n\n```'a (exec)'\nx\n\n```\n";
+        let got: Vec<_> = parse_dump(dump)
+            .into_iter()
+            .map(|s| (s.path, s.executable))
+            .collect();
+        assert_eq!(
+            got,
+            [("bin/deploy.sh".into(), true), ("a (exec)".into(), false)]
+        );
+    }
+
+    #[test]
+    fn continuation_pieces_merge_in_order() {
+        let dump = "<!-- continuation: a.rs 2/2 -->\n```rust a.rs chunk=2/2\nfn b() {}\n\n```\n\n\
+                    ```b.rs\nx\n\n```\n\n\
+                    <!-- continuation: a.rs 1/2 -->\n```rust a.rs (exec) chunk=1/2\nfn a() {}\n\n```\n";
+        let sections = parse_dump(dump);
+        assert_eq!(sections[0].chunk, Some((2, 2)));
+        assert!(sections[2].executable);
+        let got: Vec<_> = merge_continuations(sections)
+            .into_iter()
+            .map(|s| (s.path, s.content, s.chunk))
+            .collect();
+        assert_eq!(
+            got,
+            [
+                ("a.rs".into(), "fn a() {}\nfn b() {}\n".into(), None),
+                ("b.rs".into(), "x\n".into(), None),
+            ]
+        );
+        assert_eq!(
+            parse_dump("```a.txt chunk=0/2\nx\n\n```\n")[0].path,
+            "a.txt chunk=0/2"
+        );
+    }
+}
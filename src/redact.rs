@@ -0,0 +1,91 @@
+//! `--env-file`: redact the values a project keeps in its `.env` file.
+
+use std::{borrow::Cow, path::Path};
+
+use regex::Regex;
+
+/// What a redacted value is replaced with.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Values shorter than this (in characters) are left alone: `true`, `3000`
+/// or `dev` would match all over the code.
+pub const MIN_SECRET_LEN: usize = 8;
+
+/// The values (not the keys) defined in the `.env` file at `path`, at least
+/// `min_len` characters long, as regex-escaped literals. Longer values come
+/// first, so one that contains another is redacted whole.
+///
+/// # Errors
+/// Returns the message when the file cannot be read or parsed.
+pub fn load_env_redaction_patterns(path: &Path, min_len: usize) -> Result<Vec<String>, String> {
+    let mut values = Vec::new();
+    for item in dotenvy::from_path_iter(path).map_err(|e| e.to_string())? {
+        let (_, value) = item.map_err(|e| e.to_string())?;
+        if value.chars().count() >= min_len {
+            values.push(value);
+        }
+    }
+    values.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    values.dedup();
+    Ok(values.iter().map(|v| regex::escape(v)).collect())
+}
+
+/// One regex matching any of `patterns`, or `None` when there are none.
+///
+/// # Errors
+/// Returns the message when the combined pattern is too large to compile.
+pub fn compile_redactions(patterns: &[String]) -> Result<Option<Regex>, String> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    Regex::new(&patterns.join("|"))
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+/// `content` with every match of `re` replaced by [`REDACTED`].
+#[must_use]
+pub fn redact<'a>(content: &'a str, re: &Regex) -> Cow<'a, str> {
+    re.replace_all(content, REDACTED)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{MIN_SECRET_LEN, compile_redactions, load_env_redaction_patterns, redact};
+
+    #[test]
+    fn redacts_long_env_values_only() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let env = tmp.path().join(".env");
+        fs::write(
+            &env,
+            "# local settings\nPORT=3000\nDB_URL=\"postgres://u:p4ss@db/app\"\n\
+             export API_TOKEN=tok.en+secret\nTOKEN_PREFIX=tok.en+s\n",
+        )
+        .expect("write");
+
+        let patterns = load_env_redaction_patterns(&env, MIN_SECRET_LEN).expect("parse");
+        assert_eq!(
+            patterns,
+            [
+                r"postgres://u:p4ss@db/app",
+                r"tok\.en\+secret",
+                r"tok\.en\+s"
+            ]
+        );
+        let re = compile_redactions(&patterns)
+            .expect("compile")
+            .expect("some");
+        assert_eq!(
+            redact(
+                "url = postgres://u:p4ss@db/app; t = tok.en+secret; p = 3000",
+                &re
+            ),
+            "url = [REDACTED]; t = [REDACTED]; p = 3000"
+        );
+        assert!(compile_redactions(&[]).expect("compile").is_none());
+        assert!(load_env_redaction_patterns(&tmp.path().join("missing"), 8).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+//! `--transform-script`: user-defined content transforms in [Rhai](https://rhai.rs).
+//!
+//! The script sees two variables and must evaluate to a string:
+//!
+//! - `path`: the file's label, e.g. `src/main.rs`
+//! - `content`: the file's text (after the built-in transforms)
+//!
+//! ```rhai
+//! if path.ends_with(".rs") { content.replace("TODO", "todo"); }
+//! content
+//! ```
+//!
+//! Scripts run sandboxed: Rhai has no filesystem or network access, `import`
+//! is disabled, `print`/`debug` are silenced, and runaway loops are cut off by
+//! an operation limit.
+
+use std::path::Path;
+
+use rhai::{AST, Engine, Scope, module_resolvers::DummyModuleResolver};
+
+/// Upper bound on script operations per file.
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+pub struct TransformScript {
+    engine: Engine,
+    ast: AST,
+}
+
+impl TransformScript {
+    /// Compile a script from source.
+    ///
+    /// # Errors
+    /// Returns the Rhai parse error message.
+    pub fn compile(source: &str) -> Result<Self, String> {
+        let mut engine = Engine::new();
+        engine
+            .set_module_resolver(DummyModuleResolver::new())
+            .set_max_operations(MAX_OPERATIONS)
+            .on_print(|_| {})
+            .on_debug(|_, _, _| {});
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        Ok(Self { engine, ast })
+    }
+
+    /// Read and compile a script file.
+    ///
+    /// # Errors
+    /// Returns a message naming the file on read or parse errors.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        Self::compile(&source).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Run the script for one file and return the new content.
+    ///
+    /// # Errors
+    /// Returns runtime errors, or a message when the result is not a string.
+    pub fn apply(&self, path: &str, content: &str) -> Result<String, String> {
+        let mut scope = Scope::new();
+        scope.push("path", path.to_string());
+        scope.push("content", content.to_string());
+        let result = self
+            .engine
+            .eval_ast_with_scope::<rhai::Dynamic>(&mut scope, &self.ast)
+            .map_err(|e| e.to_string())?;
+        let type_name = result.type_name();
+        result
+            .into_string()
+            .map_err(|_| format!("script must return a string, got {type_name}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TransformScript;
+
+    #[test]
+    fn script_sees_path_and_content() {
+        let script = TransformScript::compile(
+            r#"if path.ends_with(".rs") { "// " + path + "\n" + content } else { content }"#,
+        )
+        .expect("compile");
+        assert_eq!(script.apply("a.rs", "x\n").expect("apply"), "// a.rs\nx\n");
+        assert_eq!(script.apply("a.md", "x\n").expect("apply"), "x\n");
+    }
+
+    #[test]
+    fn script_errors_are_reported() {
+        let script = TransformScript::compile("42").expect("compile");
+        assert!(script.apply("a", "b").is_err());
+        let looping = TransformScript::compile("loop {}").expect("compile");
+        assert!(looping.apply("a", "b").is_err());
+        let import = TransformScript::compile(r#"import "x" as x; content"#).expect("compile");
+        assert!(import.apply("a", "b").is_err());
+    }
+}
@@ -0,0 +1,299 @@
+//! `--repo-summary`: a short description of the project ahead of the fences,
+//! so a model knows what it is looking at before reading any file.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::{
+    FileInfo,
+    lang::language_display_name,
+    units::{fmt_count, fmt_size},
+};
+
+/// Dependencies that name a framework worth mentioning, per manifest, in
+/// the order they are reported.
+const RUST_FRAMEWORKS: &[(&str, &str)] = &[
+    ("axum", "Axum"),
+    ("actix-web", "Actix Web"),
+    ("rocket", "Rocket"),
+    ("warp", "warp"),
+    ("leptos", "Leptos"),
+    ("dioxus", "Dioxus"),
+    ("yew", "Yew"),
+    ("bevy", "Bevy"),
+    ("tauri", "Tauri"),
+    ("tokio", "Tokio"),
+    ("clap", "clap"),
+];
+const JS_FRAMEWORKS: &[(&str, &str)] = &[
+    ("next", "Next.js"),
+    ("nuxt", "Nuxt"),
+    ("@angular/core", "Angular"),
+    ("@sveltejs/kit", "SvelteKit"),
+    ("svelte", "Svelte"),
+    ("vue", "Vue"),
+    ("react", "React"),
+    ("@nestjs/core", "NestJS"),
+    ("express", "Express"),
+    ("fastify", "Fastify"),
+    ("electron", "Electron"),
+    ("vite", "Vite"),
+];
+
+/// What a manifest says about the project.
+#[derive(Debug, Default)]
+struct Manifest {
+    name: Option<String>,
+    version: Option<String>,
+    description: Option<String>,
+    language: Option<String>,
+    frameworks: Vec<&'static str>,
+}
+
+/// The summary block for the files collected under `root`: project name,
+/// version and description from the nearest `Cargo.toml` or `package.json`
+/// (in `root` or above it), the language and frameworks, and the file count
+/// and total size (`exact` as for `--bytes`). Without a manifest the name is
+/// `root`'s and the language is the most common one among `files`, which
+/// should be the files emitted with their content.
+#[must_use]
+pub fn generate_repo_summary(root: &Path, files: &[&FileInfo], exact: bool) -> String {
+    let root = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+    let manifest = find_manifest(&root).unwrap_or_default();
+
+    let name = manifest
+        .name
+        .or_else(|| root.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "(unnamed)".to_string());
+    let mut lines = vec![match manifest.version {
+        Some(version) => format!("Project: {name} {version}"),
+        None => format!("Project: {name}"),
+    }];
+    if let Some(description) = manifest.description.filter(|d| !d.trim().is_empty()) {
+        lines.push(format!("Description: {}", description.trim()));
+    }
+    if let Some(language) = manifest.language.or_else(|| dominant_language(files)) {
+        lines.push(format!("Language: {language}"));
+    }
+    if !manifest.frameworks.is_empty() {
+        lines.push(format!("Frameworks: {}", manifest.frameworks.join(", ")));
+    }
+    let bytes: u64 = files.iter().map(|f| f.size).sum();
+    lines.push(format!(
+        "Files: {}, {}",
+        fmt_count(files.len(), exact),
+        fmt_size(bytes, exact)
+    ));
+
+    // Two trailing spaces keep the lines apart when the quote is rendered;
+    // every line of a multi-line description stays inside the quote
+    let mut out = lines
+        .iter()
+        .flat_map(|l| l.lines())
+        .map(|l| format!("> {}", l.trim_end()))
+        .collect::<Vec<_>>()
+        .join("  \n");
+    out.push_str("\n\n");
+    out
+}
+
+/// The first directory with a manifest from `root` up to the top of its
+/// repository (the directory holding `.git`), read. Outside a repository
+/// only `root` itself is looked at.
+fn find_manifest(root: &Path) -> Option<Manifest> {
+    let top = root.ancestors().find(|dir| dir.join(".git").exists());
+    let depth = top.map_or(1, |top| {
+        root.ancestors().position(|d| d == top).unwrap_or(0) + 1
+    });
+    root.ancestors().take(depth).find_map(|dir| {
+        read_cargo_manifest(&dir.join("Cargo.toml"))
+            .or_else(|| read_package_json(&dir.join("package.json")))
+    })
+}
+
+fn read_cargo_manifest(path: &Path) -> Option<Manifest> {
+    let manifest: toml::Table = fs::read_to_string(path).ok()?.parse().ok()?;
+    let package = manifest.get("package");
+    let workspace = manifest.get("workspace").and_then(|w| w.get("package"));
+    // `version.workspace = true` and friends resolve to [workspace.package]
+    let field = |key: &str| {
+        package
+            .and_then(|p| p.get(key))
+            .and_then(toml::Value::as_str)
+            .or_else(|| workspace?.get(key)?.as_str())
+            .map(str::to_string)
+    };
+    let edition = field("edition").or_else(|| package.map(|_| "2015".to_string()));
+    let mut dependencies: Vec<&str> = Vec::new();
+    for table in ["dependencies", "dev-dependencies"] {
+        let deps = manifest
+            .get(table)
+            .or_else(|| manifest.get("workspace")?.get(table))
+            .and_then(toml::Value::as_table);
+        dependencies.extend(deps.into_iter().flat_map(|d| d.keys().map(String::as_str)));
+    }
+    Some(Manifest {
+        name: field("name").or_else(|| dir_name(path)),
+        version: field("version"),
+        description: field("description"),
+        language: Some(match edition {
+            Some(edition) => format!("Rust (edition {edition})"),
+            None => "Rust".to_string(),
+        }),
+        frameworks: frameworks(RUST_FRAMEWORKS, &dependencies),
+    })
+}
+
+fn read_package_json(path: &Path) -> Option<Manifest> {
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+    let field = |key: &str| json.get(key)?.as_str().map(str::to_string);
+    let dependencies: Vec<&str> = ["dependencies", "devDependencies", "peerDependencies"]
+        .iter()
+        .filter_map(|table| json.get(table)?.as_object())
+        .flat_map(|deps| deps.keys().map(String::as_str))
+        .collect();
+    let typescript =
+        dependencies.contains(&"typescript") || path.with_file_name("tsconfig.json").is_file();
+    let language = if typescript {
+        "TypeScript"
+    } else {
+        "JavaScript"
+    };
+    Some(Manifest {
+        name: field("name").or_else(|| dir_name(path)),
+        version: field("version"),
+        description: field("description"),
+        language: Some(language.to_string()),
+        frameworks: frameworks(JS_FRAMEWORKS, &dependencies),
+    })
+}
+
+/// Name of the directory holding the manifest at `path`.
+fn dir_name(path: &Path) -> Option<String> {
+    path.parent()
+        .and_then(Path::file_name)
+        .map(|n| n.to_string_lossy().into_owned())
+}
+
+fn frameworks(known: &[(&str, &'static str)], dependencies: &[&str]) -> Vec<&'static str> {
+    known
+        .iter()
+        .filter(|(dep, _)| dependencies.contains(dep))
+        .map(|&(_, name)| name)
+        .collect()
+}
+
+/// The language most of `files` are fenced as (ties by name).
+fn dominant_language(files: &[&FileInfo]) -> Option<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for lang in files.iter().filter_map(|f| f.lang.as_deref()) {
+        *counts.entry(lang).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+        .map(|(lang, _)| language_display_name(lang).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, fs, path::PathBuf};
+
+    use super::generate_repo_summary;
+    use crate::FileInfo;
+
+    fn file(rel: &str, size: u64, lang: &'static str) -> FileInfo {
+        FileInfo {
+            path: PathBuf::from(rel),
+            rel: rel.into(),
+            size,
+            lang: Some(Cow::Borrowed(lang)),
+            mtime: None,
+            lines: None,
+            sha256: None,
+            media: None,
+            symlink: None,
+            mode: None,
+            executable: false,
+            content_id: None,
+            duplicate_of: None,
+            anchor: None,
+            data: None,
+        }
+    }
+
+    #[test]
+    fn summarizes_from_the_nearest_manifest() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        fs::write(
+            tmp.path().join("Cargo.toml"),
+            "[package]\nname = \"demo\"\nversion = \"0.3.1\"\nedition = \"2021\"\n\
+             description = \"A demo crate\"\n\n[dependencies]\naxum = \"0.8\"\ntokio = \"1\"\n",
+        )
+        .expect("write");
+        fs::create_dir(tmp.path().join("src")).expect("mkdir");
+        fs::create_dir(tmp.path().join(".git")).expect("mkdir");
+        let (main, util) = (file("main.rs", 2000, "rust"), file("util.rs", 1000, "rust"));
+        let files = [&main, &util];
+
+        // Found from a subdirectory of the repository too
+        assert_eq!(
+            generate_repo_summary(&tmp.path().join("src"), &files, false),
+            "> Project: demo 0.3.1  \n\
+             > Description: A demo crate  \n\
+             > Language: Rust (edition 2021)  \n\
+             > Frameworks: Axum, Tokio  \n\
+             > Files: 2, 2.9 KiB\n\n"
+        );
+        assert!(
+            generate_repo_summary(&tmp.path().join("src"), &files, true)
+                .ends_with("> Files: 2, 3000 B\n\n")
+        );
+    }
+
+    #[test]
+    fn package_json_and_no_manifest() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        let web = tmp.path().join("web");
+        fs::create_dir(&web).expect("mkdir");
+        fs::write(
+            web.join("package.json"),
+            r#"{"name": "site", "version": "1.0.0",
+                "dependencies": {"react": "^19"}, "devDependencies": {"typescript": "^5"}}"#,
+        )
+        .expect("write");
+        let summary = generate_repo_summary(&web, &[&file("a.tsx", 10, "tsx")], false);
+        assert_eq!(
+            summary,
+            "> Project: site 1.0.0  \n> Language: TypeScript  \n> Frameworks: React  \n\
+             > Files: 1, 10 B\n\n"
+        );
+
+        // Outside a repository only the directory itself counts
+        let bare = web.join("scripts");
+        fs::create_dir(&bare).expect("mkdir");
+        let files = [
+            file("a.py", 1, "python"),
+            file("b.py", 1, "python"),
+            file("c.sh", 1, "sh"),
+        ];
+        assert_eq!(
+            generate_repo_summary(&bare, &files.iter().collect::<Vec<_>>(), false),
+            "> Project: scripts  \n> Language: Python  \n> Files: 3, 3 B\n\n"
+        );
+    }
+
+    #[test]
+    fn multi_line_description_stays_quoted() {
+        let tmp = tempfile::tempdir().expect("tempdir");
+        fs::write(
+            tmp.path().join("Cargo.toml"),
+            "[package]\nname = \"demo\"\ndescription = \"\"\"\nFirst line.\nSecond line.\n\"\"\"\n",
+        )
+        .expect("write");
+        assert_eq!(
+            generate_repo_summary(tmp.path(), &[], false),
+            "> Project: demo  \n> Description: First line.  \n> Second line.  \n\
+             > Language: Rust (edition 2015)  \n> Files: 0, 0 B\n\n"
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! `--timings`: where a run spends its time.
+//!
+//! The pipeline carries an `Option<Timings>`; [`span`] only reads the clock
+//! when it is `Some`, so a run without the flag constructs no timers.
+
+use std::{
+    fmt::Write as _,
+    time::{Duration, Instant},
+};
+
+use crate::units::{fmt_count, fmt_duration};
+
+/// Pipeline stages, in report order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Directory traversal (the walker itself, including ignore rules).
+    Walk,
+    /// Extension, regex, size and content-sniff checks per entry.
+    Filter,
+    /// Reading file bytes.
+    Read,
+    /// UTF-8 decoding and content transforms.
+    Decode,
+    /// Building the Markdown or JSON output.
+    Format,
+    /// Token estimates (budgets, chunking, `--count-tokens`).
+    Tokenize,
+    /// Writing to stdout or `-o`.
+    Write,
+    /// Clipboard copy and `--save-clip`.
+    Clipboard,
+}
+
+impl Phase {
+    const ALL: [Self; 8] = [
+        Self::Walk,
+        Self::Filter,
+        Self::Read,
+        Self::Decode,
+        Self::Format,
+        Self::Tokenize,
+        Self::Write,
+        Self::Clipboard,
+    ];
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Walk => "walk",
+            Self::Filter => "filter",
+            Self::Read => "read",
+            Self::Decode => "decode",
+            Self::Format => "format",
+            Self::Tokenize => "tokenize",
+            Self::Write => "write",
+            Self::Clipboard => "clipboard",
+        }
+    }
+}
+
+/// Accumulated elapsed time and item count per [`Phase`].
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    spans: [(Duration, usize); Phase::ALL.len()],
+    /// Work done on several threads at once (`--jobs`), which the spans
+    /// count per thread.
+    parallel: Option<Parallel>,
+}
+
+/// See [`Timings::ran_in_parallel`].
+#[derive(Debug, Clone, Copy)]
+struct Parallel {
+    threads: usize,
+    wall: Duration,
+    summed: Duration,
+}
+
+impl Timings {
+    pub fn record(&mut self, phase: Phase, elapsed: Duration, items: usize) {
+        let span = &mut self.spans[phase as usize];
+        span.0 += elapsed;
+        span.1 += items;
+    }
+
+    #[must_use]
+    pub fn get(&self, phase: Phase) -> (Duration, usize) {
+        self.spans[phase as usize]
+    }
+
+    /// Add `other`'s spans to these, e.g. from a worker thread.
+    pub fn merge(&mut self, other: &Self) {
+        for (span, (elapsed, items)) in self.spans.iter_mut().zip(other.spans) {
+            span.0 += elapsed;
+            span.1 += items;
+        }
+    }
+
+    /// Note that `summed` of the recorded time was spent on `threads`
+    /// threads side by side, in `wall` time. The phases keep the summed
+    /// time; the total counts the wall time instead.
+    pub fn ran_in_parallel(&mut self, threads: usize, wall: Duration, summed: Duration) {
+        let p = self.parallel.get_or_insert(Parallel {
+            threads,
+            wall: Duration::ZERO,
+            summed: Duration::ZERO,
+        });
+        p.threads = p.threads.max(threads);
+        p.wall += wall;
+        p.summed += summed;
+    }
+
+    /// Wall-clock time of the run's stages.
+    #[must_use]
+    pub fn total(&self) -> Duration {
+        let sum: Duration = self.spans.iter().map(|(d, _)| *d).sum();
+        self.parallel
+            .map_or(sum, |p| sum.saturating_sub(p.summed) + p.wall)
+    }
+
+    /// Aligned `phase: elapsed items` lines, ending with the total. Times
+    /// go through [`fmt_duration`] and counts through [`fmt_count`], exact
+    /// under `--bytes`.
+    #[must_use]
+    pub fn report(&self, exact: bool) -> String {
+        let mut out = String::new();
+        for phase in Phase::ALL {
+            let (elapsed, items) = self.get(phase);
+            let key = format!("{}:", phase.name());
+            let elapsed = fmt_duration(elapsed, exact);
+            let items = fmt_count(items, exact);
+            let _ = writeln!(out, "{key:<11}{elapsed:>12} {items:>8} items");
+        }
+        if let Some(p) = self.parallel {
+            let _ = writeln!(
+                out,
+                "({} of that on {} threads, {} wall clock)",
+                fmt_duration(p.summed, exact),
+                fmt_count(p.threads, exact),
+                fmt_duration(p.wall, exact)
+            );
+        }
+        let total = fmt_duration(self.total(), exact);
+        let _ = writeln!(out, "{:<11}{total:>12}", "total:");
+        out
+    }
+}
+
+/// Run `f`, charging its duration and `items` to `phase` when timing is on.
+pub fn span<T>(
+    timings: &mut Option<Timings>,
+    phase: Phase,
+    items: usize,
+    f: impl FnOnce() -> T,
+) -> T {
+    let Some(t) = timings else {
+        return f();
+    };
+    let start = Instant::now();
+    let out = f();
+    t.record(phase, start.elapsed(), items);
+    out
+}
+
+/// Open a span by hand, for code that does not fit in a closure. Returns
+/// `None` (reading no clock) when timing is off.
+#[must_use]
+pub fn start(timings: Option<&Timings>) -> Option<Instant> {
+    timings.map(|_| Instant::now())
+}
+
+/// Close a span opened with [`start`].
+pub fn stop(timings: &mut Option<Timings>, phase: Phase, started: Option<Instant>, items: usize) {
+    if let (Some(t), Some(started)) = (timings, started) {
+        t.record(phase, started.elapsed(), items);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Phase, Timings, span};
+
+    #[test]
+    fn spans_accumulate_only_when_enabled() {
+        let mut off = None;
+        assert_eq!(span(&mut off, Phase::Read, 1, || 7), 7);
+        assert!(off.is_none());
+
+        let mut on = Some(Timings::default());
+        span(&mut on, Phase::Read, 2, || ());
+        let t = on.expect("enabled");
+        assert_eq!(t.get(Phase::Read).1, 2);
+        let mut t = t;
+        t.record(Phase::Walk, Duration::from_millis(3), 10);
+        assert!(
+            t.report(true)
+                .starts_with("walk:          3.000 ms       10 items\n")
+        );
+        let report = t.report(false);
+        assert!(report.starts_with("walk:            3.0 ms       10 items\n"));
+        assert!(report.lines().last().expect("total").starts_with("total:"));
+        t.record(Phase::Walk, Duration::ZERO, 12_340);
+        assert!(
+            t.report(false)
+                .starts_with("walk:            3.0 ms   12,350 items\n")
+        );
+        assert!(
+            t.report(true)
+                .starts_with("walk:          3.000 ms    12350 items\n")
+        );
+
+        // Four threads reading 2 ms each in 2 ms: the total is wall time
+        let mut t = Timings::default();
+        t.record(Phase::Walk, Duration::from_millis(1), 1);
+        t.record(Phase::Read, Duration::from_millis(8), 4);
+        t.ran_in_parallel(4, Duration::from_millis(2), Duration::from_millis(8));
+        assert_eq!(t.total(), Duration::from_millis(3));
+        let report = t.report(true);
+        assert!(report.contains("\n(8.000 ms of that on 4 threads, 2.000 ms wall clock)\n"));
+        assert!(report.ends_with("total:         3.000 ms\n"));
+    }
+}
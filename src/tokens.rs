@@ -0,0 +1,149 @@
+use clap::ValueEnum;
+
+/// Tokenizer families used for token estimates.
+///
+/// Counts are heuristic (characters per token), not exact encodings, but are
+/// close enough for budgeting a prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Tokenizer {
+    /// OpenAI `cl100k_base` (GPT-4, GPT-3.5)
+    Cl100k,
+    /// OpenAI `o200k_base` (GPT-4o and newer). The `gpt-4o` preset still
+    /// estimates with `cl100k`, which counts slightly more tokens
+    O200k,
+    /// Anthropic Claude models
+    Claude,
+    /// Google Gemini models
+    Gemini,
+}
+
+impl Tokenizer {
+    /// Average characters per token, in tenths.
+    const fn chars_per_token_x10(self) -> usize {
+        match self {
+            Self::Cl100k | Self::Gemini => 40,
+            Self::O200k => 42,
+            Self::Claude => 35,
+        }
+    }
+
+    /// Estimated tokens for a text of `chars` characters.
+    const fn tokens_for_chars(self, chars: usize) -> usize {
+        (chars * 10).div_ceil(self.chars_per_token_x10())
+    }
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Cl100k => "cl100k",
+            Self::O200k => "o200k",
+            Self::Claude => "claude",
+            Self::Gemini => "gemini",
+        }
+    }
+}
+
+/// Estimate how many tokens `text` costs under the given tokenizer.
+#[must_use]
+pub fn estimate_tokens(text: &str, tokenizer: Tokenizer) -> usize {
+    tokenizer.tokens_for_chars(text.chars().count())
+}
+
+/// Cut `text` into runs of whole lines of at most `limit` estimated tokens
+/// each (`--fence-continuation`). A single longer line is a piece of its
+/// own. The pieces concatenate back to `text`.
+#[must_use]
+pub fn split_by_tokens(text: &str, limit: usize, tokenizer: Tokenizer) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let (mut start, mut end, mut chars) = (0, 0, 0);
+    for line in text.split_inclusive('\n') {
+        let line_chars = line.chars().count();
+        if end > start && tokenizer.tokens_for_chars(chars + line_chars) > limit {
+            pieces.push(&text[start..end]);
+            (start, chars) = (end, 0);
+        }
+        end += line.len();
+        chars += line_chars;
+    }
+    if end > start || pieces.is_empty() {
+        pieces.push(&text[start..end]);
+    }
+    pieces
+}
+
+/// What `--max-tokens` does with files once the budget runs out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BudgetPolicy {
+    /// Drop the first file that does not fit and everything after it
+    Stop,
+    /// Drop only the files that do not fit; smaller later files may still fit
+    Skip,
+}
+
+impl BudgetPolicy {
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Stop => "stop",
+            Self::Skip => "skip",
+        }
+    }
+}
+
+/// Decide which files fit in `budget` tokens, in the given order.
+///
+/// `costs[i]` is the estimated cost of file `i`; the result says whether it
+/// is kept. This is the single source of truth for `--max-tokens` and
+/// `--budget-report`.
+#[must_use]
+pub fn plan_budget(costs: &[usize], budget: usize, policy: BudgetPolicy) -> Vec<bool> {
+    let mut used = 0;
+    let mut stopped = false;
+    costs
+        .iter()
+        .map(|&cost| {
+            let fits = !stopped && used + cost <= budget;
+            if fits {
+                used += cost;
+            } else if policy == BudgetPolicy::Stop {
+                stopped = true;
+            }
+            fits
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BudgetPolicy, Tokenizer, estimate_tokens, plan_budget, split_by_tokens};
+
+    #[test]
+    fn budget_policies() {
+        let costs = [40, 50, 30, 10];
+        assert_eq!(
+            plan_budget(&costs, 100, BudgetPolicy::Stop),
+            [true, true, false, false]
+        );
+        assert_eq!(
+            plan_budget(&costs, 100, BudgetPolicy::Skip),
+            [true, true, false, true]
+        );
+        assert_eq!(plan_budget(&costs, 0, BudgetPolicy::Skip), [false; 4]);
+    }
+
+    #[test]
+    fn splits_at_line_boundaries() {
+        let text = "aaaa\nbbbb\ncccccccccccccccc\nd";
+        let pieces = split_by_tokens(text, 3, Tokenizer::Cl100k);
+        assert_eq!(pieces, ["aaaa\nbbbb\n", "cccccccccccccccc\n", "d"]);
+        assert_eq!(pieces.concat(), text);
+        assert_eq!(split_by_tokens("", 3, Tokenizer::Cl100k), [""]);
+    }
+
+    #[test]
+    fn estimate_rounds_up() {
+        assert_eq!(estimate_tokens("", Tokenizer::Cl100k), 0);
+        assert_eq!(estimate_tokens("abcd", Tokenizer::Cl100k), 1);
+        assert_eq!(estimate_tokens("abcde", Tokenizer::Cl100k), 2);
+    }
+}
@@ -0,0 +1,785 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    hash::{DefaultHasher, Hash as _, Hasher as _},
+};
+
+use clap::ValueEnum;
+
+/// Import syntax for the languages `strip_leading_imports` understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ImportStyle {
+    Rust,
+    Python,
+    Script,
+    Go,
+    C,
+}
+
+impl ImportStyle {
+    fn for_lang(lang: &str) -> Option<Self> {
+        Some(match lang {
+            "rust" => Self::Rust,
+            "python" => Self::Python,
+            "typescript" | "tsx" | "javascript" | "jsx" => Self::Script,
+            "go" => Self::Go,
+            "c" | "cpp" => Self::C,
+            _ => return None,
+        })
+    }
+
+    const fn marker(self) -> &'static str {
+        match self {
+            Self::Python => "# … imports omitted …",
+            _ => "// … imports omitted …",
+        }
+    }
+
+    fn is_comment(self, line: &str) -> bool {
+        match self {
+            Self::Python => line.starts_with('#'),
+            _ => line.starts_with("//") || line.starts_with("/*") || line.starts_with('*'),
+        }
+    }
+
+    /// Lines allowed before the import block, kept verbatim.
+    fn is_preamble(self, line: &str) -> bool {
+        match self {
+            Self::Go => line.starts_with("package "),
+            Self::Script => line == "\"use strict\";" || line == "'use strict';",
+            _ => false,
+        }
+    }
+
+    fn starts_import(self, line: &str) -> bool {
+        match self {
+            Self::Rust => {
+                let l = strip_rust_visibility(line).unwrap_or(line);
+                l.starts_with("use ") || l.starts_with("extern crate ")
+            }
+            Self::Python => line.starts_with("import ") || line.starts_with("from "),
+            Self::Script => {
+                line.starts_with("import ")
+                    || line.starts_with("import{")
+                    || ((line.starts_with("const ")
+                        || line.starts_with("let ")
+                        || line.starts_with("var "))
+                        && line.contains("require("))
+            }
+            Self::Go => line.starts_with("import ") || line.starts_with("import("),
+            Self::C => line.starts_with("#include") || line.starts_with("# include"),
+        }
+    }
+}
+
+/// A Rust `pub use` (or `pub(crate) use`, …) re-export: part of the API, so
+/// kept where the import block is stripped.
+fn is_reexport(style: ImportStyle, line: &str) -> bool {
+    style == ImportStyle::Rust && strip_rust_visibility(line).is_some()
+}
+
+/// The rest of `line` after a leading `pub` or `pub(…)` visibility.
+fn strip_rust_visibility(line: &str) -> Option<&str> {
+    let rest = line.strip_prefix("pub")?;
+    let rest = match rest.strip_prefix('(') {
+        Some(inner) => inner.split_once(')')?.1,
+        None => rest,
+    };
+    rest.strip_prefix(' ')
+}
+
+/// Net change in bracket nesting across a line (good enough for import lists).
+fn depth_delta(line: &str) -> i32 {
+    line.chars()
+        .map(|c| match c {
+            '{' | '(' | '[' => 1,
+            '}' | ')' | ']' => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Replace the contiguous block of imports at the top of a file with a short
+/// marker comment. Leading comments, blank lines, Rust inner attributes
+/// (`#![…]`), Python docstrings and Go `package` clauses are kept, and so are
+/// Rust `pub use` re-exports in the block (after the marker, with the comments
+/// right above them); code after the block is untouched.
+///
+/// Unknown languages and files without a leading import block are returned
+/// unchanged.
+#[must_use]
+pub fn strip_leading_imports<'a>(content: &'a str, lang: &str) -> Cow<'a, str> {
+    let Some(style) = ImportStyle::for_lang(lang) else {
+        return Cow::Borrowed(content);
+    };
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+
+    // Preamble: blank lines, comments, docstrings, package clauses, inner
+    // attributes
+    let mut i = 0;
+    while i < lines.len() {
+        let t = lines[i].trim();
+        if t.is_empty() || style.is_comment(t) || style.is_preamble(t) {
+            i += 1;
+        } else if style == ImportStyle::Rust && t.starts_with("#![") {
+            let mut depth = depth_delta(t);
+            i += 1;
+            while depth > 0 && i < lines.len() {
+                depth += depth_delta(lines[i].trim());
+                i += 1;
+            }
+        } else if style == ImportStyle::Python && (t.starts_with("\"\"\"") || t.starts_with("'''"))
+        {
+            let quote = &t[..3];
+            let closed_here = t.len() >= 6 && t[3..].contains(quote);
+            i += 1;
+            if !closed_here {
+                while i < lines.len() && !lines[i].contains(quote) {
+                    i += 1;
+                }
+                i += 1;
+            }
+        } else {
+            break;
+        }
+    }
+    let block_start = i;
+
+    // Import statements, possibly multi-line, separated by blanks/comments
+    let mut block_end = None;
+    let mut stripped = false;
+    let mut kept = Vec::new();
+    while i < lines.len() {
+        let t = lines[i].trim();
+        if t.is_empty() || style.is_comment(t) {
+            i += 1;
+            continue;
+        }
+        if !style.starts_import(t) {
+            break;
+        }
+        let reexport = is_reexport(style, t);
+        let mut start = i;
+        while reexport && start > block_end.unwrap_or(block_start) {
+            let above = lines[start - 1].trim();
+            if above.is_empty() || !style.is_comment(above) {
+                break;
+            }
+            start -= 1;
+        }
+        let mut depth = depth_delta(t);
+        let mut cont = style == ImportStyle::Python && t.ends_with('\\');
+        i += 1;
+        while (depth > 0 || cont) && i < lines.len() {
+            let t = lines[i].trim();
+            depth += depth_delta(t);
+            cont = style == ImportStyle::Python && t.ends_with('\\');
+            i += 1;
+        }
+        if reexport {
+            kept.extend_from_slice(&lines[start..i]);
+        } else {
+            stripped = true;
+        }
+        block_end = Some(i);
+    }
+
+    let Some(block_end) = block_end.filter(|_| stripped) else {
+        return Cow::Borrowed(content);
+    };
+    let mut out = String::with_capacity(content.len());
+    out.extend(lines[..block_start].iter().copied());
+    out.push_str(style.marker());
+    out.push('\n');
+    out.extend(kept);
+    out.extend(lines[block_end..].iter().copied());
+    Cow::Owned(out)
+}
+
+/// Expand tabs to spaces using tab stops every `width` columns.
+///
+/// Only the leading indentation of each line is touched unless `all` is set,
+/// so tab-delimited data further into a line survives.
+#[must_use]
+pub fn expand_tabs(content: &str, width: usize, all: bool) -> Cow<'_, str> {
+    if width == 0 || !content.contains('\t') {
+        return Cow::Borrowed(content);
+    }
+    let mut out = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        let mut col = 0;
+        let mut indent = true;
+        for c in line.chars() {
+            match c {
+                '\t' if indent || all => {
+                    let n = width - col % width;
+                    out.extend(std::iter::repeat_n(' ', n));
+                    col += n;
+                }
+                ' ' => {
+                    out.push(' ');
+                    col += 1;
+                }
+                _ => {
+                    indent = false;
+                    out.push(c);
+                    col += 1;
+                }
+            }
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// Marker that starts the continuation of a soft-wrapped line.
+pub const WRAP_MARKER: &str = "\u{21aa} ";
+
+/// Soft-wrap lines longer than `width` characters (`--wrap`).
+///
+/// A line is broken at its last run of whitespace that fits (the run itself
+/// is dropped), or mid-word when there is none. Continuation lines repeat
+/// the line's indentation followed by [`WRAP_MARKER`], unless the indent
+/// leaves too little room, in which case only the marker is kept. Line
+/// endings (CRLF or LF) are kept per line.
+#[must_use]
+pub fn soft_wrap(content: &str, width: usize) -> Cow<'_, str> {
+    if width == 0
+        || !content
+            .split('\n')
+            .any(|line| line.trim_end_matches('\r').chars().count() > width)
+    {
+        return Cow::Borrowed(content);
+    }
+    let marker_len = WRAP_MARKER.chars().count();
+    let mut out = String::with_capacity(content.len() + content.len() / width * 8);
+    for line in content.split_inclusive('\n') {
+        let body = line.trim_end_matches('\n').trim_end_matches('\r');
+        let nl = &line[body.len()..];
+        if body.chars().count() <= width {
+            out.push_str(line);
+            continue;
+        }
+        let indent = &body[..body.len() - body.trim_start_matches([' ', '\t']).len()];
+        let indent_len = indent.chars().count();
+        // Keep at least a few columns of text beside the indent and marker
+        let prefix = if indent_len + marker_len + 8 <= width {
+            format!("{indent}{WRAP_MARKER}")
+        } else {
+            WRAP_MARKER.to_string()
+        };
+        let room = width.saturating_sub(prefix.chars().count()).max(1);
+        let (first, mut rest) = take_columns(body, width, indent.len());
+        out.push_str(first);
+        while !rest.is_empty() {
+            out.push_str(if nl.is_empty() { "\n" } else { nl });
+            out.push_str(&prefix);
+            let (piece, tail) = take_columns(rest, room, 0);
+            out.push_str(piece);
+            rest = tail;
+        }
+        out.push_str(nl);
+    }
+    Cow::Owned(out)
+}
+
+/// Split off up to `width` characters of `text` at the last whitespace run
+/// after byte offset `from`, or exactly at `width` when there is none. The
+/// whitespace at the break belongs to neither half.
+fn take_columns(text: &str, width: usize, from: usize) -> (&str, &str) {
+    let Some((cut, _)) = text.char_indices().nth(width) else {
+        return (text, "");
+    };
+    let head = &text[..cut];
+    let from = from.min(cut);
+    let break_at = if text[cut..].starts_with(char::is_whitespace) {
+        Some(cut)
+    } else {
+        // Start of the last whitespace run in the head
+        head[from..]
+            .char_indices()
+            .filter(|&(i, c)| c.is_whitespace() && !head[..from + i].ends_with(char::is_whitespace))
+            .map(|(i, _)| from + i)
+            .next_back()
+    };
+    match break_at {
+        Some(at) if !text[..at].trim_start().is_empty() => {
+            (text[..at].trim_end(), text[at..].trim_start())
+        }
+        _ => (head, &text[cut..]),
+    }
+}
+
+/// Remove ANSI/VT escape sequences (colors, cursor movement, titles) from
+/// captured terminal output.
+///
+/// Handles CSI (`ESC [ … final`, and the 8-bit `\u{9b}` form), OSC
+/// (`ESC ] … BEL` or `ESC \\`), DCS/SOS/PM/APC strings terminated by `ESC \\`,
+/// and two-character escapes with intermediates (`ESC ( B`). A lone `ESC`
+/// at the end is dropped.
+#[must_use]
+pub fn strip_ansi(content: &str) -> Cow<'_, str> {
+    #[derive(Clone, Copy)]
+    enum State {
+        Text,
+        Escape,
+        Csi,
+        /// OSC: ends at BEL or ST.
+        Osc,
+        /// DCS, SOS, PM, APC: end at ST only.
+        Str,
+        /// `ESC` inside an OSC or string: `\\` completes ST, anything else
+        /// starts a new escape.
+        StrEscape,
+    }
+    const fn after_escape(c: char) -> State {
+        match c {
+            '[' => State::Csi,
+            ']' => State::Osc,
+            'P' | 'X' | '^' | '_' => State::Str,
+            // Intermediate bytes; the sequence ends at the next final byte
+            ' '..='/' => State::Escape,
+            _ => State::Text,
+        }
+    }
+
+    if !content.contains(['\x1b', '\u{9b}']) {
+        return Cow::Borrowed(content);
+    }
+    let mut out = String::with_capacity(content.len());
+    let mut state = State::Text;
+    for c in content.chars() {
+        state = match (state, c) {
+            (State::Text, '\x1b') => State::Escape,
+            (State::Text, '\u{9b}') => State::Csi,
+            (State::Text, c) => {
+                out.push(c);
+                State::Text
+            }
+            (State::StrEscape, '\\') | (State::Csi, '@'..='~') | (State::Osc, '\x07') => {
+                State::Text
+            }
+            (State::Escape | State::StrEscape, c) => after_escape(c),
+            (State::Csi, _) => State::Csi,
+            (State::Osc | State::Str, '\x1b') => State::StrEscape,
+            (State::Osc, _) => State::Osc,
+            (State::Str, _) => State::Str,
+        };
+    }
+    Cow::Owned(out)
+}
+
+/// Replace `${VAR}` references with the value of the environment variable
+/// (`--stdin-env-vars`). Unset variables, malformed names (`${1X}`, `${a-b}`)
+/// and unclosed references are left as they are; with `warn`, each unset
+/// variable is reported on stderr.
+#[must_use]
+pub fn substitute_env_vars(content: &str, warn: bool) -> String {
+    substitute_vars(
+        content,
+        |name| std::env::var(name).ok(),
+        |name| {
+            if warn {
+                eprintln!(
+                    ">> warning: environment variable {name} is not set; leaving ${{{name}}}"
+                );
+            }
+        },
+    )
+}
+
+fn substitute_vars(
+    content: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+    mut missing: impl FnMut(&str),
+) -> String {
+    #[derive(Clone, Copy)]
+    enum State {
+        Text,
+        /// After a `$` at this offset.
+        Dollar(usize),
+        /// Inside `${` opened at this offset.
+        Name(usize),
+    }
+    let is_name = |name: &str| {
+        name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+
+    if !content.contains("${") {
+        return content.to_string();
+    }
+    let mut out = String::with_capacity(content.len());
+    let mut state = State::Text;
+    for (i, c) in content.char_indices() {
+        state = match (state, c) {
+            (State::Dollar(start), '{') => State::Name(start),
+            (State::Name(start), '}') => {
+                let name = &content[start + 2..i];
+                match is_name(name).then(|| lookup(name)) {
+                    Some(Some(value)) => out.push_str(&value),
+                    Some(None) => {
+                        missing(name);
+                        out.push_str(&content[start..=i]);
+                    }
+                    None => out.push_str(&content[start..=i]),
+                }
+                State::Text
+            }
+            (State::Name(_), c) if c.is_ascii_alphanumeric() || c == '_' => state,
+            (State::Name(start) | State::Dollar(start), c) => {
+                out.push_str(&content[start..i]);
+                if c == '$' {
+                    State::Dollar(i)
+                } else {
+                    out.push(c);
+                    State::Text
+                }
+            }
+            (State::Text, '$') => State::Dollar(i),
+            (State::Text, c) => {
+                out.push(c);
+                State::Text
+            }
+        };
+    }
+    if let State::Dollar(start) | State::Name(start) = state {
+        out.push_str(&content[start..]);
+    }
+    out
+}
+
+/// How trailing newlines are handled when content is put in a fence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum EofNewline {
+    /// Keep the content as is
+    #[default]
+    Preserve,
+    /// End the content with exactly one newline
+    Ensure,
+    /// Remove trailing newlines; the closing fence follows the last line
+    Strip,
+}
+
+/// Everything between an opening fence line and its closing fence.
+///
+/// The closing fence always starts a line of its own, so content without a
+/// final newline gets one. `Preserve` and `Ensure` also keep the customary
+/// blank line before the closing fence; `Strip` drops it along with the
+/// content's own trailing newlines. The content's last line keeps its own
+/// ending (CRLF stays CRLF); the lines added here end with `nl`.
+#[must_use]
+pub fn fence_body(content: &str, mode: EofNewline, nl: &str) -> String {
+    let trimmed = content.trim_end_matches(['\r', '\n']);
+    let tail = &content[trimmed.len()..];
+    let eol = if tail.starts_with("\r\n") {
+        "\r\n"
+    } else if tail.is_empty() {
+        nl
+    } else {
+        "\n"
+    };
+    match mode {
+        EofNewline::Preserve if content.ends_with('\n') => format!("{content}{nl}"),
+        EofNewline::Preserve => format!("{content}{nl}{nl}"),
+        EofNewline::Ensure => format!("{trimmed}{eol}{nl}"),
+        EofNewline::Strip => format!("{trimmed}{eol}"),
+    }
+}
+
+/// Line endings of the text fencecat writes itself (`--output-newline`):
+/// fence lines, blank separators, listings and notes. File content keeps its
+/// own endings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputNewline {
+    #[default]
+    Lf,
+    Crlf,
+    /// CRLF on Windows, LF elsewhere
+    Native,
+}
+
+impl OutputNewline {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::Crlf => "\r\n",
+            Self::Native if cfg!(windows) => "\r\n",
+            Self::Native => "\n",
+        }
+    }
+
+    /// Convert the `\n` line breaks of generated text, which must not
+    /// contain file content.
+    #[must_use]
+    pub fn apply(self, text: &str) -> Cow<'_, str> {
+        match self.as_str() {
+            "\n" => Cow::Borrowed(text),
+            nl => Cow::Owned(text.replace('\n', nl)),
+        }
+    }
+}
+
+/// Line comment token for a fence language (`#` or `//`).
+fn line_comment(lang: Option<&str>) -> &'static str {
+    match lang {
+        Some(
+            "python" | "sh" | "bash" | "zsh" | "fish" | "ruby" | "perl" | "yaml" | "toml"
+            | "makefile" | "cmake" | "nix" | "elixir" | "powershell" | "dockerfile" | "just",
+        ) => "#",
+        _ => "//",
+    }
+}
+
+/// Finds file headers (the first N lines: license blocks, "generated by"
+/// banners) shared by many files, for `--compress-repetitive`.
+///
+/// Feed every file through [`observe`](Self::observe) first, then
+/// [`compress`](Self::compress) replaces the header of every file but the
+/// first once it appears in more than `threshold` files.
+#[derive(Debug, Clone)]
+pub struct RepetitionDetector {
+    lines: usize,
+    threshold: usize,
+    /// Header hash → (first path seen, number of files).
+    seen: HashMap<u64, (String, usize)>,
+}
+
+impl RepetitionDetector {
+    #[must_use]
+    pub fn new(lines: usize, threshold: usize) -> Self {
+        Self {
+            lines,
+            threshold,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Byte length and hash of the header, if the file is long enough to have one.
+    fn header(&self, content: &str) -> Option<(usize, u64)> {
+        let head: Vec<&str> = content.split_inclusive('\n').take(self.lines).collect();
+        if self.lines == 0 || head.len() < self.lines {
+            return None;
+        }
+        let len = head.iter().map(|l| l.len()).sum();
+        let header = &content[..len];
+        if header.trim().is_empty() {
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        header.hash(&mut hasher);
+        Some((len, hasher.finish()))
+    }
+
+    /// Record one file's header.
+    pub fn observe(&mut self, path: &str, content: &str) {
+        if let Some((_, hash)) = self.header(content) {
+            self.seen
+                .entry(hash)
+                .or_insert_with(|| (path.to_string(), 0))
+                .1 += 1;
+        }
+    }
+
+    /// Replace a repeated header with a pointer to its first occurrence.
+    #[must_use]
+    pub fn compress<'a>(&self, path: &str, content: &'a str, lang: Option<&str>) -> Cow<'a, str> {
+        let Some((len, hash)) = self.header(content) else {
+            return Cow::Borrowed(content);
+        };
+        match self.seen.get(&hash) {
+            Some((first, count)) if *count > self.threshold && first != path => {
+                Cow::Owned(format!(
+                    "{} [header repeated from first occurrence — see {first}]\n{}",
+                    line_comment(lang),
+                    &content[len..]
+                ))
+            }
+            _ => Cow::Borrowed(content),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::{
+        EofNewline, RepetitionDetector, expand_tabs, fence_body, soft_wrap, strip_ansi,
+        strip_leading_imports, substitute_vars,
+    };
+
+    #[test]
+    fn substitutes_set_variables_only() {
+        let lookup = |name: &str| (name == "DATABASE_URL").then(|| "postgres://db".to_string());
+        let mut missing = Vec::new();
+        let out = substitute_vars(
+            "url=${DATABASE_URL}\nkey=${API_KEY} $HOME ${1X} ${a-b} $${DATABASE_URL} ${OPEN",
+            lookup,
+            |name| missing.push(name.to_string()),
+        );
+        assert_eq!(
+            out,
+            "url=postgres://db\nkey=${API_KEY} $HOME ${1X} ${a-b} $postgres://db ${OPEN"
+        );
+        assert_eq!(missing, ["API_KEY"]);
+    }
+
+    #[test]
+    fn strips_ansi_sequences() {
+        let log = "\x1b[1;32mok\x1b[0m done\n\x1b]0;title\x07\x1b(Bhi\x1b[2K\x1bP1$r\x1b\\!\n";
+        assert_eq!(strip_ansi(log), "ok done\nhi!\n");
+        assert_eq!(strip_ansi("\u{9b}31mred\x1b"), "red");
+        assert!(matches!(strip_ansi("plain\n"), Cow::Borrowed("plain\n")));
+    }
+
+    #[test]
+    fn fence_body_eof_modes() {
+        let cases = [
+            // (content, preserve, ensure, strip)
+            ("a", "a\n\n", "a\n\n", "a\n"),
+            ("a\n", "a\n\n", "a\n\n", "a\n"),
+            ("a\n\n\n", "a\n\n\n\n", "a\n\n", "a\n"),
+            ("a\r\n\r\n", "a\r\n\r\n\n", "a\r\n\n", "a\r\n"),
+        ];
+        for (content, preserve, ensure, strip) in cases {
+            assert_eq!(fence_body(content, EofNewline::Preserve, "\n"), preserve);
+            assert_eq!(fence_body(content, EofNewline::Ensure, "\n"), ensure);
+            assert_eq!(fence_body(content, EofNewline::Strip, "\n"), strip);
+        }
+    }
+
+    #[test]
+    fn fence_body_adds_crlf_but_keeps_content_endings() {
+        let cases = [
+            // (content, preserve, ensure, strip)
+            ("a", "a\r\n\r\n", "a\r\n\r\n", "a\r\n"),
+            ("a\n", "a\n\r\n", "a\n\r\n", "a\n"),
+            ("a\r\n", "a\r\n\r\n", "a\r\n\r\n", "a\r\n"),
+        ];
+        for (content, preserve, ensure, strip) in cases {
+            assert_eq!(fence_body(content, EofNewline::Preserve, "\r\n"), preserve);
+            assert_eq!(fence_body(content, EofNewline::Ensure, "\r\n"), ensure);
+            assert_eq!(fence_body(content, EofNewline::Strip, "\r\n"), strip);
+        }
+    }
+
+    #[test]
+    fn repeated_headers_point_at_first_file() {
+        let header = "// Copyright Acme\n// SPDX: MIT\n";
+        let files: Vec<(String, String)> = (0..4)
+            .map(|i| (format!("f{i}.rs"), format!("{header}fn f{i}() {{}}\n")))
+            .collect();
+        let mut det = RepetitionDetector::new(2, 3);
+        for (path, content) in &files {
+            det.observe(path, content);
+        }
+        assert_eq!(det.compress("f0.rs", &files[0].1, Some("rust")), files[0].1);
+        assert_eq!(
+            det.compress("f2.rs", &files[2].1, Some("rust")),
+            "// [header repeated from first occurrence — see f0.rs]\nfn f2() {}\n"
+        );
+
+        let mut few = RepetitionDetector::new(2, 3);
+        for (path, content) in &files[..3] {
+            few.observe(path, content);
+        }
+        assert_eq!(few.compress("f2.rs", &files[2].1, Some("rust")), files[2].1);
+    }
+
+    #[test]
+    fn expands_only_leading_tabs_by_default() {
+        let src = "\tfn x() {\n\t\ta\tb\n  \tc\n}\n";
+        assert_eq!(
+            expand_tabs(src, 4, false),
+            "    fn x() {\n        a\tb\n    c\n}\n"
+        );
+    }
+
+    #[test]
+    fn expands_all_tabs_to_tab_stops() {
+        assert_eq!(expand_tabs("ab\tc\td\n", 4, true), "ab  c   d\n");
+        assert_eq!(expand_tabs("no tabs\n", 4, true), "no tabs\n");
+    }
+
+    #[test]
+    fn soft_wrap_breaks_at_whitespace_and_keeps_indent() {
+        let src = "short\n    let total = first + second + third;\r\nend";
+        assert_eq!(
+            soft_wrap(src, 24),
+            "short\n    let total = first +\r\n    \u{21aa} second + third;\r\nend"
+        );
+        assert_eq!(
+            soft_wrap("abcdefghij\n", 4),
+            "abcd\n\u{21aa} ef\n\u{21aa} gh\n\u{21aa} ij\n"
+        );
+        assert!(matches!(soft_wrap(src, 0), Cow::Borrowed(_)));
+        assert!(matches!(soft_wrap("fits\n", 4), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn rust_multiline_use() {
+        let src = "//! Crate docs\n\nuse std::{\n    fs,\n    io,\n};\nuse regex::Regex;\n\nfn main() {}\n";
+        assert_eq!(
+            strip_leading_imports(src, "rust"),
+            "//! Crate docs\n\n// … imports omitted …\n\nfn main() {}\n"
+        );
+    }
+
+    #[test]
+    fn rust_inner_attributes_are_skipped() {
+        let src = "//! Crate docs\n#![allow(dead_code)]\n#![cfg_attr(\n    docsrs,\n    feature(doc_cfg)\n)]\n\n/// Docs\nuse std::fs;\n\nfn main() {}\n";
+        assert_eq!(
+            strip_leading_imports(src, "rust"),
+            "//! Crate docs\n#![allow(dead_code)]\n#![cfg_attr(\n    docsrs,\n    feature(doc_cfg)\n)]\n\n/// Docs\n// … imports omitted …\n\nfn main() {}\n"
+        );
+    }
+
+    #[test]
+    fn rust_reexports_are_kept() {
+        let src = "use std::fs;\n/// The parser.\npub use parse::{\n    Parser,\n};\n// internal\nuse regex::Regex;\npub(crate) use lang::Lang;\n\nfn main() {}\n";
+        assert_eq!(
+            strip_leading_imports(src, "rust"),
+            "// … imports omitted …\n/// The parser.\npub use parse::{\n    Parser,\n};\npub(crate) use lang::Lang;\n\nfn main() {}\n"
+        );
+        let only = "pub use a::A;\n\nfn main() {}\n";
+        assert!(matches!(
+            strip_leading_imports(only, "rust"),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn python_docstring_and_parens() {
+        let src = "\"\"\"Module.\n\nMore.\n\"\"\"\nimport os\nfrom typing import (\n    Any,\n)\n\nx = 1\n";
+        assert_eq!(
+            strip_leading_imports(src, "python"),
+            "\"\"\"Module.\n\nMore.\n\"\"\"\n# … imports omitted …\n\nx = 1\n"
+        );
+    }
+
+    #[test]
+    fn typescript_imports_and_require() {
+        let src = "import {\n  a,\n  b,\n} from './ab';\nimport 'side-effect';\nconst fs = require('fs');\nexport const c = a + b;\n";
+        assert_eq!(
+            strip_leading_imports(src, "typescript"),
+            "// … imports omitted …\nexport const c = a + b;\n"
+        );
+    }
+
+    #[test]
+    fn go_keeps_package_clause() {
+        let src = "package main\n\nimport (\n\t\"fmt\"\n\t\"os\"\n)\n\nfunc main() {}\n";
+        assert_eq!(
+            strip_leading_imports(src, "go"),
+            "package main\n\n// … imports omitted …\n\nfunc main() {}\n"
+        );
+    }
+
+    #[test]
+    fn leaves_code_without_imports_alone() {
+        let src = "fn main() {\n    use std::fs;\n}\n";
+        assert_eq!(strip_leading_imports(src, "rust"), src);
+        assert_eq!(strip_leading_imports("use x;\n", "toml"), "use x;\n");
+    }
+}
@@ -0,0 +1,126 @@
+//! Sizes, counts and durations for people, or exact for scripts (`--bytes`).
+
+use std::time::Duration;
+
+const SIZE_UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+/// Format a byte count with binary units and one decimal, e.g. `12.0 KiB`.
+/// A value that would round up to 1024 of a unit is shown in the next one
+/// (`1.0 MiB`, not `1024.0 KiB`).
+#[must_use]
+pub fn human_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let bytes = u128::from(bytes);
+    let mut unit = 1;
+    // Tenths of the unit, rounded half up, in integers to avoid float casts
+    let tenths = |unit: u32| {
+        let scale = 1024u128.pow(unit);
+        (bytes * 10 + scale / 2) / scale
+    };
+    while unit + 1 < SIZE_UNITS.len() && tenths(unit as u32) >= 10 * 1024 {
+        unit += 1;
+    }
+    let t = tenths(unit as u32);
+    format!("{}.{} {}", t / 10, t % 10, SIZE_UNITS[unit])
+}
+
+/// Format a count with `,` between groups of three digits, e.g. `3,412`.
+#[must_use]
+pub fn group_digits(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// A size for messages: [`human_size`], or the exact `N B` when `exact`.
+#[must_use]
+pub fn fmt_size(bytes: u64, exact: bool) -> String {
+    if exact {
+        format!("{bytes} B")
+    } else {
+        human_size(bytes)
+    }
+}
+
+/// A count for messages: [`group_digits`], or plain digits when `exact`.
+#[must_use]
+pub fn fmt_count(n: usize, exact: bool) -> String {
+    if exact {
+        n.to_string()
+    } else {
+        group_digits(n)
+    }
+}
+
+/// A duration for messages: `850 µs`, `12.3 ms`, `4.2 s` or `3m 07s`, or
+/// milliseconds with three decimals when `exact`.
+#[must_use]
+pub fn fmt_duration(d: Duration, exact: bool) -> String {
+    let micros = d.as_micros();
+    if exact {
+        return format!("{}.{:03} ms", micros / 1000, micros % 1000);
+    }
+    // Rounded to tenths of a millisecond / of a second
+    let ms_tenths = (micros + 50) / 100;
+    let s_tenths = (micros + 50_000) / 100_000;
+    if micros < 1000 {
+        format!("{micros} µs")
+    } else if ms_tenths < 10_000 {
+        format!("{}.{} ms", ms_tenths / 10, ms_tenths % 10)
+    } else if s_tenths < 600 {
+        format!("{}.{} s", s_tenths / 10, s_tenths % 10)
+    } else {
+        let secs = (micros + 500_000) / 1_000_000;
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{fmt_count, fmt_duration, fmt_size, group_digits, human_size};
+
+    #[test]
+    fn sizes_round_at_unit_edges() {
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(1023), "1023 B");
+        assert_eq!(human_size(1024), "1.0 KiB");
+        assert_eq!(human_size(1535), "1.5 KiB");
+        assert_eq!(human_size(1_048_575), "1.0 MiB");
+        assert_eq!(human_size(1_048_576), "1.0 MiB");
+        assert_eq!(human_size(1_073_741_823), "1.0 GiB");
+        assert_eq!(human_size(u64::MAX), "16777216.0 TiB");
+        assert_eq!(fmt_size(1_048_575, true), "1048575 B");
+    }
+
+    #[test]
+    fn groups_digits_in_threes() {
+        assert_eq!(group_digits(0), "0");
+        assert_eq!(group_digits(999), "999");
+        assert_eq!(group_digits(3412), "3,412");
+        assert_eq!(group_digits(1_234_567), "1,234,567");
+        assert_eq!(fmt_count(1_234_567, true), "1234567");
+    }
+
+    #[test]
+    fn durations_pick_a_readable_unit() {
+        let us = Duration::from_micros;
+        assert_eq!(fmt_duration(us(999), false), "999 µs");
+        assert_eq!(fmt_duration(us(1000), false), "1.0 ms");
+        assert_eq!(fmt_duration(us(999_949), false), "999.9 ms");
+        assert_eq!(fmt_duration(us(999_950), false), "1.0 s");
+        assert_eq!(fmt_duration(us(59_949_999), false), "59.9 s");
+        assert_eq!(fmt_duration(us(59_950_000), false), "1m 00s");
+        assert_eq!(fmt_duration(us(187_000_000), false), "3m 07s");
+        assert_eq!(fmt_duration(us(1_234), true), "1.234 ms");
+    }
+}
@@ -0,0 +1,2600 @@
+use std::{fs, path::Path, process::Command};
+
+fn fencecat(dir: &Path, args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_fencecat"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .expect("run fencecat")
+}
+
+#[test]
+fn output_file_is_not_fed_back_into_the_next_run() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("b.rs"), "fn b() {}\n").expect("write");
+
+    let first = fencecat(tmp.path(), &[".", "-o", "context.md"]);
+    assert!(first.status.success());
+    let first_out = fs::read_to_string(tmp.path().join("context.md")).expect("read");
+
+    let second = fencecat(tmp.path(), &[".", "-o", "context.md"]);
+    assert!(second.status.success());
+    let second_out = fs::read_to_string(tmp.path().join("context.md")).expect("read");
+
+    assert_eq!(first_out, second_out);
+    assert!(!second_out.contains("context.md"));
+}
+
+#[test]
+fn copied_dump_is_skipped_unless_requested() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("b.rs"), "fn b() {}\n").expect("write");
+    fs::write(tmp.path().join("c.rs"), "fn c() {}\n").expect("write");
+    let dump = fencecat(tmp.path(), &["."]).stdout;
+    fs::write(tmp.path().join("old.md"), dump).expect("write");
+    // Path-labeled fences without fencecat's padding are an ordinary doc
+    fs::write(
+        tmp.path().join("guide.md"),
+        "```rust a.rs\nfn a() {}\n```\n\n```rust b.rs\nfn b() {}\n```\n\n```rust c.rs\nfn c() {}\n```\n",
+    )
+    .expect("write");
+
+    let out = fencecat(tmp.path(), &["."]);
+    assert!(!String::from_utf8_lossy(&out.stdout).contains("old.md"));
+    assert!(String::from_utf8_lossy(&out.stdout).contains("guide.md"));
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains(">> skip old.md: looks like fencecat output")
+    );
+    for quiet in [&[".", "-q"][..], &[".", "--on-error", "skip"]] {
+        let out = fencecat(tmp.path(), quiet);
+        assert!(!String::from_utf8_lossy(&out.stderr).contains("fencecat output"));
+    }
+
+    let out = fencecat(tmp.path(), &[".", "--include-fencecat-output"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("old.md"));
+
+    // A default dump written by an earlier run under another name
+    for name in ["ctx.md", "ctx2.md"] {
+        assert!(fencecat(tmp.path(), &[".", "-o", name]).status.success());
+    }
+    let second = fs::read_to_string(tmp.path().join("ctx2.md")).expect("read");
+    assert!(!second.contains("ctx.md"));
+}
+
+#[test]
+fn missing_path_is_a_usage_error() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let out = fencecat(tmp.path(), &["does-not-exist"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).starts_with("error: no such file"));
+}
+
+#[test]
+fn bad_regex_is_a_usage_error() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let out = fencecat(tmp.path(), &[".", "--regex", "("]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("invalid regex in --regex"));
+}
+
+#[test]
+fn empty_match_set_exits_3() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+    let out = fencecat(tmp.path(), &[".", "--ext", "py"]);
+    assert_eq!(out.status.code(), Some(3));
+    assert!(out.stdout.is_empty());
+}
+
+#[test]
+fn include_cargo_lock_bypasses_ignore_rules() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join(".ignore"), "Cargo.lock\n").expect("write");
+    fs::write(tmp.path().join("Cargo.lock"), "version = 4\n").expect("write");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+
+    let plain = fencecat(tmp.path(), &["."]);
+    assert!(!String::from_utf8_lossy(&plain.stdout).contains("Cargo.lock"));
+
+    let forced = fencecat(
+        tmp.path(),
+        &[
+            ".",
+            "--not-ext",
+            "lock",
+            "--include-cargo-lock",
+            "--include-package-lock",
+        ],
+    );
+    assert!(forced.status.success());
+    let stdout = String::from_utf8_lossy(&forced.stdout);
+    assert!(stdout.contains("Cargo.lock\nversion = 4\n"));
+    assert!(
+        forced.stderr.is_empty(),
+        "absent package-lock.json is not an error"
+    );
+}
+
+#[test]
+fn force_include_stays_under_the_scan_root() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::create_dir(tmp.path().join("sub")).expect("mkdir");
+    fs::write(tmp.path().join("sub/.ignore"), "a.rs\n").expect("write");
+    fs::write(tmp.path().join("sub/a.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("out.rs"), "fn out() {}\n").expect("write");
+
+    let out = fencecat(tmp.path(), &["sub", "--force-include", "./a.rs"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with("```rust a.rs\n"));
+
+    let outside = tmp.path().join("out.rs");
+    for path in ["../out.rs", outside.to_str().expect("utf-8")] {
+        let out = fencecat(tmp.path(), &["sub", "--force-include", path]);
+        assert_eq!(out.status.code(), Some(2), "{path}");
+        assert!(String::from_utf8_lossy(&out.stderr).contains("not a path under the scan root"));
+        assert!(out.stdout.is_empty());
+    }
+}
+
+#[test]
+fn fencecat_opts_are_defaults_that_flags_override() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("b.py"), "b = 1\n").expect("write");
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_fencecat"))
+            .current_dir(tmp.path())
+            .env("FENCECAT_OPTS", "--ext rs --format json --verbose")
+            .args(args)
+            .output()
+            .expect("run fencecat")
+    };
+
+    let out = run(&["."]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("a.rs") && !stdout.contains("b.py"));
+    assert!(stdout.starts_with('['));
+    assert!(
+        String::from_utf8_lossy(&out.stderr)
+            .contains("options from FENCECAT_OPTS: --ext rs --format json --verbose")
+    );
+
+    let out = run(&[".", "--format", "md"]);
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with("```rust a.rs\n"));
+
+    // List flags replace the defaults too
+    let out = run(&[".", "--ext", "py"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("b.py") && !stdout.contains("a.rs"));
+
+    let out = run(&[".", "--no-env"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("a.rs") && stdout.contains("b.py"));
+    assert!(out.stderr.is_empty());
+}
+
+#[test]
+fn diff_against_stdin_emits_only_changes() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("same.rs"), "fn same() {}\n").expect("write");
+    fs::write(tmp.path().join("edit.rs"), "fn old() {}\n").expect("write");
+    fs::write(tmp.path().join("gone.rs"), "fn gone() {}\n").expect("write");
+    let previous = fencecat(tmp.path(), &["."]).stdout;
+
+    fs::write(tmp.path().join("edit.rs"), "fn new() {}\n").expect("write");
+    fs::remove_file(tmp.path().join("gone.rs")).expect("remove");
+    let out = fencecat_stdin(tmp.path(), &[".", "--diff-against-stdin"], &previous);
+
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("```diff edit.rs\n"));
+    assert!(stdout.contains("-fn old() {}\n+fn new() {}\n"));
+    assert!(stdout.contains("[removed: gone.rs]"));
+    assert!(!stdout.contains("same.rs"));
+
+    // Diff headers name the file as the fence label does
+    let out = fencecat_stdin(
+        tmp.path(),
+        &[".", "--diff-against-stdin", "--absolute-paths"],
+        &previous,
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let edit = fs::canonicalize(tmp.path())
+        .expect("canonicalize")
+        .join("edit.rs");
+    let edit = edit.to_string_lossy().replace('\\', "/");
+    assert!(
+        stdout.contains(&format!("```diff {edit}\n--- a/{edit}\n+++ b/{edit}\n")),
+        "{stdout}"
+    );
+
+    let current = fencecat(tmp.path(), &["."]).stdout;
+    let out = fencecat_stdin(tmp.path(), &[".", "--diff-against-stdin"], &current);
+    assert!(out.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&out.stderr).contains(">> no changes since the previous dump"));
+    let out = fencecat_stdin(tmp.path(), &[".", "--diff-against-stdin", "-q"], &current);
+    assert!(out.stderr.is_empty());
+
+    // Markdown is the only format with room for diffs; saying so is fine
+    for md in [&["--format", "md"], &["--preset-llm", "gpt-4o"]] {
+        let args = [&[".", "--diff-against-stdin", "-q"][..], md].concat();
+        let out = fencecat_stdin(tmp.path(), &args, &current);
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        assert!(out.stdout.is_empty());
+    }
+    let args = [".", "--diff-against-stdin", "--format", "json"];
+    let out = fencecat_stdin(tmp.path(), &args, &current);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn budget_report_matches_max_tokens() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("b.txt"), "b".repeat(2000)).expect("write");
+    fs::write(tmp.path().join("c.rs"), "fn c() {}\n").expect("write");
+
+    let report = fencecat(
+        tmp.path(),
+        &[".", "--budget-report", "100", "--format", "json"],
+    );
+    let report: serde_json::Value = serde_json::from_slice(&report.stdout).expect("json");
+    assert_eq!(report["dropped"]["skip"], serde_json::json!(["b.txt"]));
+    assert_eq!(
+        report["dropped"]["stop"],
+        serde_json::json!(["b.txt", "c.rs"])
+    );
+
+    let skip = fencecat(
+        tmp.path(),
+        &[".", "--max-tokens", "100", "--budget-policy", "skip"],
+    );
+    let skip = String::from_utf8_lossy(&skip.stdout);
+    assert!(skip.contains("a.rs") && skip.contains("c.rs") && !skip.contains("b.txt"));
+
+    let stop = fencecat(tmp.path(), &[".", "--max-tokens", "100"]);
+    let stop = String::from_utf8_lossy(&stop.stdout);
+    assert!(stop.contains("a.rs") && !stop.contains("c.rs"));
+}
+
+#[test]
+fn budget_report_costs_duplicates_as_notes() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let content: String = (0..40).map(|i| format!("fn f{i}() {{}}\n")).collect();
+    fs::write(tmp.path().join("a.rs"), &content).expect("write");
+    fs::write(tmp.path().join("b.rs"), &content).expect("write");
+
+    // Two full fences do not fit in 200 tokens; a fence and a note do
+    let args = [".", "--content-hash-header", "--format", "json"];
+    let report = fencecat(
+        tmp.path(),
+        &[&args[..], &["--budget-report", "200"]].concat(),
+    );
+    let report: serde_json::Value = serde_json::from_slice(&report.stdout).expect("json");
+    assert_eq!(report["dropped"]["stop"], serde_json::json!([]));
+    assert_eq!(report["files"][1]["fits"], true);
+
+    let out = fencecat(tmp.path(), &[&args[..2], &["--max-tokens", "200"]].concat());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("[duplicate: b.rs"));
+
+    // The fence alone fits in 150 tokens, with its note it does not: the stop
+    // plan drops both, and the report says so
+    let report = fencecat(
+        tmp.path(),
+        &[&args[..], &["--budget-report", "150"]].concat(),
+    );
+    let report: serde_json::Value = serde_json::from_slice(&report.stdout).expect("json");
+    assert!(report["files"][0]["cumulative"].as_u64() <= Some(150));
+    assert_eq!(report["files"][0]["fits"], false);
+    assert_eq!(report["files"][1]["fits"], false);
+    let text = fencecat(
+        tmp.path(),
+        &[&args[..2], &["--budget-report", "150"]].concat(),
+    );
+    let text = String::from_utf8_lossy(&text.stdout);
+    let cut = text.find("budget exhausted").expect("cut line");
+    assert!(cut < text.find("  a.rs\n").expect("a.rs row"), "{text}");
+}
+
+fn fencecat_stdin(dir: &Path, args: &[&str], stdin: &[u8]) -> std::process::Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fencecat"))
+        .current_dir(dir)
+        .args(args)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn");
+    std::io::Write::write_all(&mut child.stdin.take().expect("stdin"), stdin).expect("pipe");
+    child.wait_with_output().expect("wait")
+}
+
+#[test]
+fn extract_round_trips_a_dump() {
+    let src = tempfile::tempdir().expect("tempdir");
+    fs::create_dir(src.path().join("src")).expect("mkdir");
+    fs::write(src.path().join("src/lib.rs"), "pub fn a() {}\n").expect("write");
+    fs::write(src.path().join("README.md"), "# x\n\n```sh\nls\n```\n").expect("write");
+    let dump = fencecat(src.path(), &[".", "--dir-list"]).stdout;
+
+    let dst = tempfile::tempdir().expect("tempdir");
+    let out = fencecat_stdin(dst.path(), &["--extract", "tree"], &dump);
+    assert!(out.status.success());
+    // The dump comes on stdin only; a PATH is refused rather than ignored
+    let out = fencecat(dst.path(), &["--extract", "tree", "dump.md"]);
+    assert_eq!(out.status.code(), Some(2));
+    for rel in ["src/lib.rs", "README.md"] {
+        assert_eq!(
+            fs::read(dst.path().join("tree").join(rel)).expect("read"),
+            fs::read(src.path().join(rel)).expect("read"),
+        );
+    }
+}
+
+#[test]
+fn extract_refuses_paths_outside_dir() {
+    let dst = tempfile::tempdir().expect("tempdir");
+    let dump = b"```../evil.sh\necho hi\n\n```\n\n```ok.txt\nok\n\n```\n";
+    let out = fencecat_stdin(dst.path(), &["--extract", "tree", "--strict"], dump);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(!dst.path().join("evil.sh").exists());
+    assert!(dst.path().join("tree/ok.txt").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn extract_refuses_to_write_through_symlinks() {
+    use std::os::unix::fs::symlink;
+
+    let outside = tempfile::tempdir().expect("tempdir");
+    let dst = tempfile::tempdir().expect("tempdir");
+    fs::create_dir(dst.path().join("tree")).expect("mkdir");
+    symlink(outside.path(), dst.path().join("tree/link")).expect("symlink");
+    symlink(
+        outside.path().join("target.txt"),
+        dst.path().join("tree/file.txt"),
+    )
+    .expect("symlink");
+    let dump = b"```link/pwn.txt\npwned\n```\n\n```file.txt\npwned\n```\n\n```ok.txt\nok\n```\n";
+    let out = fencecat_stdin(dst.path(), &["--extract", "tree", "--strict"], dump);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("is a symlink"));
+    assert!(!outside.path().join("pwn.txt").exists());
+    assert!(!outside.path().join("target.txt").exists());
+    assert!(dst.path().join("tree/ok.txt").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_policies() {
+    use std::os::unix::fs::symlink;
+
+    let outside = tempfile::tempdir().expect("tempdir");
+    fs::write(outside.path().join("shared.js"), "export const x = 1;\n").expect("write");
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::create_dir(tmp.path().join("real")).expect("mkdir");
+    fs::write(tmp.path().join("real/a.js"), "export const a = 1;\n").expect("write");
+    symlink("real/a.js", tmp.path().join("inside.js")).expect("symlink");
+    symlink(
+        outside.path().join("shared.js"),
+        tmp.path().join("outside.js"),
+    )
+    .expect("symlink");
+
+    let skip = String::from_utf8_lossy(&fencecat(tmp.path(), &["."]).stdout).into_owned();
+    assert!(!skip.contains("inside.js") && !skip.contains("outside.js"));
+
+    let follow = fencecat(tmp.path(), &[".", "--symlinks", "follow"]).stdout;
+    let follow = String::from_utf8_lossy(&follow);
+    assert!(follow.contains("```javascript outside.js\nexport const x = 1;\n"));
+
+    let annotate = fencecat(tmp.path(), &[".", "--symlinks", "annotate"]).stdout;
+    let annotate = String::from_utf8_lossy(&annotate);
+    assert!(annotate.contains("```inside.js\n→ real/a.js (symlink)\n"));
+    let shared = outside.path().join("shared.js");
+    assert!(annotate.contains(&format!(
+        "```outside.js\n→ {} (symlink)\n",
+        shared.display()
+    )));
+    assert!(annotate.contains("```javascript real/a.js\nexport const a = 1;\n"));
+}
+
+#[cfg(unix)]
+#[test]
+fn preserve_permissions_round_trips_the_exec_bit() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let src = tempfile::tempdir().expect("tempdir");
+    let script = src.path().join("run.sh");
+    fs::write(&script, "#!/bin/sh\necho hi\n").expect("write");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).expect("chmod");
+    let dump = fencecat(src.path(), &[".", "--preserve-permissions"]).stdout;
+    assert!(String::from_utf8_lossy(&dump).contains(" mode=0755 -->"));
+
+    let dst = tempfile::tempdir().expect("tempdir");
+    fencecat_stdin(dst.path(), &["--extract", "a"], &dump);
+    fencecat_stdin(
+        dst.path(),
+        &["--extract", "b", "--preserve-permissions"],
+        &dump,
+    );
+    let mode = |p: &str| {
+        fs::metadata(dst.path().join(p))
+            .expect("stat")
+            .permissions()
+            .mode()
+            & 0o777
+    };
+    assert_eq!(mode("a/run.sh") & 0o111, 0);
+    assert_eq!(mode("b/run.sh"), 0o755);
+}
+
+#[cfg(unix)]
+#[test]
+fn preserve_permissions_drops_special_and_write_bits() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let dst = tempfile::tempdir().expect("tempdir");
+    let dump = b"<!-- fencecat: path=pwn.sh mode=6777 -->\n```sh pwn.sh\necho hi\n```\n";
+    let out = fencecat_stdin(
+        dst.path(),
+        &["--extract", "out", "--preserve-permissions"],
+        dump,
+    );
+    assert!(out.status.success());
+    let mode = fs::metadata(dst.path().join("out/pwn.sh"))
+        .expect("stat")
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o7777, 0o755);
+}
+
+#[cfg(unix)]
+#[test]
+fn warn_limit_summarizes_the_rest_by_kind() {
+    use std::os::unix::fs::symlink;
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+    for i in 0..30 {
+        symlink(
+            format!("/nonexistent/{i}"),
+            tmp.path().join(format!("l{i}.rs")),
+        )
+        .expect("link");
+    }
+    let stderr = |args: &[&str]| {
+        let out = fencecat(tmp.path(), args);
+        assert!(out.status.success());
+        String::from_utf8_lossy(&out.stderr).into_owned()
+    };
+    let errors = |s: &str| s.lines().filter(|l| l.starts_with("walk error")).count();
+
+    let capped = stderr(&[".", "--symlinks", "follow"]);
+    assert_eq!(errors(&capped), 20);
+    assert!(capped.ends_with(">> … and 10 more not-found errors (--warn-limit)\n"));
+
+    let few = stderr(&[".", "--symlinks", "follow", "--warn-limit", "5"]);
+    assert_eq!(errors(&few), 5);
+    assert!(few.contains(">> … and 25 more not-found errors"));
+
+    for args in [["--warn-limit", "0"], ["--verbose", "--warn-limit=20"]] {
+        let all = stderr(&[".", "--symlinks", "follow", args[0], args[1]]);
+        assert_eq!(errors(&all), 30);
+        assert!(!all.contains("more not-found"));
+    }
+
+    // Symlink loops are I/O errors, summarized apart from the missing targets
+    let mixed = tempfile::tempdir().expect("tempdir");
+    for i in 0..3 {
+        let name = format!("loop{i}.rs");
+        symlink(&name, mixed.path().join(&name)).expect("link");
+    }
+    symlink("/nonexistent", mixed.path().join("gone.rs")).expect("link");
+    let out = fencecat(
+        mixed.path(),
+        &[
+            ".",
+            "--symlinks",
+            "follow",
+            "--warn-limit",
+            "1",
+            "--allow-empty",
+        ],
+    );
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert_eq!(errors(&stderr), 1);
+    assert!(stderr.contains(">> … and 2 more I/O errors"), "{stderr}");
+    assert!(
+        stderr.contains(">> … and 1 more not-found error"),
+        "{stderr}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn on_error_policy_decides_output_and_exit_code() {
+    use std::os::unix::fs::symlink;
+
+    // Dangling symlinks fail under --symlinks follow, even for root
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+    fs::create_dir(tmp.path().join("sub")).expect("mkdir");
+    symlink("/nonexistent/b", tmp.path().join("sub/b.rs")).expect("link");
+    symlink("/nonexistent/z", tmp.path().join("zero.rs")).expect("link");
+    let only_a = "```rust a.rs\nfn a() {}\n\n```\n\n";
+
+    let warn = fencecat(tmp.path(), &[".", "--symlinks", "follow"]);
+    let stderr = String::from_utf8_lossy(&warn.stderr);
+    assert_eq!(warn.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&warn.stdout), only_a);
+    assert!(stderr.contains("walk error:"), "{stderr}");
+    assert!(stderr.contains("zero.rs"), "{stderr}");
+    assert!(
+        stderr.contains(">> skipped 2 unreadable path(s)"),
+        "{stderr}"
+    );
+
+    let skip = fencecat(
+        tmp.path(),
+        &[".", "--symlinks", "follow", "--on-error", "skip"],
+    );
+    assert_eq!(skip.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&skip.stdout), only_a);
+    assert!(skip.stderr.is_empty());
+
+    let strict = fencecat(
+        tmp.path(),
+        &[
+            ".",
+            "--symlinks",
+            "follow",
+            "--on-error",
+            "skip",
+            "--strict",
+        ],
+    );
+    assert_eq!(strict.status.code(), Some(1));
+
+    let fail = fencecat(
+        tmp.path(),
+        &[".", "--symlinks", "follow", "--on-error", "fail"],
+    );
+    let stderr = String::from_utf8_lossy(&fail.stderr);
+    assert_eq!(fail.status.code(), Some(1));
+    assert!(fail.stdout.is_empty());
+    assert!(stderr.starts_with("error: "), "{stderr}");
+    assert!(stderr.ends_with("(--on-error fail)\n"), "{stderr}");
+    assert_eq!(stderr.lines().count(), 1);
+}
+
+#[test]
+fn number_files_follows_biggest_first_order() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    for (i, name) in [
+        "a.txt", "b.txt", "c.txt", "d.txt", "e.txt", "f.txt", "g.txt", "h.txt", "i.txt", "j.txt",
+    ]
+    .iter()
+    .enumerate()
+    {
+        fs::write(tmp.path().join(name), "x".repeat(i + 1) + "\n").expect("write");
+    }
+    let out = fencecat(
+        tmp.path(),
+        &[".", "--number-files", "--biggest-first", "--dir-list"],
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let listing = "```\n[ 1/10] j.txt\n[ 2/10] i.txt\n[ 3/10] h.txt\n[ 4/10] g.txt\n[ 5/10] f.txt\n[ 6/10] e.txt\n[ 7/10] d.txt\n[ 8/10] c.txt\n[ 9/10] b.txt\n[10/10] a.txt\n```\n\n";
+    assert!(stdout.starts_with(listing), "{stdout}");
+    assert!(stdout.contains("```[ 1/10] j.txt\nxxxxxxxxxx\n\n```\n"));
+    assert!(stdout.ends_with("```[10/10] a.txt\nx\n\n```\n\n"));
+}
+
+#[test]
+fn every_preset_works_with_the_markdown_only_flags() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+    let previous = fencecat(tmp.path(), &["."]).stdout;
+    for preset in fencecat::config::LLM_PRESETS {
+        for flags in [
+            &["--chunk-by-tokens", "1000"][..],
+            &["--diff-against-stdin"],
+            &["--repo-summary"],
+        ] {
+            let args = [&[".", "--preset-llm", preset.name][..], flags].concat();
+            let out = fencecat_stdin(tmp.path(), &args, &previous);
+            assert!(
+                out.status.success(),
+                "{args:?}: {}",
+                String::from_utf8_lossy(&out.stderr)
+            );
+        }
+    }
+}
+
+#[test]
+fn chunk_by_tokens_refuses_only_json() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.txt"), "a\n").expect("write");
+    let out = fencecat(
+        tmp.path(),
+        &[".", "--preset-llm", "gpt-4o", "--chunk-by-tokens", "1000"],
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("```a.txt\na\n"));
+    let out = fencecat(
+        tmp.path(),
+        &[".", "--format", "md", "--chunk-by-tokens", "1000"],
+    );
+    assert!(out.status.success());
+
+    let out = fencecat(
+        tmp.path(),
+        &[".", "--format", "json", "--chunk-by-tokens", "1000"],
+    );
+    assert_eq!(out.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8_lossy(&out.stderr),
+        "error: the argument '--format json' cannot be used with '--chunk-by-tokens <N>'\n"
+    );
+}
+
+#[test]
+fn chunk_by_tokens_writes_whole_files_per_part() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        fs::write(
+            tmp.path().join(name),
+            format!("{name}\n{}\n", "x".repeat(200)),
+        )
+        .expect("write");
+    }
+    let out = fencecat(
+        tmp.path(),
+        &[".", "--chunk-by-tokens", "100", "-o", "ctx.md"],
+    );
+    assert!(out.status.success());
+    assert!(!tmp.path().join("ctx.md").exists());
+
+    let parts: Vec<String> = (1..=3)
+        .map(|k| fs::read_to_string(tmp.path().join(format!("ctx.part{k}.md"))).expect("part"))
+        .collect();
+    assert!(!tmp.path().join("ctx.part4.md").exists());
+    for (k, part) in parts.iter().enumerate() {
+        assert!(part.starts_with(&format!("<!-- fencecat: part {}/3 -->\n\n```", k + 1)));
+        assert!(part.len() <= 100 * 4, "part {k} is over budget");
+    }
+    assert!(parts[1].contains("```b.txt\nb.txt\n"));
+
+    // A rescan leaves the parts out quietly and writes the same parts
+    let again = fencecat(
+        tmp.path(),
+        &[".", "--chunk-by-tokens", "100", "-o", "ctx.md"],
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&again.stderr),
+        ">> wrote 3 parts next to ctx.md\n"
+    );
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("ctx.part2.md")).expect("part"),
+        parts[1]
+    );
+}
+
+#[test]
+fn fence_continuation_round_trips_split_files() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let big: String = (0..60).map(|i| format!("fn f{i}() {{}}\n")).collect();
+    fs::write(tmp.path().join("big.rs"), &big).expect("write");
+    fs::write(tmp.path().join("small.rs"), "fn s() {}\n").expect("write");
+    let out = fencecat(
+        tmp.path(),
+        &[
+            ".",
+            "--chunk-by-tokens",
+            "100",
+            "--fence-continuation",
+            "-o",
+            "ctx.md",
+        ],
+    );
+    assert!(out.status.success());
+    assert!(!String::from_utf8_lossy(&out.stderr).contains("warning"));
+
+    let parts: Vec<String> = (1..)
+        .map(|k| tmp.path().join(format!("ctx.part{k}.md")))
+        .take_while(|p| p.exists())
+        .map(|p| fs::read_to_string(p).expect("part"))
+        .collect();
+    assert!(parts.len() > 2);
+    let pieces = parts.concat().matches("<!-- continuation: big.rs ").count();
+    assert!(pieces > 1);
+    assert!(parts[0].contains(&format!(
+        "<!-- continuation: big.rs 1/{pieces} -->\n```rust big.rs chunk=1/{pieces}\nfn f0() {{}}\n"
+    )));
+    for part in &parts {
+        assert!(part.len() <= 100 * 4, "part is over budget");
+    }
+
+    // Parts in any order
+    let mut texts: Vec<&str> = parts.iter().map(String::as_str).collect();
+    texts.reverse();
+    let files = fencecat::reassemble_fences(&texts);
+    assert_eq!(files.len(), 2);
+    assert_eq!(files["big.rs"], big);
+    assert_eq!(files["small.rs"], "fn s() {}\n");
+
+    let extracted = fencecat_stdin(tmp.path(), &["--extract", "out"], parts.concat().as_bytes());
+    assert!(extracted.status.success());
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("out/big.rs")).expect("read"),
+        big
+    );
+}
+
+#[test]
+fn timings_report_every_stage_on_stderr() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+
+    let out = fencecat(tmp.path(), &[".", "--timings", "--bytes"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```rust a.rs\nfn a() {}\n\n```\n\n"
+    );
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains(">> timings\n"));
+    for key in [
+        "walk:",
+        "filter:",
+        "read:",
+        "decode:",
+        "format:",
+        "tokenize:",
+        "write:",
+        "clipboard:",
+        "total:",
+    ] {
+        assert!(
+            stderr
+                .lines()
+                .any(|l| l.starts_with(key) && l.contains(" ms")),
+            "missing {key} in {stderr}"
+        );
+    }
+
+    let quiet = fencecat(tmp.path(), &["."]);
+    assert!(!String::from_utf8_lossy(&quiet.stderr).contains("timings"));
+
+    // Asked for explicitly, so --quiet does not drop it
+    let profile = fencecat(tmp.path(), &[".", "--profile", "--quiet"]);
+    assert!(String::from_utf8_lossy(&profile.stderr).contains(">> timings\nwalk:"));
+}
+
+#[test]
+fn rust_fences_carry_the_cargo_edition_on_request() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(
+        tmp.path().join("Cargo.toml"),
+        "[package]\nname = \"demo\"\nedition = \"2021\"\n",
+    )
+    .expect("write");
+    fs::create_dir(tmp.path().join("src")).expect("mkdir");
+    fs::write(tmp.path().join("src/main.rs"), "fn main() {}\n").expect("write");
+
+    // Opt-in: default output stays as it was
+    let out = fencecat(tmp.path(), &[".", "--ext", "rs"]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```rust src/main.rs\nfn main() {}\n\n```\n\n"
+    );
+    let out = fencecat(tmp.path(), &[".", "--ext", "rs", "--rust-edition", "auto"]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```rust edition=2021 src/main.rs\nfn main() {}\n\n```\n\n"
+    );
+    let out = fencecat(
+        tmp.path(),
+        &[".", "--ext", "rs,toml", "--rust-edition", "2024"],
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("```rust edition=2024 src/main.rs\n"));
+    assert!(stdout.contains("```toml Cargo.toml\n"));
+}
+
+#[test]
+fn not_regex_that_excludes_everything_is_reported() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("b.md"), "# b\n").expect("write");
+
+    let out = fencecat(
+        tmp.path(),
+        &[".", "--not-regex", "nomatch", "--not-regex", "."],
+    );
+    assert_eq!(out.status.code(), Some(3));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("--not-regex excluded all 2 file(s)"));
+    assert!(stderr.contains("`.` matched 2"));
+    assert!(!stderr.contains("`nomatch`"));
+
+    let out = fencecat(tmp.path(), &[".", "--not-regex", ".", "--allow-empty"]);
+    assert_eq!(out.status.code(), Some(0));
+
+    let out = fencecat(
+        tmp.path(),
+        &[".", "-v", "--not-regex", "\\.md$", "--not-regex", "nomatch"],
+    );
+    assert!(out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("--not-regex `\\.md$`       1\n"));
+    assert!(stderr.contains("--not-regex `nomatch`     0  possibly obsolete\n"));
+}
+
+#[test]
+fn filter_stats_count_overlapping_patterns() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::create_dir(tmp.path().join("src")).expect("mkdir");
+    fs::create_dir(tmp.path().join("tests")).expect("mkdir");
+    fs::write(tmp.path().join("src/a.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("src/b.rs"), "fn b() {}\n").expect("write");
+    fs::write(tmp.path().join("tests/c.rs"), "fn c() {}\n").expect("write");
+    fs::write(tmp.path().join("README.md"), "# r\n").expect("write");
+
+    let out = fencecat(
+        tmp.path(),
+        &[
+            ".",
+            "--filter-stats",
+            "--regex",
+            "src",
+            "--regex",
+            "\\.rs$",
+            "--not-regex",
+            "b\\.rs",
+            "--not-regex",
+            "^zzz",
+            "--not-regex",
+            "(b|c)\\.rs",
+            "-g",
+            "*.rs",
+            "-g",
+            "!target/",
+        ],
+    );
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```rust src/a.rs\nfn a() {}\n\n```\n\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&out.stderr),
+        ">> filter stats\n\
+         --regex     `src`           2\n\
+         --regex     `\\.rs$`         3\n\
+         --not-regex `b\\.rs`         1\n\
+         --not-regex `^zzz`          0  possibly obsolete\n\
+         --not-regex `(b|c)\\.rs`     2\n\
+         --glob      `*.rs`          3\n\
+         --glob      `!target/`      -  applied during the walk, not counted\n"
+    );
+}
+
+#[test]
+fn group_by_lang_emits_headings_per_language() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.ts"), "let a = 1;\n").expect("write");
+    fs::write(tmp.path().join("b.rs"), "fn b() {}\n").expect("write");
+    fs::write(tmp.path().join("c.rs"), "fn c() {}\n").expect("write");
+    fs::write(tmp.path().join("notes.xyz"), "n\n").expect("write");
+
+    let out = fencecat(tmp.path(), &[".", "--group-by-lang"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "## Rust\n\n```rust b.rs\nfn b() {}\n\n```\n\n```rust c.rs\nfn c() {}\n\n```\n\n\
+         ## TypeScript\n\n```typescript a.ts\nlet a = 1;\n\n```\n\n\
+         ## Other\n\n```notes.xyz\nn\n\n```\n\n"
+    );
+}
+
+#[test]
+fn from_archive_reads_zip_and_tar_members() {
+    use std::io::Write as _;
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let write_zip = |file: &str, members: &[(&str, &[u8])]| {
+        let mut zip = zip::ZipWriter::new(fs::File::create(tmp.path().join(file)).expect("zip"));
+        let opts = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        for (name, body) in members {
+            zip.start_file(*name, opts).expect("start_file");
+            zip.write_all(body).expect("write");
+        }
+        zip.finish().expect("finish");
+    };
+    write_zip(
+        "src.zip",
+        &[
+            ("src/a.rs", b"fn a() {}\n"),
+            ("src/blob.bin", b"\0\x01\x02"),
+            (
+                "src/big.rs",
+                b"fn big() { /* too large for the limit */ }\n",
+            ),
+            ("notes.txt", b"n\n"),
+        ],
+    );
+    write_zip(
+        "evil.zip",
+        &[
+            ("src/a.rs", b"fn a() {}\n"),
+            ("../evil.rs", b"fn evil() {}\n"),
+        ],
+    );
+
+    let out = fencecat(
+        tmp.path(),
+        &[
+            "--from-archive",
+            "src.zip",
+            "--ext",
+            "rs,bin",
+            "--max-file-size",
+            "32",
+            "--dir-list-excluded",
+        ],
+    );
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.ends_with("```rust src/a.rs\nfn a() {}\n\n```\n\n"));
+    assert!(stdout.contains("[size]   src/big.rs\n"), "{stdout}");
+
+    // Path traversal is an error, not a skipped file
+    for args in [
+        &["--from-archive", "evil.zip"][..],
+        &["--from-archive", "evil.zip", "--on-error", "skip"],
+    ] {
+        let out = fencecat(tmp.path(), args);
+        assert_eq!(out.status.code(), Some(1));
+        assert!(out.stdout.is_empty());
+        assert!(
+            String::from_utf8_lossy(&out.stderr)
+                .contains("error: archive member ../evil.rs escapes the archive root")
+        );
+    }
+
+    let gz = flate2::write::GzEncoder::new(
+        fs::File::create(tmp.path().join("src.tar.gz")).expect("tar"),
+        flate2::Compression::default(),
+    );
+    let mut tar = tar::Builder::new(gz);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(10);
+    header.set_mode(0o644);
+    tar.append_data(&mut header, "lib/b.rs", &b"fn b() {}\n"[..])
+        .expect("append");
+    tar.into_inner().expect("tar").finish().expect("gzip");
+
+    let out = fencecat(tmp.path(), &["--from-archive", "src.tar.gz"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```rust lib/b.rs\nfn b() {}\n\n```\n\n"
+    );
+}
+
+#[test]
+fn dir_list_excluded_tags_and_caps_skipped_files() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("b.py"), "b = 1\n").expect("write");
+    fs::write(tmp.path().join("big.rs"), "fn big() { /* ... */ }\n").expect("write");
+    fs::write(tmp.path().join("blob.rs"), b"\0\x01\x02").expect("write");
+
+    let out = fencecat(
+        tmp.path(),
+        &[
+            ".",
+            "--ext",
+            "rs",
+            "--max-file-size",
+            "16",
+            "--dir-list-excluded",
+        ],
+    );
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```excluded\n[binary] blob.rs\n[ext]    b.py\n[size]   big.rs\n```\n\n\
+         ```rust a.rs\nfn a() {}\n\n```\n\n"
+    );
+
+    let out = fencecat(
+        tmp.path(),
+        &[
+            ".",
+            "--ext",
+            "rs",
+            "--max-file-size",
+            "16",
+            "--dir-list-excluded",
+            "--dir-list-excluded-limit",
+            "1",
+        ],
+    );
+    assert!(
+        String::from_utf8_lossy(&out.stdout)
+            .starts_with("```excluded\n[binary] blob.rs\n… and 2 more\n```\n\n")
+    );
+}
+
+#[test]
+fn explain_json_lists_every_visited_file() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("b.py"), "b = 1\n").expect("write");
+
+    let out = fencecat(tmp.path(), &[".", "--not-ext", "py", "--explain-json"]);
+    assert!(out.status.success());
+    let v: serde_json::Value = serde_json::from_slice(&out.stdout).expect("json");
+    assert_eq!(
+        v,
+        serde_json::json!([
+            { "path": "a.rs", "included": true, "reason": null },
+            { "path": "b.py", "included": false, "reason": "ExtDenied" },
+        ])
+    );
+}
+
+#[test]
+fn ext_matches_extensions_and_file_names_not_languages() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(
+        tmp.path().join("Gemfile"),
+        "source 'https://rubygems.org'\n",
+    )
+    .expect("write");
+    fs::write(tmp.path().join("app.rb"), "puts 1\n").expect("write");
+
+    let listed = |args: &[&str]| {
+        let mut args = args.to_vec();
+        args.extend([".", "--dir-list"]);
+        let out = fencecat(tmp.path(), &args);
+        let stdout = String::from_utf8_lossy(&out.stdout).into_owned();
+        stdout
+            .split("\n```\n")
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    };
+    assert_eq!(listed(&["--not-ext", "rb"]), "```\nGemfile");
+    assert_eq!(listed(&["--not-ext", "ruby"]), "```\nGemfile\napp.rb");
+    assert_eq!(listed(&["--ext", "gemfile"]), "```\nGemfile");
+    assert_eq!(
+        fencecat(tmp.path(), &[".", "--ext", "ruby"]).status.code(),
+        Some(3)
+    );
+}
+
+#[test]
+fn named_file_bypasses_filters() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let init = Command::new("git")
+        .current_dir(tmp.path())
+        .args(["init", "-q"])
+        .env("HOME", tmp.path())
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .status()
+        .expect("git init");
+    assert!(init.success());
+    fs::write(tmp.path().join(".gitignore"), "ignored.rs\n").expect("write");
+    fs::write(tmp.path().join("ignored.rs"), "fn i() {}\n").expect("write");
+    fs::write(tmp.path().join("empty.txt"), "").expect("write");
+    fs::write(tmp.path().join("blob.bin"), b"\0\x01\x02\x03").expect("write");
+
+    // A walk leaves the gitignored file out; naming it emits it
+    let out = fencecat(tmp.path(), &[".", "--include-empty"]);
+    assert!(!String::from_utf8_lossy(&out.stdout).contains("ignored.rs"));
+    let out = fencecat(tmp.path(), &["ignored.rs", "--ext", "py"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```rust ignored.rs\nfn i() {}\n\n```\n\n"
+    );
+    assert!(String::from_utf8_lossy(&out.stderr).contains("the filters exclude ignored.rs (ext)"));
+
+    let out = fencecat(
+        tmp.path(),
+        &["ignored.rs", "--ext", "py", "--apply-filters-to-args"],
+    );
+    assert_eq!(out.status.code(), Some(3));
+
+    let out = fencecat(tmp.path(), &["empty.txt"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```empty.txt\n\n\n```\n\n"
+    );
+
+    let out = fencecat(tmp.path(), &["blob.bin"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("blob.bin is a binary file"));
+    let out = fencecat(tmp.path(), &["blob.bin", "--apply-filters-to-args"]);
+    assert_eq!(out.status.code(), Some(3));
+    assert!(out.stdout.is_empty());
+
+    let out = fencecat(tmp.path(), &["blob.bin", "--binary", "placeholder"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "[media: blob.bin (4 B, bin, application/octet-stream) — content omitted]\n\n"
+    );
+
+    fs::write(tmp.path().join("site.tar.gz"), b"\x1f\x8b\x08\0\0").expect("write");
+    let out = fencecat(tmp.path(), &["site.tar.gz", "--binary", "placeholder"]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "[media: site.tar.gz (5 B, tar.gz, application/octet-stream) — content omitted]\n\n"
+    );
+}
+
+#[test]
+fn prune_regex_skips_descent() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::create_dir_all(tmp.path().join("src")).expect("mkdir");
+    fs::create_dir_all(tmp.path().join("target/debug")).expect("mkdir");
+    fs::write(tmp.path().join("src/main.rs"), "fn main() {}\n").expect("write");
+    for i in 0..10 {
+        fs::write(tmp.path().join(format!("target/debug/{i}.rs")), "").expect("write");
+    }
+
+    // --not-regex still walks target/, and the walk crosses the limit
+    let out = fencecat(
+        tmp.path(),
+        &[".", "--scan-limit", "5", "--not-regex", "^target/"],
+    );
+    assert_eq!(out.status.code(), Some(2));
+
+    let out = fencecat(
+        tmp.path(),
+        &[
+            ".",
+            "--scan-limit",
+            "5",
+            "--prune-regex",
+            "(^|/)target/$",
+            "--dir-list",
+        ],
+    );
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```\nsrc/main.rs\n```\n\n```rust src/main.rs\nfn main() {}\n\n```\n\n"
+    );
+
+    let out = fencecat(tmp.path(), &[".", "--prune-regex", "("]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("invalid regex in --prune-regex"));
+}
+
+#[test]
+fn scan_limit_aborts_runaway_walks() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    for name in ["a.rs", "b.rs", "c.rs", "d.rs"] {
+        fs::write(tmp.path().join(name), "fn f() {}\n").expect("write");
+    }
+
+    // Without a terminal to ask on, crossing the limit aborts
+    let out = fencecat(tmp.path(), &[".", "--scan-limit", "3"]);
+    assert_eq!(out.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("the walk visited 4 entries under . (--scan-limit 3)"));
+    assert!(stderr.contains("--force"));
+    assert!(out.stdout.is_empty());
+
+    let out = fencecat(tmp.path(), &[".", "--scan-limit", "3", "--force"]);
+    assert!(out.status.success());
+    let out = fencecat(tmp.path(), &[".", "--scan-limit", "0"]);
+    assert!(out.status.success());
+
+    if cfg!(unix) {
+        let out = fencecat(tmp.path(), &["/"]);
+        assert_eq!(out.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&out.stderr).contains("/ is the filesystem root"));
+    }
+}
+
+#[test]
+fn output_filename_template_names_parts() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::create_dir(tmp.path().join("src")).expect("mkdir");
+    fs::write(tmp.path().join("src/a.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("src/b.rs"), "fn b() {}\n").expect("write");
+
+    let args = ["src", "--chunk-by-tokens", "10", "-o", "out.md"];
+    let out = fencecat(
+        tmp.path(),
+        &[
+            &args[..],
+            &["--output-filename-template", "{dir}_{count}.{ext}"],
+        ]
+        .concat(),
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let part1 = fs::read_to_string(tmp.path().join("src_1.md")).expect("part 1");
+    assert!(part1.contains("```rust a.rs\n"));
+    let part2 = fs::read_to_string(tmp.path().join("src_2.md")).expect("part 2");
+    assert!(part2.contains("```rust b.rs\n"));
+
+    let out = fencecat(
+        tmp.path(),
+        &[&args[..], &["--output-filename-template", "{name}.md"]].concat(),
+    );
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("unknown placeholder `{name}`"));
+
+    let out = fencecat(
+        tmp.path(),
+        &[&args[..], &["--output-filename-template", "../{count}.md"]].concat(),
+    );
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("which is not a file name"));
+
+    // A rescan leaves out the parts of earlier runs, whatever their date,
+    // even where copied dumps are let in
+    let args = [
+        ".",
+        "--include-fencecat-output",
+        "--chunk-by-tokens",
+        "10",
+        "-o",
+        "out.md",
+        "--output-filename-template",
+        "{date}-{count}.{ext}",
+    ];
+    for _ in 0..2 {
+        assert!(fencecat(tmp.path(), &args).status.success());
+    }
+    let today = fs::read_dir(tmp.path())
+        .expect("ls")
+        .filter_map(|e| e.ok()?.file_name().into_string().ok())
+        .find(|n| n.ends_with("-1.md"))
+        .expect("part 1");
+    fs::rename(tmp.path().join(&today), tmp.path().join("2001-01-01-9.md")).expect("rename");
+    let out = fencecat(tmp.path(), &args);
+    assert!(out.status.success());
+    for entry in fs::read_dir(tmp.path()).expect("ls") {
+        let text = fs::read_to_string(entry.expect("entry").path()).unwrap_or_default();
+        assert!(!text.contains("markdown 20"), "{text}");
+    }
+}
+
+#[test]
+fn with_tree_outlines_the_emitted_files() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::create_dir(tmp.path().join("src")).expect("mkdir");
+    fs::write(tmp.path().join("src/a.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("notes.md"), "n\n").expect("write");
+
+    let out = fencecat(tmp.path(), &[".", "--with-tree", "--ext", "rs"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```\n.\n└── src/\n    └── a.rs\n```\n\n```rust src/a.rs\nfn a() {}\n\n```\n\n"
+    );
+}
+
+#[test]
+fn binary_extensions_are_skipped_by_name() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("text.png"), "not really an image\n").expect("write");
+
+    let out = fencecat(tmp.path(), &["."]);
+    assert_eq!(out.status.code(), Some(3));
+
+    for flag in ["--no-ignore-binary-extensions", "-H"] {
+        let out = fencecat(tmp.path(), &[".", flag]);
+        assert!(out.status.success(), "{flag}");
+        assert_eq!(
+            String::from_utf8_lossy(&out.stdout),
+            "```text.png\nnot really an image\n\n```\n\n"
+        );
+    }
+    let out = fencecat(tmp.path(), &[".", "-H", "--ignore-binary-extensions"]);
+    assert_eq!(out.status.code(), Some(3));
+}
+
+#[test]
+fn output_newline_sets_structural_endings_only() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("b.txt"), "x\r\ny\r\n").expect("write");
+
+    let out = fencecat(tmp.path(), &[".", "--dir-list", "--output-newline", "crlf"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```\r\na.rs\r\nb.txt\r\n```\r\n\r\n\
+         ```rust a.rs\r\nfn a() {}\n\r\n```\r\n\r\n\
+         ```b.txt\r\nx\r\ny\r\n\r\n```\r\n\r\n"
+    );
+
+    let out = fencecat(tmp.path(), &[".", "--dir-list", "--output-newline", "lf"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```\na.rs\nb.txt\n```\n\n\
+         ```rust a.rs\nfn a() {}\n\n```\n\n\
+         ```b.txt\nx\r\ny\r\n\n```\n\n"
+    );
+}
+
+#[test]
+fn path_style_relative_to_home_collapses_and_expands_tilde() {
+    let home = tempfile::tempdir().expect("tempdir");
+    let home_path = fs::canonicalize(home.path()).expect("canonicalize");
+    fs::create_dir(home_path.join("proj")).expect("mkdir");
+    fs::write(home_path.join("proj/a.rs"), "fn a() {}\n").expect("write");
+
+    let out = Command::new(env!("CARGO_BIN_EXE_fencecat"))
+        .current_dir(&home_path)
+        .env("HOME", &home_path)
+        .args(["~/proj", "--dir-list", "--path-style-relative-to-home"])
+        .output()
+        .expect("run fencecat");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("```\n~/proj/a.rs\n```"));
+    assert!(stdout.contains("```rust ~/proj/a.rs\n"));
+
+    let out = fencecat(&home_path, &["proj", "--absolute-paths"]);
+    let absolute = format!("```rust {}/a.rs\n", home_path.join("proj").display());
+    assert!(String::from_utf8_lossy(&out.stdout).contains(&absolute));
+}
+
+#[cfg(unix)]
+#[test]
+fn show_exec_marks_executables_and_extract_restores_the_bit() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let src = tempfile::tempdir().expect("tempdir");
+    let script = src.path().join("run.sh");
+    fs::write(&script, "#!/bin/sh\necho hi\n").expect("write");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o744)).expect("chmod");
+    fs::write(src.path().join("a.txt"), "x\n").expect("write");
+
+    let dump = fencecat(src.path(), &[".", "--dir-list", "--show-exec"]).stdout;
+    let text = String::from_utf8_lossy(&dump);
+    assert!(text.starts_with("```\na.txt\nrun.sh (exec)\n```\n"));
+    assert!(text.contains(" run.sh (exec)\n#!/bin/sh\n"));
+    assert!(!text.contains("a.txt (exec)"));
+
+    let json = fencecat(src.path(), &[".", "--format", "json"]).stdout;
+    let records: serde_json::Value = serde_json::from_slice(&json).expect("json");
+    assert_eq!(records[0]["executable"], false);
+    assert_eq!(records[1]["executable"], true);
+
+    let dst = tempfile::tempdir().expect("tempdir");
+    fencecat_stdin(dst.path(), &["--extract", "out"], &dump);
+    let mode = fs::metadata(dst.path().join("out/run.sh"))
+        .expect("stat")
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o111, (mode & 0o444) >> 2);
+    assert_ne!(mode & 0o100, 0);
+}
+
+#[test]
+fn ext_matches_compound_extensions() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.ts"), "export const a = 1;\n").expect("write");
+    fs::write(
+        tmp.path().join("index.d.ts"),
+        "export declare const a: number;\n",
+    )
+    .expect("write");
+    fs::write(tmp.path().join("site.tar.gz"), b"\x1f\x8b\x08\x00\x00\x00").expect("write");
+
+    let out = fencecat(
+        tmp.path(),
+        &[".", "--ext", "d.ts", "--lang-map", "d.ts=dts"],
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("```dts index.d.ts\n"));
+    assert!(!stdout.contains("a.ts\n"));
+
+    let out = fencecat(tmp.path(), &[".", "--ext", "tar.gz", "--explain-json"]);
+    let explained: serde_json::Value = serde_json::from_slice(&out.stdout).expect("json");
+    let archive = explained
+        .as_array()
+        .expect("array")
+        .iter()
+        .find(|e| e["path"] == "site.tar.gz")
+        .expect("archive listed");
+    assert_eq!(archive["reason"], "Binary");
+}
+
+#[test]
+fn empty_files_are_listed_and_fenced_on_request() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.py"), "a = 1\n").expect("write");
+    fs::write(tmp.path().join("py.typed"), "").expect("write");
+
+    let out = fencecat(tmp.path(), &[".", "--dir-list"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```\na.py\npy.typed (empty)\n```\n\n```python a.py\na = 1\n\n```\n\n"
+    );
+
+    let out = fencecat(tmp.path(), &[".", "--include-empty"]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```python a.py\na = 1\n\n```\n\n```py.typed\n\n\n```\n\n"
+    );
+
+    // Numbering counts fences, not listed empty files
+    fs::write(tmp.path().join("b.py"), "b = 2\n").expect("write");
+    let out = fencecat(tmp.path(), &[".", "--number-files", "--dir-list"]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```\n[1/2] a.py\n[2/2] b.py\npy.typed (empty)\n```\n\n\
+         ```python [1/2] a.py\na = 1\n\n```\n\n```python [2/2] b.py\nb = 2\n\n```\n\n"
+    );
+}
+
+#[test]
+fn named_empty_files_keep_their_fence_in_any_position() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::create_dir(tmp.path().join("pkg")).expect("mkdir");
+    fs::write(tmp.path().join("pkg/a.py"), "a = 1\n").expect("write");
+    fs::write(tmp.path().join("pkg/__init__.py"), "").expect("write");
+    fs::write(tmp.path().join("py.typed"), "").expect("write");
+
+    for args in [["pkg", "py.typed"], ["py.typed", "pkg"]] {
+        let out = fencecat(tmp.path(), &args);
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        assert!(stdout.contains("```py.typed\n"), "{args:?}: {stdout}");
+        assert!(!stdout.contains("__init__.py"), "{args:?}: {stdout}");
+    }
+}
+
+#[test]
+fn only_empty_files_is_nothing_matched() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("__init__.py"), "").expect("write");
+    fs::write(tmp.path().join("py.typed"), "").expect("write");
+
+    let out = fencecat(tmp.path(), &["."]);
+    assert_eq!(out.status.code(), Some(3));
+    assert!(out.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&out.stderr).contains(">> no files matched"));
+
+    let out = fencecat(tmp.path(), &[".", "--include-empty"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("```py.typed\n"));
+}
+
+#[test]
+fn piped_override_implies_quiet_unless_told_otherwise() {
+    let src = tempfile::tempdir().expect("tempdir");
+    fs::write(src.path().join("a.rs"), "fn a() {}\n").expect("write");
+    let dump = fencecat(src.path(), &["."]).stdout;
+    let dst = tempfile::tempdir().expect("tempdir");
+
+    let stderr = |args: &[&str]| {
+        String::from_utf8_lossy(&fencecat_stdin(dst.path(), args, &dump).stderr).into_owned()
+    };
+    assert!(stderr(&["--extract", "a"]).contains(">> extracted 1 file(s)"));
+    assert!(stderr(&["--extract", "b", "--stdout-format-override-when-piped"]).is_empty());
+    assert!(
+        stderr(&[
+            "--extract",
+            "c",
+            "--stdout-format-override-when-piped",
+            "--no-quiet"
+        ])
+        .contains(">> extracted 1 file(s)")
+    );
+    assert!(stderr(&["--extract", "d", "-q"]).is_empty());
+}
+
+/// `fencecat ARGS` with git isolated from the machine's configuration.
+fn fencecat_git_env(
+    dir: &Path,
+    home: &Path,
+    env: &[(&str, &Path)],
+    args: &[&str],
+) -> std::process::Output {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_fencecat"));
+    cmd.current_dir(dir)
+        .args(args)
+        .env("HOME", home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env_remove("GIT_DIR")
+        .env_remove("GIT_WORK_TREE")
+        .env_remove("GIT_CONFIG_GLOBAL");
+    for (k, v) in env {
+        cmd.env(k, v);
+    }
+    cmd.output().expect("run fencecat")
+}
+
+#[test]
+fn git_config_decides_ignores() {
+    let home = tempfile::tempdir().expect("tempdir");
+    let repo = home.path().join("repo");
+    fs::create_dir(&repo).expect("mkdir");
+    let git = |args: &[&str]| {
+        let ok = Command::new("git")
+            .current_dir(&repo)
+            .args(args)
+            .env("HOME", home.path())
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .output()
+            .is_ok_and(|o| o.status.success());
+        assert!(ok, "git {args:?}");
+    };
+    git(&["init", "-q"]);
+    fs::write(repo.join("a.rs"), "fn a() {}\n").expect("write");
+    fs::write(repo.join("b.secret"), "token\n").expect("write");
+    fs::write(repo.join("c.log"), "log\n").expect("write");
+    fs::write(repo.join(".gitignore"), "*.log\n").expect("write");
+
+    // An excludes file named by a config file git finds but the walker does not
+    fs::create_dir_all(home.path().join("etc")).expect("mkdir");
+    let excludes = home.path().join("etc/my-excludes");
+    fs::write(&excludes, "*.secret\n").expect("write");
+    let config = home.path().join("etc/gitconfig");
+    fs::write(
+        &config,
+        format!("[core]\n\texcludesFile = {}\n", excludes.display()),
+    )
+    .expect("write");
+    let scan = |env: &[(&str, &Path)]| {
+        let out = fencecat_git_env(&repo, home.path(), env, &["."]);
+        String::from_utf8_lossy(&out.stdout).into_owned()
+    };
+    let out = scan(&[("GIT_CONFIG_GLOBAL", &config)]);
+    assert!(out.contains("a.rs"));
+    assert!(!out.contains("b.secret"));
+    assert!(!out.contains("c.log"));
+
+    // A repository whose git dir lives elsewhere
+    fs::rename(repo.join(".git"), home.path().join("repo.git")).expect("rename");
+    assert!(scan(&[]).contains("c.log"));
+    let out = scan(&[
+        ("GIT_DIR", &home.path().join("repo.git")),
+        ("GIT_WORK_TREE", &repo),
+    ]);
+    assert!(out.contains("a.rs"));
+    assert!(!out.contains("c.log"));
+}
+
+#[test]
+fn content_hash_header_cross_references_duplicates() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn shared() {}\n").expect("write");
+    fs::write(tmp.path().join("b.rs"), "fn other() {}\n").expect("write");
+    fs::write(tmp.path().join("c.rs"), "fn shared() {}\n").expect("write");
+
+    let out = fencecat(tmp.path(), &[".", "--content-hash-header"]);
+    assert!(out.status.success());
+    let text = String::from_utf8_lossy(&out.stdout);
+    let ids: Vec<&str> = text
+        .lines()
+        .filter_map(|l| l.split(" content=").nth(1))
+        .collect();
+    assert_eq!(ids.len(), 2);
+    assert_eq!(ids[0], ids[1]);
+    assert!(text.contains("```rust b.rs\n"));
+    assert!(text.contains("[duplicate: c.rs — identical to [a.rs] (15 B) — content omitted]"));
+    assert_eq!(text.matches("fn shared()").count(), 1);
+
+    // --extract skips the note
+    let sections = fencecat_stdin(tmp.path(), &["--extract", "out"], text.as_bytes());
+    assert!(sections.status.success());
+    assert!(!tmp.path().join("out/c.rs").exists());
+}
+
+#[test]
+fn max_tokens_budgets_duplicates_with_their_original() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let big: String = (0..100).map(|i| format!("fn f{i}() {{}}\n")).collect();
+    fs::write(tmp.path().join("a.rs"), &big).expect("write");
+    fs::write(tmp.path().join("b.rs"), "fn b() {}\n").expect("write");
+    fs::write(tmp.path().join("c.rs"), &big).expect("write");
+
+    let out = fencecat(
+        tmp.path(),
+        &[
+            ".",
+            "--content-hash-header",
+            "--max-tokens",
+            "200",
+            "--budget-policy",
+            "skip",
+        ],
+    );
+    assert!(out.status.success());
+    let text = String::from_utf8_lossy(&out.stdout);
+    assert!(text.contains("```rust b.rs\n"));
+    assert!(!text.contains("a.rs") && !text.contains("c.rs"), "{text}");
+}
+
+/// Run fencecat with an empty `PATH`, so no external tool can be found.
+fn fencecat_without_tools(dir: &Path, args: &[&str]) -> std::process::Output {
+    let empty_path = tempfile::tempdir().expect("tempdir");
+    Command::new(env!("CARGO_BIN_EXE_fencecat"))
+        .current_dir(dir)
+        .env("PATH", empty_path.path())
+        .args(args)
+        .output()
+        .expect("run fencecat")
+}
+
+#[test]
+fn python_stub_keeps_source_without_python() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.py"), "def f():\n    return 1\n").expect("write");
+
+    let out = fencecat_without_tools(tmp.path(), &[".", "--python-stub"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```python a.py\ndef f():\n    return 1\n\n```\n\n"
+    );
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains(">> warning: a.py: no Python interpreter")
+    );
+}
+
+#[test]
+fn typescript_is_stripped_in_process_and_kept_when_it_does_not_parse() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.ts"), "export const a: number = 1;\n").expect("write");
+    fs::write(tmp.path().join("b.ts"), "export const b: = ;\n").expect("write");
+
+    let out = fencecat_without_tools(tmp.path(), &[".", "--typescript-strip-types"]);
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.starts_with("```javascript a.ts\nexport const a = 1;\n"));
+    assert!(stdout.contains("```typescript b.ts\n// fencecat: types not stripped (line 1: "));
+    assert!(stdout.contains("export const b: = ;\n"));
+    assert!(String::from_utf8_lossy(&out.stderr).contains(">> warning: b.ts: line 1: "));
+}
+
+#[cfg(unix)]
+#[test]
+fn typescript_left_to_tsc_is_compiled_in_one_run() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let bin = tempfile::tempdir().expect("tempdir");
+    let log = bin.path().join("runs.log");
+    // Stands in for `npx tsc`: logs the run and emits a stub per input
+    let npx = bin.path().join("npx");
+    fs::write(
+        &npx,
+        format!(
+            "#!/bin/sh\necho run >> '{}'\nprev=\nfor a in \"$@\"; do\n\
+             [ \"$prev\" = --outDir ] && out=$a\nprev=$a\ndone\nmkdir -p \"$out\"\n\
+             for a in \"$@\"; do case $a in *.ts) echo \"var tsc;\" > \"$out/$(basename \"$a\" .ts).js\";; esac; done\n",
+            log.display()
+        ),
+    )
+    .expect("write");
+    fs::set_permissions(&npx, fs::Permissions::from_mode(0o755)).expect("chmod");
+    for name in ["a.ts", "b.ts", "c.ts"] {
+        fs::write(tmp.path().join(name), "let x: number = 1;\n").expect("write");
+    }
+
+    // SWC does not target ES3, so every file goes to tsc
+    let out = Command::new(env!("CARGO_BIN_EXE_fencecat"))
+        .current_dir(tmp.path())
+        .env("PATH", format!("{}:/bin:/usr/bin", bin.path().display()))
+        .args([".", "--typescript-strip-types", "--ts-target", "es3"])
+        .output()
+        .expect("run fencecat");
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert_eq!(stdout.matches("var tsc;").count(), 3, "{stdout}");
+    assert_eq!(fs::read_to_string(&log).expect("log"), "run\n");
+}
+
+#[test]
+fn label_from_git_root_keeps_the_subdirectory_prefix() {
+    let home = tempfile::tempdir().expect("tempdir");
+    let repo = home.path().join("repo");
+    fs::create_dir_all(repo.join("src/net")).expect("mkdir");
+    fs::write(repo.join("src/main.rs"), "fn main() {}\n").expect("write");
+    fs::write(repo.join("src/net/a.rs"), "fn a() {}\n").expect("write");
+    fs::write(repo.join("README.md"), "# repo\n").expect("write");
+    let init = Command::new("git")
+        .current_dir(&repo)
+        .args(["init", "-q"])
+        .env("HOME", home.path())
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .status()
+        .expect("git init");
+    assert!(init.success());
+
+    let run = |cwd: &Path, args: &[&str]| {
+        let out = fencecat_git_env(cwd, home.path(), &[], args);
+        String::from_utf8_lossy(&out.stdout).into_owned()
+    };
+    let out = run(
+        &repo.join("src"),
+        &[".", "--dir-list", "--label-from-git-root"],
+    );
+    assert!(out.starts_with("```\nsrc/main.rs\nsrc/net/a.rs\n```\n"));
+    assert!(out.contains("```rust src/net/a.rs\n"));
+    assert!(!out.contains("README.md"));
+
+    let out = run(&repo.join("src/net"), &["a.rs", "--label-from-git-root"]);
+    assert!(out.starts_with("```rust src/net/a.rs\n"));
+
+    // Outside a repository the labels stay as they were
+    let outside = home.path().join("plain");
+    fs::create_dir(&outside).expect("mkdir");
+    fs::write(outside.join("b.rs"), "fn b() {}\n").expect("write");
+    assert!(run(&outside, &[".", "--label-from-git-root"]).starts_with("```rust b.rs\n"));
+}
+
+#[test]
+fn honor_ignore_for_explicit_skips_ignored_paths() {
+    let home = tempfile::tempdir().expect("tempdir");
+    let repo = home.path().join("repo");
+    fs::create_dir_all(repo.join("build")).expect("mkdir");
+    fs::write(repo.join(".gitignore"), "*.env\nbuild/\n").expect("write");
+    fs::write(repo.join("a.rs"), "fn a() {}\n").expect("write");
+    fs::write(repo.join("secret.env"), "TOKEN=1\n").expect("write");
+    fs::write(repo.join("build/gen.rs"), "fn gen() {}\n").expect("write");
+    let init = Command::new("git")
+        .current_dir(&repo)
+        .args(["init", "-q"])
+        .env("HOME", home.path())
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .status()
+        .expect("git init");
+    assert!(init.success());
+
+    let run = |args: &[&str]| fencecat_git_env(&repo, home.path(), &[], args);
+    // Naming a file includes it by default
+    assert!(run(&["secret.env"]).status.success());
+
+    for ignored in ["secret.env", "build/gen.rs"] {
+        let out = run(&[ignored, "--honor-ignore-for-explicit"]);
+        assert_eq!(out.status.code(), Some(3), "{ignored}");
+        assert!(out.stdout.is_empty());
+        assert!(
+            String::from_utf8_lossy(&out.stderr)
+                .contains("ignored by the repository's ignore rules"),
+            "{ignored}"
+        );
+    }
+    let out = run(&["a.rs", "--honor-ignore-for-explicit"]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```rust a.rs\nfn a() {}\n\n```\n\n"
+    );
+}
+
+#[test]
+fn include_root_name_uses_the_real_directory_name() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let root = tmp.path().join("backend");
+    fs::create_dir_all(root.join("src")).expect("mkdir");
+    fs::write(root.join("src/api.rs"), "fn api() {}\n").expect("write");
+    let stdout = |dir: &Path, args: &[&str]| {
+        let out = fencecat(dir, args);
+        assert!(out.status.success());
+        String::from_utf8_lossy(&out.stdout).into_owned()
+    };
+
+    let dot = stdout(&root, &[".", "--dir-list", "--include-root-name"]);
+    assert!(dot.starts_with("```\nbackend/src/api.rs\n```\n"));
+    assert!(dot.contains("```rust backend/src/api.rs\n"));
+    let toc = stdout(&root, &[".", "--section-toc", "--include-root-name"]);
+    assert!(toc.starts_with("- [backend/src/api.rs](#backend-src-api-rs)\n"));
+
+    let slash = stdout(tmp.path(), &["backend/", "--include-root-name"]);
+    assert!(slash.starts_with("```rust backend/src/api.rs\n"));
+
+    // A single file is labeled under its directory's name
+    let file = stdout(&root.join("src"), &["api.rs", "--include-root-name"]);
+    assert!(file.starts_with("```rust src/api.rs\n"));
+}
+
+#[test]
+fn dir_list_links_only_fenced_files() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("empty.rs"), "").expect("write");
+
+    let out = fencecat(tmp.path(), &[".", "--dir-list", "--fence-anchor"]);
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.starts_with("- [a.rs](#a-rs)\n- empty.rs (empty)\n\n<a id=\"a-rs\"></a>\n"),
+        "{stdout}"
+    );
+    assert!(!stdout.contains("#empty-rs"));
+}
+
+#[test]
+fn section_toc_links_only_fenced_files() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("empty.txt"), "").expect("write");
+    fs::write(tmp.path().join("logo.png"), b"\x89PNG\r\n\x1a\n\0\0").expect("write");
+
+    let out = fencecat(tmp.path(), &[".", "--section-toc", "--note-images"]);
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.starts_with("- [a.rs](#a-rs)\n\n<a id=\"a-rs\"></a>\n"),
+        "{stdout}"
+    );
+    assert!(!stdout.contains("(#logo-png)") && !stdout.contains("(#empty-txt)"));
+
+    // Links show the label as printed, but point at the anchor
+    let out = fencecat(tmp.path(), &["a.rs", "--section-toc", "--absolute-paths"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let root = fs::canonicalize(tmp.path()).expect("canonicalize");
+    assert!(
+        stdout.starts_with(&format!("- [{}/a.rs](#a-rs)\n", root.display())),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn colliding_anchors_are_numbered_and_link_text_escaped() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a-b.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("a.b.rs"), "fn b() {}\n").expect("write");
+    fs::create_dir(tmp.path().join("[id]")).expect("mkdir");
+    fs::write(tmp.path().join("[id]/page_1.tsx"), "x;\n").expect("write");
+
+    let out = fencecat(tmp.path(), &[".", "--section-toc", "--dir-list"]);
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let toc = "- [\\[id\\]/page\\_1.tsx](#-id--page-1-tsx)\n- [a-b.rs](#a-b-rs)\n- [a.b.rs](#a-b-rs-1)\n\n";
+    assert!(stdout.starts_with(toc), "{stdout}");
+    // The listing stays plain rather than repeat the list
+    assert!(
+        stdout[toc.len()..].starts_with("```\n[id]/page_1.tsx\na-b.rs\na.b.rs\n```\n"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("<a id=\"a-b-rs\"></a>\n```rust a-b.rs\n"));
+    assert!(stdout.contains("<a id=\"a-b-rs-1\"></a>\n```rust a.b.rs\n"));
+
+    // Without the list, the --fence-anchor listing links the same ids
+    let out = fencecat(tmp.path(), &[".", "--fence-anchor", "--dir-list"]);
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with(toc.trim_end()));
+}
+
+#[test]
+fn sort_by_complexity_puts_branchy_rust_first() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("b.rs"), "fn b(x: bool) {\n    if x {}\n}\n").expect("write");
+    fs::write(
+        tmp.path().join("c.rs"),
+        "fn c(x: u8) {\n    while x > 0 && x < 9 {}\n}\n",
+    )
+    .expect("write");
+    fs::write(tmp.path().join("README.md"), "# if if if\n").expect("write");
+
+    let out = fencecat(tmp.path(), &[".", "--dir-list", "--sort-by-complexity"]);
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let order: Vec<&str> = stdout
+        .lines()
+        .filter_map(|l| {
+            l.strip_prefix("```rust ")
+                .or(l.strip_prefix("```markdown "))
+        })
+        .collect();
+    assert_eq!(order, ["c.rs", "b.rs", "a.rs", "README.md"]);
+
+    let out = fencecat(
+        tmp.path(),
+        &[
+            ".",
+            "--sort-by-complexity",
+            "--complexity-threshold",
+            "1",
+            "--dir-list-excluded",
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("```excluded\n[complexity] a.rs\n```\n"));
+    assert!(!stdout.contains("```rust a.rs"));
+    assert!(stdout.contains("```markdown README.md"));
+}
+
+#[test]
+fn audit_reports_the_worst_verdict() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+
+    let out = fencecat(tmp.path(), &[".", "--audit"]);
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "green  a.rs\n\nsafe to share: 0 red, 0 yellow, 1 green\n"
+    );
+
+    // Over budget, and a hidden file only -H lets in
+    fs::write(tmp.path().join(".notes"), "todo\n").expect("write");
+    let out = fencecat(tmp.path(), &[".", "--audit", "-H", "--max-tokens", "1"]);
+    assert_eq!(out.status.code(), Some(4));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.starts_with(
+        "yellow .notes\n         hidden or gitignored; only -H/--no-ignore includes it\n"
+    ));
+    assert!(stdout.contains("yellow a.rs\n         ~"));
+    assert!(stdout.contains("\nreview before sharing: 0 red, 2 yellow, 0 green\n"));
+
+    fs::write(
+        tmp.path().join("config.py"),
+        "API_KEY = \"0123456789abcdef\"\n",
+    )
+    .expect("write");
+    let out = fencecat(tmp.path(), &[".", "--audit"]);
+    assert_eq!(out.status.code(), Some(5));
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("red    config.py\n         line 1: possible hard-coded credential\n"));
+    assert!(!stdout.contains("0123456789abcdef"));
+    assert!(stdout.ends_with("NOT safe to share: 1 red, 0 yellow, 1 green\n"));
+}
+
+#[test]
+fn line_filters_are_inclusive() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("four.txt"), "1\n2\n3\n4\n").expect("write");
+    fs::write(tmp.path().join("five.txt"), "1\n2\n3\n4\n5").expect("write");
+    fs::write(tmp.path().join("six.txt"), "1\n2\n3\n4\n5\n6\n").expect("write");
+    let kept = |args: &[&str]| {
+        let out = fencecat(tmp.path(), args);
+        assert!(out.status.success());
+        String::from_utf8_lossy(&out.stdout)
+            .lines()
+            .filter_map(|l| {
+                l.strip_prefix("```")
+                    .filter(|label| label.ends_with(".txt"))
+            })
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(kept(&[".", "--min-lines", "5"]), ["five.txt", "six.txt"]);
+    assert_eq!(kept(&[".", "--max-lines", "5"]), ["five.txt", "four.txt"]);
+    assert_eq!(
+        kept(&[".", "--min-lines", "5", "--max-lines", "5"]),
+        ["five.txt"]
+    );
+
+    let out = fencecat(
+        tmp.path(),
+        &[
+            ".",
+            "--min-lines",
+            "5",
+            "--max-lines",
+            "5",
+            "--dir-list-excluded",
+        ],
+    );
+    assert!(
+        String::from_utf8_lossy(&out.stdout)
+            .starts_with("```excluded\n[lines] four.txt\n[lines] six.txt\n```\n")
+    );
+}
+
+#[test]
+fn strict_binary_skips_invalid_utf8() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.txt"), "ok\n").expect("write");
+    fs::write(tmp.path().join("latin1.txt"), b"caf\xe9\n").expect("write");
+
+    let lossy = fencecat(tmp.path(), &["."]);
+    assert!(String::from_utf8_lossy(&lossy.stdout).contains("caf\u{fffd}"));
+
+    let out = fencecat(tmp.path(), &[".", "--strict-binary", "--dir-list-excluded"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8(out.stdout).expect("clean UTF-8"),
+        "```excluded\n[utf8] latin1.txt\n```\n\n```a.txt\nok\n\n```\n\n"
+    );
+    assert!(
+        String::from_utf8_lossy(&out.stderr)
+            .contains(">> skipped 1 file(s) that are not valid UTF-8 (--strict-binary)")
+    );
+
+    let quiet = fencecat(tmp.path(), &[".", "--strict-binary", "--quiet"]);
+    assert!(quiet.status.success());
+    assert!(quiet.stderr.is_empty());
+
+    // A named file is skipped too, not decoded lossily
+    let named = fencecat(tmp.path(), &["latin1.txt", "--strict-binary"]);
+    assert_eq!(named.status.code(), Some(3));
+    assert!(named.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&named.stderr).contains("not valid UTF-8 (--strict-binary)"));
+    let named = fencecat(tmp.path(), &["a.txt", "latin1.txt", "--strict-binary"]);
+    assert_eq!(
+        String::from_utf8(named.stdout).expect("clean UTF-8"),
+        "```a.txt\nok\n\n```\n\n"
+    );
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn fake_root_scans_the_manifest_not_the_disk() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("real.rs"), "fn real() {}\n").expect("write");
+    fs::write(
+        tmp.path().join("fixture.json"),
+        r#"[{"path": "lib/x.rs", "content": "fn x() {}\n"}]"#,
+    )
+    .expect("write");
+
+    let out = fencecat(
+        tmp.path(),
+        &["--fake-root", ".", "--fake-root-manifest", "fixture.json"],
+    );
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```rust lib/x.rs\nfn x() {}\n\n```\n\n"
+    );
+
+    let out = fencecat(tmp.path(), &["--fake-root", "."]);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn bytes_flag_writes_exact_sizes() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("big.txt"), "x\n".repeat(1024)).expect("write");
+
+    let out = fencecat(tmp.path(), &[".", "--warn-large-file", "1k"]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("big.txt is 2.0 KiB;"));
+    let out = fencecat(tmp.path(), &[".", "--warn-large-file", "1k", "--bytes"]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("big.txt is 2048 B;"));
+}
+
+#[test]
+fn passthrough_binary_keeps_flagged_extensions() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.proto"), "syntax = \"proto3\";\0\n").expect("write");
+    fs::write(tmp.path().join("b.bin"), "x\0y\n").expect("write");
+
+    let out = fencecat(tmp.path(), &[".", "--allow-empty"]);
+    assert!(out.stdout.is_empty());
+
+    let out = fencecat(tmp.path(), &[".", "--passthrough-binary", ".PROTO"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("a.proto\nsyntax = \"proto3\";\0\n"),
+        "{stdout}"
+    );
+    assert!(!stdout.contains("b.bin"));
+}
+
+/// Answer one HTTP request on a local port with `status` and `body`; the
+/// handle yields the raw request.
+fn one_shot_server(status: &str, body: &str) -> (String, std::thread::JoinHandle<String>) {
+    use std::{
+        io::{BufRead as _, BufReader, Read as _, Write as _},
+        net::TcpListener,
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+    let url = format!("http://{}/ctx", listener.local_addr().expect("addr"));
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    let handle = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().expect("accept");
+        let mut reader = BufReader::new(stream);
+        let mut request = String::new();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).expect("read");
+            if let Some(v) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                length = v.trim().parse().expect("length");
+            }
+            request.push_str(&line);
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).expect("body");
+        request.push_str(&String::from_utf8_lossy(&body));
+        reader
+            .get_mut()
+            .write_all(response.as_bytes())
+            .expect("respond");
+        request
+    });
+    (url, handle)
+}
+
+#[test]
+fn http_post_sends_the_output_and_checks_the_status() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("a.rs"), "fn a() {}\n").expect("write");
+    let dump = "```rust a.rs\nfn a() {}\n\n```\n\n";
+
+    let (url, server) = one_shot_server("201 Created", "{\"id\":7}");
+    let out = fencecat(
+        tmp.path(),
+        &[
+            "a.rs",
+            "--http-post",
+            &url,
+            "--http-header",
+            "Authorization: Bearer t0k",
+            "--http-post-response-file",
+            "resp.json",
+        ],
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&out.stdout), dump);
+    let request = server.join().expect("server");
+    assert!(request.starts_with("POST /ctx HTTP/1.1\r\n"), "{request}");
+    let lower = request.to_ascii_lowercase();
+    assert!(lower.contains("content-type: text/markdown; charset=utf-8\r\n"));
+    assert!(lower.contains("authorization: bearer t0k\r\n"));
+    assert!(request.ends_with(&format!("\r\n\r\n{dump}")));
+    assert_eq!(
+        fs::read_to_string(tmp.path().join("resp.json")).expect("read"),
+        "{\"id\":7}"
+    );
+
+    let (url, server) = one_shot_server("500 Internal Server Error", "cache down\n");
+    let out = fencecat(tmp.path(), &["a.rs", "--http-post", &url, "--no-print"]);
+    server.join().expect("server");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(out.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("500 Internal Server Error: cache down"));
+
+    let out = fencecat(tmp.path(), &["a.rs", "--http-post", "ftp://example.com/x"]);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn unexpanded_glob_path_is_expanded_internally() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::create_dir_all(tmp.path().join("src/net")).expect("mkdir");
+    fs::write(tmp.path().join("src/a.rs"), "fn a() {}\n").expect("write");
+    fs::write(tmp.path().join("src/b.py"), "b = 1\n").expect("write");
+    fs::write(tmp.path().join("src/net/c.rs"), "fn c() {}\n").expect("write");
+
+    let out = fencecat(tmp.path(), &["src/*.rs", "--dir-list"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```\na.rs\n```\n\n```rust a.rs\nfn a() {}\n\n```\n\n"
+    );
+
+    let out = fencecat(tmp.path(), &["src/**/*.rs"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("```rust a.rs\n") && stdout.contains("```rust net/c.rs\n"));
+    assert!(!stdout.contains("b.py"));
+
+    let out = fencecat(tmp.path(), &["src/*.go"]);
+    assert_eq!(out.status.code(), Some(3));
+    assert_eq!(
+        String::from_utf8_lossy(&out.stderr),
+        ">> no files matched\n"
+    );
+    assert_eq!(fencecat(tmp.path(), &["src/[a.rs"]).status.code(), Some(2));
+
+    // Hidden entries only with -H, as in a walk
+    fs::write(tmp.path().join("src/.local.rs"), "fn local() {}\n").expect("write");
+    let out = fencecat(tmp.path(), &["src/*.rs"]);
+    assert!(!String::from_utf8_lossy(&out.stdout).contains(".local.rs"));
+    let out = fencecat(tmp.path(), &["src/*.rs", "-H"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("```rust .local.rs\n"));
+
+    // Ignore rules apply unless -H; binary matches are skipped
+    fs::write(tmp.path().join(".ignore"), "src/gen.rs\n").expect("write");
+    fs::write(tmp.path().join("src/gen.rs"), "fn gen() {}\n").expect("write");
+    fs::write(tmp.path().join("src/blob"), b"\x00\x01\x02").expect("write");
+    let out = fencecat(tmp.path(), &["src/*"]);
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("```rust a.rs\n") && stdout.contains("```python b.py\n"));
+    assert!(!stdout.contains("gen.rs") && !stdout.contains("blob"));
+    let out = fencecat(tmp.path(), &["src/*.rs", "-H"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("```rust gen.rs\n"));
+}
+
+#[test]
+fn jobs_keep_the_serial_output() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    for i in 0..40 {
+        let dir = tmp.path().join(format!("d{}", i % 4));
+        fs::create_dir_all(&dir).expect("mkdir");
+        fs::write(
+            dir.join(format!("f{i}.rs")),
+            format!("fn f{i}() {{}}\n").repeat(i + 1),
+        )
+        .expect("write");
+    }
+    fs::write(tmp.path().join("empty.txt"), "").expect("write");
+
+    let serial = fencecat(tmp.path(), &["--dir-list", "-B"]);
+    assert!(serial.status.success());
+    for jobs in ["4", "0"] {
+        let parallel = fencecat(tmp.path(), &["--dir-list", "-B", "--jobs", jobs]);
+        assert!(parallel.status.success());
+        assert_eq!(parallel.stdout, serial.stdout);
+    }
+
+    // Per-thread read times are summed, so the report gives the wall time too
+    let timed = fencecat(tmp.path(), &["--jobs", "3", "--timings"]);
+    assert!(String::from_utf8_lossy(&timed.stderr).contains(" on 3 threads, "));
+}
+
+#[test]
+fn env_file_values_are_redacted() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(
+        tmp.path().join(".env"),
+        "API_KEY=abc123secret\nDEBUG=true\n",
+    )
+    .expect("write");
+    fs::write(
+        tmp.path().join("app.py"),
+        "key = \"abc123secret\"\ndebug = true\n",
+    )
+    .expect("write");
+
+    let out = fencecat(tmp.path(), &["--env-file"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "```python app.py\nkey = \"[REDACTED]\"\ndebug = true\n\n```\n\n"
+    );
+
+    let out = fencecat(tmp.path(), &["--env-file=missing.env"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("failed to read --env-file missing.env"));
+
+    fs::remove_file(tmp.path().join(".env")).expect("remove");
+    let out = fencecat(tmp.path(), &["--env-file"]);
+    assert!(out.status.success());
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains(">> warning: --env-file: no .env file in")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn biggest_first_ties_sort_by_normalized_name() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    // Same size; stored precomposed (é) and decomposed (e + U+0301)
+    fs::write(tmp.path().join("caf\u{e9}-1.rs"), "x\n").expect("write");
+    fs::write(tmp.path().join("cafe\u{301}-2.rs"), "y\n").expect("write");
+    fs::write(tmp.path().join("cafez.rs"), "z\n").expect("write");
+
+    for args in [&["--dir-list"][..], &["--dir-list", "-B"]] {
+        let out = fencecat(tmp.path(), args);
+        assert!(out.status.success());
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        let labels: Vec<&str> = stdout
+            .lines()
+            .filter_map(|l| l.strip_prefix("```rust "))
+            .collect();
+        assert_eq!(labels, ["cafez.rs", "caf\u{e9}-1.rs", "cafe\u{301}-2.rs"]);
+        assert!(stdout.starts_with("```\ncafez.rs\ncaf\u{e9}-1.rs\ncafe\u{301}-2.rs\n```\n"));
+    }
+}
+
+#[test]
+fn extension_order_puts_listed_groups_first() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("Cargo.toml"), "[package]\n").expect("write");
+    fs::write(tmp.path().join("a.md"), "# a\n").expect("write");
+    fs::write(tmp.path().join("z.rs"), "fn z() {}\n").expect("write");
+
+    let out = fencecat(tmp.path(), &["--dir-list", "--extension-order", "rs,.TOML"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with("```\nz.rs\nCargo.toml\na.md\n```\n"));
+
+    let out = fencecat(tmp.path(), &["--dir-list", "--sort-extensions-first"]);
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with("```\na.md\nz.rs\nCargo.toml\n```\n"));
+
+    // Empty-dir markers join their siblings without re-sorting the files
+    fs::create_dir_all(tmp.path().join("emptyd/sub")).expect("mkdir");
+    fs::create_dir(tmp.path().join("b")).expect("mkdir");
+    let out = fencecat(
+        tmp.path(),
+        &[
+            "--dir-list",
+            "--extension-order",
+            "rs,toml",
+            "--emit-empty-dir-markers",
+        ],
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with(
+        "```\nz.rs\nCargo.toml\na.md\nb/ (empty/filtered)\nemptyd/ (empty/filtered)\n\
+         emptyd/sub/ (empty/filtered)\n```\n"
+    ));
+    let out = fencecat(tmp.path(), &["--dir-list", "--emit-empty-dir-markers"]);
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with(
+        "```\nCargo.toml\na.md\nb/ (empty/filtered)\nemptyd/ (empty/filtered)\n\
+         emptyd/sub/ (empty/filtered)\nz.rs\n```\n"
+    ));
+}
+
+#[cfg(unix)]
+#[test]
+fn no_content_checks_never_opens_a_file() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(tmp.path().join("blob.txt"), b"\0\x01\x02 binary inside").expect("write");
+    fs::write(tmp.path().join("logo.png"), "not really a png").expect("write");
+    for i in 0..3 {
+        let path = tmp.path().join(format!("big{i}.log"));
+        fs::write(&path, "x".repeat(1 << 20)).expect("write");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o000)).expect("chmod");
+    }
+
+    let explain = |extra: &[&str]| {
+        let mut args = vec![".", "--explain-json"];
+        args.extend(extra);
+        let out = fencecat(tmp.path(), &args);
+        assert!(out.status.success());
+        let json: serde_json::Value = serde_json::from_slice(&out.stdout).expect("json");
+        (json, String::from_utf8_lossy(&out.stderr).into_owned())
+    };
+    let entry = |json: &serde_json::Value, path: &str| {
+        json.as_array()
+            .expect("array")
+            .iter()
+            .find(|e| e["path"] == path)
+            .cloned()
+            .expect("entry")
+    };
+
+    // The content sniff spots the NUL byte; the name alone cannot
+    let (checked, _) = explain(&[]);
+    assert_eq!(entry(&checked, "blob.txt")["reason"], "Binary");
+    let (fast, stderr) = explain(&["--no-content-checks"]);
+    let blob = entry(&fast, "blob.txt");
+    assert_eq!(
+        (&blob["included"], &blob["assumed_text"]),
+        (&true.into(), &true.into())
+    );
+    assert_eq!(entry(&fast, "logo.png")["reason"], "Binary");
+    assert!(entry(&checked, "big0.log").get("assumed_text").is_none());
+    assert!(stderr.contains(
+        ">> approximate: 4 file(s) included as text without opening them, 0 checked \
+         (--no-content-checks)"
+    ));
+
+    // Unreadable files are no error when nothing is opened
+    assert!(stderr.lines().all(|l| !l.starts_with("skip ")), "{stderr}");
+    assert_eq!(entry(&fast, "big2.log")["included"], true);
+
+    let out = fencecat(tmp.path(), &[".", "--no-content-checks"]);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn test_files_can_be_excluded_or_kept_alone() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    for (rel, body) in [
+        ("src/lib.rs", "pub fn f() {}\n"),
+        ("src/lib_test.rs", "fn t() {}\n"),
+        ("tests/cli.rs", "fn c() {}\n"),
+        ("web/app.ts", "export {};\n"),
+        ("web/app.test.ts", "test();\n"),
+        ("test_app.py", "def test(): pass\n"),
+    ] {
+        let path = tmp.path().join(rel);
+        fs::create_dir_all(path.parent().expect("parent")).expect("mkdir");
+        fs::write(path, body).expect("write");
+    }
+    let listing = |args: &[&str]| {
+        let out = fencecat(tmp.path(), args);
+        assert!(out.status.success());
+        let stdout = String::from_utf8_lossy(&out.stdout).into_owned();
+        stdout.split("```").nth(1).expect("listing").to_string()
+    };
+
+    assert_eq!(
+        listing(&["--dir-list", "--exclude-tests"]),
+        "\nsrc/lib.rs\nweb/app.ts\n"
+    );
+    assert_eq!(
+        listing(&["--dir-list", "--only-tests", "--ext", "rs,py"]),
+        "\nsrc/lib_test.rs\ntest_app.py\ntests/cli.rs\n"
+    );
+    let out = fencecat(tmp.path(), &["--exclude-tests", "--only-tests"]);
+    assert_eq!(out.status.code(), Some(2));
+
+    // Only the part below the scan root counts, wherever it sits or is run from
+    let proj = tmp.path().join("test/proj");
+    fs::create_dir_all(proj.join("tests")).expect("mkdir");
+    fs::write(proj.join("main.rs"), "fn main() {}\n").expect("write");
+    fs::write(proj.join("tests/cli.rs"), "fn c() {}\n").expect("write");
+    let out = fencecat(tmp.path(), &["test/proj", "--dir-list", "--exclude-tests"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("```\nmain.rs\n```"));
+    let out = fencecat(
+        &proj.join("tests"),
+        &["..", "--dir-list", "--exclude-tests"],
+    );
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("```\nmain.rs\n```"));
+}
+
+#[test]
+fn named_files_keep_command_line_order() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::create_dir(tmp.path().join("src")).expect("mkdir");
+    fs::write(tmp.path().join("README.md"), "# demo\n").expect("write");
+    fs::write(tmp.path().join("src/lib.rs"), "pub fn lib() {}\n").expect("write");
+    fs::write(tmp.path().join("src/main.rs"), "fn main() {}\n").expect("write");
+
+    let out = fencecat(
+        tmp.path(),
+        &[
+            "src/main.rs",
+            "README.md",
+            "src/lib.rs",
+            "--dir-list",
+            "--number-files",
+        ],
+    );
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.starts_with("```\n[1/3] src/main.rs\n[2/3] README.md\n[3/3] src/lib.rs\n```\n"));
+    let marked = fencecat(
+        tmp.path(),
+        &[
+            "src/main.rs",
+            "README.md",
+            "src/lib.rs",
+            "--dir-list",
+            "--number-files",
+            "--emit-empty-dir-markers",
+        ],
+    );
+    assert_eq!(marked.stdout, out.stdout);
+    let fences: Vec<&str> = stdout
+        .lines()
+        .filter(|l| l.starts_with("```") && l.len() > 3)
+        .collect();
+    assert_eq!(fences.len(), 3);
+    assert!(fences[0].ends_with("src/main.rs") && fences[2].ends_with("src/lib.rs"));
+
+    // Files in one directory are labeled relative to it; -B sorts again
+    let out = fencecat(
+        tmp.path(),
+        &["src/main.rs", "src/lib.rs", "--dir-list", "-B"],
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with("```\nlib.rs\nmain.rs\n```\n"));
+
+    // A named directory is walked in place; files are emitted once
+    fs::create_dir(tmp.path().join("src/empty")).expect("mkdir");
+    fs::write(tmp.path().join("src/a.rs"), "fn a() {}\n").expect("write");
+    let out = fencecat(
+        tmp.path(),
+        &[
+            "src/main.rs",
+            "README.md",
+            "src",
+            "./README.md",
+            "--dir-list",
+            "--emit-empty-dir-markers",
+        ],
+    );
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with(
+        "```\nsrc/main.rs\nREADME.md\nsrc/a.rs\nsrc/empty/ (empty/filtered)\nsrc/lib.rs\n```\n"
+    ));
+
+    // Globs are expanded in place; --force-include files are added
+    fs::write(tmp.path().join("Cargo.lock"), "version = 4\n").expect("write");
+    let out = fencecat(
+        tmp.path(),
+        &[
+            "src/*.rs",
+            "README.md",
+            "--dir-list",
+            "--include-cargo-lock",
+        ],
+    );
+    assert!(out.status.success());
+    assert!(
+        String::from_utf8_lossy(&out.stdout)
+            .starts_with("```\nsrc/a.rs\nsrc/lib.rs\nsrc/main.rs\nREADME.md\nCargo.lock\n```\n")
+    );
+
+    let out = fencecat(tmp.path(), &["README.md", "missing"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("no such file or directory: missing"));
+}
+
+#[test]
+fn repo_summary_describes_the_project_first() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    fs::write(
+        tmp.path().join("Cargo.toml"),
+        "[package]\nname = \"demo\"\nversion = \"0.1.0\"\nedition = \"2024\"\n",
+    )
+    .expect("write");
+    fs::write(tmp.path().join("lib.rs"), "pub fn f() {}\n").expect("write");
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_fencecat"))
+            .current_dir(tmp.path())
+            .env("FENCECAT_OPTS", "--repo-summary --ext rs")
+            .args(args)
+            .output()
+            .expect("run fencecat")
+    };
+
+    let out = run(&["--dir-list"]);
+    assert!(out.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "> Project: demo 0.1.0  \n> Language: Rust (edition 2024)  \n> Files: 1, 14 B\n\n\
+         ```\nlib.rs\n```\n\n```rust lib.rs\npub fn f() {}\n\n```\n\n"
+    );
+
+    let out = run(&["--no-repo-summary"]);
+    assert!(String::from_utf8_lossy(&out.stdout).starts_with("```rust lib.rs\n"));
+
+    // JSON has nowhere to put the summary; Markdown, asked for or not, does
+    let out = run(&["--format", "json"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8_lossy(&out.stderr),
+        "error: the argument '--format json' cannot be used with '--repo-summary'\n"
+    );
+    for md in [&["--format", "md"], &["--preset-llm", "gpt-4o"]] {
+        let out = run(md);
+        assert!(out.status.success());
+        assert!(String::from_utf8_lossy(&out.stdout).starts_with("> Project: demo 0.1.0  \n"));
+    }
+    let out = run(&["--format", "json", "--no-repo-summary"]);
+    assert!(out.status.success());
+
+    // Only files emitted with their content are counted
+    fs::write(tmp.path().join("copy.rs"), "pub fn f() {}\n").expect("write");
+    fs::write(tmp.path().join("empty.rs"), "").expect("write");
+    let out = run(&["--content-hash-header"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("> Files: 1, 14 B\n"));
+}